@@ -22,6 +22,7 @@ defmodule MarkdownLd.TurboBenchmark do
     run_extraction_benchmarks(test_cases)
     run_batch_benchmarks(test_cases)
     run_zero_copy_benchmarks(test_cases)
+    run_scheduler_benchmarks(test_cases)
     
     # Performance tracking
     display_performance_stats()
@@ -144,6 +145,51 @@ defmodule MarkdownLd.TurboBenchmark do
     IO.puts("Speedup: #{Float.round(speedup, 2)}x faster")
   end
 
+  defp run_scheduler_benchmarks(test_cases) do
+    IO.puts("\n🧵 Scheduler Responsiveness (dirty CPU parse)")
+    IO.puts("-" |> String.duplicate(30))
+
+    # One ticker per normal scheduler; if the parse held a scheduler, the
+    # tickers queued behind it would show up as late ticks.
+    content = String.duplicate(test_cases.huge, 20)
+    size_mb = byte_size(content) / (1024 * 1024)
+
+    tickers =
+      for _ <- 1..System.schedulers_online() do
+        spawn(fn -> tick_loop(0) end)
+      end
+
+    Process.sleep(20)
+    {time_parse, {:ok, _}} = :timer.tc(fn -> MarkdownLd.parse(content) end)
+
+    worst_lateness =
+      tickers
+      |> Enum.map(fn pid ->
+        send(pid, {:stop, self()})
+
+        receive do
+          {:max_lateness, ^pid, lateness} -> lateness
+        end
+      end)
+      |> Enum.max()
+
+    IO.puts("Content Size: #{format_size(size_mb)} MB")
+    IO.puts("Parse Time: #{format_time(time_parse)}")
+    IO.puts("Worst Tick Lateness: #{format_time(worst_lateness)} (1ms ticks)")
+  end
+
+  defp tick_loop(max_lateness) do
+    started = System.monotonic_time(:microsecond)
+
+    receive do
+      {:stop, from} -> send(from, {:max_lateness, self(), max_lateness})
+    after
+      1 ->
+        lateness = System.monotonic_time(:microsecond) - started - 1_000
+        tick_loop(max(max_lateness, lateness))
+    end
+  end
+
   defp display_performance_stats do
     IO.puts("\n📊 Performance Statistics")
     IO.puts("-" |> String.duplicate(30))
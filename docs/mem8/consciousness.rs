@@ -1,4 +1,6 @@
-use crate::mem8::wave::{FrequencyBand, MemoryWave, WaveGrid};
+use crate::mem8::wave::{
+    adapt_noise_floor, FrequencyBand, MemoryWave, WaveGrid, DEFAULT_NOISE_FLOOR,
+};
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -45,12 +47,32 @@ impl ConsciousnessState {
     }
 
     /// Calculate current awareness level based on activity
+    ///
+    /// Memory activity and mean attention are each clamped to 0..1 and
+    /// combined as `1 - (1 - activity)(1 - focus)`, so either signal alone
+    /// raises awareness. Non-finite weights are ignored; with no memories and
+    /// no attention the result is 0.0.
     fn calculate_awareness(&self) -> f32 {
         let memory_activity = (self.active_memories.len() as f32 / 100.0).min(1.0);
-        let attention_focus = self.attention_weights.values().sum::<f32>()
-            / self.attention_weights.len().max(1) as f32;
 
-        (memory_activity + attention_focus) / 2.0
+        let weights: Vec<f32> = self
+            .attention_weights
+            .values()
+            .copied()
+            .filter(|weight| weight.is_finite())
+            .collect();
+        let attention_focus = if weights.is_empty() {
+            0.0
+        } else {
+            (weights.iter().sum::<f32>() / weights.len() as f32).clamp(0.0, 1.0)
+        };
+
+        let awareness = 1.0 - (1.0 - memory_activity) * (1.0 - attention_focus);
+        if awareness.is_nan() {
+            0.0
+        } else {
+            awareness.clamp(0.0, 1.0)
+        }
     }
 }
 
@@ -64,6 +86,57 @@ pub enum MemoryRegion {
     Emotional(String), // emotional category
 }
 
+/// Semantic category names, one per frequency band
+const SEMANTIC_CATEGORIES: &[(FrequencyBand, &str)] = &[
+    (FrequencyBand::DeepStructural, "structural"),
+    (FrequencyBand::Conversational, "conversational"),
+    (FrequencyBand::Technical, "technical"),
+    (FrequencyBand::Implementation, "implementation"),
+    (FrequencyBand::Abstract, "abstract"),
+    (FrequencyBand::Beta, "beta_awareness"),
+    (FrequencyBand::Gamma, "gamma_consciousness"),
+];
+
+impl MemoryRegion {
+    /// Semantic category that attention uses for a frequency band
+    pub fn semantic_category(band: FrequencyBand) -> &'static str {
+        SEMANTIC_CATEGORIES
+            .iter()
+            .find(|(b, _)| *b == band)
+            .map(|(_, name)| *name)
+            .expect("every band has a category")
+    }
+
+    /// Inverse of `semantic_category`
+    pub fn semantic_band(category: &str) -> Option<FrequencyBand> {
+        SEMANTIC_CATEGORIES
+            .iter()
+            .find(|(_, name)| *name == category)
+            .map(|(band, _)| *band)
+    }
+}
+
+/// Waves at or below this decay are too faint to enter consciousness
+const MIN_SAMPLE_DECAY: f32 = 0.1;
+
+/// Cap on waves a single band- or emotion-selected region contributes
+const MAX_REGION_SAMPLES: usize = 100;
+
+/// Push up to `MAX_REGION_SAMPLES` live waves accepted by `matches`
+fn sample_matching(
+    grid: &WaveGrid,
+    active: &mut Vec<Arc<MemoryWave>>,
+    matches: impl Fn(&MemoryWave) -> bool,
+) {
+    active.extend(
+        grid.iter_occupied()
+            .map(|(_, _, _, wave)| wave)
+            .filter(|wave| wave.calculate_decay() > MIN_SAMPLE_DECAY && matches(wave))
+            .take(MAX_REGION_SAMPLES)
+            .cloned(),
+    );
+}
+
 /// Reflexive response component
 #[derive(Clone)]
 pub struct ReflexiveComponent {
@@ -82,6 +155,29 @@ pub struct SensorGrid {
     pub grid: Arc<RwLock<WaveGrid>>,
     /// Temporal blanket configuration
     pub temporal_blanket: TemporalBlanket,
+    /// Signal level this sensor must exceed, adapted per grid
+    pub noise_floor: f32,
+}
+
+impl SensorGrid {
+    pub fn new(
+        id: impl Into<String>,
+        grid_type: SensorGridType,
+        grid: Arc<RwLock<WaveGrid>>,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            grid_type,
+            grid,
+            temporal_blanket: TemporalBlanket::new(),
+            noise_floor: DEFAULT_NOISE_FLOOR,
+        }
+    }
+
+    /// Adapt this sensor's noise floor to its own environment
+    pub fn adjust_noise_floor(&mut self, environmental_noise: f32) {
+        self.noise_floor = adapt_noise_floor(self.noise_floor, environmental_noise);
+    }
 }
 
 /// Types of sensor grids
@@ -119,13 +215,16 @@ pub enum MotionDirection {
     Vertical,
 }
 
+/// Bounds of `TemporalBlanket::beta_calib`
+pub const BETA_CALIB_RANGE: (f32, f32) = (-1.0, 1.0);
+
 /// Temporal blanket for environmental adaptation
 pub struct TemporalBlanket {
     /// Interest-based adjustment factor
     pub alpha: f32,
     /// Attention-based decay rate
     pub lambda: f32,
-    /// Environmental calibration
+    /// Environmental calibration, kept within `BETA_CALIB_RANGE`
     pub beta_calib: f32,
     /// Hard blankets (fixed calibration patterns)
     pub hard_blankets: Vec<CalibrationPattern>,
@@ -151,15 +250,35 @@ impl TemporalBlanket {
     }
 
     /// Calculate blanket value at time t
+    ///
+    /// Composed in this order:
+    /// 1. base decay `alpha · exp(-lambda · interest · t)`
+    /// 2. hard blankets, in order, each applying its pattern sample at `t`
+    /// 3. soft blankets, each scaling by its attenuation at `t`
+    /// 4. `beta_calib` added last, so calibration is never filtered
     pub fn calculate(&self, t: f32, interest: f32) -> f32 {
-        self.alpha * (-self.lambda * interest * t).exp() + self.beta_calib
+        let mut value = self.alpha * (-self.lambda * interest * t).exp();
+
+        for blanket in &self.hard_blankets {
+            value = blanket.apply(value, t);
+        }
+        for filter in &self.soft_blankets {
+            value *= filter.attenuation(t);
+        }
+
+        value + self.beta_calib
     }
 
     /// Apply environmental adaptation
-    pub fn adapt_to_environment(&mut self, env_changes: &[(String, f32)]) {
+    ///
+    /// Returns the change actually applied to `beta_calib`. Once the
+    /// calibration saturates at either end of `BETA_CALIB_RANGE` this is
+    /// smaller than the unclamped step (zero when pinned), which callers can
+    /// use to detect saturation. Non-finite magnitudes are ignored.
+    pub fn adapt_to_environment(&mut self, env_changes: &[(String, f32)]) -> f32 {
         let mut delta_sum = 0.0;
 
-        for (change_type, magnitude) in env_changes {
+        for (change_type, magnitude) in env_changes.iter().filter(|(_, m)| m.is_finite()) {
             let weight = match change_type.as_str() {
                 "lighting" => 0.4,
                 "motion" => 0.3,
@@ -169,23 +288,68 @@ impl TemporalBlanket {
             delta_sum += weight * magnitude;
         }
 
-        self.beta_calib = self.beta_calib * 0.9 + delta_sum * 0.1;
+        let (min, max) = BETA_CALIB_RANGE;
+        let previous = self.beta_calib;
+        self.beta_calib = (previous * 0.9 + delta_sum * 0.1).clamp(min, max);
+        self.beta_calib - previous
     }
 }
 
+/// How a hard blanket's samples combine with the blanket value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalibrationMode {
+    Additive,
+    Multiplicative,
+}
+
+/// Hard blanket: a fixed calibration cycled with one sample per unit of time
 #[derive(Clone)]
 pub struct CalibrationPattern {
     pub name: String,
     pub pattern: Vec<f32>,
+    pub mode: CalibrationMode,
 }
 
+impl CalibrationPattern {
+    /// Sample at time index `floor(t)`, wrapping; negative `t` uses index 0
+    pub fn sample(&self, t: f32) -> Option<f32> {
+        if self.pattern.is_empty() {
+            return None;
+        }
+        let index = t.max(0.0) as usize % self.pattern.len();
+        Some(self.pattern[index])
+    }
+
+    /// Apply this pattern to `value`; an empty pattern is a no-op
+    pub fn apply(&self, value: f32, t: f32) -> f32 {
+        match (self.sample(t), self.mode) {
+            (Some(sample), CalibrationMode::Additive) => value + sample,
+            (Some(sample), CalibrationMode::Multiplicative) => value * sample,
+            (None, _) => value,
+        }
+    }
+}
+
+/// Soft blanket: an adaptive filter that habituates to a steady signal
 #[derive(Clone)]
 pub struct AdaptiveFilter {
     pub name: String,
+    /// Fraction of the signal removed once fully adapted (clamped to 0..=1)
     pub strength: f32,
+    /// Rate (per unit time) at which the filter reaches full strength
     pub adaptation_rate: f32,
 }
 
+impl AdaptiveFilter {
+    /// Gain at time `t`: `1 - strength · (1 - exp(-adaptation_rate · t))`
+    ///
+    /// Starts at 1.0 (no filtering) and approaches `1 - strength`.
+    pub fn attenuation(&self, t: f32) -> f32 {
+        let adapted = 1.0 - (-self.adaptation_rate.max(0.0) * t.max(0.0)).exp();
+        1.0 - self.strength.clamp(0.0, 1.0) * adapted
+    }
+}
+
 /// Sensor arbitration system with human-AI control
 pub struct SensorArbitrator {
     /// Human control weight (0.0 to 1.0)
@@ -201,12 +365,54 @@ pub struct SensorArbitrator {
 }
 
 impl SensorArbitrator {
-    pub fn new(human_weight: f32, ai_weight: f32) -> Self {
-        assert!(
-            (human_weight + ai_weight - 1.0).abs() < 0.001,
-            "Weights must sum to 1.0"
-        );
+    /// Create an arbitrator with exact weights
+    ///
+    /// Fails unless both weights are finite, non-negative and sum to 1.0
+    /// (within 0.001). Use `new_normalized` for dynamically computed weights.
+    pub fn new(human_weight: f32, ai_weight: f32) -> Result<Self, String> {
+        if !human_weight.is_finite() || !ai_weight.is_finite() {
+            return Err(format!(
+                "Weights must be finite (human {human_weight}, ai {ai_weight})"
+            ));
+        }
+        if human_weight < 0.0 || ai_weight < 0.0 {
+            return Err(format!(
+                "Weights must be non-negative (human {human_weight}, ai {ai_weight})"
+            ));
+        }
+        if (human_weight + ai_weight - 1.0).abs() >= 0.001 {
+            return Err(format!(
+                "Weights must sum to 1.0 (human {human_weight} + ai {ai_weight})"
+            ));
+        }
 
+        Ok(Self::with_weights(human_weight, ai_weight))
+    }
+
+    /// Create an arbitrator, rescaling the weights to sum to 1.0
+    ///
+    /// Negative or non-finite weights count as 0.0; if neither weight is
+    /// positive, control is split evenly.
+    pub fn new_normalized(human_weight: f32, ai_weight: f32) -> Self {
+        let sanitize = |weight: f32| {
+            if weight.is_finite() {
+                weight.max(0.0)
+            } else {
+                0.0
+            }
+        };
+        let human = sanitize(human_weight);
+        let ai = sanitize(ai_weight);
+        let total = human + ai;
+
+        if total > 0.0 {
+            Self::with_weights(human / total, ai / total)
+        } else {
+            Self::with_weights(0.5, 0.5)
+        }
+    }
+
+    fn with_weights(human_weight: f32, ai_weight: f32) -> Self {
         Self {
             human_weight,
             ai_weight,
@@ -229,8 +435,15 @@ impl SensorArbitrator {
         base_interest + 0.3 * base_interest * subconscious_weight + 0.7 * base_interest * ai_weight
     }
 
-    /// Check if AI can override noise floor
-    pub fn should_process(&self, sensor_id: &str, signal_strength: f32, noise_floor: f32) -> bool {
+    /// Noise floor of a registered sensor, `DEFAULT_NOISE_FLOOR` otherwise
+    pub fn noise_floor(&self, sensor_id: &str) -> f32 {
+        self.sensor_grids
+            .get(sensor_id)
+            .map_or(DEFAULT_NOISE_FLOOR, |sensor| sensor.noise_floor)
+    }
+
+    /// Whether a signal clears the sensor's own noise floor, or AI overrides it
+    pub fn should_process(&self, sensor_id: &str, signal_strength: f32) -> bool {
         let ai_weight = self.ai_interests.get(sensor_id).unwrap_or(&0.0);
 
         // AI override when weight > 0.8
@@ -240,7 +453,7 @@ impl SensorArbitrator {
 
         // Normal processing
         let weighted_interest = self.calculate_weighted_interest(sensor_id, signal_strength);
-        weighted_interest > noise_floor
+        weighted_interest > self.noise_floor(sensor_id)
     }
 }
 
@@ -261,7 +474,7 @@ impl ConsciousnessEngine {
         Self {
             wave_grid,
             state: RwLock::new(ConsciousnessState::new()),
-            arbitrator: SensorArbitrator::new(0.3, 0.7), // 30% human, 70% AI control
+            arbitrator: SensorArbitrator::new_normalized(0.3, 0.7), // 30% human, 70% AI control
             attention_strategy: AttentionStrategy::default(),
         }
     }
@@ -272,7 +485,7 @@ impl ConsciousnessEngine {
         let mut state = self.state.write().unwrap();
 
         // Collect active memories based on attention
-        let active_memories = self.collect_active_memories(&grid);
+        let active_memories = self.collect_active_memories(&grid, &state);
 
         // Update attention weights
         self.update_attention_weights(&mut state, &active_memories);
@@ -284,13 +497,21 @@ impl ConsciousnessEngine {
     }
 
     /// Collect memories that are currently active in consciousness
-    fn collect_active_memories(&self, grid: &WaveGrid) -> Vec<Arc<MemoryWave>> {
+    ///
+    /// Visual and temporal regions sample fixed grid coordinates. Semantic
+    /// regions named after a frequency band and auditory regions select
+    /// waves by band, emotional ones by valence/arousal, each scanning
+    /// occupied cells up to `MAX_REGION_SAMPLES` matches. Any other semantic
+    /// category is looked up as a `WaveGrid` label.
+    fn collect_active_memories(
+        &self,
+        grid: &WaveGrid,
+        state: &ConsciousnessState,
+    ) -> Vec<Arc<MemoryWave>> {
         let mut active = Vec::new();
         let attention_threshold = 0.3;
 
         // Sample based on attention weights
-        let state = self.state.read().unwrap();
-
         for (region, &weight) in &state.attention_weights {
             if weight > attention_threshold {
                 // Sample memories from this region
@@ -299,7 +520,7 @@ impl ConsciousnessEngine {
                         // Sample around visual coordinates
                         for z in 0..100 {
                             if let Some(wave) = grid.get(*x, *y, z) {
-                                if wave.calculate_decay() > 0.1 {
+                                if wave.calculate_decay() > MIN_SAMPLE_DECAY {
                                     active.push(wave.clone());
                                 }
                             }
@@ -310,14 +531,42 @@ impl ConsciousnessEngine {
                         for x in 0..16 {
                             for y in 0..16 {
                                 if let Some(wave) = grid.get(x * 16, y * 16, *z) {
-                                    if wave.calculate_decay() > 0.1 {
+                                    if wave.calculate_decay() > MIN_SAMPLE_DECAY {
                                         active.push(wave.clone());
                                     }
                                 }
                             }
                         }
                     }
-                    _ => {} // Handle other regions
+                    MemoryRegion::Semantic(category) => {
+                        if let Some(band) = MemoryRegion::semantic_band(category) {
+                            sample_matching(grid, &mut active, |wave| {
+                                band.contains(wave.frequency)
+                            });
+                        } else {
+                            active.extend(
+                                grid.get_by_label(category)
+                                    .into_iter()
+                                    .filter(|wave| wave.calculate_decay() > MIN_SAMPLE_DECAY)
+                                    .take(MAX_REGION_SAMPLES)
+                                    .cloned(),
+                            );
+                        }
+                    }
+                    MemoryRegion::Auditory(frequency) => {
+                        let band = FrequencyBand::from_frequency(*frequency as f32);
+                        sample_matching(grid, &mut active, |wave| {
+                            FrequencyBand::from_frequency(wave.frequency) == band
+                        });
+                    }
+                    MemoryRegion::Emotional(category) => match category.as_str() {
+                        "positive" => sample_matching(grid, &mut active, |w| w.valence > 0.3),
+                        "negative" => sample_matching(grid, &mut active, |w| w.valence < -0.3),
+                        "neutral" => sample_matching(grid, &mut active, |w| w.valence.abs() <= 0.3),
+                        "aroused" => sample_matching(grid, &mut active, |w| w.arousal > 0.7),
+                        "calm" => sample_matching(grid, &mut active, |w| w.arousal < 0.3),
+                        _ => {}
+                    },
                 }
             }
         }
@@ -340,19 +589,7 @@ impl ConsciousnessEngine {
         for memory in memories {
             // Determine region based on frequency
             let band = FrequencyBand::from_frequency(memory.frequency);
-            let region = match band {
-                FrequencyBand::DeepStructural => MemoryRegion::Semantic("structural".to_string()),
-                FrequencyBand::Conversational => {
-                    MemoryRegion::Semantic("conversational".to_string())
-                }
-                FrequencyBand::Technical => MemoryRegion::Semantic("technical".to_string()),
-                FrequencyBand::Implementation => {
-                    MemoryRegion::Semantic("implementation".to_string())
-                }
-                FrequencyBand::Abstract => MemoryRegion::Semantic("abstract".to_string()),
-                FrequencyBand::Beta => MemoryRegion::Semantic("beta_awareness".to_string()),
-                FrequencyBand::Gamma => MemoryRegion::Semantic("gamma_consciousness".to_string()),
-            };
+            let region = MemoryRegion::Semantic(MemoryRegion::semantic_category(band).to_string());
 
             *state.attention_weights.entry(region).or_insert(0.0) += 0.1;
         }
@@ -460,7 +697,17 @@ impl ForgettingProcessor {
     }
 
     /// Process memory for context-aware forgetting
-    pub fn process(&self, _memory: &mut MemoryWave, context: &str) -> ForgetCurve {
+    ///
+    /// Sets the wave's `decay_tau` from the curve chosen for `context` (see
+    /// `ForgetCurve::decay_tau`) and returns that curve. The wave keeps its
+    /// age, so a shortened tau can make an old memory fade at once.
+    pub fn process(&self, memory: &mut MemoryWave, context: &str) -> ForgetCurve {
+        let curve = Self::curve_for(context);
+        memory.decay_tau = curve.decay_tau();
+        curve
+    }
+
+    fn curve_for(context: &str) -> ForgetCurve {
         match context {
             "transient_detail" => ForgetCurve::Flash(Duration::from_millis(500)),
             "resolved_threat" => ForgetCurve::Fade(Duration::from_secs(5)),
@@ -473,7 +720,7 @@ impl ForgettingProcessor {
 }
 
 /// Forgetting curve types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ForgetCurve {
     Flash(Duration),   // Very short retention
     Fade(Duration),    // Quick fade
@@ -482,12 +729,23 @@ pub enum ForgetCurve {
     Consolidate,       // Permanent memory
 }
 
+impl ForgetCurve {
+    /// Decay time constant for a wave on this curve; `None` never decays
+    pub fn decay_tau(&self) -> Option<Duration> {
+        match self {
+            Self::Flash(tau) | Self::Fade(tau) | Self::Linger(tau) | Self::Persist(tau) => {
+                Some(*tau)
+            }
+            Self::Consolidate => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    #[ignore = "Wave interference calculation needs tuning for awareness level"]
     fn test_consciousness_state() {
         let mut state = ConsciousnessState::new();
         assert_eq!(state.awareness_level, 0.5);
@@ -504,12 +762,185 @@ mod tests {
         let wave = Arc::new(MemoryWave::new(440.0, 0.8));
         state.update(vec![wave], vec![]);
 
+        // activity 0.01, focus 0.7: 1 - 0.99 * 0.3
         assert!(state.awareness_level > 0.5);
+        assert!((state.awareness_level - 0.703).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_semantic_and_emotional_regions_yield_memories() {
+        let mut grid = WaveGrid::new();
+        // Technical band (400-600Hz), stored away from the visual/temporal samples
+        grid.store(3, 5, 7, MemoryWave::new(450.0, 0.9));
+        grid.store(4, 5, 7, MemoryWave::new(520.0, 0.9));
+        let mut upset = MemoryWave::new(100.0, 0.9);
+        upset.valence = -0.8;
+        grid.store(5, 5, 7, upset);
+
+        let engine = ConsciousnessEngine::new(Arc::new(RwLock::new(grid)));
+        {
+            let mut state = engine.state.write().unwrap();
+            state
+                .attention_weights
+                .insert(MemoryRegion::Semantic("technical".to_string()), 0.9);
+        }
+        engine.update();
+
+        let state = engine.state.read().unwrap();
+        assert_eq!(state.active_memories.len(), 2);
+        assert!(state
+            .active_memories
+            .iter()
+            .all(|wave| FrequencyBand::from_frequency(wave.frequency) == FrequencyBand::Technical));
+        drop(state);
+
+        let grid = engine.wave_grid.read().unwrap();
+        let mut emotional = ConsciousnessState::new();
+        emotional
+            .attention_weights
+            .insert(MemoryRegion::Emotional("negative".to_string()), 0.5);
+        // Below the attention threshold: never sampled
+        emotional
+            .attention_weights
+            .insert(MemoryRegion::Auditory(450), 0.2);
+        let memories = engine.collect_active_memories(&grid, &emotional);
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].valence, -0.8);
+        drop(grid);
+
+        // Categories that aren't band names select labeled waves
+        let mut grid = engine.wave_grid.write().unwrap();
+        grid.store_labeled(6, 5, 7, MemoryWave::new(130.0, 0.9), "deployment");
+        let mut labeled = ConsciousnessState::new();
+        labeled
+            .attention_weights
+            .insert(MemoryRegion::Semantic("deployment".to_string()), 0.5);
+        let memories = engine.collect_active_memories(&grid, &labeled);
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].frequency, 130.0);
+
+        assert_eq!(
+            MemoryRegion::semantic_band(MemoryRegion::semantic_category(FrequencyBand::Gamma)),
+            Some(FrequencyBand::Gamma)
+        );
+
+        // Brainwave categories select by the cognitive interpretation
+        let mut cognitive = ConsciousnessState::new();
+        cognitive.attention_weights.insert(
+            MemoryRegion::Semantic("gamma_consciousness".to_string()),
+            0.5,
+        );
+        assert!(engine.collect_active_memories(&grid, &cognitive).is_empty());
+        drop(grid);
+        engine
+            .wave_grid
+            .write()
+            .unwrap()
+            .store(6, 5, 7, MemoryWave::new(40.0, 0.9));
+        let grid = engine.wave_grid.read().unwrap();
+        let memories = engine.collect_active_memories(&grid, &cognitive);
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].frequency, 40.0);
+    }
+
+    #[test]
+    fn test_awareness_bounds() {
+        let mut state = ConsciousnessState::new();
+        state.update(vec![], vec![]);
+        assert_eq!(state.awareness_level, 0.0);
+
+        state
+            .attention_weights
+            .insert(MemoryRegion::Temporal(1), f32::NAN);
+        state.update(vec![], vec![]);
+        assert_eq!(state.awareness_level, 0.0);
+
+        state
+            .attention_weights
+            .insert(MemoryRegion::Temporal(2), 7.5);
+        state.update(vec![], vec![]);
+        assert_eq!(state.awareness_level, 1.0);
+    }
+
+    #[test]
+    fn test_temporal_blanket_stays_bounded() {
+        let mut blanket = TemporalBlanket::new();
+        let change = [("lighting".to_string(), 1e6)];
+
+        let first = blanket.adapt_to_environment(&change);
+        assert_eq!(first, BETA_CALIB_RANGE.1);
+        for _ in 0..1000 {
+            blanket.adapt_to_environment(&change);
+        }
+        // Saturated: nothing more is applied
+        assert_eq!(blanket.adapt_to_environment(&change), 0.0);
+        assert_eq!(blanket.beta_calib, BETA_CALIB_RANGE.1);
+
+        for t in [0.0, 1.0, 100.0] {
+            let value = blanket.calculate(t, 1.0);
+            assert!(value.is_finite() && value <= blanket.alpha + BETA_CALIB_RANGE.1);
+        }
+
+        blanket.adapt_to_environment(&[("noise".to_string(), f32::NAN)]);
+        assert!(blanket.beta_calib.is_finite());
+    }
+
+    #[test]
+    fn test_temporal_blanket_applies_hard_and_soft_blankets() {
+        let mut blanket = TemporalBlanket::new();
+        blanket.lambda = 0.0; // base value stays at alpha = 1.0
+        blanket.hard_blankets.push(CalibrationPattern {
+            name: "flicker".to_string(),
+            pattern: vec![0.5, 0.0],
+            mode: CalibrationMode::Additive,
+        });
+        assert_eq!(blanket.calculate(0.0, 1.0), 1.5);
+        assert_eq!(blanket.calculate(1.0, 1.0), 1.0);
+        assert_eq!(blanket.calculate(2.5, 1.0), 1.5);
+
+        blanket.soft_blankets.push(AdaptiveFilter {
+            name: "hum".to_string(),
+            strength: 0.5,
+            adaptation_rate: 1.0,
+        });
+        // Not yet adapted at t = 0; halved once adapted
+        assert_eq!(blanket.calculate(0.0, 1.0), 1.5);
+        assert!((blanket.calculate(20.0, 1.0) - 0.75).abs() < 1e-6);
+        let partial = blanket.calculate(1.0, 1.0);
+        assert!(partial > 0.5 && partial < 1.0);
+
+        // Calibration is added after filtering
+        blanket.beta_calib = 0.25;
+        assert!((blanket.calculate(20.0, 1.0) - 1.0).abs() < 1e-6);
+
+        blanket.hard_blankets[0].mode = CalibrationMode::Multiplicative;
+        assert!((blanket.calculate(21.0, 1.0) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_forgetting_updates_decay() {
+        let processor = ForgettingProcessor::new();
+        let mut wave = MemoryWave::new(300.0, 0.8);
+        let original = wave.decay_tau.unwrap();
+
+        let curve = processor.process(&mut wave, "transient_detail");
+        assert_eq!(curve, ForgetCurve::Flash(Duration::from_millis(500)));
+        assert!(wave.decay_tau.unwrap() < original);
+
+        assert_eq!(
+            processor.process(&mut wave, "learned_pattern"),
+            ForgetCurve::Consolidate
+        );
+        assert_eq!(wave.decay_tau, None);
+        assert_eq!(wave.calculate_decay(), 1.0);
+
+        processor.process(&mut wave, "actionable_info");
+        assert_eq!(wave.decay_tau, Some(Duration::from_secs(300)));
     }
 
     #[test]
     fn test_sensor_arbitration() {
-        let arbitrator = SensorArbitrator::new(0.3, 0.7);
+        let arbitrator = SensorArbitrator::new(0.3, 0.7).unwrap();
 
         let human_value = 0.5;
         let ai_value = 0.8;
@@ -518,14 +949,68 @@ mod tests {
         assert!((result - (0.3 * 0.5 + 0.7 * 0.8)).abs() < 0.001);
     }
 
+    #[test]
+    fn test_sensor_arbitrator_weights() {
+        assert!(SensorArbitrator::new(0.3, 0.6).is_err());
+        assert!(SensorArbitrator::new(-0.5, 1.5).is_err());
+        assert!(SensorArbitrator::new(f32::NAN, 1.0).is_err());
+
+        let arbitrator = SensorArbitrator::new_normalized(0.3, 0.6);
+        assert!((arbitrator.human_weight - 1.0 / 3.0).abs() < 1e-6);
+        assert!((arbitrator.ai_weight - 2.0 / 3.0).abs() < 1e-6);
+
+        let arbitrator = SensorArbitrator::new_normalized(0.0, f32::NAN);
+        assert_eq!(arbitrator.human_weight, 0.5);
+        assert_eq!(arbitrator.ai_weight, 0.5);
+    }
+
     #[test]
     fn test_ai_override() {
-        let mut arbitrator = SensorArbitrator::new(0.3, 0.7);
+        let mut arbitrator = SensorArbitrator::new(0.3, 0.7).unwrap();
         arbitrator
             .ai_interests
             .insert("critical_sensor".to_string(), 0.9);
 
         // AI override should process even below noise floor
-        assert!(arbitrator.should_process("critical_sensor", 0.05, 0.1));
+        assert!(arbitrator.should_process("critical_sensor", 0.05));
+    }
+
+    #[test]
+    fn test_sensor_noise_floors_adapt_independently() {
+        let mut arbitrator = SensorArbitrator::new(0.3, 0.7).unwrap();
+        for (id, grid_type) in [
+            ("quiet", SensorGridType::Luminance),
+            ("noisy", SensorGridType::Amplitude),
+        ] {
+            let grid = Arc::new(RwLock::new(WaveGrid::new()));
+            arbitrator
+                .sensor_grids
+                .insert(id.to_string(), SensorGrid::new(id, grid_type, grid));
+        }
+
+        for _ in 0..20 {
+            arbitrator
+                .sensor_grids
+                .get_mut("quiet")
+                .unwrap()
+                .adjust_noise_floor(0.0);
+            arbitrator
+                .sensor_grids
+                .get_mut("noisy")
+                .unwrap()
+                .adjust_noise_floor(0.6);
+        }
+
+        let quiet = arbitrator.noise_floor("quiet");
+        let noisy = arbitrator.noise_floor("noisy");
+        assert!(
+            quiet < DEFAULT_NOISE_FLOOR && noisy > 0.4,
+            "{quiet} {noisy}"
+        );
+        assert_eq!(arbitrator.noise_floor("unregistered"), DEFAULT_NOISE_FLOOR);
+
+        // The same signal clears one sensor's floor but not the other's
+        assert!(arbitrator.should_process("quiet", 0.2));
+        assert!(!arbitrator.should_process("noisy", 0.2));
     }
 }
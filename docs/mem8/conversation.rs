@@ -1,8 +1,10 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use super::wave::{MemoryWave, WaveGrid};
@@ -15,6 +17,8 @@ pub struct ConversationMemory {
     wave_grid: WaveGrid,
     /// Smart structure analyzer
     analyzer: ConversationAnalyzer,
+    /// How message emotion and importance become wave parameters
+    emotion_map: EmotionMap,
 }
 
 impl ConversationMemory {
@@ -22,8 +26,11 @@ impl ConversationMemory {
     pub fn new() -> Result<Self> {
         let home_dir = dirs::home_dir().context("Failed to get home directory")?;
 
-        let base_path = home_dir.join(".mem8").join("conversations");
+        Self::with_base_path(home_dir.join(".mem8").join("conversations"))
+    }
 
+    /// Create a conversation memory system rooted at `base_path`
+    pub fn with_base_path(base_path: PathBuf) -> Result<Self> {
         // Create directory if it doesn't exist
         fs::create_dir_all(&base_path)?;
 
@@ -31,28 +38,41 @@ impl ConversationMemory {
             base_path,
             wave_grid: WaveGrid::new(),
             analyzer: ConversationAnalyzer::new(),
+            emotion_map: EmotionMap::default(),
         })
     }
 
+    /// Mapping used when converting messages to waves
+    pub fn emotion_map(&self) -> &EmotionMap {
+        &self.emotion_map
+    }
+
+    /// Replace the emotion mapping, e.g. to tune it for a particular corpus
+    ///
+    /// Only affects conversations saved afterwards.
+    pub fn set_emotion_map(&mut self, emotion_map: EmotionMap) {
+        self.emotion_map = emotion_map;
+    }
+
     /// Intelligently detect and save conversation from JSON
+    ///
+    /// Nothing is printed; the returned `SaveOutcome` carries what a caller
+    /// may want to log.
     pub fn save_conversation(
         &mut self,
         json_data: &Value,
         source: Option<&str>,
-    ) -> Result<PathBuf> {
+    ) -> Result<SaveOutcome> {
         // Analyze the JSON structure to understand conversation format
         let analysis = self.analyzer.analyze(json_data)?;
 
-        // Generate a unique filename based on content
-        let timestamp = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs();
-
+        // Name the file after its content so identical exports land on the
+        // same file and distinct ones never collide
         let filename = format!(
-            "conv_{}_{}_{}.m8",
+            "conv_{}_{}_{:016x}.m8",
             analysis.conversation_type.as_str(),
             source.unwrap_or("unknown"),
-            timestamp
+            content_hash(json_data)
         );
 
         let file_path = self.base_path.join(&filename);
@@ -60,27 +80,64 @@ impl ConversationMemory {
         // Convert conversation to wave patterns
         let waves = self.conversation_to_waves(&analysis)?;
 
-        // Store in wave grid
+        // Store in wave grid, filling x then y then z
+        let (width, height) = (self.wave_grid.width, self.wave_grid.height);
         for (idx, wave) in waves.iter().enumerate() {
-            let x = (idx % 256) as u8;
-            let y = ((idx / 256) % 256) as u8;
-            let z = (idx / (256 * 256)) as u16;
+            let x = (idx % width) as u8;
+            let y = ((idx / width) % height) as u8;
+            let z = (idx / (width * height)) as u16;
             self.wave_grid.store(x, y, z, wave.clone());
         }
 
-        // For now, save directly as JSON until M8Writer is properly implemented
-        // TODO: Implement proper M8Writer integration
+        let m8 = M8Conversation {
+            conversation_type: analysis.conversation_type.clone(),
+            participants: analysis.participants.clone(),
+            waves,
+        };
+        let mut file = fs::File::create(&file_path)?;
+        m8.write_to(&mut file)?;
 
         // Also save a JSON companion file for easy retrieval
         let json_path = file_path.with_extension("json");
         fs::write(&json_path, serde_json::to_string_pretty(json_data)?)?;
 
-        println!("🧠 Conversation saved to MEM|8: {}", filename);
-        println!("   Type: {:?}", analysis.conversation_type);
-        println!("   Messages: {}", analysis.message_count);
-        println!("   Participants: {}", analysis.participants.join(", "));
+        Ok(SaveOutcome {
+            path: file_path,
+            conversation_type: analysis.conversation_type,
+            message_count: analysis.message_count,
+            participants: analysis.participants,
+        })
+    }
 
-        Ok(file_path)
+    /// Save every `.json` file in `dir` (not recursive), in file name order
+    ///
+    /// A file that can't be read, parsed or recognized as a conversation is
+    /// recorded in `ImportReport::failed` and the import carries on. Only
+    /// failing to list `dir` itself is an error.
+    pub fn import_directory(&mut self, dir: &Path) -> Result<ImportReport> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_file() && path.extension() == Some(std::ffi::OsStr::new("json")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut report = ImportReport::default();
+        for path in paths {
+            let result = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|json_str| Ok(serde_json::from_str::<Value>(&json_str)?))
+                .and_then(|json_data| self.save_conversation(&json_data, Some("import")));
+
+            match result {
+                Ok(outcome) => report.imported.push((path, outcome)),
+                Err(e) => report.failed.push((path, format!("{:#}", e))),
+            }
+        }
+
+        Ok(report)
     }
 
     /// Convert conversation analysis to wave patterns
@@ -88,25 +145,13 @@ impl ConversationMemory {
         let mut waves = Vec::new();
 
         for message in &analysis.messages {
-            // Map message emotion to frequency
-            let frequency = match message.emotion.as_str() {
-                "happy" | "excited" => 100.0,    // High energy
-                "sad" | "worried" => 20.0,       // Low energy
-                "angry" | "frustrated" => 150.0, // Intense
-                "neutral" | "thinking" => 50.0,  // Balanced
-                _ => 44.1,                       // Default to audio baseline
-            };
+            let tone = self.emotion_map.tone(&message.emotion);
 
             // Create wave with message characteristics
-            let mut wave = MemoryWave::new(frequency, message.importance as f32);
+            let mut wave = MemoryWave::new(tone.frequency, message.importance as f32);
             wave.phase = message.timestamp as f32;
-            wave.valence = match message.emotion.as_str() {
-                "happy" | "excited" => 0.8,
-                "sad" | "worried" => -0.5,
-                "angry" | "frustrated" => -0.8,
-                _ => 0.0,
-            };
-            wave.arousal = message.importance as f32 / 10.0;
+            wave.valence = tone.valence;
+            wave.arousal = (self.emotion_map.arousal)(message.importance);
 
             waves.push(wave);
         }
@@ -123,7 +168,22 @@ impl ConversationMemory {
             let path = entry.path();
 
             if path.extension() == Some(std::ffi::OsStr::new("m8")) {
-                // Read the companion JSON for quick summary
+                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+                let timestamp = entry.metadata()?.modified()?;
+
+                // The .m8 header carries everything a summary needs
+                if let Ok(m8) = M8Conversation::read_from_path(&path) {
+                    summaries.push(ConversationSummary {
+                        file_name,
+                        conversation_type: m8.conversation_type,
+                        message_count: m8.waves.len(),
+                        participants: m8.participants,
+                        timestamp,
+                    });
+                    continue;
+                }
+
+                // Fall back to the companion JSON for files we can't decode
                 let json_path = path.with_extension("json");
                 if json_path.exists() {
                     let json_str = fs::read_to_string(&json_path)?;
@@ -131,11 +191,11 @@ impl ConversationMemory {
 
                     let analysis = self.analyzer.analyze(&json_data)?;
                     summaries.push(ConversationSummary {
-                        file_name: path.file_name().unwrap().to_string_lossy().to_string(),
+                        file_name,
                         conversation_type: analysis.conversation_type,
                         message_count: analysis.message_count,
                         participants: analysis.participants,
-                        timestamp: entry.metadata()?.modified()?,
+                        timestamp,
                     });
                 }
             }
@@ -143,8 +203,430 @@ impl ConversationMemory {
 
         Ok(summaries)
     }
+
+    /// Find saved conversations matching every filter set in `query`
+    ///
+    /// Participant, type and date filters use the `.m8` summaries; the
+    /// companion JSON is only loaded for a keyword search, and only for
+    /// conversations that passed the other filters. Results are newest first.
+    pub fn search(&self, query: &ConversationQuery) -> Result<Vec<ConversationSummary>> {
+        let mut results = Vec::new();
+
+        for summary in self.list_conversations()? {
+            if !query.matches_summary(&summary) {
+                continue;
+            }
+            if let Some(keyword) = &query.keyword {
+                if !self.mentions(&summary.file_name, keyword)? {
+                    continue;
+                }
+            }
+            results.push(summary);
+        }
+
+        results.sort_by_key(|summary| std::cmp::Reverse(summary.timestamp));
+        Ok(results)
+    }
+
+    /// Whether any message of a saved conversation contains `keyword`,
+    /// ignoring case; conversations without a companion JSON never match
+    fn mentions(&self, file_name: &str, keyword: &str) -> Result<bool> {
+        let json_path = self.base_path.join(file_name).with_extension("json");
+        if !json_path.exists() {
+            return Ok(false);
+        }
+
+        let json_data: Value = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+        let keyword = keyword.to_lowercase();
+        Ok(self
+            .analyzer
+            .analyze(&json_data)?
+            .messages
+            .iter()
+            .any(|message| message.content.to_lowercase().contains(&keyword)))
+    }
 }
 
+/// Filters for `ConversationMemory::search`; `None` fields match anything
+#[derive(Debug, Clone, Default)]
+pub struct ConversationQuery {
+    /// Participant name, compared case-insensitively
+    pub participant: Option<String>,
+    pub conversation_type: Option<ConversationType>,
+    /// Saved at or after this time
+    pub since: Option<SystemTime>,
+    /// Saved at or before this time
+    pub until: Option<SystemTime>,
+    /// Case-insensitive substring of any message's content
+    pub keyword: Option<String>,
+}
+
+impl ConversationQuery {
+    fn matches_summary(&self, summary: &ConversationSummary) -> bool {
+        let participant = self.participant.as_ref().is_none_or(|wanted| {
+            summary
+                .participants
+                .iter()
+                .any(|p| p.eq_ignore_ascii_case(wanted))
+        });
+        let conversation_type = self
+            .conversation_type
+            .as_ref()
+            .is_none_or(|wanted| *wanted == summary.conversation_type);
+        let since = self.since.is_none_or(|since| summary.timestamp >= since);
+        let until = self.until.is_none_or(|until| summary.timestamp <= until);
+
+        participant && conversation_type && since && until
+    }
+}
+
+/// FNV-1a (64-bit) over the canonical JSON form of `value`
+///
+/// Stable across runs and platforms, unlike `DefaultHasher`, so it can name
+/// files.
+fn content_hash(value: &Value) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut canonical = String::new();
+    write_canonical_json(value, &mut canonical);
+    canonical.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Compact JSON with object keys sorted, independent of map ordering
+fn write_canonical_json(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical_json(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical_json(item, out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Magic bytes at the start of every `.m8` file
+const M8_MAGIC: &[u8; 4] = b"MEM8";
+/// Version of the conversation wave layout below
+const M8_VERSION: u16 = 1;
+/// Section code for wave memory payloads (see docs/mem8/design.md)
+const M8_SECTION_WAVE_MEMORY: u8 = 0x0F;
+
+/// Conversation waves as stored in a `.m8` file
+///
+/// Layout (all integers and floats little-endian):
+///
+/// ```text
+/// [0-3]   magic "MEM8"
+/// [4-5]   format version (u16)
+/// [6]     section code 0x0F (WaveMemoryBlob)
+/// [7]     conversation type code
+/// [8-11]  wave count (u32)
+/// [12-13] participant count (u16)
+/// [14-15] reserved (0)
+/// then per participant: name length (u16) + UTF-8 bytes
+/// then per wave: amplitude, frequency, phase, valence, arousal (5 x f32)
+/// ```
+#[derive(Debug, Clone)]
+pub struct M8Conversation {
+    pub conversation_type: ConversationType,
+    pub participants: Vec<String>,
+    pub waves: Vec<MemoryWave>,
+}
+
+impl M8Conversation {
+    /// Serialize to the binary `.m8` layout
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let wave_count = u32::try_from(self.waves.len()).context("too many waves for .m8")?;
+        let participant_count =
+            u16::try_from(self.participants.len()).context("too many participants for .m8")?;
+
+        writer.write_all(M8_MAGIC)?;
+        writer.write_all(&M8_VERSION.to_le_bytes())?;
+        writer.write_all(&[M8_SECTION_WAVE_MEMORY, self.conversation_type.code()])?;
+        writer.write_all(&wave_count.to_le_bytes())?;
+        writer.write_all(&participant_count.to_le_bytes())?;
+        writer.write_all(&[0, 0])?;
+
+        for name in &self.participants {
+            let len = u16::try_from(name.len()).context("participant name too long")?;
+            writer.write_all(&len.to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+        }
+
+        for wave in &self.waves {
+            for value in [
+                wave.amplitude,
+                wave.frequency,
+                wave.phase,
+                wave.valence,
+                wave.arousal,
+            ] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deserialize from the binary `.m8` layout
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut header = [0u8; 16];
+        reader
+            .read_exact(&mut header)
+            .context("truncated .m8 header")?;
+
+        if &header[0..4] != M8_MAGIC {
+            anyhow::bail!("not a MEM8 file");
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != M8_VERSION {
+            anyhow::bail!("unsupported .m8 version {}", version);
+        }
+        if header[6] != M8_SECTION_WAVE_MEMORY {
+            anyhow::bail!("unexpected .m8 section 0x{:02X}", header[6]);
+        }
+
+        let conversation_type = ConversationType::from_code(header[7]);
+        let wave_count = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+        let participant_count = u16::from_le_bytes([header[12], header[13]]);
+
+        let mut participants = Vec::with_capacity(participant_count as usize);
+        for _ in 0..participant_count {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            let mut name = vec![0u8; u16::from_le_bytes(len) as usize];
+            reader.read_exact(&mut name)?;
+            participants.push(String::from_utf8(name).context("participant is not UTF-8")?);
+        }
+
+        let mut waves = Vec::new();
+        for _ in 0..wave_count {
+            let mut fields = [0f32; 5];
+            for field in fields.iter_mut() {
+                let mut bytes = [0u8; 4];
+                reader
+                    .read_exact(&mut bytes)
+                    .context("truncated .m8 wave data")?;
+                *field = f32::from_le_bytes(bytes);
+            }
+
+            let [amplitude, frequency, phase, valence, arousal] = fields;
+            let mut wave = MemoryWave::new(frequency, amplitude);
+            wave.phase = phase;
+            wave.valence = valence;
+            wave.arousal = arousal;
+            waves.push(wave);
+        }
+
+        Ok(Self {
+            conversation_type,
+            participants,
+            waves,
+        })
+    }
+
+    /// Read a `.m8` file from disk
+    pub fn read_from_path(path: &Path) -> Result<Self> {
+        let mut file = fs::File::open(path)?;
+        Self::read_from(&mut file)
+    }
+}
+
+/// Wave parameters for one emotion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmotionTone {
+    /// Wave frequency in Hz
+    pub frequency: f32,
+    /// Wave valence, -1.0 to 1.0
+    pub valence: f32,
+}
+
+/// How `ConversationMemory` turns a message's emotion and importance into a
+/// wave
+///
+/// The default puts high-energy emotions high and low-energy ones low:
+///
+/// | emotion                | frequency | valence |
+/// |------------------------|-----------|---------|
+/// | `happy`, `excited`     | 100 Hz    | 0.8     |
+/// | `sad`, `worried`       | 20 Hz     | -0.5    |
+/// | `angry`, `frustrated`  | 150 Hz    | -0.8    |
+/// | `neutral`, `thinking`  | 50 Hz     | 0.0     |
+/// | anything else          | 44.1 Hz   | 0.0     |
+///
+/// and arousal is importance / 10.
+#[derive(Debug, Clone)]
+pub struct EmotionMap {
+    /// Emotion label (as produced by `ConversationAnalyzer`) -> tone
+    pub tones: HashMap<String, EmotionTone>,
+    /// Tone for labels missing from `tones`
+    pub fallback: EmotionTone,
+    /// Message importance (0-10) -> wave arousal
+    pub arousal: fn(u8) -> f32,
+}
+
+impl Default for EmotionMap {
+    fn default() -> Self {
+        let tones = [
+            (&["happy", "excited"], 100.0, 0.8),     // High energy
+            (&["sad", "worried"], 20.0, -0.5),       // Low energy
+            (&["angry", "frustrated"], 150.0, -0.8), // Intense
+            (&["neutral", "thinking"], 50.0, 0.0),   // Balanced
+        ]
+        .into_iter()
+        .flat_map(|(labels, frequency, valence)| {
+            labels
+                .iter()
+                .map(move |label| (label.to_string(), EmotionTone { frequency, valence }))
+        })
+        .collect();
+
+        Self {
+            tones,
+            // Audio baseline
+            fallback: EmotionTone {
+                frequency: 44.1,
+                valence: 0.0,
+            },
+            arousal: |importance| importance as f32 / 10.0,
+        }
+    }
+}
+
+impl EmotionMap {
+    /// Set the tone for `emotion`, replacing any existing one
+    pub fn with_tone(mut self, emotion: &str, frequency: f32, valence: f32) -> Self {
+        self.tones
+            .insert(emotion.to_string(), EmotionTone { frequency, valence });
+        self
+    }
+
+    /// Tone for `emotion`, or `fallback` if it has none
+    pub fn tone(&self, emotion: &str) -> EmotionTone {
+        self.tones.get(emotion).copied().unwrap_or(self.fallback)
+    }
+}
+
+/// Emotion label -> keyword weights used by `ConversationAnalyzer::detect_emotion`
+const EMOTION_LEXICON: &[(&str, &[(&str, f32)])] = &[
+    (
+        "happy",
+        &[
+            ("happy", 1.0),
+            ("glad", 1.0),
+            ("pleased", 1.0),
+            ("love", 1.0),
+            ("great", 0.75),
+            ("awesome", 0.75),
+            ("perfect", 0.75),
+            ("thanks", 0.5),
+            ("thank", 0.5),
+            ("nice", 0.5),
+            ("works", 0.5),
+        ],
+    ),
+    (
+        "excited",
+        &[
+            ("excited", 1.0),
+            ("amazing", 1.0),
+            ("incredible", 1.0),
+            ("wow", 1.0),
+            ("thrilled", 1.0),
+            ("finally", 0.5),
+        ],
+    ),
+    (
+        "sad",
+        &[
+            ("sad", 1.0),
+            ("unhappy", 1.0),
+            ("disappointed", 1.0),
+            ("lonely", 1.0),
+            ("miss", 0.75),
+            ("sorry", 0.5),
+            ("unfortunately", 0.5),
+            ("lost", 0.5),
+        ],
+    ),
+    (
+        "angry",
+        &[
+            ("angry", 1.0),
+            ("furious", 1.0),
+            ("hate", 1.0),
+            ("stupid", 0.75),
+            ("ridiculous", 0.75),
+            ("annoying", 0.75),
+            ("frustrated", 0.75),
+            ("broken", 0.5),
+        ],
+    ),
+    (
+        "worried",
+        &[
+            ("worried", 1.0),
+            ("afraid", 1.0),
+            ("anxious", 1.0),
+            ("nervous", 1.0),
+            ("concerned", 0.75),
+            ("risk", 0.5),
+            ("might", 0.25),
+            ("fail", 0.5),
+        ],
+    ),
+];
+
+/// Words that cancel the lexicon hit immediately after them
+const NEGATIONS: &[&str] = &["not", "no", "never", "don't", "isn't", "wasn't", "aren't"];
+
+/// Words that mark a message as carrying a decision, failure, or deadline
+const IMPORTANCE_KEYWORDS: &[&str] = &[
+    "error",
+    "errors",
+    "urgent",
+    "decision",
+    "decided",
+    "critical",
+    "production",
+    "asap",
+    "important",
+    "deadline",
+    "bug",
+    "fail",
+    "failed",
+    "security",
+    "breaking",
+];
+
+/// Verbs that make a message an instruction when they open it
+const IMPERATIVE_VERBS: &[&str] = &[
+    "deploy", "fix", "run", "stop", "delete", "remove", "add", "update", "create", "make", "check",
+    "use", "revert", "ship", "merge", "please", "don't", "never", "always",
+];
+
 /// Smart conversation structure analyzer
 pub struct ConversationAnalyzer {
     /// Known conversation patterns
@@ -196,21 +678,43 @@ impl ConversationAnalyzer {
     }
 
     /// Detect conversation type from JSON structure
+    ///
+    /// - ChatGPT exports keep messages in a `mapping` object keyed by node id,
+    ///   older/API dumps use a `messages` array of objects with `role`
+    /// - Claude exports use a `chat_messages` array of objects with `sender`
+    ///   (older dumps used `conversation`)
     fn detect_type(&self, json_data: &Value) -> ConversationType {
-        // Check for common conversation patterns
-        if json_data.get("messages").is_some() {
-            ConversationType::ChatGPT
-        } else if json_data.get("conversation").is_some() {
+        if json_data.get("mapping").is_some_and(Value::is_object) {
+            ConversationType::ChatGptMapping
+        } else if Self::is_message_array(json_data.get("chat_messages"), &["sender", "role"])
+            || Self::is_message_array(json_data.get("conversation"), &["sender"])
+        {
+            ConversationType::Claude
+        } else if Self::is_message_array(json_data.get("messages"), &["sender"]) {
+            // Message list whose entries carry a Claude-style `sender`
             ConversationType::Claude
+        } else if Self::is_message_array(json_data.get("messages"), &["role", "author"]) {
+            ConversationType::ChatGPT
         } else if json_data.get("history").is_some() {
             ConversationType::Generic
-        } else if json_data.is_array() {
+        } else if Self::is_message_array(Some(json_data), &["sender", "role"]) {
             ConversationType::MessageArray
         } else {
             ConversationType::Unknown
         }
     }
 
+    /// Whether `value` is a non-empty array of objects carrying one of `role_keys`
+    fn is_message_array(value: Option<&Value>, role_keys: &[&str]) -> bool {
+        match value.and_then(Value::as_array) {
+            Some(items) if !items.is_empty() => items.iter().all(|item| {
+                item.as_object()
+                    .is_some_and(|obj| role_keys.iter().any(|key| obj.contains_key(*key)))
+            }),
+            _ => false,
+        }
+    }
+
     /// Extract messages from JSON based on type
     fn extract_messages(
         &self,
@@ -220,46 +724,80 @@ impl ConversationAnalyzer {
         let mut messages = Vec::new();
 
         match conv_type {
+            ConversationType::ChatGptMapping => {
+                if let Some(mapping) = json_data.get("mapping").and_then(|m| m.as_object()) {
+                    let current = json_data.get("current_node").and_then(|n| n.as_str());
+                    self.extract_mapping_messages(mapping, current, &mut messages);
+                }
+            }
             ConversationType::ChatGPT => {
                 if let Some(msgs) = json_data.get("messages").and_then(|m| m.as_array()) {
                     for (idx, msg) in msgs.iter().enumerate() {
+                        let content = msg
+                            .get("content")
+                            .and_then(|c| c.as_str())
+                            .unwrap_or("")
+                            .to_string();
+                        let role = msg
+                            .get("role")
+                            .and_then(|r| r.as_str())
+                            .unwrap_or("unknown")
+                            .to_string();
                         messages.push(Message {
-                            content: msg
-                                .get("content")
-                                .and_then(|c| c.as_str())
-                                .unwrap_or("")
-                                .to_string(),
-                            role: msg
-                                .get("role")
-                                .and_then(|r| r.as_str())
-                                .unwrap_or("unknown")
-                                .to_string(),
+                            emotion: self.detect_emotion(&content),
+                            importance: self.calculate_importance(&content, &role),
+                            content,
+                            role,
                             timestamp: idx as u64,
-                            emotion: self.detect_emotion(msg),
-                            importance: self.calculate_importance(msg),
                         });
                     }
                 }
             }
+            ConversationType::Claude => {
+                let msgs = json_data
+                    .get("chat_messages")
+                    .or_else(|| json_data.get("conversation"))
+                    .or_else(|| json_data.get("messages"))
+                    .and_then(|m| m.as_array());
+
+                for (idx, msg) in msgs.into_iter().flatten().enumerate() {
+                    let content = Self::claude_text(msg);
+                    let role = msg
+                        .get("sender")
+                        .or_else(|| msg.get("role"))
+                        .and_then(|r| r.as_str())
+                        .unwrap_or("unknown")
+                        .to_string();
+                    messages.push(Message {
+                        emotion: self.detect_emotion(&content),
+                        importance: self.calculate_importance(&content, &role),
+                        content,
+                        role,
+                        timestamp: idx as u64,
+                    });
+                }
+            }
             ConversationType::MessageArray => {
                 if let Some(msgs) = json_data.as_array() {
                     for (idx, msg) in msgs.iter().enumerate() {
+                        let content = msg
+                            .get("text")
+                            .or_else(|| msg.get("content"))
+                            .and_then(|c| c.as_str())
+                            .unwrap_or("")
+                            .to_string();
+                        let role = msg
+                            .get("sender")
+                            .or_else(|| msg.get("role"))
+                            .and_then(|r| r.as_str())
+                            .unwrap_or("unknown")
+                            .to_string();
                         messages.push(Message {
-                            content: msg
-                                .get("text")
-                                .or_else(|| msg.get("content"))
-                                .and_then(|c| c.as_str())
-                                .unwrap_or("")
-                                .to_string(),
-                            role: msg
-                                .get("sender")
-                                .or_else(|| msg.get("role"))
-                                .and_then(|r| r.as_str())
-                                .unwrap_or("unknown")
-                                .to_string(),
+                            emotion: self.detect_emotion(&content),
+                            importance: self.calculate_importance(&content, &role),
+                            content,
+                            role,
                             timestamp: idx as u64,
-                            emotion: self.detect_emotion(msg),
-                            importance: self.calculate_importance(msg),
                         });
                     }
                 }
@@ -273,6 +811,129 @@ impl ConversationAnalyzer {
         Ok(messages)
     }
 
+    /// Extract messages from a ChatGPT `mapping` export
+    ///
+    /// The mapping is a tree: edits and regenerations create sibling branches.
+    /// The visible conversation is the path from the root to `current_node`,
+    /// so only that branch is kept, in order.
+    fn extract_mapping_messages(
+        &self,
+        mapping: &Map<String, Value>,
+        current_node: Option<&str>,
+        messages: &mut Vec<Message>,
+    ) {
+        let nodes = Self::mapping_path(mapping, current_node);
+
+        for msg in nodes.into_iter().filter_map(|node| node.get("message")) {
+            if !msg.is_object() {
+                continue;
+            }
+            let content = Self::mapping_text(msg);
+            if content.is_empty() {
+                // Skip the empty system/root nodes ChatGPT inserts
+                continue;
+            }
+
+            let role = msg
+                .pointer("/author/role")
+                .and_then(|r| r.as_str())
+                .unwrap_or("unknown")
+                .to_string();
+            messages.push(Message {
+                emotion: self.detect_emotion(&content),
+                importance: self.calculate_importance(&content, &role),
+                content,
+                role,
+                timestamp: messages.len() as u64,
+            });
+        }
+    }
+
+    /// Nodes from the root to `current_node`, root first
+    ///
+    /// Without a usable `current_node` the walk starts from the leaf reached by
+    /// following the last child of each node from the root, i.e. the newest
+    /// branch.
+    fn mapping_path<'a>(
+        mapping: &'a Map<String, Value>,
+        current_node: Option<&str>,
+    ) -> Vec<&'a Value> {
+        fn parent_of(node: &Value) -> Option<&str> {
+            node.get("parent").and_then(|p| p.as_str())
+        }
+
+        let leaf = current_node
+            .filter(|id| mapping.contains_key(*id))
+            .map(str::to_string)
+            .or_else(|| {
+                let mut id = mapping
+                    .iter()
+                    .find(|(_, node)| parent_of(node).is_none_or(|p| !mapping.contains_key(p)))?
+                    .0
+                    .clone();
+                // Bounded by the node count so a malformed cycle can't spin
+                for _ in 0..mapping.len() {
+                    match mapping[&id]
+                        .get("children")
+                        .and_then(|c| c.as_array())
+                        .and_then(|c| c.last())
+                        .and_then(|c| c.as_str())
+                        .filter(|child| mapping.contains_key(*child))
+                    {
+                        Some(child) => id = child.to_string(),
+                        None => break,
+                    }
+                }
+                Some(id)
+            });
+
+        let mut path = Vec::new();
+        let mut next = leaf.as_deref();
+        while let Some(id) = next {
+            let Some(node) = mapping.get(id) else { break };
+            if path.len() >= mapping.len() {
+                break;
+            }
+            path.push(node);
+            next = parent_of(node);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Join the string `content.parts` of a ChatGPT mapping message
+    fn mapping_text(msg: &Value) -> String {
+        msg.pointer("/content/parts")
+            .and_then(|p| p.as_array())
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(|part| part.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default()
+    }
+
+    /// Text of a Claude message: `text`, else the text blocks of `content`
+    fn claude_text(msg: &Value) -> String {
+        if let Some(text) = msg.get("text").and_then(|t| t.as_str()) {
+            if !text.is_empty() {
+                return text.to_string();
+            }
+        }
+
+        match msg.get("content") {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Array(blocks)) => blocks
+                .iter()
+                .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        }
+    }
+
     /// Recursively extract text from generic JSON
     fn extract_generic_messages(&self, value: &Value, messages: &mut Vec<Message>, depth: usize) {
         if depth > 10 {
@@ -285,8 +946,8 @@ impl ConversationAnalyzer {
                     content: s.clone(),
                     role: "extracted".to_string(),
                     timestamp: messages.len() as u64,
-                    emotion: "neutral".to_string(),
-                    importance: 5,
+                    emotion: self.detect_emotion(s),
+                    importance: self.calculate_importance(s, "extracted"),
                 });
             }
             Value::Object(map) => {
@@ -314,29 +975,108 @@ impl ConversationAnalyzer {
         participants
     }
 
-    /// Detect emotion from message (simple heuristic)
-    fn detect_emotion(&self, _msg: &Value) -> String {
-        // TODO: Implement actual emotion detection
-        "neutral".to_string()
+    /// Detect emotion from message content
+    ///
+    /// Keyword-weighted lexicon scorer: each word found in `EMOTION_LEXICON`
+    /// adds its weight to that emotion unless the previous word negates it,
+    /// and each `!` adds 0.25 to excited/angry when they already scored. The
+    /// best label wins if it reaches 1.0, otherwise the message is neutral.
+    /// Ties go to the label listed first in the lexicon.
+    fn detect_emotion(&self, content: &str) -> String {
+        let lowered = content.to_lowercase();
+        let words: Vec<&str> = lowered
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        let mut scores = [0.0f32; EMOTION_LEXICON.len()];
+        for (idx, word) in words.iter().enumerate() {
+            let negated = idx > 0 && NEGATIONS.contains(&words[idx - 1]);
+            if negated {
+                continue;
+            }
+            for (slot, (_, keywords)) in EMOTION_LEXICON.iter().enumerate() {
+                if let Some((_, weight)) = keywords.iter().find(|(k, _)| k == word) {
+                    scores[slot] += weight;
+                }
+            }
+        }
+
+        let exclamations = content.matches('!').count().min(4) as f32;
+        for (slot, (label, _)) in EMOTION_LEXICON.iter().enumerate() {
+            if matches!(*label, "excited" | "angry") && scores[slot] > 0.0 {
+                scores[slot] += 0.25 * exclamations;
+            }
+        }
+
+        let mut best: Option<(usize, f32)> = None;
+        for (slot, &score) in scores.iter().enumerate() {
+            if score >= 1.0 && best.is_none_or(|(_, b)| score > b) {
+                best = Some((slot, score));
+            }
+        }
+
+        match best {
+            Some((slot, _)) => EMOTION_LEXICON[slot].0.to_string(),
+            None => "neutral".to_string(),
+        }
     }
 
     /// Calculate message importance (1-10)
-    fn calculate_importance(&self, msg: &Value) -> u8 {
-        // Simple heuristic based on length
-        if let Some(content) = msg.get("content").and_then(|c| c.as_str()) {
-            let len = content.len();
-            if len > 500 {
-                8
-            } else if len > 200 {
-                6
-            } else if len > 50 {
-                5
-            } else {
-                3
-            }
+    ///
+    /// Starts at 3 and adds:
+    /// - length: +1 over 50 chars, +2 over 200, +3 over 500
+    /// - role: +2 for `system`, +1 for `user`/`human`
+    /// - +1 if the message asks a question (`?`)
+    /// - +1 if it opens with an imperative verb (`IMPERATIVE_VERBS`)
+    /// - +2 for the first `IMPORTANCE_KEYWORDS` hit, +1 for each further hit
+    ///   (keywords contribute at most +3)
+    fn calculate_importance(&self, content: &str, role: &str) -> u8 {
+        let mut score: u32 = 3;
+
+        let len = content.chars().count();
+        score += if len > 500 {
+            3
+        } else if len > 200 {
+            2
+        } else if len > 50 {
+            1
         } else {
-            5
+            0
+        };
+
+        score += match role.to_lowercase().as_str() {
+            "system" => 2,
+            "user" | "human" => 1,
+            _ => 0,
+        };
+
+        if content.contains('?') {
+            score += 1;
+        }
+
+        let lowered = content.to_lowercase();
+        let words: Vec<&str> = lowered
+            .split(|c: char| !c.is_alphanumeric() && c != '\'')
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        if words
+            .first()
+            .is_some_and(|first| IMPERATIVE_VERBS.contains(first))
+        {
+            score += 1;
         }
+
+        let keyword_hits = words
+            .iter()
+            .filter(|w| IMPORTANCE_KEYWORDS.contains(w))
+            .count() as u32;
+        if keyword_hits > 0 {
+            score += (keyword_hits + 1).min(3);
+        }
+
+        score.clamp(1, 10) as u8
     }
 
     /// Default conversation patterns
@@ -350,7 +1090,7 @@ impl ConversationAnalyzer {
             },
             ConversationPattern {
                 name: "Claude".to_string(),
-                message_path: vec!["conversation".to_string()],
+                message_path: vec!["chat_messages".to_string()],
                 content_field: "text".to_string(),
                 role_field: "sender".to_string(),
             },
@@ -359,9 +1099,11 @@ impl ConversationAnalyzer {
 }
 
 /// Conversation type enumeration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ConversationType {
     ChatGPT,
+    /// ChatGPT export storing messages as a `mapping` tree
+    ChatGptMapping,
     Claude,
     Generic,
     MessageArray,
@@ -369,9 +1111,33 @@ pub enum ConversationType {
 }
 
 impl ConversationType {
+    /// Type code stored in the `.m8` header
+    fn code(&self) -> u8 {
+        match self {
+            Self::ChatGPT => 1,
+            Self::Claude => 2,
+            Self::Generic => 3,
+            Self::MessageArray => 4,
+            Self::ChatGptMapping => 5,
+            Self::Unknown => 0,
+        }
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => Self::ChatGPT,
+            2 => Self::Claude,
+            3 => Self::Generic,
+            4 => Self::MessageArray,
+            5 => Self::ChatGptMapping,
+            _ => Self::Unknown,
+        }
+    }
+
     fn as_str(&self) -> &str {
         match self {
             Self::ChatGPT => "chatgpt",
+            Self::ChatGptMapping => "chatgpt_mapping",
             Self::Claude => "claude",
             Self::Generic => "generic",
             Self::MessageArray => "array",
@@ -415,6 +1181,49 @@ pub struct ConversationPattern {
     pub role_field: String,
 }
 
+/// Result of `ConversationMemory::save_conversation`
+#[derive(Debug, Clone, Serialize)]
+pub struct SaveOutcome {
+    /// The `.m8` file written; its `.json` companion sits alongside
+    #[serde(serialize_with = "super::memory::portable_path::serialize")]
+    pub path: PathBuf,
+    pub conversation_type: ConversationType,
+    pub message_count: usize,
+    pub participants: Vec<String>,
+}
+
+impl std::fmt::Display for SaveOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "saved {:?} conversation ({} messages; {}) to {}",
+            self.conversation_type,
+            self.message_count,
+            self.participants.join(", "),
+            self.path.display()
+        )
+    }
+}
+
+/// Result of `ConversationMemory::import_directory`, per source file
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// Source file and what saving it produced
+    pub imported: Vec<(PathBuf, SaveOutcome)>,
+    /// Source file and why it was skipped
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl ImportReport {
+    /// The `.m8` files written, in import order
+    pub fn saved_paths(&self) -> Vec<PathBuf> {
+        self.imported
+            .iter()
+            .map(|(_, outcome)| outcome.path.clone())
+            .collect()
+    }
+}
+
 /// Conversation summary for listing
 #[derive(Debug, Serialize)]
 pub struct ConversationSummary {
@@ -424,3 +1233,471 @@ pub struct ConversationSummary {
     pub participants: Vec<String>,
     pub timestamp: std::time::SystemTime,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claude_export() -> Value {
+        json!({
+            "uuid": "3f0c",
+            "name": "Grid sizing",
+            "chat_messages": [
+                {"uuid": "a", "sender": "human", "text": "How big is the wave grid?", "content": []},
+                {"uuid": "b", "sender": "assistant", "text": "",
+                 "content": [{"type": "text", "text": "64x64x256 by default."}]}
+            ]
+        })
+    }
+
+    fn chatgpt_mapping_export() -> Value {
+        json!({
+            "title": "Grid sizing",
+            "current_node": "n2",
+            "mapping": {
+                "root": {"id": "root", "message": null, "parent": null, "children": ["n1"]},
+                "n2": {"id": "n2", "parent": "n1", "children": [], "message": {
+                    "author": {"role": "assistant"}, "create_time": 20.0,
+                    "content": {"content_type": "text", "parts": ["64x64x256 by default."]}}},
+                "n1": {"id": "n1", "parent": "root", "children": ["n2"], "message": {
+                    "author": {"role": "user"}, "create_time": 10.0,
+                    "content": {"content_type": "text", "parts": ["How big is the wave grid?"]}}}
+            }
+        })
+    }
+
+    fn chatgpt_messages_export() -> Value {
+        json!({
+            "messages": [
+                {"role": "user", "content": "How big is the wave grid?"},
+                {"role": "assistant", "content": "64x64x256 by default."}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_detect_claude_export() {
+        let analyzer = ConversationAnalyzer::new();
+        let analysis = analyzer.analyze(&claude_export()).unwrap();
+
+        assert!(matches!(
+            analysis.conversation_type,
+            ConversationType::Claude
+        ));
+        assert_eq!(analysis.message_count, 2);
+        assert_eq!(analysis.participants, vec!["human", "assistant"]);
+        assert_eq!(analysis.messages[1].content, "64x64x256 by default.");
+    }
+
+    #[test]
+    fn test_detect_chatgpt_mapping_export() {
+        let analyzer = ConversationAnalyzer::new();
+        let analysis = analyzer.analyze(&chatgpt_mapping_export()).unwrap();
+
+        assert!(matches!(
+            analysis.conversation_type,
+            ConversationType::ChatGptMapping
+        ));
+        assert_eq!(analysis.participants, vec!["user", "assistant"]);
+        assert_eq!(analysis.messages[0].content, "How big is the wave grid?");
+    }
+
+    #[test]
+    fn test_chatgpt_mapping_follows_current_branch() {
+        // Shape of a real export: a null-message root, a system node, and an
+        // edited user turn whose abandoned branch is newer than the kept one
+        let export = json!({
+            "title": "Grid sizing",
+            "current_node": "a2",
+            "mapping": {
+                "client-created-root": {"id": "client-created-root", "message": null,
+                    "parent": null, "children": ["sys"]},
+                "sys": {"id": "sys", "parent": "client-created-root", "children": ["u1"],
+                    "message": {"id": "sys", "author": {"role": "system", "name": null},
+                        "create_time": null,
+                        "content": {"content_type": "text", "parts": [""]}}},
+                "u1": {"id": "u1", "parent": "sys", "children": ["a1"],
+                    "message": {"id": "u1", "author": {"role": "user"}, "create_time": 100.0,
+                        "content": {"content_type": "text", "parts": ["How big is the wave grid?"]}}},
+                "a1": {"id": "a1", "parent": "u1", "children": ["u2", "u2-edit"],
+                    "message": {"id": "a1", "author": {"role": "assistant"}, "create_time": 101.0,
+                        "content": {"content_type": "text", "parts": ["64x64x256 by default."]}}},
+                "u2-edit": {"id": "u2-edit", "parent": "a1", "children": [],
+                    "message": {"id": "u2-edit", "author": {"role": "user"}, "create_time": 300.0,
+                        "content": {"content_type": "text", "parts": ["Abandoned edit"]}}},
+                "u2": {"id": "u2", "parent": "a1", "children": ["a2"],
+                    "message": {"id": "u2", "author": {"role": "user"}, "create_time": 200.0,
+                        "content": {"content_type": "text", "parts": ["And the depth?"]}}},
+                "a2": {"id": "a2", "parent": "u2", "children": [],
+                    "message": {"id": "a2", "author": {"role": "assistant"}, "create_time": 50.0,
+                        "content": {"content_type": "text", "parts": ["256 layers,", "one per tick."]}}}
+            }
+        });
+
+        let analysis = ConversationAnalyzer::new().analyze(&export).unwrap();
+        let turns: Vec<(&str, &str)> = analysis
+            .messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect();
+        assert_eq!(
+            turns,
+            vec![
+                ("user", "How big is the wave grid?"),
+                ("assistant", "64x64x256 by default."),
+                ("user", "And the depth?"),
+                ("assistant", "256 layers,\none per tick."),
+            ]
+        );
+
+        // Without current_node the newest branch is followed
+        let mut no_pointer = export.clone();
+        no_pointer.as_object_mut().unwrap().remove("current_node");
+        let analysis = ConversationAnalyzer::new().analyze(&no_pointer).unwrap();
+        assert_eq!(analysis.messages.last().unwrap().content, "Abandoned edit");
+        assert_eq!(analysis.message_count, 3);
+    }
+
+    #[test]
+    fn test_detect_chatgpt_messages_export() {
+        let analyzer = ConversationAnalyzer::new();
+        let analysis = analyzer.analyze(&chatgpt_messages_export()).unwrap();
+
+        assert!(matches!(
+            analysis.conversation_type,
+            ConversationType::ChatGPT
+        ));
+        assert_eq!(analysis.message_count, 2);
+    }
+
+    #[test]
+    fn test_detect_emotion() {
+        let analyzer = ConversationAnalyzer::new();
+        let cases = [
+            ("I'm so happy with this, thanks!", "happy"),
+            ("Wow, this is amazing!", "excited"),
+            ("I'm sad and a bit disappointed it didn't ship", "sad"),
+            ("This is stupid and I'm furious", "angry"),
+            ("I'm worried the migration might fail", "worried"),
+            ("The grid is 64 cells wide", "neutral"),
+            ("I'm not happy about it", "neutral"),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(analyzer.detect_emotion(text), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_importance_short_but_critical() {
+        let analyzer = ConversationAnalyzer::new();
+        let rambling = "I was thinking about the grid layout and how the cells line up, \
+            and there are a lot of ways we could go about it, some nicer than others, \
+            though honestly any of them would be fine for now and we can revisit later \
+            once we have a better sense of the access patterns in real sessions.";
+
+        let urgent = analyzer.calculate_importance("Deploy to production now", "user");
+        let long = analyzer.calculate_importance(rambling, "assistant");
+
+        assert_eq!(urgent, 7);
+        assert_eq!(long, 5);
+        assert!(urgent > long);
+    }
+
+    #[test]
+    fn test_importance_role_and_bounds() {
+        let analyzer = ConversationAnalyzer::new();
+
+        assert_eq!(analyzer.calculate_importance("ok", "assistant"), 3);
+        assert_eq!(analyzer.calculate_importance("ok", "system"), 5);
+        assert_eq!(
+            analyzer.calculate_importance(
+                &format!(
+                    "Fix the critical security error before the deadline? {}",
+                    "x".repeat(600)
+                ),
+                "system"
+            ),
+            10
+        );
+    }
+
+    #[test]
+    fn test_conversation_waves_follow_emotion() {
+        let analyzer = ConversationAnalyzer::new();
+        let data = json!([
+            {"role": "user", "content": "Wow, this is amazing!"},
+            {"role": "user", "content": "I'm worried the migration might fail"}
+        ]);
+        let analysis = analyzer.analyze(&data).unwrap();
+
+        assert_eq!(analysis.messages[0].emotion, "excited");
+        assert_eq!(analysis.messages[1].emotion, "worried");
+    }
+
+    #[test]
+    fn test_custom_emotion_map_sets_wave_frequencies() {
+        let dir = std::env::temp_dir().join(format!("mem8_emotions_{}", std::process::id()));
+        let mut memory = ConversationMemory::with_base_path(dir.clone()).unwrap();
+        let data = json!([
+            {"role": "user", "content": "Wow, this is amazing!"},
+            {"role": "user", "content": "I'm worried the migration might fail"},
+            {"role": "user", "content": "Ship it"}
+        ]);
+        let analysis = memory.analyzer.analyze(&data).unwrap();
+
+        let defaults = memory.conversation_to_waves(&analysis).unwrap();
+        let frequencies: Vec<f32> = defaults.iter().map(|wave| wave.frequency).collect();
+        assert_eq!(frequencies, vec![100.0, 20.0, 50.0]);
+
+        memory.set_emotion_map(EmotionMap {
+            fallback: EmotionTone {
+                frequency: 60.0,
+                valence: 0.1,
+            },
+            arousal: |importance| if importance >= 5 { 1.0 } else { 0.0 },
+            ..EmotionMap::default()
+                .with_tone("excited", 300.0, 0.9)
+                .with_tone("worried", 10.0, -0.9)
+                .with_tone("neutral", 40.0, 0.0)
+        });
+        let waves = memory.conversation_to_waves(&analysis).unwrap();
+        let frequencies: Vec<f32> = waves.iter().map(|wave| wave.frequency).collect();
+        assert_eq!(frequencies, vec![300.0, 10.0, 40.0]);
+        assert_eq!(waves[0].valence, 0.9);
+        assert_eq!(memory.emotion_map().tone("bored").frequency, 60.0);
+        for (wave, message) in waves.iter().zip(&analysis.messages) {
+            let expected = if message.importance >= 5 { 1.0 } else { 0.0 };
+            assert_eq!(wave.arousal, expected);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_m8_round_trip() {
+        let mut wave = MemoryWave::new(100.0, 7.0);
+        wave.phase = 3.0;
+        wave.valence = -0.5;
+        wave.arousal = 0.7;
+
+        let m8 = M8Conversation {
+            conversation_type: ConversationType::Claude,
+            participants: vec!["human".to_string(), "assistant".to_string()],
+            waves: vec![wave, MemoryWave::new(50.0, 3.0)],
+        };
+
+        let mut bytes = Vec::new();
+        m8.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[0..4], b"MEM8");
+
+        let decoded = M8Conversation::read_from(&mut bytes.as_slice()).unwrap();
+        assert!(matches!(
+            decoded.conversation_type,
+            ConversationType::Claude
+        ));
+        assert_eq!(decoded.participants, m8.participants);
+        assert_eq!(decoded.waves.len(), 2);
+        assert_eq!(decoded.waves[0].frequency, 100.0);
+        assert_eq!(decoded.waves[0].amplitude, 7.0);
+        assert_eq!(decoded.waves[0].phase, 3.0);
+        assert_eq!(decoded.waves[0].valence, -0.5);
+        assert_eq!(decoded.waves[0].arousal, 0.7);
+
+        assert!(M8Conversation::read_from(&mut &bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn test_save_names_files_by_content() {
+        let dir = std::env::temp_dir().join(format!("mem8_names_{}", std::process::id()));
+        let mut memory = ConversationMemory::with_base_path(dir.clone()).unwrap();
+
+        let first = claude_export();
+        let mut second = claude_export();
+        second["chat_messages"][0]["text"] = json!("And how deep?");
+
+        let paths: Vec<PathBuf> = [&first, &second, &first]
+            .into_iter()
+            .map(|export| {
+                memory
+                    .save_conversation(export, Some("batch"))
+                    .unwrap()
+                    .path
+            })
+            .collect();
+
+        assert_ne!(paths[0], paths[1]);
+        // Re-saving identical content is idempotent
+        assert_eq!(paths[0], paths[2]);
+        assert_eq!(
+            fs::read_dir(&dir)
+                .unwrap()
+                .filter(|entry| entry.as_ref().unwrap().path().extension()
+                    == Some(std::ffi::OsStr::new("m8")))
+                .count(),
+            2
+        );
+
+        // Key order does not change the name
+        let reordered: Value = serde_json::from_str(
+            r#"{"chat_messages": [
+                {"content": [], "text": "How big is the wave grid?", "sender": "human", "uuid": "a"},
+                {"content": [{"text": "64x64x256 by default.", "type": "text"}], "text": "", "sender": "assistant", "uuid": "b"}
+            ], "name": "Grid sizing", "uuid": "3f0c"}"#,
+        )
+        .unwrap();
+        assert_eq!(content_hash(&reordered), content_hash(&first));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_directory_skips_bad_files() {
+        let root = std::env::temp_dir().join(format!("mem8_import_{}", std::process::id()));
+        let source = root.join("exports");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("a_claude.json"), claude_export().to_string()).unwrap();
+        fs::write(source.join("b_broken.json"), "{\"chat_messages\": [").unwrap();
+        fs::write(
+            source.join("c_chatgpt.json"),
+            chatgpt_mapping_export().to_string(),
+        )
+        .unwrap();
+        fs::write(source.join("notes.txt"), "not an export").unwrap();
+
+        let mut memory = ConversationMemory::with_base_path(root.join("store")).unwrap();
+        let report = memory.import_directory(&source).unwrap();
+
+        assert_eq!(report.imported.len(), 2);
+        assert_eq!(report.imported[0].0, source.join("a_claude.json"));
+        assert!(matches!(
+            report.imported[1].1.conversation_type,
+            ConversationType::ChatGptMapping
+        ));
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, source.join("b_broken.json"));
+        assert!(report.failed[0].1.contains("EOF"), "{}", report.failed[0].1);
+
+        let saved = report.saved_paths();
+        assert!(saved.iter().all(|path| path.exists()));
+        assert_eq!(memory.list_conversations().unwrap().len(), 2);
+
+        assert!(memory.import_directory(&root.join("missing")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_search_filters_and_orders_by_recency() {
+        let dir = std::env::temp_dir().join(format!("mem8_search_{}", std::process::id()));
+        let mut memory = ConversationMemory::with_base_path(dir.clone()).unwrap();
+
+        let claude = memory
+            .save_conversation(&claude_export(), Some("a"))
+            .unwrap();
+        let chatgpt = memory
+            .save_conversation(&chatgpt_messages_export(), Some("b"))
+            .unwrap();
+        let mut other = claude_export();
+        other["chat_messages"][0]["text"] = json!("What does the noise floor do?");
+        let noise = memory.save_conversation(&other, Some("c")).unwrap();
+
+        // Spread the saves out in time: claude oldest, noise newest
+        let now = SystemTime::now();
+        for (outcome, age) in [(&claude, 300), (&chatgpt, 200), (&noise, 100)] {
+            fs::File::options()
+                .write(true)
+                .open(&outcome.path)
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        let names = |results: Vec<ConversationSummary>| -> Vec<String> {
+            results.into_iter().map(|s| s.file_name).collect()
+        };
+        let name = |outcome: &SaveOutcome| {
+            outcome
+                .path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let everything = memory.search(&ConversationQuery::default()).unwrap();
+        assert_eq!(
+            names(everything),
+            vec![name(&noise), name(&chatgpt), name(&claude)]
+        );
+
+        let by_keyword = ConversationQuery {
+            keyword: Some("WAVE GRID".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(memory.search(&by_keyword).unwrap()),
+            vec![name(&chatgpt), name(&claude)]
+        );
+
+        let by_participant_and_type = ConversationQuery {
+            participant: Some("Human".to_string()),
+            conversation_type: Some(ConversationType::Claude),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(memory.search(&by_participant_and_type).unwrap()),
+            vec![name(&noise), name(&claude)]
+        );
+
+        let by_date = ConversationQuery {
+            since: Some(now - std::time::Duration::from_secs(250)),
+            until: Some(now - std::time::Duration::from_secs(150)),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(memory.search(&by_date).unwrap()),
+            vec![name(&chatgpt)]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_conversations_reads_m8() {
+        let dir = std::env::temp_dir().join(format!("mem8_list_{}", std::process::id()));
+        let mut memory = ConversationMemory::with_base_path(dir.clone()).unwrap();
+
+        let outcome = memory
+            .save_conversation(&claude_export(), Some("test"))
+            .unwrap();
+        let path = outcome.path.clone();
+        assert!(path.exists());
+        assert_eq!(outcome.message_count, 2);
+        assert_eq!(outcome.participants, vec!["human", "assistant"]);
+
+        // Summaries come from the .m8 itself, not the companion JSON
+        fs::remove_file(path.with_extension("json")).unwrap();
+        let summaries = memory.list_conversations().unwrap();
+
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message_count, 2);
+        assert_eq!(summaries[0].participants, vec!["human", "assistant"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_detect_message_array() {
+        let analyzer = ConversationAnalyzer::new();
+        let data = json!([{"sender": "alice", "text": "hi"}, {"sender": "bob", "text": "hey"}]);
+
+        assert!(matches!(
+            analyzer.detect_type(&data),
+            ConversationType::MessageArray
+        ));
+        assert!(matches!(
+            analyzer.detect_type(&json!({"title": "x"})),
+            ConversationType::Unknown
+        ));
+    }
+}
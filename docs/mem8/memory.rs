@@ -3,8 +3,27 @@ use chrono::{DateTime, Local, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use super::conversation::{ConversationAnalyzer, Message};
+use super::wave::WaveGrid;
+
+/// Maximum number of topics recorded per block
+const MAX_BLOCK_TOPICS: usize = 8;
+
+/// Maximum number of concepts kept in `ConceptGraph::recent`
+const MAX_RECENT_CONCEPTS: usize = 32;
+
+/// Words too common to be useful as topics
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "also", "because", "been", "before", "being", "could", "does",
+    "doing", "from", "have", "here", "into", "just", "like", "make", "more", "most", "much",
+    "only", "other", "over", "really", "should", "some", "such", "than", "that", "their", "them",
+    "then", "there", "these", "they", "this", "those", "very", "want", "were", "what", "when",
+    "where", "which", "while", "will", "with", "would", "your",
+];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MemIndex {
     /// Index version
@@ -44,6 +63,7 @@ pub struct UserContext {
     pub tone: TonePrefs,
 
     /// Current working directory preference
+    #[serde(with = "portable_path::option")]
     pub preferred_cwd: Option<PathBuf>,
 
     /// Active project (if any)
@@ -105,6 +125,11 @@ pub struct BlockMeta {
 
     /// Quick summary
     pub summary: String,
+
+    /// Occupied/total cells of the grid, for blocks that are `WaveGrid`
+    /// snapshots (see `WaveGrid::compaction_stats`)
+    #[serde(default)]
+    pub compression_ratio: Option<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -113,6 +138,7 @@ pub struct ProjectInfo {
     pub name: String,
 
     /// Project root path
+    #[serde(with = "portable_path")]
     pub path: PathBuf,
 
     /// Current status
@@ -136,16 +162,55 @@ pub struct ProjectInfo {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConceptGraph {
-    /// Concept -> Related concepts with weight
+    /// Concept -> Related concepts with weight (one edge per pair)
     pub relationships: HashMap<String, Vec<(String, f32)>>,
 
     /// Concept -> Memory blocks containing it
     pub concept_blocks: HashMap<String, Vec<String>>,
 
-    /// Recent concepts (for quick access)
+    /// Recent concepts (for quick access), most recent first
     pub recent: Vec<String>,
 }
 
+impl ConceptGraph {
+    /// Insert or update the directed edge `from -> to`
+    ///
+    /// Repeated relations do not accumulate: the edge keeps the strongest
+    /// weight observed so far.
+    fn upsert_edge(&mut self, from: &str, to: &str, weight: f32) {
+        let edges = self.relationships.entry(from.to_string()).or_default();
+        match edges.iter_mut().find(|(concept, _)| concept == to) {
+            Some(edge) => edge.1 = edge.1.max(weight),
+            None => edges.push((to.to_string(), weight)),
+        }
+    }
+
+    /// Move a concept to the front of the recent list
+    fn touch(&mut self, concept: &str) {
+        self.recent.retain(|c| c != concept);
+        self.recent.insert(0, concept.to_string());
+        self.recent.truncate(MAX_RECENT_CONCEPTS);
+    }
+
+    /// Drop weak edges and keep at most `max_edges_per_node` strongest edges
+    /// per concept. Concepts left without edges are removed. Returns the
+    /// number of edges removed.
+    pub fn prune(&mut self, min_weight: f32, max_edges_per_node: usize) -> usize {
+        let mut removed = 0;
+
+        for edges in self.relationships.values_mut() {
+            let before = edges.len();
+            edges.retain(|(_, weight)| *weight >= min_weight);
+            edges.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            edges.truncate(max_edges_per_node);
+            removed += before - edges.len();
+        }
+
+        self.relationships.retain(|_, edges| !edges.is_empty());
+        removed
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionContext {
     /// Current session ID
@@ -158,6 +223,7 @@ pub struct SessionContext {
     pub topics: Vec<String>,
 
     /// Files/directories accessed
+    #[serde(with = "portable_path::vec")]
     pub accessed_paths: Vec<PathBuf>,
 
     /// Tools used
@@ -199,7 +265,8 @@ pub struct IndexStats {
     /// Last updated
     pub last_updated: DateTime<Utc>,
 
-    /// Compression ratio achieved
+    /// Mean `BlockMeta::compression_ratio` over grid snapshot blocks,
+    /// 0.0 when there are none
     pub avg_compression_ratio: f32,
 }
 
@@ -286,7 +353,7 @@ impl MemIndex {
 
         // Save main index
         let content = serde_json::to_string_pretty(self)?;
-        fs::write(&path, content)?;
+        write_atomic(&path, |file| Ok(file.write_all(content.as_bytes())?))?;
 
         // Save user preferences
         self.save_user_prefs()?;
@@ -341,40 +408,126 @@ impl MemIndex {
 
         // Save user flags
         let flags_content = serde_json::to_string_pretty(&self.user.flags)?;
-        fs::write(prefs_dir.join("user_flags.json"), flags_content)?;
+        write_atomic(&prefs_dir.join("user_flags.json"), |file| {
+            Ok(file.write_all(flags_content.as_bytes())?)
+        })?;
 
         // Save style
         let style_content = serde_json::to_string_pretty(&self.user.style)?;
-        fs::write(prefs_dir.join("style.json"), style_content)?;
+        write_atomic(&prefs_dir.join("style.json"), |file| {
+            Ok(file.write_all(style_content.as_bytes())?)
+        })?;
 
         // Save tone
         let tone_content = serde_json::to_string_pretty(&self.user.tone)?;
-        fs::write(prefs_dir.join("tone.json"), tone_content)?;
+        write_atomic(&prefs_dir.join("tone.json"), |file| {
+            Ok(file.write_all(tone_content.as_bytes())?)
+        })?;
 
         Ok(())
     }
 
     /// Register a new memory block
-    pub fn register_block(&mut self, filename: &str, path: &Path) -> Result<()> {
+    ///
+    /// Entry count and topics are read from the companion `.json` written by
+    /// `ConversationMemory`. If it is missing or unparseable the block is still
+    /// registered with empty metadata and a warning is returned.
+    pub fn register_block(&mut self, filename: &str, path: &Path) -> Result<Option<String>> {
         let metadata = fs::metadata(path)?;
 
+        let (entry_count, topics, warning) = match Self::read_block_contents(path) {
+            Ok((count, topics)) => (count, topics, None),
+            Err(e) => (0, Vec::new(), Some(format!("{}: {:#}", filename, e))),
+        };
+
+        for topic in &topics {
+            let blocks = self
+                .concepts
+                .concept_blocks
+                .entry(topic.clone())
+                .or_default();
+            if !blocks.iter().any(|b| b == filename) {
+                blocks.push(filename.to_string());
+            }
+        }
+
         let block_meta = BlockMeta {
             filename: filename.to_string(),
             created: Utc::now(),
             last_accessed: Utc::now(),
             size: metadata.len() as usize,
-            entry_count: 0, // Would be extracted from .m8 file
-            topics: Vec::new(),
+            entry_count,
+            topics,
             projects: Vec::new(),
             summary: format!("Memory block: {}", filename),
+            compression_ratio: None,
         };
 
         self.blocks.insert(filename.to_string(), block_meta);
+        self.refresh_stats();
+
+        Ok(warning)
+    }
+
+    /// Register a block stored as a `WaveGrid::to_bytes` snapshot
+    ///
+    /// The entry count is the number of stored waves and the block records
+    /// the grid's compression ratio, which feeds
+    /// `IndexStats::avg_compression_ratio`.
+    pub fn register_grid_snapshot(&mut self, filename: &str, path: &Path) -> Result<()> {
+        let bytes = fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+        let grid = WaveGrid::from_bytes(&bytes)?;
+        let stats = grid.compaction_stats();
+
+        let block_meta = BlockMeta {
+            filename: filename.to_string(),
+            created: Utc::now(),
+            last_accessed: Utc::now(),
+            size: bytes.len(),
+            entry_count: stats.occupied,
+            topics: Vec::new(),
+            projects: Vec::new(),
+            summary: format!("Wave grid: {} of {} cells", stats.occupied, stats.total),
+            compression_ratio: Some(stats.ratio),
+        };
+
+        self.blocks.insert(filename.to_string(), block_meta);
+        self.refresh_stats();
+
+        Ok(())
+    }
+
+    /// Recompute `stats` from `blocks`
+    fn refresh_stats(&mut self) {
         self.stats.total_blocks = self.blocks.len();
         self.stats.total_size = self.blocks.values().map(|b| b.size).sum();
+        self.stats.total_conversations = self.blocks.values().filter(|b| b.entry_count > 0).count();
+
+        let ratios: Vec<f32> = self
+            .blocks
+            .values()
+            .filter_map(|b| b.compression_ratio)
+            .collect();
+        self.stats.avg_compression_ratio = if ratios.is_empty() {
+            0.0
+        } else {
+            ratios.iter().sum::<f32>() / ratios.len() as f32
+        };
         self.stats.last_updated = Utc::now();
+    }
 
-        Ok(())
+    /// Read message count and topics from a block's companion JSON
+    fn read_block_contents(path: &Path) -> Result<(usize, Vec<String>)> {
+        let json_path = path.with_extension("json");
+        let content = fs::read_to_string(&json_path)
+            .with_context(|| format!("could not read {}", json_path.display()))?;
+        let json_data: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("could not parse {}", json_path.display()))?;
+
+        let analysis = ConversationAnalyzer::new().analyze(&json_data)?;
+        let topics = extract_topics(&analysis.messages, MAX_BLOCK_TOPICS);
+
+        Ok((analysis.message_count, topics))
     }
 
     /// Add or update a project
@@ -408,18 +561,24 @@ impl MemIndex {
     }
 
     /// Update concept relationships
+    ///
+    /// Edges are undirected and deduplicated; relating the same pair again
+    /// keeps the larger of the old and new weight rather than adding an edge.
     pub fn add_concept_relation(&mut self, concept1: &str, concept2: &str, weight: f32) {
-        self.concepts
-            .relationships
-            .entry(concept1.to_string())
-            .or_default()
-            .push((concept2.to_string(), weight));
+        self.concepts.upsert_edge(concept1, concept2, weight);
+        self.concepts.upsert_edge(concept2, concept1, weight);
 
-        self.concepts
-            .relationships
-            .entry(concept2.to_string())
-            .or_default()
-            .push((concept1.to_string(), weight));
+        self.concepts.touch(concept2);
+        self.concepts.touch(concept1);
+    }
+
+    /// Prune the concept graph, see `ConceptGraph::prune`
+    pub fn prune_concepts(&mut self, min_weight: f32, max_edges_per_node: usize) -> usize {
+        let removed = self.concepts.prune(min_weight, max_edges_per_node);
+        if removed > 0 {
+            self.stats.last_updated = Utc::now();
+        }
+        removed
     }
 
     /// Write daily journal entry
@@ -454,3 +613,343 @@ impl MemIndex {
         Ok(())
     }
 }
+
+/// Pick the most frequent non-trivial words across messages as topics
+fn extract_topics(messages: &[Message], limit: usize) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for msg in messages {
+        for word in msg
+            .content
+            .split(|c: char| !c.is_alphanumeric() && c != '_' && c != '-')
+        {
+            let word = word.trim_matches('-').to_lowercase();
+            if word.chars().count() < 4
+                || word.chars().all(|c| c.is_ascii_digit())
+                || STOPWORDS.contains(&word.as_str())
+            {
+                continue;
+            }
+            *counts.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    // Sort by frequency, then alphabetically so ties are deterministic
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(word, _)| word)
+        .collect()
+}
+
+/// Replace `path` with whatever `write` puts in a file, never leaving it
+/// half-written
+///
+/// `write` fills a temp file in the same directory, which is synced and then
+/// renamed over `path` (atomic on one filesystem). Until the rename, `path`
+/// keeps its old contents; if `write` or the sync fails the temp file is
+/// removed and `path` is untouched.
+fn write_atomic(path: &Path, write: impl FnOnce(&mut fs::File) -> Result<()>) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".tmp{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = fs::File::create(&tmp_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| Ok(fs::rename(&tmp_path, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.with_context(|| format!("could not write {}", path.display()))
+}
+
+/// Serde helpers storing paths with `/` separators whatever the OS, so a
+/// `.mem8` directory synced between Windows and Unix machines round-trips
+///
+/// Loading accepts both `/` and `\\` and rebuilds a native `PathBuf`. A
+/// Unix file name containing a literal backslash does not survive this.
+pub mod portable_path {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
+
+    /// `path` with every separator written as `/`
+    pub fn to_portable(path: &Path) -> String {
+        path.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Native path from a portable (or OS-specific) string
+    pub fn from_portable(path: &str) -> PathBuf {
+        PathBuf::from(path.replace(['/', '\\'], MAIN_SEPARATOR_STR))
+    }
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_portable(path))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(from_portable(&String::deserialize(deserializer)?))
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            path: &Option<PathBuf>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match path {
+                Some(path) => serializer.serialize_some(&to_portable(path)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<PathBuf>, D::Error> {
+            Ok(Option::<String>::deserialize(deserializer)?.map(|path| from_portable(&path)))
+        }
+    }
+
+    pub mod vec {
+        use super::*;
+        use serde::ser::SerializeSeq;
+
+        pub fn serialize<S: Serializer>(
+            paths: &[PathBuf],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(paths.len()))?;
+            for path in paths {
+                seq.serialize_element(&to_portable(path))?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<PathBuf>, D::Error> {
+            Ok(Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|path| from_portable(path))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::wave::MemoryWave;
+    use super::*;
+
+    #[test]
+    fn test_concept_relation_deduplicates_edges() {
+        let mut index = MemIndex::new();
+        index.add_concept_relation("rust", "wasm", 0.4);
+        index.add_concept_relation("rust", "wasm", 0.7);
+        index.add_concept_relation("wasm", "rust", 0.5);
+
+        let edges = &index.concepts.relationships["rust"];
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0], ("wasm".to_string(), 0.7));
+        assert_eq!(index.concepts.relationships["wasm"].len(), 1);
+        assert_eq!(index.concepts.recent, vec!["wasm", "rust"]);
+    }
+
+    #[test]
+    fn test_paths_serialize_portably() {
+        let mut index = MemIndex::new();
+        index.user.preferred_cwd = Some(PathBuf::from(r"C:\Users\me\work"));
+        index.session.accessed_paths = vec![
+            PathBuf::from(r"C:\Users\me\work\notes.md"),
+            PathBuf::from("/home/me/work"),
+        ];
+        index.update_project("site", PathBuf::from(r"D:\src\site"));
+
+        let json = serde_json::to_value(&index).unwrap();
+        assert_eq!(json["user"]["preferred_cwd"], "C:/Users/me/work");
+        assert_eq!(
+            json["session"]["accessed_paths"],
+            serde_json::json!(["C:/Users/me/work/notes.md", "/home/me/work"])
+        );
+        assert_eq!(json["projects"]["site"]["path"], "D:/src/site");
+
+        // An index written on Windows before paths were normalized
+        let mut windows = json.clone();
+        windows["user"]["preferred_cwd"] = r"C:\Users\me\work".into();
+        let loaded: MemIndex = serde_json::from_value(windows).unwrap();
+        let cwd = loaded.user.preferred_cwd.unwrap();
+        #[cfg(unix)]
+        assert_eq!(cwd, PathBuf::from("C:/Users/me/work"));
+        assert_eq!(cwd.file_name().unwrap(), "work");
+        assert_eq!(
+            loaded.session.accessed_paths[1],
+            portable_path::from_portable("/home/me/work")
+        );
+
+        let mut none = json;
+        none["user"]["preferred_cwd"] = serde_json::Value::Null;
+        let loaded: MemIndex = serde_json::from_value(none).unwrap();
+        assert!(loaded.user.preferred_cwd.is_none());
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_old_file_until_rename() {
+        let dir = std::env::temp_dir().join(format!("mem8_atomic_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("memindex.json");
+        fs::write(&path, "old").unwrap();
+
+        // A write that dies halfway leaves the old index and no temp file
+        let failed = write_atomic(&path, |file| {
+            file.write_all(b"{\"version\": ")?;
+            anyhow::bail!("simulated crash")
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // While writing, the new bytes go to a sibling temp file
+        write_atomic(&path, |file| {
+            assert_eq!(fs::read_to_string(&path)?, "old");
+            assert_eq!(fs::read_dir(&dir)?.count(), 2);
+            file.write_all(b"new")?;
+            assert_eq!(fs::read_to_string(&path)?, "old");
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_register_block_reads_companion_json() {
+        let dir = std::env::temp_dir().join(format!("mem8_register_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut index = MemIndex::new();
+
+        let conversation = serde_json::json!({
+            "messages": [
+                {"role": "user", "content": "How should the wave grid handle decay?"},
+                {"role": "assistant", "content": "Grid decay sweeps evict waves below threshold."},
+                {"role": "user", "content": "Then tune the decay threshold per grid."}
+            ]
+        });
+        fs::write(dir.join("chat.m8"), b"m8").unwrap();
+        fs::write(dir.join("chat.json"), conversation.to_string()).unwrap();
+        let warning = index
+            .register_block("chat.m8", &dir.join("chat.m8"))
+            .unwrap();
+        assert_eq!(warning, None);
+
+        let block = &index.blocks["chat.m8"];
+        assert_eq!(block.entry_count, 3);
+        assert_eq!(&block.topics[..3], ["decay", "grid", "threshold"]);
+        assert_eq!(index.concepts.concept_blocks["decay"], vec!["chat.m8"]);
+        assert_eq!(index.stats.total_conversations, 1);
+
+        // Registering again doesn't list the block twice under a topic
+        index
+            .register_block("chat.m8", &dir.join("chat.m8"))
+            .unwrap();
+        assert_eq!(index.concepts.concept_blocks["decay"], vec!["chat.m8"]);
+
+        fs::write(dir.join("lonely.m8"), b"m8").unwrap();
+        let warning = index
+            .register_block("lonely.m8", &dir.join("lonely.m8"))
+            .unwrap();
+        assert!(warning.unwrap().starts_with("lonely.m8: could not read"));
+        fs::write(dir.join("broken.m8"), b"m8").unwrap();
+        fs::write(dir.join("broken.json"), "{\"messages\": [").unwrap();
+        let warning = index
+            .register_block("broken.m8", &dir.join("broken.m8"))
+            .unwrap();
+        assert!(warning.unwrap().starts_with("broken.m8: could not parse"));
+        assert_eq!(index.blocks["broken.m8"].entry_count, 0);
+        assert!(index.blocks["broken.m8"].topics.is_empty());
+        assert_eq!(index.stats.total_blocks, 3);
+        assert_eq!(index.stats.total_conversations, 1);
+
+        assert!(index
+            .register_block("gone.m8", &dir.join("gone.m8"))
+            .is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_grid_snapshot_blocks_set_compression_ratio() {
+        let dir = std::env::temp_dir().join(format!("mem8_grid_blocks_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut index = MemIndex::new();
+
+        let mut sparse = WaveGrid::with_dimensions(4, 4, 4).unwrap();
+        for z in 0..4 {
+            sparse.store(0, 0, z, MemoryWave::new(200.0, 0.8));
+        }
+        let mut dense = WaveGrid::with_dimensions(2, 2, 2).unwrap();
+        for x in 0..2 {
+            for y in 0..2 {
+                dense.store(x, y, 0, MemoryWave::new(200.0, 0.8));
+            }
+        }
+        for (name, grid) in [("sparse.grid", &sparse), ("dense.grid", &dense)] {
+            fs::write(dir.join(name), grid.to_bytes().unwrap()).unwrap();
+            index.register_grid_snapshot(name, &dir.join(name)).unwrap();
+        }
+
+        assert_eq!(
+            index.blocks["sparse.grid"].compression_ratio,
+            Some(4.0 / 64.0)
+        );
+        assert_eq!(index.blocks["sparse.grid"].entry_count, 4);
+        assert_eq!(index.blocks["dense.grid"].compression_ratio, Some(0.5));
+        assert_eq!(index.stats.avg_compression_ratio, (4.0 / 64.0 + 0.5) / 2.0);
+
+        fs::write(dir.join("bad.grid"), b"not a grid").unwrap();
+        assert!(index
+            .register_grid_snapshot("bad.grid", &dir.join("bad.grid"))
+            .is_err());
+
+        // A tiny file claiming a huge grid is refused, not allocated
+        let mut huge = dense.to_bytes().unwrap();
+        huge[2..14].copy_from_slice(&[0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0]);
+        fs::write(dir.join("huge.grid"), huge).unwrap();
+        assert!(index
+            .register_grid_snapshot("huge.grid", &dir.join("huge.grid"))
+            .is_err());
+        assert_eq!(index.stats.total_blocks, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concept_prune() {
+        let mut index = MemIndex::new();
+        index.add_concept_relation("rust", "wasm", 0.9);
+        index.add_concept_relation("rust", "elixir", 0.6);
+        index.add_concept_relation("rust", "cobol", 0.05);
+
+        let removed = index.prune_concepts(0.1, 1);
+
+        assert_eq!(
+            index.concepts.relationships["rust"],
+            vec![("wasm".to_string(), 0.9)]
+        );
+        assert!(!index.concepts.relationships.contains_key("cobol"));
+        // rust->elixir, rust->cobol, cobol->rust
+        assert_eq!(removed, 3);
+    }
+}
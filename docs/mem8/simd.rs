@@ -1,5 +1,14 @@
 use crate::mem8::wave::{MemoryWave, WaveGrid};
-use std::f32::consts::PI;
+use std::f32::consts::{FRAC_PI_2, PI};
+use wide::{f32x8, CmpGt, CmpLt};
+
+/// Lane count of the vectorized wave path
+const LANES: usize = 8;
+
+/// 2π split for Cody-Waite range reduction; `k * TWO_PI_HI` is exact for
+/// any realistic multiple `k`, so reduction error comes only from the tail
+const TWO_PI_HI: f32 = 6.28125;
+const TWO_PI_LO: f32 = 0.001_935_307_2;
 
 /// SIMD-style wave processor using manual vectorization
 pub struct SimdWaveProcessor {
@@ -52,6 +61,39 @@ impl SimdWaveProcessor {
         results
     }
 
+    /// Process waves with 8-lane SIMD (`wide::f32x8`)
+    ///
+    /// Decay and emotional modulation are computed per wave since decay reads
+    /// the clock; the phase angle and `scale * sin(angle)` run across lanes
+    /// using `sin_f32x8`. Angles are formed in the same operation order as
+    /// `MemoryWave::calculate`, so results match the scalar path to within
+    /// the sine approximation error.
+    pub fn calculate_waves_vectorized(&self, waves: &[MemoryWave], t: f32) -> Vec<f32> {
+        let mut results = Vec::with_capacity(waves.len());
+        let two_pi = f32x8::splat(2.0 * PI);
+        let t = f32x8::splat(t);
+
+        for chunk in waves.chunks(LANES) {
+            let mut scale = [0.0f32; LANES];
+            let mut frequency = [0.0f32; LANES];
+            let mut phase = [0.0f32; LANES];
+
+            for (lane, wave) in chunk.iter().enumerate() {
+                scale[lane] =
+                    wave.amplitude * wave.calculate_decay() * wave.calculate_emotional_modulation();
+                frequency[lane] = wave.frequency;
+                phase[lane] = wave.phase;
+            }
+
+            let angle = two_pi * f32x8::from(frequency) * t + f32x8::from(phase);
+            let values = f32x8::from(scale) * sin_f32x8(angle);
+
+            results.extend_from_slice(&values.to_array()[..chunk.len()]);
+        }
+
+        results
+    }
+
     /// Calculate interference pattern using cache-aware blocking
     pub fn calculate_interference_block_simd(
         &self,
@@ -175,35 +217,97 @@ impl SimdWaveProcessor {
     }
 }
 
+/// Quantization steps per octave of amplitude
+const QUANT_STEPS_PER_OCTAVE: f32 = 32.0;
+
 /// Logarithmic amplitude quantization
+///
+/// Code 255 is amplitude 1.0 and each step down is 1/32 of an octave, so
+/// codes 1..=255 cover roughly 0.0041..=1.0. Zero (and anything below the
+/// smallest step) maps to 0; amplitudes above 1.0 saturate at 255. See
+/// `dequantize_amplitude` for the inverse.
 #[inline(always)]
 fn quantize_amplitude(amplitude: f32) -> u8 {
-    if amplitude <= 0.0 {
+    if amplitude.is_nan() || amplitude <= 0.0 {
+        return 0;
+    }
+
+    let code = 255.0 + QUANT_STEPS_PER_OCTAVE * amplitude.log2();
+    if code < 0.5 {
         0
     } else {
-        (32.0 * amplitude.log2()).clamp(0.0, 255.0) as u8
+        code.round().min(255.0) as u8
+    }
+}
+
+/// Inverse of `quantize_amplitude`: code `c > 0` maps to `2^((c - 255) / 32)`
+///
+/// Round-tripping an in-range amplitude is accurate to half a step, a
+/// relative error of at most 2^(1/64) - 1 ≈ 1.1%.
+#[inline(always)]
+pub fn dequantize_amplitude(code: u8) -> f32 {
+    if code == 0 {
+        0.0
+    } else {
+        ((code as f32 - 255.0) / QUANT_STEPS_PER_OCTAVE).exp2()
     }
 }
 
-/// Fast sine approximation using Taylor series
+/// Vectorized sine, accurate to ~4e-6 for any finite input
+///
+/// Reduces to [-π, π] with a two-constant Cody-Waite step, folds into
+/// [-π/2, π/2] via sin(π - x) = sin(x), then evaluates the odd Taylor
+/// polynomial through x⁹ in Horner form.
+#[inline(always)]
+fn sin_f32x8(x: f32x8) -> f32x8 {
+    let k = (x * f32x8::splat(1.0 / (2.0 * PI))).round();
+    let x = x - k * f32x8::splat(TWO_PI_HI) - k * f32x8::splat(TWO_PI_LO);
+
+    let half_pi = f32x8::splat(FRAC_PI_2);
+    let x = x.cmp_gt(half_pi).blend(f32x8::splat(PI) - x, x);
+    let x = x.cmp_lt(-half_pi).blend(f32x8::splat(-PI) - x, x);
+
+    let x2 = x * x;
+    let poly = f32x8::splat(1.0 / 362_880.0)
+        .mul_add(x2, f32x8::splat(-1.0 / 5_040.0))
+        .mul_add(x2, f32x8::splat(1.0 / 120.0))
+        .mul_add(x2, f32x8::splat(-1.0 / 6.0))
+        .mul_add(x2, f32x8::splat(1.0));
+
+    x * poly
+}
+
+/// Documented accuracy of `fast_sin` against `f32::sin`
+///
+/// The polynomial truncation error peaks at ±π/2 around 3.6e-6; the bound
+/// leaves headroom for f32 rounding in the range reduction.
+pub const FAST_SIN_MAX_ERROR: f32 = 1e-4;
+
+/// Fast sine approximation, accurate to `FAST_SIN_MAX_ERROR`
+///
+/// Scalar twin of `sin_f32x8`: Cody-Waite reduction to [-π, π], fold into
+/// [-π/2, π/2], then the odd Taylor polynomial through x⁹.
 #[inline(always)]
 fn fast_sin(x: f32) -> f32 {
-    // Normalize to [-PI, PI]
-    let x = x % (2.0 * PI);
-    let x = if x > PI {
-        x - 2.0 * PI
-    } else if x < -PI {
-        x + 2.0 * PI
+    let k = (x * (1.0 / (2.0 * PI))).round();
+    let x = x - k * TWO_PI_HI - k * TWO_PI_LO;
+
+    let x = if x > FRAC_PI_2 {
+        PI - x
+    } else if x < -FRAC_PI_2 {
+        -PI - x
     } else {
         x
     };
 
-    // Taylor series: sin(x) ≈ x - x³/6 + x⁵/120
     let x2 = x * x;
-    let x3 = x2 * x;
-    let x5 = x3 * x2;
+    let poly = (1.0f32 / 362_880.0)
+        .mul_add(x2, -1.0 / 5_040.0)
+        .mul_add(x2, 1.0 / 120.0)
+        .mul_add(x2, -1.0 / 6.0)
+        .mul_add(x2, 1.0);
 
-    x - x3 / 6.0 + x5 / 120.0
+    x * poly
 }
 
 /// Optimized grid operations with cache blocking
@@ -225,34 +329,113 @@ impl SimdGridOps {
     }
 
     /// Process entire grid layer using 8×8 blocks
+    ///
+    /// With the `rayon` feature each band of 8 rows is computed on the rayon
+    /// pool. Bands own disjoint rows of the result and only read the grid, so
+    /// the output is identical to `process_grid_layer_serial`.
     pub fn process_grid_layer(&self, grid: &WaveGrid, z: u16, t: f32) -> Vec<Vec<f32>> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+
+            let mut result = vec![vec![0.0f32; grid.width]; grid.height];
+            result
+                .par_chunks_mut(8)
+                .enumerate()
+                .for_each(|(band, rows)| self.process_block_row(grid, band * 8, rows, z, t));
+            result
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        self.process_grid_layer_serial(grid, z, t)
+    }
+
+    /// Single-threaded `process_grid_layer`
+    pub fn process_grid_layer_serial(&self, grid: &WaveGrid, z: u16, t: f32) -> Vec<Vec<f32>> {
         let mut result = vec![vec![0.0f32; grid.width]; grid.height];
+        for (band, rows) in result.chunks_mut(8).enumerate() {
+            self.process_block_row(grid, band * 8, rows, z, t);
+        }
+        result
+    }
 
+    /// Fill `rows` (up to 8 rows starting at `block_y`) one 8×8 block at a time
+    fn process_block_row(
+        &self,
+        grid: &WaveGrid,
+        block_y: usize,
+        rows: &mut [Vec<f32>],
+        z: u16,
+        t: f32,
+    ) {
         // Process in 8×8 blocks for cache efficiency
-        for block_y in (0..grid.height).step_by(8) {
-            for block_x in (0..grid.width).step_by(8) {
-                let block = self.processor.calculate_interference_block_simd(
-                    grid,
-                    block_x as u8,
-                    block_y as u8,
-                    z,
-                    t,
-                );
-
-                // Copy block results
-                for (dy, row) in block.iter().enumerate() {
-                    for (dx, value) in row.iter().enumerate() {
-                        let y = block_y + dy;
-                        let x = block_x + dx;
-                        if y < grid.height && x < grid.width {
-                            result[y][x] = *value;
-                        }
+        for block_x in (0..grid.width).step_by(8) {
+            let block = self.processor.calculate_interference_block_simd(
+                grid,
+                block_x as u8,
+                block_y as u8,
+                z,
+                t,
+            );
+
+            // Copy block results
+            for (row, block_row) in rows.iter_mut().zip(block.iter()) {
+                for (dx, value) in block_row.iter().enumerate() {
+                    if let Some(cell) = row.get_mut(block_x + dx) {
+                        *cell = *value;
                     }
                 }
             }
         }
+    }
 
-        result
+    /// Render one layer of interference as an image buffer
+    ///
+    /// Values are normalized to 0–255 according to `options.range` and then
+    /// passed through `options.colormap`. Pixels are row-major, top row first.
+    pub fn render_interference_layer(
+        &self,
+        grid: &WaveGrid,
+        z: u16,
+        t: f32,
+        options: &RenderOptions,
+    ) -> RenderedLayer {
+        let layer = self.process_grid_layer(grid, z, t);
+
+        let (min, max) = match options.range {
+            IntensityRange::Fixed { min, max } => (min, max),
+            IntensityRange::Auto => layer
+                .iter()
+                .flatten()
+                .filter(|v| v.is_finite())
+                .fold(None, |range, &v| match range {
+                    None => Some((v, v)),
+                    Some((lo, hi)) => Some((f32::min(lo, v), f32::max(hi, v))),
+                })
+                .unwrap_or((0.0, 0.0)),
+        };
+        let span = max - min;
+
+        let channels = options.colormap.channels();
+        let mut pixels = Vec::with_capacity(grid.width * grid.height * channels);
+        for &value in layer.iter().flatten() {
+            // A flat or inverted window renders as level 0; NaN casts to 0 too
+            let level = if span > 0.0 {
+                (((value - min) / span).clamp(0.0, 1.0) * 255.0).round() as u8
+            } else {
+                0
+            };
+            options.colormap.push_pixel(level, &mut pixels);
+        }
+
+        RenderedLayer {
+            width: grid.width,
+            height: grid.height,
+            channels,
+            pixels,
+            min,
+            max,
+        }
     }
 
     /// Batch phase calculation for temporal relationships
@@ -297,6 +480,100 @@ impl SimdGridOps {
     }
 }
 
+/// Value window mapped onto 0–255 when rendering a layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntensityRange {
+    /// Stretch the layer's own finite min..max over the full range
+    Auto,
+    /// Clamp to a fixed window, so frames rendered at different `t` compare
+    Fixed { min: f32, max: f32 },
+}
+
+/// Mapping from a 0–255 level to pixel bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    /// One byte per pixel
+    Grayscale,
+    /// RGB black → red → yellow → white
+    Heat,
+    /// RGB blue → white → red, for signed interference centered in the window
+    Diverging,
+}
+
+impl Colormap {
+    /// Bytes per pixel
+    pub fn channels(self) -> usize {
+        match self {
+            Colormap::Grayscale => 1,
+            Colormap::Heat | Colormap::Diverging => 3,
+        }
+    }
+
+    fn push_pixel(self, level: u8, out: &mut Vec<u8>) {
+        match self {
+            Colormap::Grayscale => out.push(level),
+            Colormap::Heat => {
+                let l = level as u16 * 3;
+                out.extend_from_slice(&[
+                    l.min(255) as u8,
+                    l.saturating_sub(255).min(255) as u8,
+                    l.saturating_sub(510) as u8,
+                ]);
+            }
+            Colormap::Diverging => {
+                if level < 128 {
+                    let s = level * 2;
+                    out.extend_from_slice(&[s, s, 255]);
+                } else {
+                    let s = (255 - level) * 2;
+                    out.extend_from_slice(&[255, s, s]);
+                }
+            }
+        }
+    }
+}
+
+/// Options for `SimdGridOps::render_interference_layer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    pub range: IntensityRange,
+    pub colormap: Colormap,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            range: IntensityRange::Auto,
+            colormap: Colormap::Grayscale,
+        }
+    }
+}
+
+/// Rendered interference layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedLayer {
+    pub width: usize,
+    pub height: usize,
+    /// Bytes per pixel: 1 for grayscale, 3 for RGB colormaps
+    pub channels: usize,
+    /// Row-major pixel bytes, `width * height * channels` long
+    pub pixels: Vec<u8>,
+    /// Value mapped to level 0
+    pub min: f32,
+    /// Value mapped to level 255
+    pub max: f32,
+}
+
+impl RenderedLayer {
+    /// Binary PGM (grayscale) or PPM (RGB) file contents
+    pub fn to_pnm(&self) -> Vec<u8> {
+        let magic = if self.channels == 1 { "P5" } else { "P6" };
+        let mut out = format!("{}\n{} {}\n255\n", magic, self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.pixels);
+        out
+    }
+}
+
 /// Performance benchmarking utilities
 pub struct PerformanceBenchmark {
     simd_ops: SimdGridOps,
@@ -336,10 +613,10 @@ impl PerformanceBenchmark {
         }
         let duration_standard = start_standard.elapsed();
 
-        // Benchmark optimized calculation
+        // Benchmark vectorized calculation
         let processor = SimdWaveProcessor::new();
         let start_simd = Instant::now();
-        let results_simd = processor.calculate_waves_simd(&waves, 1.0);
+        let results_simd = processor.calculate_waves_vectorized(&waves, 1.0);
         let duration_simd = start_simd.elapsed();
 
         // Verify results match (within floating point tolerance)
@@ -359,11 +636,11 @@ impl PerformanceBenchmark {
         }
     }
 
-    /// Benchmark grid processing performance
+    /// Benchmark grid processing performance on the grid's middle layer
     pub fn benchmark_grid_processing(&self, grid: &WaveGrid) -> BenchmarkResult {
         use std::time::Instant;
 
-        let z = 1000;
+        let z = (grid.depth / 2) as u16;
         let t = 1.0;
 
         // Benchmark standard processing
@@ -399,6 +676,38 @@ impl PerformanceBenchmark {
         }
     }
 
+    /// Benchmark serial against (with the `rayon` feature) parallel layer
+    /// processing; `standard_duration` is the serial run
+    pub fn benchmark_parallel_grid_processing(&self, grid: &WaveGrid, z: u16) -> BenchmarkResult {
+        use std::time::Instant;
+
+        let t = 1.0;
+
+        let start_serial = Instant::now();
+        let result_serial = self.simd_ops.process_grid_layer_serial(grid, z, t);
+        let duration_serial = start_serial.elapsed();
+
+        let start_parallel = Instant::now();
+        let result_parallel = self.simd_ops.process_grid_layer(grid, z, t);
+        let duration_parallel = start_parallel.elapsed();
+
+        let max_diff = result_serial
+            .iter()
+            .flatten()
+            .zip(result_parallel.iter().flatten())
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+
+        BenchmarkResult {
+            operation: "Parallel Grid Processing".to_string(),
+            num_items: grid.width * grid.height,
+            standard_duration: duration_serial,
+            simd_duration: duration_parallel,
+            speedup: duration_serial.as_secs_f64() / duration_parallel.as_secs_f64(),
+            max_error: max_diff,
+        }
+    }
+
     /// Benchmark emotional modulation calculation
     pub fn benchmark_emotional_modulation(&self, num_waves: usize) -> BenchmarkResult {
         use std::time::Instant;
@@ -468,3 +777,133 @@ impl std::fmt::Display for BenchmarkResult {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectorized_matches_scalar() {
+        let processor = SimdWaveProcessor::new();
+        let mut waves = Vec::new();
+        // 21 waves: two full lanes plus a partial one
+        for i in 0..21 {
+            let mut wave = MemoryWave::new(i as f32 * 47.0, 0.8);
+            wave.phase = i as f32 * 0.7 - 5.0;
+            wave.valence = 0.5;
+            wave.arousal = 0.25;
+            wave.decay_tau = None;
+            waves.push(wave);
+        }
+
+        for t in [0.0, 0.013, 1.0, 7.5] {
+            let vectorized = processor.calculate_waves_vectorized(&waves, t);
+            assert_eq!(vectorized.len(), waves.len());
+
+            for (wave, value) in waves.iter().zip(&vectorized) {
+                let expected = wave.calculate(t);
+                assert!(
+                    (expected - value).abs() < 1e-4,
+                    "t={t} f={}: {expected} vs {value}",
+                    wave.frequency
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_sin_error_bound() {
+        let steps = 100_000;
+        let mut max_error = 0.0f32;
+
+        for i in 0..=steps {
+            let x = -PI + 2.0 * PI * i as f32 / steps as f32;
+            max_error = max_error.max((fast_sin(x) - x.sin()).abs());
+        }
+        assert!(max_error < FAST_SIN_MAX_ERROR, "max error {max_error}");
+
+        // High-phase angles reduce back into range without drifting
+        for x in [100.0f32, -250.5, 1234.567, 6283.0] {
+            assert!((fast_sin(x) - x.sin()).abs() < FAST_SIN_MAX_ERROR, "x={x}");
+        }
+    }
+
+    #[test]
+    fn test_quantize_amplitude_spans_unit_range() {
+        let amplitudes = [0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+        let codes = SimdWaveProcessor::new().quantize_amplitudes_simd(&amplitudes);
+
+        assert!(codes.windows(2).all(|pair| pair[0] < pair[1]), "{codes:?}");
+        assert_eq!(codes[amplitudes.len() - 1], 255);
+        assert_eq!(quantize_amplitude(0.0), 0);
+        assert_eq!(quantize_amplitude(2.0), 255);
+
+        let max_relative = 2f32.powf(1.0 / 64.0) - 1.0;
+        for (&amplitude, &code) in amplitudes.iter().zip(&codes) {
+            let restored = dequantize_amplitude(code);
+            assert!(
+                ((restored - amplitude) / amplitude).abs() <= max_relative + 1e-6,
+                "{amplitude} -> {code} -> {restored}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parallel_grid_matches_serial() {
+        let mut grid = WaveGrid::with_dimensions(256, 256, 8).unwrap();
+        for y in 0..256usize {
+            for x in (0..256usize).step_by(3) {
+                let mut wave = MemoryWave::new((x * 7 + y) as f32 % 900.0 + 20.0, 0.6);
+                wave.phase = (x + y) as f32 * 0.01;
+                // Decay reads the wall clock; keep both runs comparable
+                wave.decay_tau = None;
+                grid.store(x as u8, y as u8, 4, wave);
+            }
+        }
+
+        let result = PerformanceBenchmark::new().benchmark_parallel_grid_processing(&grid, 4);
+        assert_eq!(result.num_items, 256 * 256);
+        assert_eq!(result.max_error, 0.0, "{}", result);
+    }
+
+    #[test]
+    fn test_render_interference_layer() {
+        let mut grid = WaveGrid::with_dimensions(8, 8, 1).unwrap();
+        let mut wave = MemoryWave::new(0.25, 1.0);
+        wave.decay_tau = None;
+        grid.store(1, 0, 0, wave.clone());
+        wave.phase = PI;
+        grid.store(2, 0, 0, wave);
+
+        // sin(π/2) = 1 and sin(3π/2) = -1; every other cell is empty
+        let ops = SimdGridOps::new();
+        let gray = ops.render_interference_layer(&grid, 0, 1.0, &RenderOptions::default());
+        assert_eq!((gray.width, gray.height, gray.channels), (8, 8, 1));
+        assert_eq!(gray.pixels.len(), 64);
+        assert_eq!(&gray.pixels[..3], &[128, 255, 0]);
+        assert!((gray.min + 1.0).abs() < 1e-5 && (gray.max - 1.0).abs() < 1e-5);
+        assert!(gray.to_pnm().starts_with(b"P5\n8 8\n255\n"));
+
+        let options = RenderOptions {
+            range: IntensityRange::Fixed { min: 0.0, max: 0.5 },
+            colormap: Colormap::Heat,
+        };
+        let heat = ops.render_interference_layer(&grid, 0, 1.0, &options);
+        assert_eq!(heat.channels, 3);
+        assert_eq!(heat.pixels.len(), 64 * 3);
+        // Empty, saturated high, clamped low
+        assert_eq!(&heat.pixels[..9], &[0, 0, 0, 255, 255, 255, 0, 0, 0]);
+        assert_eq!(heat.to_pnm().len(), "P6\n8 8\n255\n".len() + 64 * 3);
+
+        let flat = WaveGrid::with_dimensions(8, 8, 1).unwrap();
+        let blank = ops.render_interference_layer(&flat, 0, 1.0, &RenderOptions::default());
+        assert!(blank.pixels.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn test_benchmark_wave_calculation_error() {
+        let result = PerformanceBenchmark::new().benchmark_wave_calculation(1000);
+        assert_eq!(result.num_items, 1000);
+        assert!(result.max_error < 1e-3, "{}", result);
+    }
+}
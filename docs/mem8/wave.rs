@@ -1,8 +1,15 @@
 //! Based on the MEM8 paper - 256×256×65536 wave grid with interference patterns
+//!
+//! The paper's grid is addressed with 8-bit x/y and 16-bit z coordinates.
+//! `WaveGrid::new` allocates a smaller 64×64×256 grid to keep memory use
+//! reasonable; coordinates outside the allocated `width`/`height`/`depth`
+//! are rejected rather than wrapped.
 
+use anyhow::{Context, Result};
+use std::collections::HashMap;
 use std::f32::consts::PI;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Memory wave at a specific grid position
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -17,11 +24,16 @@ pub struct MemoryWave {
     pub valence: f32,
     /// Emotional arousal (0.0 to 1.0)
     pub arousal: f32,
-    /// Creation timestamp
-    #[serde(skip, default = "Instant::now")]
+    /// Creation timestamp, persisted as Unix milliseconds so a reloaded
+    /// wave keeps its age
+    #[serde(
+        rename = "created_unix_ms",
+        with = "created_at_serde",
+        default = "Instant::now"
+    )]
     pub created_at: Instant,
     /// Decay time constant (None = infinite)
-    #[serde(skip, default)]
+    #[serde(default)]
     pub decay_tau: Option<Duration>,
 }
 
@@ -67,11 +79,18 @@ impl MemoryWave {
         self.amplitude * decay * emotional_mod * (2.0 * PI * self.frequency * t + self.phase).sin()
     }
 
-    /// Calculate temporal decay
+    /// Calculate temporal decay as of now
     pub fn calculate_decay(&self) -> f32 {
+        self.calculate_decay_at(Instant::now())
+    }
+
+    /// Calculate temporal decay as of `now`, for tests and deterministic replay
+    ///
+    /// Instants before `created_at` count as zero elapsed time.
+    pub fn calculate_decay_at(&self, now: Instant) -> f32 {
         match self.decay_tau {
             Some(tau) => {
-                let elapsed = self.created_at.elapsed().as_secs_f32();
+                let elapsed = now.saturating_duration_since(self.created_at).as_secs_f32();
                 (-elapsed / tau.as_secs_f32()).exp()
             }
             None => 1.0, // No decay
@@ -99,18 +118,145 @@ impl MemoryWave {
     }
 }
 
-/// 3D Wave Grid: 256×256×65536 (8-bit × 8-bit × 16-bit)
+/// Serde adapter mapping the monotonic `created_at` to wall-clock Unix ms
+///
+/// On load the wave's age is re-anchored to `Instant::now()`. Ages reaching
+/// past the monotonic clock's origin (e.g. saved before a reboot) are
+/// clamped to the longest span it can represent.
+mod created_at_serde {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    fn unix_ms(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64)
+    }
+
+    pub fn serialize<S: Serializer>(
+        created_at: &Instant,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let created = SystemTime::now()
+            .checked_sub(created_at.elapsed())
+            .unwrap_or(UNIX_EPOCH);
+        serializer.serialize_u64(unix_ms(created))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Instant, D::Error> {
+        let created_ms = u64::deserialize(deserializer)?;
+        let age_ms = unix_ms(SystemTime::now()).saturating_sub(created_ms);
+        Ok(instant_from_age(Duration::from_millis(age_ms)))
+    }
+
+    fn instant_from_age(age: Duration) -> Instant {
+        let now = Instant::now();
+        if let Some(instant) = now.checked_sub(age) {
+            return instant;
+        }
+
+        // Binary search the longest representable age
+        let (mut lo, mut hi) = (0u64, age.as_millis().min(u64::MAX as u128) as u64);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            if now.checked_sub(Duration::from_millis(mid)).is_some() {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        now - Duration::from_millis(lo)
+    }
+}
+
+/// 3D Wave Grid addressed by (u8, u8, u16) coordinates
 pub struct WaveGrid {
-    /// Grid dimensions
-    pub width: usize, // 256
-    pub height: usize, // 256
-    pub depth: usize,  // 65536
+    /// Grid dimensions (at most 256×256×65536)
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
 
     /// The actual grid storage (flattened for performance)
     grid: Vec<Option<Arc<MemoryWave>>>,
 
     /// Noise floor threshold for adaptive filtering
     pub noise_floor: f32,
+
+    /// Decay threshold used to sweep dead waves when `store` finds the grid
+    /// near full (`None` disables automatic sweeping)
+    pub auto_sweep_threshold: Option<f32>,
+
+    /// Number of occupied slots
+    occupied: usize,
+
+    /// Stores left before `store` may sweep again, set when a sweep leaves
+    /// the grid near full
+    stores_until_sweep: usize,
+
+    /// Label -> coordinates of the waves stored under it by `store_labeled`
+    labels: HashMap<String, Vec<(u8, u8, u16)>>,
+
+    /// Slot index -> label, so replacing or sweeping a slot unlabels it
+    cell_labels: HashMap<usize, String>,
+}
+
+/// Decay level below which a wave no longer counts as active
+pub const ACTIVE_DECAY_THRESHOLD: f32 = 0.01;
+
+/// Fraction of occupied slots at which `store` triggers an automatic sweep
+const AUTO_SWEEP_OCCUPANCY: f32 = 0.9;
+
+/// A sweep that leaves the grid near full is not retried for the next
+/// `slots / AUTO_SWEEP_BACKOFF` stores, so a grid of live waves costs each
+/// store about this many slot checks instead of a full scan
+const AUTO_SWEEP_BACKOFF: usize = 64;
+
+/// Version of the `WaveGrid::to_bytes` snapshot format
+///
+/// Version 1 is a bincode-encoded `GridSnapshot`: the version, grid
+/// dimensions, noise floor and auto-sweep threshold, then one
+/// `(x, y, z, wave)` entry per occupied cell. Waves carry their decay
+/// constant and Unix-ms creation time.
+pub const GRID_SNAPSHOT_VERSION: u16 = 1;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GridSnapshot {
+    version: u16,
+    width: u32,
+    height: u32,
+    depth: u32,
+    noise_floor: f32,
+    auto_sweep_threshold: Option<f32>,
+    cells: Vec<(u8, u8, u16, MemoryWave)>,
+}
+
+/// How densely a grid is populated, see `WaveGrid::compaction_stats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionStats {
+    /// Occupied cells
+    pub occupied: usize,
+    /// All cells, `width * height * depth`
+    pub total: usize,
+    /// `occupied / total`: the fraction of the dense grid a `to_bytes`
+    /// snapshot keeps, so lower is better compression
+    pub ratio: f32,
+}
+
+/// Slot count above which `from_bytes` refuses a snapshot: 2^24 slots
+/// (128 MiB of pointers), 64 times the default grid
+pub const DEFAULT_MAX_SNAPSHOT_SLOTS: usize = 1 << 24;
+
+/// Largest grid extents addressable by the `u8`/`u8`/`u16` coordinates
+pub const MAX_GRID_WIDTH: usize = 1 << 8;
+pub const MAX_GRID_HEIGHT: usize = 1 << 8;
+pub const MAX_GRID_DEPTH: usize = 1 << 16;
+
+/// Row-major (x fastest, then y, then z) slot index within the given extents
+fn linear_index(width: usize, height: usize, depth: usize, x: u8, y: u8, z: u16) -> Option<usize> {
+    let (x, y, z) = (x as usize, y as usize, z as usize);
+    if x >= width || y >= height || z >= depth {
+        return None;
+    }
+    Some(z * width * height + y * width + x)
 }
 
 impl Default for WaveGrid {
@@ -120,71 +266,319 @@ impl Default for WaveGrid {
 }
 
 impl WaveGrid {
-    /// Create a new wave grid with standard MEM8 dimensions
+    /// Create a new 64×64×256 wave grid, the default working size
     pub fn new() -> Self {
-        const WIDTH: usize = 64;
-        const HEIGHT: usize = 64;
-        const DEPTH: usize = 256;
+        Self::with_dimensions(64, 64, 256).expect("default dimensions are valid")
+    }
 
-        Self {
-            width: WIDTH,
-            height: HEIGHT,
-            depth: DEPTH,
-            grid: vec![None; WIDTH * HEIGHT * DEPTH],
-            noise_floor: 0.1,
+    /// Create an empty grid of the given size
+    ///
+    /// x and y are addressed by `u8` and z by `u16`, so dimensions must be
+    /// within 1..=256, 1..=256 and 1..=65536. Storage is dense: the largest
+    /// grid holds 2^32 slots (32 GiB of pointers).
+    pub fn with_dimensions(width: usize, height: usize, depth: usize) -> Result<Self> {
+        if !(1..=MAX_GRID_WIDTH).contains(&width)
+            || !(1..=MAX_GRID_HEIGHT).contains(&height)
+            || !(1..=MAX_GRID_DEPTH).contains(&depth)
+        {
+            anyhow::bail!("invalid grid dimensions {}×{}×{}", width, height, depth);
         }
+
+        Ok(Self {
+            width,
+            height,
+            depth,
+            grid: vec![None; width * height * depth],
+            noise_floor: DEFAULT_NOISE_FLOOR,
+            auto_sweep_threshold: Some(ACTIVE_DECAY_THRESHOLD),
+            occupied: 0,
+            stores_until_sweep: 0,
+            labels: HashMap::new(),
+            cell_labels: HashMap::new(),
+        })
     }
 
-    /// Get linear index from 3D coordinates
-    fn get_index(&self, x: u8, y: u8, z: u16) -> usize {
-        let x = x as usize;
-        let y = y as usize;
-        let z = z as usize;
+    /// Get linear index from 3D coordinates, `None` if outside the grid
+    fn get_index(&self, x: u8, y: u8, z: u16) -> Option<usize> {
+        linear_index(self.width, self.height, self.depth, x, y, z)
+    }
 
-        z * self.width * self.height + y * self.width + x
+    /// Coordinates of a slot index, the inverse of `get_index`
+    fn coordinates(&self, idx: usize) -> (u8, u8, u16) {
+        let plane = self.width * self.height;
+        (
+            (idx % self.width) as u8,
+            (idx % plane / self.width) as u8,
+            (idx / plane) as u16,
+        )
     }
 
     /// Store a memory wave at specific coordinates
-    pub fn store(&mut self, x: u8, y: u8, z: u16, wave: MemoryWave) {
-        // Clamp coordinates to grid dimensions
-        let x = (x as usize % self.width) as u8;
-        let y = (y as usize % self.height) as u8;
-        let z = (z as usize % self.depth) as u16;
+    ///
+    /// Returns `false` if the coordinates are outside the grid or the wave
+    /// is below the noise floor. When the grid is near full and
+    /// `auto_sweep_threshold` is set, decayed waves are swept first; a sweep
+    /// that frees too little is not retried for a while (see
+    /// `AUTO_SWEEP_BACKOFF`). A wave it replaces loses its label.
+    pub fn store(&mut self, x: u8, y: u8, z: u16, wave: MemoryWave) -> bool {
+        self.store_with_label(x, y, z, wave, None)
+    }
+
+    /// `store`, also indexing the wave under `label` for `get_by_label`
+    ///
+    /// Labels live in memory only; `to_bytes` snapshots don't keep them.
+    pub fn store_labeled(&mut self, x: u8, y: u8, z: u16, wave: MemoryWave, label: &str) -> bool {
+        self.store_with_label(x, y, z, wave, Some(label))
+    }
 
-        let idx = self.get_index(x, y, z);
+    fn store_with_label(
+        &mut self,
+        x: u8,
+        y: u8,
+        z: u16,
+        wave: MemoryWave,
+        label: Option<&str>,
+    ) -> bool {
+        let Some(idx) = self.get_index(x, y, z) else {
+            return false;
+        };
 
         // Apply noise floor filtering
-        if wave.amplitude > self.noise_floor {
-            self.grid[idx] = Some(Arc::new(wave));
+        if wave.amplitude <= self.noise_floor {
+            return false;
+        }
+
+        if let Some(threshold) = self.auto_sweep_threshold {
+            if self.stores_until_sweep > 0 {
+                self.stores_until_sweep -= 1;
+            } else if self.near_full() {
+                self.sweep_decayed(threshold);
+                if self.near_full() {
+                    self.stores_until_sweep = self.grid.len() / AUTO_SWEEP_BACKOFF;
+                }
+            }
+        }
+
+        if self.grid[idx].replace(Arc::new(wave)).is_none() {
+            self.occupied += 1;
+        }
+        self.unlabel(idx);
+        if let Some(label) = label {
+            self.labels
+                .entry(label.to_string())
+                .or_default()
+                .push((x, y, z));
+            self.cell_labels.insert(idx, label.to_string());
+        }
+        true
+    }
+
+    /// Whether occupancy has reached `AUTO_SWEEP_OCCUPANCY`
+    fn near_full(&self) -> bool {
+        self.occupied as f32 >= self.grid.len() as f32 * AUTO_SWEEP_OCCUPANCY
+    }
+
+    /// Drop the label of a slot whose wave is gone or replaced
+    fn unlabel(&mut self, idx: usize) {
+        let Some(label) = self.cell_labels.remove(&idx) else {
+            return;
+        };
+        let coordinates = self.coordinates(idx);
+        if let Some(cells) = self.labels.get_mut(&label) {
+            cells.retain(|cell| *cell != coordinates);
+            if cells.is_empty() {
+                self.labels.remove(&label);
+            }
+        }
+    }
+
+    /// Waves stored under `label`, in the order they were labeled
+    pub fn get_by_label(&self, label: &str) -> Vec<&Arc<MemoryWave>> {
+        self.labels.get(label).map_or_else(Vec::new, |cells| {
+            cells
+                .iter()
+                .filter_map(|&(x, y, z)| self.get(x, y, z))
+                .collect()
+        })
+    }
+
+    /// Labels that currently have at least one wave
+    pub fn labels(&self) -> impl Iterator<Item = &str> + '_ {
+        self.labels.keys().map(String::as_str)
+    }
+
+    /// Evict waves whose decay has dropped below `threshold`
+    ///
+    /// Returns the number of slots cleared.
+    pub fn sweep_decayed(&mut self, threshold: f32) -> usize {
+        let mut evicted = 0;
+
+        for idx in 0..self.grid.len() {
+            if self.grid[idx]
+                .as_ref()
+                .is_some_and(|wave| wave.calculate_decay() < threshold)
+            {
+                self.grid[idx] = None;
+                self.unlabel(idx);
+                evicted += 1;
+            }
         }
+
+        self.occupied -= evicted;
+        evicted
+    }
+
+    /// Occupied cells with their coordinates, in storage order
+    pub fn iter_occupied(&self) -> impl Iterator<Item = (u8, u8, u16, &Arc<MemoryWave>)> + '_ {
+        self.grid.iter().enumerate().filter_map(move |(idx, slot)| {
+            let wave = slot.as_ref()?;
+            let (x, y, z) = self.coordinates(idx);
+            Some((x, y, z, wave))
+        })
+    }
+
+    /// Serialize occupied cells into a compact binary snapshot
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let cells = self
+            .iter_occupied()
+            .map(|(x, y, z, wave)| (x, y, z, MemoryWave::clone(wave)))
+            .collect();
+
+        let snapshot = GridSnapshot {
+            version: GRID_SNAPSHOT_VERSION,
+            width: self.width as u32,
+            height: self.height as u32,
+            depth: self.depth as u32,
+            noise_floor: self.noise_floor,
+            auto_sweep_threshold: self.auto_sweep_threshold,
+            cells,
+        };
+
+        bincode::serialize(&snapshot).context("Failed to encode grid snapshot")
+    }
+
+    /// Restore a grid from a `to_bytes` snapshot of at most
+    /// `DEFAULT_MAX_SNAPSHOT_SLOTS` slots
+    ///
+    /// Cells are restored as stored, bypassing the noise floor and
+    /// auto-sweep, so decayed waves come back until swept.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_limit(bytes, DEFAULT_MAX_SNAPSHOT_SLOTS)
+    }
+
+    /// `from_bytes` for grids of up to `max_slots` slots
+    ///
+    /// The header's dimensions and every cell are checked before the dense
+    /// grid is allocated, so a few bytes claiming a 256×256×65536 grid are
+    /// an error rather than a 32 GiB allocation.
+    pub fn from_bytes_with_limit(bytes: &[u8], max_slots: usize) -> Result<Self> {
+        let snapshot: GridSnapshot =
+            bincode::deserialize(bytes).context("Failed to decode grid snapshot")?;
+
+        if snapshot.version != GRID_SNAPSHOT_VERSION {
+            anyhow::bail!("unsupported grid snapshot version {}", snapshot.version);
+        }
+        let (width, height, depth) = (
+            snapshot.width as usize,
+            snapshot.height as usize,
+            snapshot.depth as usize,
+        );
+        let slots = width.saturating_mul(height).saturating_mul(depth);
+        if slots > max_slots {
+            anyhow::bail!(
+                "grid snapshot of {}×{}×{} exceeds the {}-slot limit",
+                width,
+                height,
+                depth,
+                max_slots
+            );
+        }
+        if snapshot.cells.len() > slots {
+            anyhow::bail!(
+                "grid snapshot holds {} cells for {} slots",
+                snapshot.cells.len(),
+                slots
+            );
+        }
+        let indices = snapshot
+            .cells
+            .iter()
+            .map(|&(x, y, z, _)| {
+                linear_index(width, height, depth, x, y, z)
+                    .with_context(|| format!("cell ({x}, {y}, {z}) outside the grid"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut grid = Self::with_dimensions(width, height, depth)?;
+        grid.noise_floor = snapshot.noise_floor;
+        grid.auto_sweep_threshold = snapshot.auto_sweep_threshold;
+
+        for (idx, (_, _, _, wave)) in indices.into_iter().zip(snapshot.cells) {
+            if grid.grid[idx].replace(Arc::new(wave)).is_none() {
+                grid.occupied += 1;
+            }
+        }
+
+        Ok(grid)
+    }
+
+    /// Number of occupied slots, decayed or not
+    pub fn occupied_count(&self) -> usize {
+        self.occupied
+    }
+
+    /// Occupied against total cells, decayed waves included
+    pub fn compaction_stats(&self) -> CompactionStats {
+        CompactionStats {
+            occupied: self.occupied,
+            total: self.grid.len(),
+            ratio: self.occupied as f32 / self.grid.len() as f32,
+        }
+    }
+
+    /// Sweep waves decayed below `threshold`, then report what is left
+    ///
+    /// Empty cells cost nothing in a snapshot already, so this is the pass
+    /// to run before `to_bytes`.
+    pub fn compact(&mut self, threshold: f32) -> CompactionStats {
+        self.sweep_decayed(threshold);
+        self.compaction_stats()
     }
 
     /// Retrieve a memory wave at specific coordinates
     pub fn get(&self, x: u8, y: u8, z: u16) -> Option<&Arc<MemoryWave>> {
-        // Clamp coordinates to grid dimensions
-        let x = (x as usize % self.width) as u8;
-        let y = (y as usize % self.height) as u8;
-        let z = (z as usize % self.depth) as u16;
-
-        let idx = self.get_index(x, y, z);
+        let idx = self.get_index(x, y, z)?;
         self.grid[idx].as_ref()
     }
 
     /// Calculate interference pattern at a specific point
+    ///
+    /// Sums the 3×3×3 neighborhood weighted by distance. Neighbors that fall
+    /// outside the grid are skipped, so edge cells only see in-bounds waves.
     pub fn calculate_interference(&self, x: u8, y: u8, z: u16, t: f32) -> f32 {
         let mut total = 0.0;
 
         // Check 3x3x3 neighborhood for interference
-        for dx in -1i8..=1 {
-            for dy in -1i8..=1 {
-                for dz in -1i16..=1 {
-                    let nx = (x as i16 + dx as i16).clamp(0, 255) as u8;
-                    let ny = (y as i16 + dy as i16).clamp(0, 255) as u8;
-                    let nz = (z as i32 + dz as i32).clamp(0, 65535) as u16;
-
-                    if let Some(wave) = self.get(nx, ny, nz) {
+        for dx in -1i32..=1 {
+            for dy in -1i32..=1 {
+                for dz in -1i32..=1 {
+                    let nx = x as i32 + dx;
+                    let ny = y as i32 + dy;
+                    let nz = z as i32 + dz;
+
+                    if nx < 0
+                        || ny < 0
+                        || nz < 0
+                        || nx >= self.width as i32
+                        || ny >= self.height as i32
+                        || nz >= self.depth as i32
+                    {
+                        continue;
+                    }
+
+                    if let Some(wave) = self.get(nx as u8, ny as u8, nz as u16) {
                         // Weight by distance (closer neighbors have more influence)
-                        let distance = ((dx * dx + dy * dy) as f32 + (dz * dz) as f32).sqrt();
+                        let distance = ((dx * dx + dy * dy + dz * dz) as f32).sqrt();
                         let weight = 1.0 / (1.0 + distance);
                         total += wave.calculate(t) * weight;
                     }
@@ -197,8 +591,7 @@ impl WaveGrid {
 
     /// Adaptive noise floor adjustment based on environmental conditions
     pub fn adjust_noise_floor(&mut self, environmental_noise: f32) {
-        // Adapt noise floor to environmental conditions
-        self.noise_floor = (self.noise_floor * 0.9 + environmental_noise * 0.1).clamp(0.01, 0.5);
+        self.noise_floor = adapt_noise_floor(self.noise_floor, environmental_noise);
     }
 
     /// Count active (non-decayed) memories
@@ -206,13 +599,80 @@ impl WaveGrid {
         self.grid
             .iter()
             .filter_map(|slot| slot.as_ref())
-            .filter(|wave| wave.calculate_decay() > 0.01)
+            .filter(|wave| wave.calculate_decay() > ACTIVE_DECAY_THRESHOLD)
             .count()
     }
+
+    /// Count stored waves per frequency band, see `band_histogram`
+    pub fn band_histogram(&self) -> HashMap<FrequencyBand, usize> {
+        band_histogram(self.grid.iter().filter_map(|slot| slot.as_deref()))
+    }
+}
+
+/// Count waves per frequency band
+///
+/// Every band is present, zero if empty. Each wave counts once under its
+/// semantic band (`from_frequency`) and, if it has one, once more under its
+/// brainwave band (`cognitive_band_from_frequency`), so the counts can sum
+/// to more than the number of waves.
+pub fn band_histogram<'a>(
+    waves: impl IntoIterator<Item = &'a MemoryWave>,
+) -> HashMap<FrequencyBand, usize> {
+    let mut histogram: HashMap<FrequencyBand, usize> =
+        FrequencyBand::ALL.iter().map(|&band| (band, 0)).collect();
+
+    for wave in waves {
+        *histogram
+            .entry(FrequencyBand::from_frequency(wave.frequency))
+            .or_default() += 1;
+
+        if let Some(band) = FrequencyBand::cognitive_band_from_frequency(wave.frequency) {
+            *histogram.entry(band).or_default() += 1;
+        }
+    }
+
+    histogram
+}
+
+/// Noise floor of a fresh grid
+pub const DEFAULT_NOISE_FLOOR: f32 = 0.1;
+
+/// One adaptation step of a noise floor towards `environmental_noise`
+///
+/// Exponential moving average with weight 0.1 on the new reading, clamped
+/// to 0.01..=0.5.
+pub fn adapt_noise_floor(noise_floor: f32, environmental_noise: f32) -> f32 {
+    (noise_floor * 0.9 + environmental_noise * 0.1).clamp(0.01, 0.5)
+}
+
+/// Phase-locking value between two wave sets, in 0..=1
+///
+/// Waves are paired by index (the longer set is truncated) and the result is
+///
+/// ```text
+/// PLV = | (1/N) · Σ exp(i·(φa_k − φb_k)) |
+///     = sqrt(mean(cos Δφ)² + mean(sin Δφ)²)
+/// ```
+///
+/// A constant phase offset across all pairs gives 1.0; unrelated phases
+/// average towards 0.0. Empty input returns 0.0.
+pub fn phase_coherence(a: &[MemoryWave], b: &[MemoryWave]) -> f32 {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+
+    let (sum_cos, sum_sin) = a.iter().zip(b).fold((0.0f64, 0.0f64), |(c, s), (wa, wb)| {
+        let delta = (wa.phase - wb.phase) as f64;
+        (c + delta.cos(), s + delta.sin())
+    });
+
+    let (mean_cos, mean_sin) = (sum_cos / n as f64, sum_sin / n as f64);
+    (mean_cos.hypot(mean_sin) as f32).min(1.0)
 }
 
 /// Frequency bands for different content types
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum FrequencyBand {
     DeepStructural, // 0-200Hz
     Conversational, // 200-400Hz
@@ -225,6 +685,17 @@ pub enum FrequencyBand {
 }
 
 impl FrequencyBand {
+    /// Every band, semantic bands first
+    pub const ALL: [FrequencyBand; 7] = [
+        Self::DeepStructural,
+        Self::Conversational,
+        Self::Technical,
+        Self::Implementation,
+        Self::Abstract,
+        Self::Beta,
+        Self::Gamma,
+    ];
+
     /// Get the frequency range for this band
     pub fn range(&self) -> (f32, f32) {
         match self {
@@ -251,7 +722,11 @@ impl FrequencyBand {
         min + (max - min) * position.clamp(0.0, 1.0)
     }
 
-    /// Determine band from frequency
+    /// Determine the semantic (content-type) band of a frequency
+    ///
+    /// Only ever returns one of the five semantic bands; Beta and Gamma
+    /// overlap DeepStructural and belong to the separate brainwave
+    /// interpretation, see `cognitive_band_from_frequency`.
     pub fn from_frequency(freq: f32) -> Self {
         match freq {
             f if f < 200.0 => Self::DeepStructural,
@@ -261,4 +736,443 @@ impl FrequencyBand {
             _ => Self::Abstract,
         }
     }
+
+    /// Determine the brainwave (cognitive-state) band of a frequency
+    ///
+    /// Beta is 13–30Hz and Gamma 30–100Hz, lower bound inclusive. Anything
+    /// outside those ranges has no cognitive band.
+    pub fn cognitive_band_from_frequency(freq: f32) -> Option<Self> {
+        [Self::Beta, Self::Gamma].into_iter().find(|band| {
+            let (min, max) = band.range();
+            (min..max).contains(&freq)
+        })
+    }
+
+    /// Whether `freq` belongs to this band under the band's own interpretation
+    ///
+    /// Semantic bands use `from_frequency`, brainwave bands
+    /// `cognitive_band_from_frequency`.
+    pub fn contains(&self, freq: f32) -> bool {
+        match self {
+            Self::Beta | Self::Gamma => Self::cognitive_band_from_frequency(freq) == Some(*self),
+            _ => Self::from_frequency(freq) == *self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_get_at_grid_edges() {
+        let mut grid = WaveGrid::new();
+        let (max_x, max_y, max_z) = (
+            (grid.width - 1) as u8,
+            (grid.height - 1) as u8,
+            (grid.depth - 1) as u16,
+        );
+
+        assert!(grid.store(0, 0, 0, MemoryWave::new(100.0, 0.5)));
+        assert!(grid.store(max_x, max_y, max_z, MemoryWave::new(900.0, 0.7)));
+
+        assert_eq!(grid.get(0, 0, 0).unwrap().frequency, 100.0);
+        assert_eq!(grid.get(max_x, max_y, max_z).unwrap().frequency, 900.0);
+        assert!(grid.get(max_x, 0, 0).is_none());
+    }
+
+    #[test]
+    fn test_corner_interference_uses_in_bounds_neighbors() {
+        let mut grid = WaveGrid::new();
+        let max_x = (grid.width - 1) as u8;
+        let max_z = (grid.depth - 1) as u16;
+
+        let mut center = MemoryWave::new(1.0, 1.0);
+        center.decay_tau = None;
+        let mut neighbor = MemoryWave::new(1.0, 1.0);
+        neighbor.decay_tau = None;
+        let mut far_edge = MemoryWave::new(1.0, 1.0);
+        far_edge.decay_tau = None;
+
+        grid.store(0, 0, 0, center);
+        grid.store(1, 0, 0, neighbor);
+        // Would contribute if neighbors wrapped around the grid
+        grid.store(max_x, 0, 0, far_edge.clone());
+        grid.store(0, 0, max_z, far_edge);
+
+        let t = 0.25; // sin(2π · 1Hz · 0.25s) = 1
+        let expected = 1.0 + 1.0 / 2.0;
+        let total = grid.calculate_interference(0, 0, 0, t);
+
+        assert!((total - expected).abs() < 1e-5, "got {total}");
+    }
+
+    #[test]
+    fn test_out_of_range_coordinates_do_not_wrap() {
+        let mut grid = WaveGrid::new();
+        let width = grid.width as u8;
+
+        assert!(grid.store(0, 0, 0, MemoryWave::new(100.0, 0.5)));
+        // x == width used to wrap to x == 0
+        assert!(!grid.store(width, 0, 0, MemoryWave::new(900.0, 0.5)));
+        assert!(grid.get(width, 0, 0).is_none());
+        assert_eq!(grid.get(0, 0, 0).unwrap().frequency, 100.0);
+        assert!(grid.get(0, 0, grid.depth as u16).is_none());
+    }
+
+    #[test]
+    fn test_with_dimensions_small_grid() {
+        let mut grid = WaveGrid::with_dimensions(4, 4, 4).unwrap();
+        assert_eq!((grid.width, grid.height, grid.depth), (4, 4, 4));
+        assert_eq!(grid.grid.len(), 64);
+
+        assert_eq!(grid.get_index(0, 0, 0), Some(0));
+        assert_eq!(grid.get_index(3, 0, 0), Some(3));
+        assert_eq!(grid.get_index(0, 1, 0), Some(4));
+        assert_eq!(grid.get_index(0, 0, 1), Some(16));
+        assert_eq!(grid.get_index(3, 3, 3), Some(63));
+        assert_eq!(grid.get_index(4, 0, 0), None);
+        assert_eq!(grid.get_index(0, 0, 4), None);
+
+        assert!(grid.store(3, 2, 1, MemoryWave::new(440.0, 0.8)));
+        assert!(grid.get(3, 2, 1).is_some());
+        assert!(!grid.store(0, 4, 0, MemoryWave::new(440.0, 0.8)));
+    }
+
+    #[test]
+    fn test_with_dimensions_limits() {
+        for (w, h, d) in [
+            (0, 4, 4),
+            (4, 0, 4),
+            (4, 4, 0),
+            (257, 4, 4),
+            (4, 257, 4),
+            (4, 4, 65537),
+        ] {
+            assert!(WaveGrid::with_dimensions(w, h, d).is_err(), "{w}×{h}×{d}");
+        }
+
+        // The full 256×256×65536 grid is too large to allocate here, so check
+        // its indexing directly: every coordinate is addressable and the last
+        // one maps to the last slot
+        let (w, h, d) = (MAX_GRID_WIDTH, MAX_GRID_HEIGHT, MAX_GRID_DEPTH);
+        assert_eq!(linear_index(w, h, d, 0, 0, 0), Some(0));
+        assert_eq!(linear_index(w, h, d, 255, 0, 0), Some(255));
+        assert_eq!(linear_index(w, h, d, 0, 255, 0), Some(255 * 256));
+        assert_eq!(linear_index(w, h, d, 0, 0, 1), Some(256 * 256));
+        assert_eq!(
+            linear_index(w, h, d, 255, 255, u16::MAX),
+            Some(w * h * d - 1)
+        );
+
+        let thin = WaveGrid::with_dimensions(MAX_GRID_WIDTH, MAX_GRID_HEIGHT, 1).unwrap();
+        assert_eq!(thin.get_index(255, 255, 0), Some(256 * 256 - 1));
+        assert_eq!(thin.get_index(255, 255, 1), None);
+    }
+
+    fn aged_wave(age: Duration) -> MemoryWave {
+        let mut wave = MemoryWave::new(200.0, 0.8);
+        wave.created_at = Instant::now().checked_sub(age).unwrap();
+        wave
+    }
+
+    #[test]
+    fn test_sweep_decayed_evicts_dead_waves() {
+        let mut grid = WaveGrid::new();
+
+        // Default tau is 5s: a minute-old wave has decayed to ~6e-6
+        grid.store(0, 0, 0, aged_wave(Duration::from_secs(60)));
+        grid.store(1, 0, 0, aged_wave(Duration::from_secs(60)));
+        grid.store(2, 0, 0, MemoryWave::new(200.0, 0.8));
+        let mut eternal = aged_wave(Duration::from_secs(60));
+        eternal.decay_tau = None;
+        grid.store(3, 0, 0, eternal);
+        assert_eq!(grid.occupied_count(), 4);
+
+        assert_eq!(grid.sweep_decayed(ACTIVE_DECAY_THRESHOLD), 2);
+        assert!(grid.get(0, 0, 0).is_none());
+        assert!(grid.get(1, 0, 0).is_none());
+        assert!(grid.get(2, 0, 0).is_some());
+        assert!(grid.get(3, 0, 0).is_some());
+        assert_eq!(grid.occupied_count(), 2);
+        assert_eq!(grid.sweep_decayed(ACTIVE_DECAY_THRESHOLD), 0);
+    }
+
+    #[test]
+    fn test_compaction_stats_on_sparse_grid() {
+        let mut grid = WaveGrid::with_dimensions(8, 8, 4).unwrap();
+        assert_eq!(
+            grid.compaction_stats(),
+            CompactionStats {
+                occupied: 0,
+                total: 256,
+                ratio: 0.0
+            }
+        );
+
+        for x in 0..6 {
+            grid.store(x, x, 0, MemoryWave::new(200.0, 0.8));
+        }
+        grid.store(7, 7, 3, aged_wave(Duration::from_secs(60)));
+        grid.store(6, 6, 3, aged_wave(Duration::from_secs(60)));
+        let stats = grid.compaction_stats();
+        assert_eq!((stats.occupied, stats.total), (8, 256));
+        assert_eq!(stats.ratio, 8.0 / 256.0);
+
+        let compacted = grid.compact(ACTIVE_DECAY_THRESHOLD);
+        assert_eq!(compacted.occupied, 6);
+        assert_eq!(compacted.ratio, 6.0 / 256.0);
+        assert_eq!(grid.occupied_count(), 6);
+    }
+
+    #[test]
+    fn test_labeled_waves_are_queryable_by_label() {
+        let mut grid = WaveGrid::new();
+        assert!(grid.store_labeled(1, 2, 3, MemoryWave::new(450.0, 0.9), "rust"));
+        assert!(grid.store_labeled(4, 5, 6, MemoryWave::new(120.0, 0.9), "elixir"));
+        assert!(grid.store_labeled(7, 8, 9, MemoryWave::new(480.0, 0.9), "rust"));
+        grid.store(0, 0, 0, MemoryWave::new(300.0, 0.9));
+
+        let rust: Vec<f32> = grid
+            .get_by_label("rust")
+            .iter()
+            .map(|wave| wave.frequency)
+            .collect();
+        assert_eq!(rust, vec![450.0, 480.0]);
+        assert!(grid.get_by_label("cobol").is_empty());
+
+        // Overwriting a labeled cell moves it to the new label, or none
+        grid.store_labeled(7, 8, 9, MemoryWave::new(130.0, 0.9), "elixir");
+        grid.store(1, 2, 3, MemoryWave::new(200.0, 0.9));
+        assert!(grid.get_by_label("rust").is_empty());
+        assert_eq!(grid.get_by_label("elixir").len(), 2);
+
+        // Swept waves leave the index too
+        grid.store_labeled(10, 10, 10, aged_wave(Duration::from_secs(60)), "stale");
+        grid.sweep_decayed(ACTIVE_DECAY_THRESHOLD);
+        let mut labels: Vec<&str> = grid.labels().collect();
+        labels.sort();
+        assert_eq!(labels, vec!["elixir"]);
+    }
+
+    #[test]
+    fn test_store_sweeps_when_near_full() {
+        let mut grid = WaveGrid::with_dimensions(2, 2, 2).unwrap();
+
+        for z in 0..2 {
+            for y in 0..2 {
+                for x in 0..2 {
+                    grid.store(x, y, z, aged_wave(Duration::from_secs(60)));
+                }
+            }
+        }
+        assert_eq!(grid.occupied_count(), 8);
+
+        assert!(grid.store(0, 0, 0, MemoryWave::new(200.0, 0.8)));
+        assert_eq!(grid.occupied_count(), 1);
+        assert!(grid.get(1, 1, 1).is_none());
+    }
+
+    #[test]
+    fn test_fruitless_auto_sweep_backs_off() {
+        // 4096 slots: a sweep that frees nothing waits 64 stores
+        let mut grid = WaveGrid::with_dimensions(64, 64, 1).unwrap();
+        let live = || {
+            let mut wave = MemoryWave::new(200.0, 0.8);
+            wave.decay_tau = None;
+            wave
+        };
+        let slots: Vec<(u8, u8)> = (0..64u8)
+            .flat_map(|y| (0..64u8).map(move |x| (x, y)))
+            .collect();
+        let full = (4096.0 * AUTO_SWEEP_OCCUPANCY).ceil() as usize;
+        for &(x, y) in &slots[..full] {
+            grid.store(x, y, 0, live());
+        }
+
+        // Sweeps, finds nothing to evict and starts backing off
+        grid.store(0, 0, 0, live());
+        let (x, y) = slots[full];
+        grid.store(x, y, 0, aged_wave(Duration::from_secs(60)));
+        for _ in 1..4096 / AUTO_SWEEP_BACKOFF {
+            grid.store(0, 0, 0, live());
+        }
+        assert!(grid.get(x, y, 0).is_some());
+
+        grid.store(0, 0, 0, live());
+        assert!(grid.get(x, y, 0).is_none());
+    }
+
+    #[test]
+    fn test_calculate_decay_at_is_deterministic() {
+        let wave = MemoryWave::new(200.0, 0.8);
+        let tau = wave.decay_tau.unwrap();
+
+        assert_eq!(wave.calculate_decay_at(wave.created_at), 1.0);
+        let one_tau = wave.calculate_decay_at(wave.created_at + tau);
+        assert!((one_tau - (-1.0f32).exp()).abs() < 1e-6);
+        let before = wave.calculate_decay_at(wave.created_at.checked_sub(tau).unwrap());
+        assert_eq!(before, 1.0);
+    }
+
+    #[test]
+    fn test_reloaded_wave_keeps_its_age() {
+        // Two time constants old: decay ≈ e^-2
+        let wave = aged_wave(Duration::from_secs(10));
+        let json = serde_json::to_string(&wave).unwrap();
+        let reloaded: MemoryWave = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(reloaded.decay_tau, wave.decay_tau);
+        let expected = (-2.0f32).exp();
+        assert!((reloaded.calculate_decay() - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut grid = WaveGrid::new();
+        grid.noise_floor = 0.2;
+
+        let mut eternal = MemoryWave::new(440.0, 0.9);
+        eternal.decay_tau = None;
+        eternal.phase = 1.25;
+        eternal.valence = -0.5;
+        eternal.arousal = 0.75;
+        grid.store(0, 0, 0, eternal);
+        grid.store(5, 17, 3, aged_wave(Duration::from_secs(10)));
+        grid.store(63, 63, 255, MemoryWave::new(900.0, 0.3));
+
+        let bytes = grid.to_bytes().unwrap();
+        let restored = WaveGrid::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            (restored.width, restored.height, restored.depth),
+            (64, 64, 256)
+        );
+        assert_eq!(restored.noise_floor, 0.2);
+        assert_eq!(restored.occupied_count(), 3);
+
+        let wave = restored.get(0, 0, 0).unwrap();
+        assert_eq!(wave.frequency, 440.0);
+        assert_eq!(wave.phase, 1.25);
+        assert_eq!(wave.valence, -0.5);
+        assert_eq!(wave.arousal, 0.75);
+        assert!(wave.decay_tau.is_none());
+
+        let aged = restored.get(5, 17, 3).unwrap();
+        assert!((aged.calculate_decay() - (-2.0f32).exp()).abs() < 0.01);
+        assert_eq!(restored.get(63, 63, 255).unwrap().frequency, 900.0);
+
+        // Sparse: far smaller than one byte per dense cell
+        assert!(bytes.len() < 1024, "{} bytes", bytes.len());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_bad_input() {
+        let mut bytes = WaveGrid::new().to_bytes().unwrap();
+        bytes[0] = 9; // version
+        assert!(WaveGrid::from_bytes(&bytes).is_err());
+        assert!(WaveGrid::from_bytes(&[1, 2, 3]).is_err());
+
+        // Header patched to the largest grid: refused before allocating
+        let mut bytes = WaveGrid::with_dimensions(2, 2, 2)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        bytes[2..6].copy_from_slice(&(MAX_GRID_WIDTH as u32).to_le_bytes());
+        bytes[6..10].copy_from_slice(&(MAX_GRID_HEIGHT as u32).to_le_bytes());
+        bytes[10..14].copy_from_slice(&(MAX_GRID_DEPTH as u32).to_le_bytes());
+        let err = WaveGrid::from_bytes(&bytes).err().unwrap();
+        assert!(err.to_string().contains("slot limit"), "{err}");
+
+        // A cell outside the header's grid is caught before allocating too
+        let mut grid = WaveGrid::with_dimensions(4, 4, 4).unwrap();
+        grid.store(3, 3, 3, MemoryWave::new(200.0, 0.8));
+        let mut bytes = grid.to_bytes().unwrap();
+        bytes[10..14].copy_from_slice(&2u32.to_le_bytes());
+        assert!(WaveGrid::from_bytes(&bytes).is_err());
+        assert!(WaveGrid::from_bytes_with_limit(&grid.to_bytes().unwrap(), 63).is_err());
+        assert_eq!(
+            WaveGrid::from_bytes_with_limit(&grid.to_bytes().unwrap(), 64)
+                .unwrap()
+                .occupied_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_band_histogram() {
+        let mut grid = WaveGrid::with_dimensions(8, 8, 1).unwrap();
+        for (x, frequency) in [20.0, 50.0, 150.0, 250.0, 450.0, 460.0, 999.0]
+            .into_iter()
+            .enumerate()
+        {
+            grid.store(x as u8, 0, 0, MemoryWave::new(frequency, 0.5));
+        }
+
+        let histogram = grid.band_histogram();
+        assert_eq!(histogram.len(), FrequencyBand::ALL.len());
+        assert_eq!(histogram[&FrequencyBand::DeepStructural], 3);
+        assert_eq!(histogram[&FrequencyBand::Beta], 1);
+        assert_eq!(histogram[&FrequencyBand::Gamma], 1);
+        assert_eq!(histogram[&FrequencyBand::Conversational], 1);
+        assert_eq!(histogram[&FrequencyBand::Technical], 2);
+        assert_eq!(histogram[&FrequencyBand::Implementation], 0);
+        assert_eq!(histogram[&FrequencyBand::Abstract], 1);
+    }
+
+    #[test]
+    fn test_cognitive_band_from_frequency() {
+        assert_eq!(
+            FrequencyBand::cognitive_band_from_frequency(20.0),
+            Some(FrequencyBand::Beta)
+        );
+        assert_eq!(
+            FrequencyBand::cognitive_band_from_frequency(50.0),
+            Some(FrequencyBand::Gamma)
+        );
+        assert_eq!(
+            FrequencyBand::cognitive_band_from_frequency(30.0),
+            Some(FrequencyBand::Gamma)
+        );
+        assert_eq!(FrequencyBand::cognitive_band_from_frequency(8.0), None);
+        assert_eq!(FrequencyBand::cognitive_band_from_frequency(100.0), None);
+
+        // The semantic interpretation is unchanged
+        assert_eq!(
+            FrequencyBand::from_frequency(20.0),
+            FrequencyBand::DeepStructural
+        );
+        assert!(FrequencyBand::Beta.contains(20.0));
+        assert!(FrequencyBand::DeepStructural.contains(20.0));
+        assert!(!FrequencyBand::Gamma.contains(20.0));
+    }
+
+    #[test]
+    fn test_phase_coherence() {
+        let with_phase = |phase: f32| {
+            let mut wave = MemoryWave::new(100.0, 1.0);
+            wave.phase = phase;
+            wave
+        };
+
+        let a: Vec<MemoryWave> = (0..50).map(|i| with_phase(i as f32 * 0.3)).collect();
+        assert!((phase_coherence(&a, &a) - 1.0).abs() < 1e-6);
+
+        // A constant offset is still perfectly locked
+        let shifted: Vec<MemoryWave> = a.iter().map(|w| with_phase(w.phase + 1.2)).collect();
+        assert!((phase_coherence(&a, &shifted) - 1.0).abs() < 1e-5);
+
+        // Uniform pseudo-random phases (LCG) average out
+        let mut state = 0x2545_f491u32;
+        let mut random_phase = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 * PI
+        };
+        let x: Vec<MemoryWave> = (0..10_000).map(|_| with_phase(random_phase())).collect();
+        let y: Vec<MemoryWave> = (0..10_000).map(|_| with_phase(random_phase())).collect();
+        assert!(phase_coherence(&x, &y) < 0.05);
+
+        assert_eq!(phase_coherence(&[], &a), 0.0);
+        assert_eq!(phase_coherence(&a, &[]), 0.0);
+    }
 }
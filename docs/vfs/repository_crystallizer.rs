@@ -3,7 +3,7 @@
 #![allow(dead_code)] // Many constants and fields are reserved for future use
 
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Write;
 use std::path::Path;
@@ -14,6 +14,9 @@ const TOKEN_RESERVED_START: u16 = 0x0000;
 const TOKEN_RESERVED_END: u16 = 0x00FF;
 const TOKEN_USER_START: u16 = 0x0100;
 
+// Names seen at least this often get a dynamic token
+const DYNAMIC_TOKEN_MIN_COUNT: u64 = 3;
+
 // Pre-defined tokens for common filesystem terms
 const TOKEN_DIR: u16 = 0x0001;
 const TOKEN_FILE: u16 = 0x0002;
@@ -50,6 +53,17 @@ const TOKEN_SIZE_SMALL: u16 = 0x00A2; // 1KB-100KB
 const TOKEN_SIZE_MEDIUM: u16 = 0x00A3; // 100KB-10MB
 const TOKEN_SIZE_LARGE: u16 = 0x00A4; // 10MB+
 
+/// Directories skipped when exclusions are enabled
+const DEFAULT_EXCLUDED_DIRS: &[&str] = &[".git", "node_modules", "target", "dist", "build"];
+
+// Size encoding tags: one tag byte, then a little-endian payload of the
+// tagged width. 0xF0-0xF4 is clear of every token low byte above.
+const SIZE_TAG_ZERO: u8 = 0xF0; // no payload
+const SIZE_TAG_U8: u8 = 0xF1;
+const SIZE_TAG_U16: u8 = 0xF2;
+const SIZE_TAG_U32: u8 = 0xF3;
+const SIZE_TAG_U64: u8 = 0xF4;
+
 // ASCII control codes for tree traversal
 const TRAVERSE_SAME: u8 = 0x0B; // Vertical Tab
 const TRAVERSE_DEEPER: u8 = 0x0E; // Shift Out
@@ -69,24 +83,37 @@ const HDR_TOKENIZED: u8 = 0b10000000;
 pub struct CodeRepoScanner<W: Write> {
     writer: W,
     token_map: HashMap<String, u16>,
-    #[allow(dead_code)]
     next_dynamic_token: u16,
 
     // Context for delta encoding
     parent_perms: u32,
-    #[allow(dead_code)]
     parent_uid: u32,
-    #[allow(dead_code)]
     parent_gid: u32,
-    #[allow(dead_code)]
     parent_time: SystemTime,
 
+    // Directories already descended into, to break cycles
+    visited_dirs: HashSet<DirKey>,
+
+    // Directory names pruned before descending (empty = scan everything)
+    excluded_dirs: HashSet<String>,
+
     // Stats tracking
     total_files: u64,
     total_dirs: u64,
+    total_links: u64,
+    total_excluded: u64,
     total_size: u64,
+    names_emitted: u64,
+    names_tokenized: u64,
 }
 
+/// Identity of a directory for cycle detection
+#[cfg(unix)]
+type DirKey = (u64, u64); // (device, inode)
+
+#[cfg(not(unix))]
+type DirKey = std::path::PathBuf; // canonical path
+
 impl<W: Write> CodeRepoScanner<W> {
     // Cross-platform permission handling
     #[cfg(unix)]
@@ -100,7 +127,37 @@ impl<W: Write> CodeRepoScanner<W> {
         0o755 // Default permissions for non-Unix
     }
 
+    #[cfg(unix)]
+    fn get_owner(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+        use std::os::unix::fs::MetadataExt;
+        Some((metadata.uid(), metadata.gid()))
+    }
+
+    #[cfg(not(unix))]
+    fn get_owner(_metadata: &fs::Metadata) -> Option<(u32, u32)> {
+        None // No uid/gid on non-Unix
+    }
+
+    #[cfg(unix)]
+    fn dir_key(_path: &Path, metadata: &fs::Metadata) -> Result<DirKey> {
+        use std::os::unix::fs::MetadataExt;
+        Ok((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn dir_key(path: &Path, _metadata: &fs::Metadata) -> Result<DirKey> {
+        Ok(fs::canonicalize(path)?)
+    }
+
     pub fn new(writer: W) -> Self {
+        Self::with_exclusions(writer, false)
+    }
+
+    /// Create a scanner, optionally pruning `DEFAULT_EXCLUDED_DIRS`
+    ///
+    /// Excluded directories are left out of the output entirely and counted
+    /// in the summary's `EXCLUDED` line.
+    pub fn with_exclusions(writer: W, exclude_defaults: bool) -> Self {
         let mut token_map = HashMap::new();
 
         // Initialize with predefined tokens
@@ -131,12 +188,39 @@ impl<W: Write> CodeRepoScanner<W> {
             parent_uid: 1000,
             parent_gid: 1000,
             parent_time: SystemTime::UNIX_EPOCH,
+            visited_dirs: HashSet::new(),
+            excluded_dirs: if exclude_defaults {
+                DEFAULT_EXCLUDED_DIRS
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect()
+            } else {
+                HashSet::new()
+            },
             total_files: 0,
             total_dirs: 0,
+            total_links: 0,
+            total_excluded: 0,
             total_size: 0,
+            names_emitted: 0,
+            names_tokenized: 0,
         }
     }
 
+    /// Also skip directories with this name
+    pub fn exclude_dir(&mut self, name: impl Into<String>) {
+        self.excluded_dirs.insert(name.into());
+    }
+
+    fn is_excluded(&self, entry: &fs::DirEntry) -> bool {
+        !self.excluded_dirs.is_empty()
+            && entry.file_type().is_ok_and(|kind| kind.is_dir())
+            && entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| self.excluded_dirs.contains(name))
+    }
+
     /// Write the format header
     pub fn write_header(&mut self) -> Result<()> {
         writeln!(self.writer, "CODEREPO_NATIVE_V1:")?;
@@ -156,25 +240,121 @@ impl<W: Write> CodeRepoScanner<W> {
 
     /// Scan a path and emit format directly
     pub fn scan(&mut self, path: &Path) -> Result<()> {
+        self.assign_dynamic_tokens(path)?;
         self.write_header()?;
         self.scan_recursive(path, 0)?;
         self.write_summary()?;
         Ok(())
     }
 
+    /// First pass: give frequently seen names tokens from `TOKEN_USER_START`
+    ///
+    /// Names longer than a token that occur at least `DYNAMIC_TOKEN_MIN_COUNT`
+    /// times are assigned in descending frequency (ties by name) so the
+    /// header map is deterministic.
+    fn assign_dynamic_tokens(&mut self, path: &Path) -> Result<()> {
+        let mut counts = HashMap::new();
+        let mut visited = HashSet::new();
+        self.count_names(path, 0, &mut counts, &mut visited)?;
+
+        let mut frequent: Vec<(String, u64)> = counts
+            .into_iter()
+            .filter(|(name, count)| {
+                *count >= DYNAMIC_TOKEN_MIN_COUNT
+                    && name.len() > 2
+                    && !self.token_map.contains_key(name)
+            })
+            .collect();
+        frequent.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        for (name, _) in frequent {
+            let token = self.next_dynamic_token;
+            if token == u16::MAX {
+                break;
+            }
+            self.token_map.insert(name, token);
+            self.next_dynamic_token += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Count entry names along the same walk `scan_recursive` takes
+    fn count_names(
+        &self,
+        path: &Path,
+        depth: usize,
+        counts: &mut HashMap<String, u64>,
+        visited: &mut HashSet<DirKey>,
+    ) -> Result<()> {
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            *counts.entry(name.to_string()).or_insert(0) += 1;
+        }
+
+        let metadata = if depth == 0 {
+            fs::metadata(path)?
+        } else {
+            fs::symlink_metadata(path)?
+        };
+        if metadata.is_dir() && visited.insert(Self::dir_key(path, &metadata)?) {
+            for entry in fs::read_dir(path)?.filter_map(|e| e.ok()) {
+                if !self.is_excluded(&entry) {
+                    self.count_names(&entry.path(), depth + 1, counts, visited)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Symlinks are emitted as link entries and never followed, except for
+    /// the scan root itself. A directory reached a second time (e.g. via a
+    /// bind mount) is emitted as a link entry instead of being descended.
     fn scan_recursive(&mut self, path: &Path, depth: usize) -> Result<()> {
-        let metadata = fs::metadata(path)?;
+        let metadata = if depth == 0 {
+            fs::metadata(path)?
+        } else {
+            fs::symlink_metadata(path)?
+        };
+
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            self.emit_link(path, &target, 0)?;
+            self.total_links += 1;
+            return Ok(());
+        }
 
         // Emit entry
         if metadata.is_dir() {
+            if !self.visited_dirs.insert(Self::dir_key(path, &metadata)?) {
+                self.emit_link(path, path, HDR_IS_DIR)?;
+                self.total_links += 1;
+                return Ok(());
+            }
+
             self.emit_directory(path, &metadata, depth)?;
 
             // Update parent context
-            let old_perms = self.parent_perms;
+            let old_context = (
+                self.parent_perms,
+                self.parent_uid,
+                self.parent_gid,
+                self.parent_time,
+            );
             self.parent_perms = Self::get_permissions(&metadata);
+            if let Some((uid, gid)) = Self::get_owner(&metadata) {
+                self.parent_uid = uid;
+                self.parent_gid = gid;
+            }
+            if let Ok(mtime) = metadata.modified() {
+                self.parent_time = mtime;
+            }
 
-            // Scan children
+            // Scan children, pruning excluded directories
             let mut entries: Vec<_> = fs::read_dir(path)?.filter_map(|e| e.ok()).collect();
+            let before = entries.len();
+            entries.retain(|entry| !self.is_excluded(entry));
+            self.total_excluded += (before - entries.len()) as u64;
 
             // Sort for consistent output
             entries.sort_by_key(|e| e.file_name());
@@ -190,7 +370,12 @@ impl<W: Write> CodeRepoScanner<W> {
             }
 
             // Restore parent context
-            self.parent_perms = old_perms;
+            (
+                self.parent_perms,
+                self.parent_uid,
+                self.parent_gid,
+                self.parent_time,
+            ) = old_context;
 
             // Emit back traversal if not at root
             if depth > 0 {
@@ -224,6 +409,9 @@ impl<W: Write> CodeRepoScanner<W> {
             data.push(delta as u8);
         }
 
+        // Modification time and owner if different
+        self.encode_time_and_owner(metadata, &mut header, &mut data);
+
         // Emit header and data
         self.writer.write_all(&[header])?;
         self.writer.write_all(&data)?;
@@ -257,6 +445,9 @@ impl<W: Write> CodeRepoScanner<W> {
             data.push(delta as u8);
         }
 
+        // Modification time and owner if different
+        self.encode_time_and_owner(metadata, &mut header, &mut data);
+
         // Emit header and data
         self.writer.write_all(&[header])?;
         self.writer.write_all(&data)?;
@@ -267,12 +458,53 @@ impl<W: Write> CodeRepoScanner<W> {
         Ok(())
     }
 
+    /// Append mtime and owner fields, setting their header bits
+    ///
+    /// Time is the mtime in whole seconds relative to `parent_time`,
+    /// zigzag-encoded and written with `encode_size`. Owner is uid then gid
+    /// as u32 LE, only on Unix.
+    fn encode_time_and_owner(&self, metadata: &fs::Metadata, header: &mut u8, data: &mut Vec<u8>) {
+        if let Ok(mtime) = metadata.modified() {
+            let delta = unix_seconds(mtime) - unix_seconds(self.parent_time);
+            if delta != 0 {
+                *header |= HDR_HAS_TIME;
+                data.extend(&self.encode_size(zigzag(delta)));
+            }
+        }
+
+        if let Some((uid, gid)) = Self::get_owner(metadata) {
+            if (uid, gid) != (self.parent_uid, self.parent_gid) {
+                *header |= HDR_HAS_OWNER;
+                data.extend_from_slice(&uid.to_le_bytes());
+                data.extend_from_slice(&gid.to_le_bytes());
+            }
+        }
+    }
+
+    /// Emit a link entry: header, name, `TOKEN_LINK`, then the target path
+    /// as an `encode_size` byte length followed by its bytes
+    ///
+    /// The prefix keeps a target containing traversal codes from running
+    /// into the next entry; `decode_link_target` reads it back.
+    fn emit_link(&mut self, path: &Path, target: &Path, extra_flags: u8) -> Result<()> {
+        let target = target.to_string_lossy();
+        self.writer.write_all(&[HDR_IS_LINK | extra_flags])?;
+        self.emit_name(path)?;
+        self.writer.write_all(&TOKEN_LINK.to_le_bytes())?;
+        self.writer
+            .write_all(&self.encode_size(target.len() as u64))?;
+        self.writer.write_all(target.as_bytes())?;
+        Ok(())
+    }
+
     fn emit_name(&mut self, path: &Path) -> Result<()> {
         let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        self.names_emitted += 1;
 
-        // Check for exact token match
+        // Check for exact token match (predefined or dynamic)
         if let Some(&token) = self.token_map.get(name) {
             self.writer.write_all(&token.to_le_bytes())?;
+            self.names_tokenized += 1;
             return Ok(());
         }
 
@@ -283,55 +515,313 @@ impl<W: Write> CodeRepoScanner<W> {
                 // Write base name + extension token
                 self.writer.write_all(&name.as_bytes()[..dot_pos])?;
                 self.writer.write_all(&token.to_le_bytes())?;
+                self.names_tokenized += 1;
                 return Ok(());
             }
         }
 
-        // No token found - consider adding dynamically for frequently seen patterns
-        // For now, just write the raw name
+        // No token found - write the raw name
         self.writer.write_all(name.as_bytes())?;
         Ok(())
     }
 
+    /// Encode a size as a `SIZE_TAG_*` byte plus the narrowest payload
     fn encode_size(&self, size: u64) -> Vec<u8> {
-        // Size-based tokenization
+        let mut out = Vec::with_capacity(9);
         match size {
-            0 => vec![TOKEN_SIZE_ZERO as u8, (TOKEN_SIZE_ZERO >> 8) as u8],
-            1..=1024 => vec![
-                TOKEN_SIZE_TINY as u8,
-                (TOKEN_SIZE_TINY >> 8) as u8,
-                size as u8,
-            ],
-            1025..=102400 => {
-                let kb = (size / 1024) as u16;
-                vec![
-                    TOKEN_SIZE_SMALL as u8,
-                    (TOKEN_SIZE_SMALL >> 8) as u8,
-                    kb as u8,
-                    (kb >> 8) as u8,
-                ]
+            0 => out.push(SIZE_TAG_ZERO),
+            1..=0xFF => {
+                out.push(SIZE_TAG_U8);
+                out.push(size as u8);
+            }
+            0x100..=0xFFFF => {
+                out.push(SIZE_TAG_U16);
+                out.extend_from_slice(&(size as u16).to_le_bytes());
+            }
+            0x1_0000..=0xFFFF_FFFF => {
+                out.push(SIZE_TAG_U32);
+                out.extend_from_slice(&(size as u32).to_le_bytes());
             }
             _ => {
-                // For larger sizes, use standard encoding
-                match size {
-                    0..=255 => vec![0x00, size as u8],
-                    256..=65535 => {
-                        let bytes = (size as u16).to_le_bytes();
-                        vec![0x01, bytes[0], bytes[1]]
-                    }
-                    _ => {
-                        let bytes = (size as u32).to_le_bytes();
-                        vec![0x02, bytes[0], bytes[1], bytes[2], bytes[3]]
-                    }
-                }
+                out.push(SIZE_TAG_U64);
+                out.extend_from_slice(&size.to_le_bytes());
             }
         }
+        out
     }
 
     fn write_summary(&mut self) -> Result<()> {
         writeln!(self.writer, "\nSUMMARY:")?;
         writeln!(self.writer, "FILES: {}", self.total_files)?;
         writeln!(self.writer, "DIRS: {}", self.total_dirs)?;
+        writeln!(self.writer, "LINKS: {}", self.total_links)?;
+        if !self.excluded_dirs.is_empty() {
+            writeln!(self.writer, "EXCLUDED: {}", self.total_excluded)?;
+        }
         writeln!(self.writer, "SIZE: {}", self.total_size)?;
+
+        // Share of names written fully or partly as tokens
+        let ratio = if self.names_emitted > 0 {
+            self.names_tokenized as f64 / self.names_emitted as f64
+        } else {
+            0.0
+        };
+        writeln!(
+            self.writer,
+            "TOKENIZED: {}/{} ({:.1}%)",
+            self.names_tokenized,
+            self.names_emitted,
+            ratio * 100.0
+        )?;
         Ok(())
     }
+}
+
+/// Whole seconds since the Unix epoch (negative before it)
+fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
+
+/// Map a signed delta onto u64 so small magnitudes stay small
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Inverse of the zigzag mapping used for time deltas
+pub fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Decode a size written by `encode_size`
+///
+/// Returns the size and the number of bytes consumed, or `None` if the tag
+/// is unknown or the payload is truncated.
+pub fn decode_size(bytes: &[u8]) -> Option<(u64, usize)> {
+    let (&tag, payload) = bytes.split_first()?;
+    let width = match tag {
+        SIZE_TAG_ZERO => return Some((0, 1)),
+        SIZE_TAG_U8 => 1,
+        SIZE_TAG_U16 => 2,
+        SIZE_TAG_U32 => 4,
+        SIZE_TAG_U64 => 8,
+        _ => return None,
+    };
+
+    let payload = payload.get(..width)?;
+    let mut le = [0u8; 8];
+    le[..width].copy_from_slice(payload);
+    Some((u64::from_le_bytes(le), 1 + width))
+}
+
+/// Decode a link target written by `emit_link`, starting after `TOKEN_LINK`
+///
+/// Returns the target and the number of bytes consumed, or `None` if the
+/// length or the target is truncated or not UTF-8.
+pub fn decode_link_target(bytes: &[u8]) -> Option<(&str, usize)> {
+    let (len, prefix) = decode_size(bytes)?;
+    let end = prefix.checked_add(usize::try_from(len).ok()?)?;
+    let target = std::str::from_utf8(bytes.get(prefix..end)?).ok()?;
+    Some((target, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_repo(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("crystallizer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        root
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symlink_to_ancestor_is_not_followed() {
+        let root = temp_repo("symlink-loop");
+        std::os::unix::fs::symlink(&root, root.join("src/loop")).unwrap();
+
+        let mut scanner = CodeRepoScanner::new(Vec::new());
+        scanner.scan(&root).unwrap();
+
+        assert_eq!(scanner.total_links, 1);
+        assert_eq!(scanner.total_dirs, 2);
+        assert_eq!(scanner.total_files, 1);
+
+        let output = &scanner.writer;
+        let link_entry = [&[HDR_IS_LINK][..], b"loop", &TOKEN_LINK.to_le_bytes()].concat();
+        assert!(output
+            .windows(link_entry.len())
+            .any(|window| window == link_entry.as_slice()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_link_target_round_trip() {
+        let target = "../shared/\u{b}odd \u{f}name.md";
+        let mut scanner = CodeRepoScanner::new(Vec::new());
+        scanner
+            .emit_link(Path::new("repo/alias"), Path::new(target), 0)
+            .unwrap();
+        scanner.writer.push(TRAVERSE_SAME);
+
+        let prefix = [&[HDR_IS_LINK][..], b"alias", &TOKEN_LINK.to_le_bytes()].concat();
+        let output = &scanner.writer;
+        assert!(output.starts_with(&prefix));
+        let (decoded, consumed) = decode_link_target(&output[prefix.len()..]).unwrap();
+        assert_eq!(decoded, target);
+        assert_eq!(&output[prefix.len() + consumed..], &[TRAVERSE_SAME]);
+
+        // A length running past the buffer is rejected
+        assert_eq!(decode_link_target(&[SIZE_TAG_U8, 4, b'a']), None);
+    }
+
+    #[test]
+    fn test_default_exclusions_are_opt_in() {
+        let root = temp_repo("exclusions");
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "").unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        // Only directories are pruned
+        fs::write(root.join("build"), "").unwrap();
+
+        let mut scanner = CodeRepoScanner::new(Vec::new());
+        scanner.scan(&root).unwrap();
+        assert_eq!(scanner.total_excluded, 0);
+        assert_eq!(scanner.total_files, 3);
+
+        let mut scanner = CodeRepoScanner::with_exclusions(Vec::new(), true);
+        scanner.scan(&root).unwrap();
+        assert_eq!(scanner.total_excluded, 2);
+        assert_eq!(scanner.total_dirs, 2);
+        assert_eq!(scanner.total_files, 2);
+        assert!(String::from_utf8_lossy(&scanner.writer).contains("EXCLUDED: 2"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_size_round_trip() {
+        let scanner = CodeRepoScanner::new(Vec::new());
+        let sizes = [
+            0,
+            1,
+            255,
+            256,
+            1024,
+            1025,
+            100 * 1024,
+            100 * 1024 + 1,
+            10 * 1024 * 1024,
+            10 * 1024 * 1024 + 1,
+            u32::MAX as u64 + 1,
+            u64::MAX,
+        ];
+
+        for size in sizes {
+            let encoded = scanner.encode_size(size);
+            assert_eq!(decode_size(&encoded), Some((size, encoded.len())), "{size}");
+        }
+
+        // Trailing bytes are left for the caller
+        let mut stream = scanner.encode_size(1024);
+        stream.push(TRAVERSE_SAME);
+        assert_eq!(decode_size(&stream), Some((1024, 3)));
+
+        assert_eq!(decode_size(&[SIZE_TAG_U32, 1, 2]), None);
+        assert_eq!(decode_size(&[TOKEN_SIZE_TINY as u8, 0]), None);
+        assert_eq!(decode_size(&[]), None);
+    }
+
+    #[test]
+    fn test_time_and_owner_fields_decode() {
+        let root = temp_repo("time-owner");
+        let file = root.join("src/main.rs");
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+
+        let mut scanner = CodeRepoScanner::new(Vec::new());
+        scanner.parent_perms = CodeRepoScanner::<Vec<u8>>::get_permissions(&metadata);
+        scanner.parent_time = mtime + std::time::Duration::from_secs(90);
+        scanner.parent_uid = u32::MAX;
+        scanner.emit_file(&file, &metadata).unwrap();
+
+        let out = &scanner.writer;
+        let header = out[0];
+        assert_ne!(header & HDR_HAS_TIME, 0);
+        assert_eq!(header & HDR_HAS_PERMS, 0);
+
+        let (size, used) = decode_size(&out[1..]).unwrap();
+        assert_eq!(size, metadata.len());
+        let mut pos = 1 + used;
+
+        let (delta, used) = decode_size(&out[pos..]).unwrap();
+        assert_eq!(unzigzag(delta), -90);
+        pos += used;
+
+        if cfg!(unix) {
+            assert_ne!(header & HDR_HAS_OWNER, 0);
+            let uid = u32::from_le_bytes(out[pos..pos + 4].try_into().unwrap());
+            let gid = u32::from_le_bytes(out[pos + 4..pos + 8].try_into().unwrap());
+            assert_eq!(
+                Some((uid, gid)),
+                CodeRepoScanner::<Vec<u8>>::get_owner(&metadata)
+            );
+            pos += 8;
+        } else {
+            assert_eq!(header & HDR_HAS_OWNER, 0);
+        }
+        assert_eq!(&out[pos..], b"main\x21\x00");
+
+        // Same time and owner as the parent: no fields
+        scanner.writer.clear();
+        scanner.parent_time = mtime;
+        if let Some((uid, gid)) = CodeRepoScanner::<Vec<u8>>::get_owner(&metadata) {
+            scanner.parent_uid = uid;
+            scanner.parent_gid = gid;
+        }
+        scanner.emit_file(&file, &metadata).unwrap();
+        assert_eq!(scanner.writer[0] & (HDR_HAS_TIME | HDR_HAS_OWNER), 0);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_frequent_names_get_dynamic_tokens() {
+        let root = temp_repo("dynamic-tokens");
+        for dir in ["a", "b", "c"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("index.ts"), "").unwrap();
+        }
+        fs::write(root.join("a/once.ts"), "").unwrap();
+
+        let mut scanner = CodeRepoScanner::new(Vec::new());
+        scanner.scan(&root).unwrap();
+
+        assert_eq!(scanner.token_map.get("index.ts"), Some(&TOKEN_USER_START));
+        assert!(!scanner.token_map.contains_key("once.ts"));
+        assert_eq!(scanner.next_dynamic_token, TOKEN_USER_START + 1);
+
+        let output = String::from_utf8_lossy(&scanner.writer).into_owned();
+        assert!(output.contains("  0100=index.ts\n"));
+        // Only the header spells the name out
+        assert_eq!(output.matches("index.ts").count(), 1);
+        // Tokenized: src, main.rs (extension) and the three index.ts
+        assert!(output.contains("TOKENIZED: 5/10 (50.0%)"), "{output}");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
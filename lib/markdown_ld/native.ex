@@ -4,6 +4,39 @@ defmodule MarkdownLd.Native do
   
   This module provides the interface to the underlying Rust implementation
   with SIMD optimizations, memory pooling, and advanced parsing capabilities.
+
+  ## Scheduling
+
+  `parse_markdown/2`, `parse_markdown_binary/2`, `parse_batch_parallel/2`
+  and the `extract_*_simd/1` functions run on dirty CPU schedulers, so a
+  large document doesn't block the normal schedulers while it parses. The
+  link, heading and code block extractors are full parses of the document,
+  not SIMD scans. Only `word_count_simd/1`, a single SIMD scan, runs on the
+  normal schedulers.
+
+  Dirty schedulers are limited (one per core by default) and a running NIF
+  can't be preempted. For unbounded input, split the work up front, e.g.
+  one call per file through `Task.async_stream/3`, instead of passing one
+  very large binary or batch.
+
+  ## Telemetry
+
+  When the crate is built with the `telemetry` Cargo feature, the parse
+  functions emit events. A process that called `set_telemetry_handler/1`
+  receives each one as
+  `{:markdown_ld_native, event, measurements, metadata}` and can pass the
+  last three on to `:telemetry.execute/3`:
+
+    * `[:markdown_ld, :native, :parse, :start]`: measurements `%{bytes: n}`
+    * `[:markdown_ld, :native, :parse, :stop]`: measurements
+      `%{bytes: n, duration_us: us}`
+    * `[:markdown_ld, :native, :error]`: metadata `%{code: code}`, where code
+      is `"invalid_utf8"`, `"parse_error"`, `"limit_exceeded"` or
+      `"serialization_error"`
+
+  Events also go to the Rust `log` crate under the `markdown_ld` target.
+  Without the feature nothing is emitted and `set_telemetry_handler/1`
+  returns `{:error, :telemetry_disabled}`.
   """
 
   # Prefer precompiled NIFs when available; fall back to local build via Rustler
@@ -45,6 +78,12 @@ defmodule MarkdownLd.Native do
   def reset_performance_stats(), do: :erlang.nif_error(:nif_not_loaded)
   def clear_pattern_cache(), do: :erlang.nif_error(:nif_not_loaded)
 
-  # Experimental: parse attribute object via Rust core, returns {:ok, json_string} or {:error, reason}
+  # Experimental: parse attribute object via Rust core. Returns {:ok, json_string},
+  # {:error, %{"message" => _, "offset" => byte_offset, "snippet" => _}} or
+  # {:error, :limit_exceeded}
   def parse_attr_object_json(_content), do: :erlang.nif_error(:nif_not_loaded)
+
+  # Registers a process for telemetry event messages (`nil` unregisters).
+  # Returns :ok, or {:error, :telemetry_disabled} without the telemetry feature
+  def set_telemetry_handler(_pid), do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -121,14 +121,19 @@ defmodule MarkdownLd.V03Complete do
   Detect polyglot documents and extract artifacts.
 
   Returns detected language, artifacts, and any concealed data.
+
+  `options` is a list of string pairs tuning detection: `{"threshold", "3.0"}`,
+  `{"primary_weight", _}`, `{"secondary_weight", _}` and per-detector
+  overrides such as `{"bash.primary_weight", "1.0"}`. Defaults are a
+  threshold of 2.0 and weights of 2.0 (primary) and 0.5 (secondary).
   """
-  @spec detect_polyglot(String.t()) :: {:ok, map()} | {:error, term()}
-  def detect_polyglot(content) do
-    with {:ok, %{"detected" => true} = polyglot} <- Native.detect_polyglot(content),
+  @spec detect_polyglot(String.t(), [{String.t(), String.t()}]) :: {:ok, map()} | {:error, term()}
+  def detect_polyglot(content, options \\ []) do
+    with {:ok, %{"detected" => true} = polyglot} <- Native.detect_polyglot(content, options),
          {:ok, concealment} <- Native.extract_concealed_data(content) do
       {:ok, Map.merge(polyglot, concealment)}
     else
-      {:ok, %{"detected" => false}} -> {:ok, %{detected: false}}
+      {:ok, %{"detected" => false} = result} -> {:ok, %{detected: false, score: result["score"]}}
       error -> error
     end
   end
@@ -157,7 +162,11 @@ defmodule MarkdownLd.V03Complete do
   @doc """
   Parse attribute objects using the mini-grammar.
 
-  Supports the `- { ... }` syntax from v0.3 spec with strict and lax modes.
+  This is the grammar of heading and link `{...}` attribute blocks. `:strict`
+  returns an error with the byte offset of any word that cannot start a
+  pair; `:lax` skips such words. Neither mode has comments: `#name` is
+  always an id, so `#todo fix this` is `id=todo fix=true this=true`, and a
+  lone `#` is an unreadable word.
   """
   @spec parse_attribute_object(String.t(), :strict | :lax) ::
           {:ok, map()} | {:error, term()}
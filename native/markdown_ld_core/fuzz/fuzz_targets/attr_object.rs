@@ -0,0 +1,16 @@
+//! `cargo +nightly fuzz run attr_object` from `native/markdown_ld_core`;
+//! the seeds in `corpus/attr_object` are also replayed by the crate's tests.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use markdown_ld_core::attr_object::{parse_attr_object, Error, Limits};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else { return };
+    if let Err(Error::ParseError { offset, snippet, .. }) = parse_attr_object(input, None) {
+        assert!(offset <= input.len());
+        assert!(input.contains(&snippet));
+    }
+    // Tight limits reach the depth/list checks on short inputs
+    let _ = parse_attr_object(input, Some(Limits { max_depth: 2, max_list: 4, max_size: 256 }));
+});
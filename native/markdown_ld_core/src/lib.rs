@@ -1,11 +1,26 @@
-use pulldown_cmark::{Event, Options, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+pub use line_index::LineIndex;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Heading {
     pub level: u32,
     pub text: String,
     pub line: usize,
+    /// Trailing `{key=value}` block, removed from `text`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attributes: Map<String, Value>,
+    /// `h{level}-{slug}`, see `heading_stable_id`
+    #[serde(default)]
+    pub stable_id: String,
+    /// Byte offset of the heading start
+    #[serde(default)]
+    pub offset: usize,
+    /// Byte offset just past the heading (the underline, for setext)
+    #[serde(default)]
+    pub end: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,35 +28,239 @@ pub struct Link {
     pub text: String,
     pub url: String,
     pub line: usize,
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Trailing `{key=value}` blocks of the text, read only with
+    /// `ParseOptions::lenient_attributes`
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attributes: Map<String, Value>,
+    /// Byte offset of the opening `[`
+    #[serde(default)]
+    pub offset: usize,
+    /// Byte offset just past the closing `)` (or `]` of a reference)
+    #[serde(default)]
+    pub end: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeBlock {
+    /// Fence info string; `None` for indented blocks and bare fences
+    pub language: Option<String>,
+    pub content: String,
+    /// Line of the opening fence (or first line of an indented block)
+    pub line: usize,
+    /// Byte offset of the opening fence (or first indented line)
+    #[serde(default)]
+    pub offset: usize,
+    /// Byte offset just past the block, closing fence included
+    #[serde(default)]
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub completed: bool,
+    pub text: String,
+    pub line: usize,
+    /// Nesting level from indentation: two columns per level, tabs count as four
+    pub depth: usize,
+    /// Byte offset of the list marker
+    #[serde(default)]
+    pub offset: usize,
+    /// Byte offset just past the task text, trailing whitespace excluded
+    #[serde(default)]
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmphasisKind {
+    Strong,
+    Emphasis,
+    Strikethrough,
+}
+
+impl EmphasisKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmphasisKind::Strong => "strong",
+            EmphasisKind::Emphasis => "emphasis",
+            EmphasisKind::Strikethrough => "strikethrough",
+        }
+    }
+}
+
+/// An emphasis, strong or strikethrough run, delimiters included in the columns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Emphasis {
+    pub kind: EmphasisKind,
+    /// Inner text without markup; nested spans contribute their text too
+    pub text: String,
+    /// Line of the opening delimiter
+    pub line: usize,
+    /// Byte column of the opening delimiter, 0-based
+    pub col_start: usize,
+    /// Byte column just past the closing delimiter, on the span's last line
+    pub col_end: usize,
+    /// Byte offset of the opening delimiter
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// A `>` blockquote; `> [!TYPE] Title` on its first line makes it a callout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blockquote {
+    /// Block text, one line per line or paragraph; nested quotes are reported
+    /// separately and left out, as is the callout marker line
+    pub text: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    /// 1 for a top-level quote, 2 for a quote inside it, and so on
+    pub depth: usize,
+    /// Upper-cased `TYPE` of a `[!TYPE]` marker (NOTE, WARNING, TIP, ...)
+    #[serde(default)]
+    pub callout_type: Option<String>,
+    /// Text after the marker on the same line, if any
+    #[serde(default)]
+    pub callout_title: Option<String>,
+    /// Byte offset of the opening `>`
+    #[serde(default)]
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ParsedDoc {
     pub headings: Vec<Heading>,
     pub links: Vec<Link>,
+    pub code_blocks: Vec<CodeBlock>,
+    pub tasks: Vec<Task>,
+    #[serde(default)]
+    pub emphasis: Vec<Emphasis>,
+    #[serde(default)]
+    pub blockquotes: Vec<Blockquote>,
+}
+
+/// Options for `parse_with_options`; the default is what `parse_basic` does
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Pandoc-style attributes: every trailing `{...}` block of heading and
+    /// link text is read (one that fails to parse adds nothing), and a
+    /// paragraph of only such blocks right after a heading adds to that
+    /// heading. Otherwise a heading takes one strict block and links none.
+    pub lenient_attributes: bool,
+}
+
+/// Headings in document order
+pub fn extract_headings(markdown: &str) -> Vec<Heading> { parse_basic(markdown).headings }
+
+/// Links in document order; `text` falls back to the title when empty
+pub fn extract_links(markdown: &str) -> Vec<Link> { parse_basic(markdown).links }
+
+/// Fenced and indented code blocks in document order
+pub fn extract_code_blocks(markdown: &str) -> Vec<CodeBlock> { parse_basic(markdown).code_blocks }
+
+/// Emphasis, strong and strikethrough spans ordered by start; a span nested
+/// in another is reported as well as the outer one
+pub fn extract_emphasis(markdown: &str) -> Vec<Emphasis> { parse_basic(markdown).emphasis }
+
+/// Blockquotes and callouts ordered by start, nested ones included
+pub fn extract_blockquotes(markdown: &str) -> Vec<Blockquote> { parse_basic(markdown).blockquotes }
+
+/// `- [ ]` / `* [x]` task lines, found line by line (also inside code blocks)
+pub fn extract_tasks(markdown: &str) -> Vec<Task> { parse_tasks(markdown) }
+
+/// Heading slug: the lowercased text with everything but alphanumerics,
+/// spaces and `-` dropped and whitespace runs joined by `-`
+pub fn heading_slug(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-')
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
 }
 
-/// Parse basic structure (headings, links) without any NIF bindings.
-pub fn parse_basic(markdown: &str) -> ParsedDoc {
+/// Stable heading identifier: `h{level}-` followed by `heading_slug`
+pub fn heading_stable_id(text: &str, level: u32) -> String { format!("h{}-{}", level, heading_slug(text)) }
+
+/// Parse basic structure (headings, links, code blocks, tasks) without any NIF bindings.
+pub fn parse_basic(markdown: &str) -> ParsedDoc { parse_with_options(markdown, ParseOptions::default()) }
+
+/// `parse_basic` with the given options
+pub fn parse_with_options(markdown: &str, parse_options: ParseOptions) -> ParsedDoc {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_FOOTNOTES);
 
-    let parser = Parser::new_ext(markdown, options);
+    let parser = Parser::new_ext(markdown, options).into_offset_iter();
 
-    let mut headings = Vec::new();
+    let mut headings: Vec<Heading> = Vec::new();
     let mut links = Vec::new();
+    let mut code_blocks = Vec::new();
+    let mut code: Option<CodeBlock> = None;
+    let mut link: Option<Link> = None;
+    // Link-like text in code is never a link, whatever a later linkifier might think
+    let mut in_code = false;
 
-    let mut current_line: usize = 1;
+    let index = LineIndex::new(markdown);
+    let line_at = |offset: usize| index.line(offset);
+    let col_at = |offset: usize| index.offset_to_line_col(offset).1;
+    let mut emphasis = Vec::new();
+    let mut open_spans: Vec<(EmphasisKind, usize, String)> = Vec::new();
+    let mut blockquotes = Vec::new();
+    let mut open_quotes: Vec<(usize, String)> = Vec::new();
+    let mut heading_offset: usize = 0;
     let mut in_heading = false;
     let mut heading_level: u32 = 1;
     let mut heading_text = String::new();
+    // Set while the heading just closed is the latest block
+    let mut after_heading = false;
 
-    for event in parser {
+    for (event, range) in parser {
+        if parse_options.lenient_attributes {
+            match &event {
+                Event::Start(tag) => {
+                    if after_heading && matches!(tag, Tag::Paragraph) {
+                        let (text, attributes) = split_attribute_blocks(markdown[range.clone()].trim());
+                        if let (true, Some(heading)) = (text.is_empty(), headings.last_mut()) {
+                            merge_attributes(&mut heading.attributes, attributes);
+                        }
+                    }
+                    after_heading = false;
+                }
+                Event::Rule | Event::Html(_) => after_heading = false,
+                _ => {}
+            }
+        }
+        if let Some((_, quote_text)) = open_quotes.last_mut() {
+            match &event {
+                Event::Text(text) | Event::Code(text) => quote_text.push_str(text),
+                Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::Item) => quote_text.push('\n'),
+                _ => {}
+            }
+        }
         match event {
+            Event::Start(Tag::BlockQuote) => open_quotes.push((range.start, String::new())),
+            Event::End(Tag::BlockQuote) => {
+                if let Some((offset, text)) = open_quotes.pop() {
+                    let last = markdown[..range.end].trim_end_matches('\n').len();
+                    let (callout_type, callout_title, text) = split_callout(text.trim());
+                    blockquotes.push(Blockquote {
+                        text,
+                        line_start: line_at(offset),
+                        line_end: line_at(last),
+                        depth: open_quotes.len() + 1,
+                        callout_type,
+                        callout_title,
+                        offset,
+                    });
+                }
+            }
             Event::Start(Tag::Heading(level, _, _)) => {
                 in_heading = true;
+                heading_offset = range.start;
                 heading_level = match level {
                     pulldown_cmark::HeadingLevel::H1 => 1,
                     pulldown_cmark::HeadingLevel::H2 => 2,
@@ -54,43 +273,327 @@ pub fn parse_basic(markdown: &str) -> ParsedDoc {
             }
             Event::End(Tag::Heading(_, _, _)) => {
                 if in_heading {
-                    headings.push(Heading {
-                        level: heading_level,
-                        text: heading_text.clone(),
-                        line: current_line,
-                    });
+                    let (text, attributes) = if parse_options.lenient_attributes {
+                        split_attribute_blocks(&heading_text)
+                    } else {
+                        split_heading_attributes(&heading_text)
+                    };
+                    let stable_id = heading_stable_id(&text, heading_level);
+                    let line = line_at(heading_offset);
+                    headings.push(Heading { level: heading_level, text, line, attributes, stable_id, offset: heading_offset, end: range.end });
                     in_heading = false;
+                    after_heading = true;
                 }
             }
-            Event::Start(Tag::Link(_, dest_url, title)) => {
-                let mut text = String::new();
-                if !title.is_empty() {
-                    text.push_str(&title);
+            Event::Start(Tag::Link(_, dest_url, title)) if !in_code => {
+                let title = (!title.is_empty()).then(|| title.to_string());
+                link = Some(Link { text: String::new(), url: dest_url.to_string(), line: line_at(range.start), title, attributes: Map::new(), offset: range.start, end: range.end });
+            }
+            Event::End(Tag::Link(_, _, _)) => {
+                if let Some(mut done) = link.take() {
+                    if parse_options.lenient_attributes {
+                        (done.text, done.attributes) = split_attribute_blocks(&done.text);
+                    }
+                    if done.text.is_empty() {
+                        done.text = done.title.clone().unwrap_or_default();
+                    }
+                    links.push(done);
+                }
+            }
+            Event::Start(tag @ (Tag::Emphasis | Tag::Strong | Tag::Strikethrough)) => {
+                let kind = match tag {
+                    Tag::Strong => EmphasisKind::Strong,
+                    Tag::Strikethrough => EmphasisKind::Strikethrough,
+                    _ => EmphasisKind::Emphasis,
+                };
+                open_spans.push((kind, range.start, String::new()));
+            }
+            Event::End(Tag::Emphasis | Tag::Strong | Tag::Strikethrough) => {
+                if let Some((kind, offset, text)) = open_spans.pop() {
+                    emphasis.push(Emphasis { kind, text, line: line_at(offset), col_start: col_at(offset), col_end: col_at(range.end), offset });
+                }
+            }
+            Event::Code(code_text) => {
+                for (_, _, text) in open_spans.iter_mut() {
+                    text.push_str(&code_text);
+                }
+                if in_heading {
+                    heading_text.push_str(&code_text);
+                }
+                if let Some(open) = link.as_mut() {
+                    open.text.push_str(&code_text);
+                }
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(lang) if !lang.is_empty() => Some(lang.to_string()),
+                    _ => None,
+                };
+                code = Some(CodeBlock { language, content: String::new(), line: line_at(range.start), offset: range.start, end: range.end });
+                in_code = true;
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code = false;
+                if let Some(block) = code.take() {
+                    code_blocks.push(block);
                 }
-                links.push(Link { text, url: dest_url.to_string(), line: current_line });
             }
             Event::Text(text) => {
+                for (_, _, span_text) in open_spans.iter_mut() {
+                    span_text.push_str(&text);
+                }
                 if in_heading {
                     heading_text.push_str(&text);
                 }
-                current_line += text.chars().filter(|&c| c == '\n').count();
+                if let Some(block) = code.as_mut() {
+                    block.content.push_str(&text);
+                }
+                if let Some(open) = link.as_mut() {
+                    open.text.push_str(&text);
+                }
             }
             Event::SoftBreak | Event::HardBreak => {
-                current_line += 1;
+                for (_, _, text) in open_spans.iter_mut() {
+                    text.push(' ');
+                }
+                if in_heading {
+                    heading_text.push(' ');
+                }
+                if let Some(open) = link.as_mut() {
+                    open.text.push(' ');
+                }
             }
             _ => {}
         }
     }
 
-    ParsedDoc { headings, links }
+    // Inner spans close first; report by position
+    emphasis.sort_by_key(|span| span.offset);
+    blockquotes.sort_by_key(|quote| quote.offset);
+    ParsedDoc { headings, links, code_blocks, tasks: parse_tasks(markdown), emphasis, blockquotes }
 }
 
+/// Split a `[!TYPE] Title` first line (Obsidian's `+`/`-` fold markers
+/// allowed) off blockquote text into (type, title, remaining text)
+fn split_callout(text: &str) -> (Option<String>, Option<String>, String) {
+    let (first, rest) = text.split_once('\n').unwrap_or((text, ""));
+    let marker = first.strip_prefix("[!").and_then(|m| m.split_once(']'));
+    match marker {
+        Some((kind, after)) if !kind.is_empty() && kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => {
+            let title = after.trim_start_matches(['+', '-']).trim();
+            let title = (!title.is_empty()).then(|| title.to_string());
+            (Some(kind.to_ascii_uppercase()), title, rest.trim().to_string())
+        }
+        _ => (None, None, text.to_string()),
+    }
+}
+
+/// Split a trailing, parseable, non-empty `{...}` attribute block off heading text
+fn split_heading_attributes(text: &str) -> (String, Map<String, Value>) {
+    let trimmed = text.trim_end();
+    if let Some(idx) = trailing_attribute_block(trimmed) {
+        if let Ok(attributes) = attr_object::parse_attr_object(&trimmed[idx..], None) {
+            if !attributes.is_empty() { return (trimmed[..idx].trim_end().to_string(), attributes); }
+        }
+    }
+    (text.to_string(), Map::new())
+}
+
+/// Split every trailing non-blank `{...}` block off `text`, merging them left
+/// to right as in `merge_attributes`; a block that fails to parse adds nothing
+fn split_attribute_blocks(text: &str) -> (String, Map<String, Value>) {
+    let mut rest = text.trim_end();
+    let mut blocks = Vec::new();
+    while let Some(idx) = trailing_attribute_block(rest).filter(|&idx| !rest[idx + 1..rest.len() - 1].trim().is_empty()) {
+        blocks.push(&rest[idx..]);
+        rest = rest[..idx].trim_end();
+    }
+    if blocks.is_empty() { return (text.to_string(), Map::new()) }
+
+    let mut attributes = Map::new();
+    for block in blocks.into_iter().rev() {
+        if let Ok(parsed) = attr_object::parse_attr_object(block, None) { merge_attributes(&mut attributes, parsed); }
+    }
+    (rest.trim().to_string(), attributes)
+}
+
+/// Start of the balanced `{...}` block `text` ends with, if it ends with one
+fn trailing_attribute_block(text: &str) -> Option<usize> {
+    if !text.ends_with('}') { return None }
+    let mut depth = 0usize;
+    for (idx, c) in text.char_indices().rev() {
+        match c {
+            '}' => depth += 1,
+            '{' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 { return Some(idx) }
+    }
+    None
+}
+
+/// Merge `from` into `into`; keys are overwritten except `class`, whose names are appended
+fn merge_attributes(into: &mut Map<String, Value>, from: Map<String, Value>) {
+    for (key, value) in from {
+        match value {
+            Value::String(classes) if key == "class" => classes.split_whitespace().for_each(|c| attr_object::push_class(into, c)),
+            value => { into.insert(key, value); }
+        }
+    }
+}
+
+/// Checkbox markers and whether they mean done
+const TASK_MARKERS: &[(&str, bool)] = &[("- [ ]", false), ("* [ ]", false), ("- [x]", true), ("* [x]", true)];
+
+/// Line-based task scan, matching the NIF's `- [ ]` / `* [x]` markers
+fn parse_tasks(markdown: &str) -> Vec<Task> {
+    let mut tasks = Vec::new();
+    let mut line_start = 0;
+    for (idx, raw) in markdown.split_inclusive('\n').enumerate() {
+        let start = line_start;
+        line_start += raw.len();
+        let line = raw.strip_suffix('\n').map_or(raw, |line| line.strip_suffix('\r').unwrap_or(line));
+        let trimmed = line.trim_start();
+        let Some((rest, completed)) = TASK_MARKERS.iter().find_map(|(marker, done)| trimmed.strip_prefix(marker).map(|rest| (rest, *done))) else { continue };
+        let indent: usize = line[..line.len() - trimmed.len()]
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum();
+        tasks.push(Task {
+            completed,
+            text: rest.trim().to_string(),
+            line: idx + 1,
+            depth: indent / 2,
+            offset: start + line.len() - trimmed.len(),
+            end: start + line.trim_end().len(),
+        });
+    }
+    tasks
+}
+
+/// Write a parsed document back out as markdown
+///
+/// Blocks are emitted in line order: ATX headings (with their attribute
+/// block), code blocks as backtick fences, runs of tasks as one list, and
+/// the remaining links as `[text](url)` paragraphs. Links on a heading or
+/// task line are part of that block's text and are not repeated. Formatting
+/// the original had beyond this structure is lost, but writing the parse of
+/// the output reproduces the output exactly.
+pub fn to_markdown(doc: &ParsedDoc) -> String {
+    enum Block<'a> { Heading(&'a Heading), Code(&'a CodeBlock), Task(&'a Task), Link(&'a Link) }
+
+    let taken: std::collections::HashSet<usize> =
+        doc.headings.iter().map(|h| h.line).chain(doc.tasks.iter().map(|t| t.line)).collect();
+    let mut blocks: Vec<(usize, Block)> = Vec::new();
+    blocks.extend(doc.headings.iter().map(|h| (h.line, Block::Heading(h))));
+    blocks.extend(doc.code_blocks.iter().map(|b| (b.line, Block::Code(b))));
+    blocks.extend(doc.tasks.iter().map(|t| (t.line, Block::Task(t))));
+    blocks.extend(doc.links.iter().filter(|l| !taken.contains(&l.line)).map(|l| (l.line, Block::Link(l))));
+    blocks.sort_by_key(|(line, _)| *line);
+
+    let mut out = String::new();
+    let mut prev_task_depth: Option<usize> = None;
+    let mut prev_link_line: Option<usize> = None;
+    for (line, block) in blocks {
+        let list_depth = prev_task_depth.take().filter(|_| matches!(block, Block::Task(_)));
+        let continues_paragraph = prev_link_line.take() == Some(line) && matches!(block, Block::Link(_));
+        if continues_paragraph {
+            out.push(' ');
+        } else if list_depth.is_some() {
+            out.push('\n');
+        } else if !out.is_empty() {
+            out.push_str("\n\n");
+        }
+
+        match block {
+            Block::Heading(h) => {
+                out.push_str(&"#".repeat(h.level.clamp(1, 6) as usize));
+                out.push(' ');
+                out.push_str(&h.text);
+                if !h.attributes.is_empty() {
+                    out.push(' ');
+                    write_attr_object(&h.attributes, &mut out);
+                }
+            }
+            Block::Code(b) => {
+                let longest_run = b.content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest_run.max(2) + 1);
+                out.push_str(&fence);
+                out.push_str(b.language.as_deref().unwrap_or(""));
+                out.push('\n');
+                out.push_str(&b.content);
+                if !b.content.is_empty() && !b.content.ends_with('\n') { out.push('\n'); }
+                out.push_str(&fence);
+            }
+            Block::Task(t) => {
+                // Deeper than one level below the previous item would turn into an indented code block
+                let depth = t.depth.min(list_depth.map_or(0, |d| d + 1));
+                out.push_str(&"  ".repeat(depth));
+                out.push_str(if t.completed { "- [x] " } else { "- [ ] " });
+                out.push_str(&t.text);
+                prev_task_depth = Some(depth);
+            }
+            Block::Link(l) => {
+                out.push_str(&format!("[{}]({})", l.text, l.url));
+                prev_link_line = Some(line);
+            }
+        }
+    }
+    if !out.is_empty() { out.push('\n'); }
+    out
+}
+
+/// `{key="value" n=1}` in the syntax `attr_object::parse_attr_object` reads back
+fn write_attr_object(map: &Map<String, Value>, out: &mut String) {
+    out.push('{');
+    for (idx, (key, value)) in map.iter().enumerate() {
+        if idx > 0 { out.push(' '); }
+        out.push_str(key);
+        out.push('=');
+        write_attr_value(value, out);
+    }
+    out.push('}');
+}
+
+fn write_attr_value(value: &Value, out: &mut String) {
+    match value {
+        Value::String(s) => { out.push('"'); out.push_str(s); out.push('"'); }
+        Value::Array(items) => {
+            out.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 { out.push_str(", "); }
+                write_attr_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(obj) => match obj.get("@id") {
+            // Escaped so markdown keeps the brackets instead of reading an autolink
+            Some(Value::String(iri)) if obj.len() == 1 => { out.push_str("\\<"); out.push_str(iri); out.push('>'); }
+            _ => write_attr_object(obj, out),
+        },
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// Parser for `{key=value ...}` attribute objects
+///
+/// Pandoc-style shorthand is accepted too: `{#intro .note .warning lang=en draft}`
+/// reads as `id="intro" class="note warning" lang="en" draft=true`.
+/// There are no comments: `#` always starts an id, and a `#` without a name
+/// is an error like any other character that cannot start a pair.
+///
+/// Every slice bound is either an end of the input or the index of an ASCII
+/// delimiter, so a slice never splits a multibyte character; malformed input
+/// comes back as `Error::ParseError`, never a panic. `fuzz/` holds a
+/// cargo-fuzz target for this.
 pub mod attr_object {
     use serde_json::{Map, Value};
 
     #[derive(Debug)]
     pub enum Error {
-        ParseError(String),
+        /// `offset` is a byte offset into the string given to `parse_attr_object`;
+        /// `snippet` is the input around it.
+        ParseError { message: String, offset: usize, snippet: String },
         LimitExceeded,
     }
 
@@ -106,50 +609,112 @@ pub mod attr_object {
         }
     }
 
-    pub fn parse_attr_object(input: &str, limits: Option<Limits>) -> Result<Map<String, Value>, Error> {
+    pub fn parse_attr_object(input: &str, limits: Option<Limits>) -> Result<Map<String, Value>, Error> { parse(input, limits, false) }
+
+    /// `parse_attr_object`, except that a word which cannot start a pair
+    /// (`!x`, a lone `#`, a stray `,`) is skipped rather than an error;
+    /// malformed values still fail
+    pub fn parse_attr_object_lenient(input: &str, limits: Option<Limits>) -> Result<Map<String, Value>, Error> { parse(input, limits, true) }
+
+    fn parse(input: &str, limits: Option<Limits>, lenient: bool) -> Result<Map<String, Value>, Error> {
         let lim = limits.unwrap_or_default();
         if input.len() > lim.max_size { return Err(Error::LimitExceeded) }
-        let s = input.trim();
-        let s = if s.starts_with('{') { &s[1..] } else { s };
-        let s = s.rsplit_once('}').map(|(a, _)| a).unwrap_or(s);
+        // Parse in place rather than on a trimmed copy so offsets refer to `input`
+        let mut start = input.len() - input.trim_start().len();
+        let mut end = input.trim_end().len().max(start);
+        if input[start..end].starts_with('{') { start += 1; }
+        if let Some(close) = input[start..end].rfind('}') { end = start + close; }
+        parse_pairs(input, start, end, &Grammar { lim, lenient }, 0)
+    }
+
+    /// Limits and mode of one parse, shared by the nested calls
+    struct Grammar { lim: Limits, lenient: bool }
+
+    fn parse_pairs(s: &str, mut i: usize, end: usize, g: &Grammar, depth: usize) -> Result<Map<String, Value>, Error> {
         let mut map = Map::new();
-        let mut i = 0usize;
         let b = s.as_bytes();
-        while i < b.len() {
-            skip_ws(b, &mut i);
-            if i >= b.len() { break; }
+        while i < end {
+            skip_ws(b, end, &mut i);
+            if i >= end { break; }
+            // `#name` is `id=name`, `.name` adds to `class`
+            if b[i] == b'#' || b[i] == b'.' {
+                let mut j = i + 1;
+                while j < end && is_name_char(b[j]) { j += 1; }
+                if j > i + 1 && (j == end || b[j].is_ascii_whitespace() || b[j] == b',') {
+                    let name = &s[i + 1..j];
+                    if b[i] == b'#' { map.insert("id".to_string(), Value::String(name.to_string())); } else { push_class(&mut map, name); }
+                    i = j;
+                    if i < end { i += 1; }
+                    continue;
+                }
+            }
             let key_start = i;
-            while i < b.len() && is_key_char(b[i]) { i += 1; }
+            while i < end && is_key_char(b[i]) { i += 1; }
             let key = &s[key_start..i];
-            skip_ws(b, &mut i);
-            if i < b.len() && b[i] == b'=' { i += 1; } else { return Err(Error::ParseError("expected '='".into())) }
-            skip_ws(b, &mut i);
-            let (val, ni) = parse_value(s, i, &lim, 0)?;
-            i = ni;
-            map.insert(key.to_string(), val);
-            skip_ws(b, &mut i);
-            if i < b.len() && (b[i] == b',' || b[i].is_ascii_whitespace()) { i += 1; }
+            if key.is_empty() {
+                if !g.lenient { return Err(error(s, i, "expected a key")) }
+                i += 1;
+                while i < end && !b[i].is_ascii_whitespace() && b[i] != b',' { i += 1; }
+                continue;
+            }
+            skip_ws(b, end, &mut i);
+            if i < end && b[i] == b'=' {
+                i += 1;
+                skip_ws(b, end, &mut i);
+                let (val, ni) = parse_value(s, i, end, g, depth)?;
+                i = ni;
+                match val {
+                    Value::String(classes) if key == "class" => classes.split_whitespace().for_each(|c| push_class(&mut map, c)),
+                    val => { map.insert(key.to_string(), val); }
+                }
+            } else {
+                // A bare key is a flag
+                map.insert(key.to_string(), Value::Bool(true));
+            }
+            skip_ws(b, end, &mut i);
+            if i < end && (b[i] == b',' || b[i].is_ascii_whitespace()) { i += 1; }
         }
         Ok(map)
     }
 
-    fn skip_ws(b: &[u8], i: &mut usize) { while *i < b.len() && b[*i].is_ascii_whitespace() { *i += 1; } }
-    fn is_key_char(c: u8) -> bool { c.is_ascii_alphanumeric() || c == b'_' || c == b'-' || c == b'.' || c == b':' || c == b'[' || c == b']' }
+    fn error(s: &str, offset: usize, message: &str) -> Error {
+        const CONTEXT: usize = 12;
+        let mut from = offset.saturating_sub(CONTEXT);
+        while !s.is_char_boundary(from) { from -= 1; }
+        let mut to = (offset + CONTEXT).min(s.len());
+        while !s.is_char_boundary(to) { to += 1; }
+        Error::ParseError { message: message.to_string(), offset, snippet: s[from..to].to_string() }
+    }
+
+    fn skip_ws(b: &[u8], end: usize, i: &mut usize) { while *i < end && b[*i].is_ascii_whitespace() { *i += 1; } }
+    fn is_key_char(c: u8) -> bool { c.is_ascii_alphanumeric() || c == b'_' || c == b'-' || c == b'.' || c == b':' || c == b'@' || c == b'[' || c == b']' }
+    fn is_name_char(c: u8) -> bool { c.is_ascii_alphanumeric() || c == b'_' || c == b'-' || c == b':' }
 
-    fn parse_value(s: &str, mut i: usize, lim: &Limits, depth: usize) -> Result<(Value, usize), Error> {
-        if depth > lim.max_depth { return Err(Error::LimitExceeded) }
+    /// Append to the space-separated `class` value, skipping repeats
+    pub(crate) fn push_class(map: &mut Map<String, Value>, class: &str) {
+        match map.get_mut("class") {
+            Some(Value::String(classes)) if !classes.is_empty() => {
+                if !classes.split_whitespace().any(|c| c == class) { classes.push(' '); classes.push_str(class); }
+            }
+            _ => { map.insert("class".to_string(), Value::String(class.to_string())); }
+        }
+    }
+
+    fn parse_value(s: &str, mut i: usize, end: usize, g: &Grammar, depth: usize) -> Result<(Value, usize), Error> {
+        if depth > g.lim.max_depth { return Err(Error::LimitExceeded) }
         let b = s.as_bytes();
-        if i >= b.len() { return Err(Error::ParseError("unexpected end".into())) }
+        if i >= end { return Err(error(s, i, "unexpected end")) }
+        let open = i;
         match b[i] {
             b'"' => {
                 i += 1; let start = i; let mut esc = false;
-                while i < b.len() {
+                while i < end {
                     if esc { esc = false; i += 1; continue; }
                     if b[i] == b'\\' { esc = true; i += 1; continue; }
                     if b[i] == b'"' { break; }
                     i += 1;
                 }
-                if i >= b.len() { return Err(Error::ParseError("unterminated string".into())) }
+                if i >= end { return Err(error(s, open, "unterminated string")) }
                 let raw = &s[start..i];
                 i += 1;
                 Ok((Value::String(raw.to_string()), i))
@@ -157,34 +722,36 @@ pub mod attr_object {
             b'[' => {
                 i += 1; let mut arr = Vec::new(); let mut count = 0;
                 loop {
-                    skip_ws(b, &mut i);
-                    if i >= b.len() { return Err(Error::ParseError("unterminated list".into())) }
+                    skip_ws(b, end, &mut i);
+                    if i >= end { return Err(error(s, open, "unterminated list")) }
                     if b[i] == b']' { i += 1; break; }
-                    let (v, ni) = parse_value(s, i, lim, depth+1)?; i = ni; arr.push(v); count += 1;
-                    if count > lim.max_list { return Err(Error::LimitExceeded) }
-                    skip_ws(b, &mut i);
-                    if i < b.len() && b[i] == b',' { i += 1; }
+                    let (v, ni) = parse_value(s, i, end, g, depth+1)?;
+                    // A stray `}` or `,` yields an empty token without moving
+                    if ni == i { return Err(error(s, i, "unexpected character in list")) }
+                    i = ni; arr.push(v); count += 1;
+                    if count > g.lim.max_list { return Err(Error::LimitExceeded) }
+                    skip_ws(b, end, &mut i);
+                    if i < end && b[i] == b',' { i += 1; }
                 }
                 Ok((Value::Array(arr), i))
             }
             b'{' => {
                 // naive nested object: find matching brace and recurse
                 let mut depth_b = 1; i += 1; let start = i;
-                while i < b.len() && depth_b > 0 { if b[i] == b'{' { depth_b += 1; } else if b[i] == b'}' { depth_b -= 1; } i += 1; }
-                if depth_b != 0 { return Err(Error::ParseError("unterminated object".into())) }
-                let inner = &s[start..i-1];
-                let m = parse_attr_object(inner, Some(Limits{..*lim}))?;
+                while i < end && depth_b > 0 { if b[i] == b'{' { depth_b += 1; } else if b[i] == b'}' { depth_b -= 1; } i += 1; }
+                if depth_b != 0 { return Err(error(s, open, "unterminated object")) }
+                let m = parse_pairs(s, start, i-1, g, depth+1)?;
                 Ok((Value::Object(m), i))
             }
             b'<' => {
-                i += 1; let start = i; while i < b.len() && b[i] != b'>' { i += 1; }
-                if i >= b.len() { return Err(Error::ParseError("unterminated IRI".into())) }
+                i += 1; let start = i; while i < end && b[i] != b'>' { i += 1; }
+                if i >= end { return Err(error(s, open, "unterminated IRI")) }
                 let iri = &s[start..i]; i += 1;
                 let mut m = Map::new(); m.insert("@id".to_string(), Value::String(iri.to_string()));
                 Ok((Value::Object(m), i))
             }
             _ => {
-                let start = i; while i < b.len() && !b[i].is_ascii_whitespace() && b[i] != b',' && b[i] != b']' && b[i] != b'}' { i += 1; }
+                let start = i; while i < end && !b[i].is_ascii_whitespace() && b[i] != b',' && b[i] != b']' && b[i] != b'}' { i += 1; }
                 let tok = &s[start..i];
                 if tok.eq_ignore_ascii_case("true") { return Ok((Value::Bool(true), i)); }
                 if tok.eq_ignore_ascii_case("false") { return Ok((Value::Bool(false), i)); }
@@ -195,3 +762,357 @@ pub mod attr_object {
         }
     }
 }
+
+pub mod line_index {
+    /// Newline positions of a text, built once, for O(log lines) mapping of
+    /// byte offsets to lines and columns
+    ///
+    /// The scan uses AVX2 when the CPU has it (32 bytes per compare, like
+    /// the word counter in the NIF's `simd.rs`) and a byte loop otherwise.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LineIndex { starts: Vec<usize> }
+
+    impl LineIndex {
+        pub fn new(text: &str) -> Self {
+            let mut starts = Vec::with_capacity(text.len() / 40 + 1);
+            starts.push(0);
+            push_line_starts(text.as_bytes(), &mut starts);
+            LineIndex { starts }
+        }
+
+        /// 1-based line of a byte offset; offsets past the end map to the last line
+        pub fn line(&self, offset: usize) -> usize {
+            match self.starts.binary_search(&offset) { Ok(line) => line + 1, Err(line) => line }
+        }
+
+        /// 1-based line and 0-based byte column of a byte offset
+        pub fn offset_to_line_col(&self, offset: usize) -> (usize, usize) {
+            let line = self.line(offset);
+            (line, offset - self.starts[line - 1])
+        }
+
+        /// Byte offset where a 1-based line starts, `None` past the last line
+        pub fn line_start(&self, line: usize) -> Option<usize> { line.checked_sub(1).and_then(|i| self.starts.get(i).copied()) }
+
+        /// Lines in the text; a trailing newline starts an (empty) last line
+        pub fn line_count(&self) -> usize { self.starts.len() }
+    }
+
+    fn push_line_starts(bytes: &[u8], starts: &mut Vec<usize>) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                // SAFETY: AVX2 support was checked just above
+                unsafe { push_line_starts_avx2(bytes, starts) };
+                return;
+            }
+        }
+        push_line_starts_scalar(bytes, 0, starts);
+    }
+
+    fn push_line_starts_scalar(bytes: &[u8], from: usize, starts: &mut Vec<usize>) {
+        starts.extend(bytes[from..].iter().enumerate().filter(|&(_, &b)| b == b'\n').map(|(i, _)| from + i + 1));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn push_line_starts_avx2(bytes: &[u8], starts: &mut Vec<usize>) {
+        use std::arch::x86_64::*;
+        let newline = _mm256_set1_epi8(b'\n' as i8);
+        let mut i = 0;
+        while i + 32 <= bytes.len() {
+            let chunk = _mm256_loadu_si256(bytes.as_ptr().add(i) as *const __m256i);
+            let mut mask = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk, newline)) as u32;
+            while mask != 0 {
+                starts.push(i + mask.trailing_zeros() as usize + 1);
+                mask &= mask - 1;
+            }
+            i += 32;
+        }
+        push_line_starts_scalar(bytes, i, starts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_basic_collects_code_blocks_and_tasks() {
+        let doc = parse_basic("# Plan\n\n- [ ] Draft\n  - [x] Outline\n\n```rust\nfn main() {}\n```\n\n    indented\n");
+
+        assert_eq!(doc.code_blocks.len(), 2);
+        assert_eq!(doc.code_blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(doc.code_blocks[0].content, "fn main() {}\n");
+        assert_eq!(doc.code_blocks[0].line, 6);
+        assert_eq!(doc.code_blocks[1].language, None);
+        assert_eq!(doc.code_blocks[1].line, 10);
+
+        let tasks: Vec<(bool, &str, usize, usize)> =
+            doc.tasks.iter().map(|t| (t.completed, t.text.as_str(), t.line, t.depth)).collect();
+        assert_eq!(tasks, vec![(false, "Draft", 3, 0), (true, "Outline", 4, 1)]);
+
+        let json = serde_json::to_string(&doc).unwrap();
+        let back: ParsedDoc = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    }
+
+    #[test]
+    fn parse_basic_collects_link_text() {
+        let doc = parse_basic("See [click me](https://x) and [`code` *too*](https://y).\n\n[](https://z \"Title\")\n");
+
+        let links: Vec<(&str, &str)> = doc.links.iter().map(|l| (l.text.as_str(), l.url.as_str())).collect();
+        assert_eq!(
+            links,
+            vec![("click me", "https://x"), ("code too", "https://y"), ("Title", "https://z")]
+        );
+    }
+
+    #[test]
+    fn links_in_code_are_not_reported() {
+        let md = "Run `curl https://x` or `[a](https://a)`, then [docs](https://d).\n\n```\n[z](https://z) <https://w>\n```\n\n    [i](https://i)\n\n<https://auto>\n";
+        let urls: Vec<String> = extract_links(md).into_iter().map(|l| l.url).collect();
+        assert_eq!(urls, vec!["https://d", "https://auto"]);
+    }
+
+    #[test]
+    fn extractors_report_lines_offsets_and_ids() {
+        let md = "Intro\n\n| a |\n|---|\n| b |\n\n## Getting Started! {id=\"start\"}\n\nSee [docs](https://d \"Docs\").\n\n```sh\nrun\n```\n";
+
+        let headings = extract_headings(md);
+        assert_eq!(headings.len(), 1);
+        assert_eq!((headings[0].line, headings[0].offset), (7, 26));
+        assert_eq!(headings[0].text, "Getting Started!");
+        assert_eq!(headings[0].stable_id, "h2-getting-started");
+        assert_eq!(headings[0].attributes["id"], "start");
+
+        let links = extract_links(md);
+        assert_eq!((links[0].line, links[0].offset), (9, 64));
+        assert_eq!(links[0].title.as_deref(), Some("Docs"));
+        assert_eq!(&md[links[0].offset..links[0].offset + 6], "[docs]");
+
+        let code = extract_code_blocks(md);
+        assert_eq!((code[0].line, code[0].offset), (11, 91));
+        assert_eq!(code[0].content, "run\n");
+
+        assert!(extract_tasks(md).is_empty());
+        assert_eq!(heading_stable_id("  Spaces  and -- dashes ", 3), "h3-spaces-and----dashes");
+    }
+
+    #[test]
+    fn extract_emphasis_reports_nested_spans() {
+        let md = "Plain *italic **bold** tail* and ~~gone~~.\n\n__strong `code`__\nnext *a\nb*\n";
+        let emphasis = extract_emphasis(md);
+        let spans: Vec<(&str, &str, usize, usize, usize)> = emphasis
+            .iter()
+            .map(|s| (s.kind.as_str(), s.text.as_str(), s.line, s.col_start, s.col_end))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("emphasis", "italic bold tail", 1, 6, 28),
+                ("strong", "bold", 1, 14, 22),
+                ("strikethrough", "gone", 1, 33, 41),
+                ("strong", "strong code", 3, 0, 17),
+                ("emphasis", "a b", 4, 5, 2),
+            ]
+        );
+        assert_eq!(serde_json::to_value(&parse_basic(md).emphasis[2]).unwrap()["kind"], "strikethrough");
+    }
+
+    #[test]
+    fn extract_blockquotes_parses_callouts_and_nesting() {
+        let md = "> [!WARNING] Mind the gap\n> Trains are *fast*.\n>\n> > Nested `quote`\n\nText\n\n> [!tip]-\n> Folded\n\n> Plain\n> two\n";
+        let quotes = extract_blockquotes(md);
+        assert_eq!(quotes.len(), 4);
+
+        let warning = &quotes[0];
+        assert_eq!(warning.callout_type.as_deref(), Some("WARNING"));
+        assert_eq!(warning.callout_title.as_deref(), Some("Mind the gap"));
+        assert_eq!(warning.text, "Trains are fast.");
+        assert_eq!((warning.line_start, warning.line_end, warning.depth), (1, 4, 1));
+
+        let nested = &quotes[1];
+        assert_eq!((nested.text.as_str(), nested.line_start, nested.line_end, nested.depth), ("Nested quote", 4, 4, 2));
+        assert_eq!(nested.callout_type, None);
+
+        assert_eq!(quotes[2].callout_type.as_deref(), Some("TIP"));
+        assert_eq!((quotes[2].callout_title.as_deref(), quotes[2].text.as_str()), (None, "Folded"));
+
+        assert_eq!((quotes[3].text.as_str(), quotes[3].line_start, quotes[3].line_end), ("Plain\ntwo", 11, 12));
+    }
+
+    #[test]
+    fn line_index_matches_a_naive_scan() {
+        let naive = |text: &str, offset: usize| {
+            let before = &text[..offset];
+            (before.matches('\n').count() + 1, offset - before.rfind('\n').map_or(0, |nl| nl + 1))
+        };
+        let mut text = String::new();
+        for i in 0..300 {
+            // Line lengths straddle the 32-byte AVX2 chunk, with CRLF and multibyte text mixed in
+            text.push_str(&"é".repeat(i % 23));
+            text.push_str(&"x".repeat(i % 37));
+            text.push_str(if i % 5 == 0 { "\r\n" } else { "\n" });
+            if i % 7 == 0 { text.push('\n'); }
+        }
+        let index = LineIndex::new(&text);
+        assert_eq!(index.line_count(), text.matches('\n').count() + 1);
+        for offset in (0..=text.len()).filter(|&o| text.is_char_boundary(o)) {
+            assert_eq!(index.offset_to_line_col(offset), naive(&text, offset), "offset {}", offset);
+        }
+        assert_eq!(index.line_start(1), Some(0));
+        assert_eq!(index.line_start(0), None);
+        assert_eq!(index.line_start(index.line_count() + 1), None);
+        assert_eq!(LineIndex::new("").offset_to_line_col(0), (1, 0));
+    }
+
+    /// `cargo test --release -- --ignored --nocapture line_index_benchmark`
+    #[test]
+    #[ignore]
+    fn line_index_benchmark() {
+        let doc = "## Section\n\nSome prose with a [link](https://example.org) and `code`.\n\n- [ ] task\n".repeat(50_000);
+        let offsets: Vec<usize> = (0..doc.len()).step_by(97).collect();
+
+        let start = std::time::Instant::now();
+        let index = LineIndex::new(&doc);
+        let built = start.elapsed();
+        let indexed: usize = offsets.iter().map(|&o| index.line(o)).sum();
+        let lookups = start.elapsed() - built;
+
+        let sample = &offsets[..2_000];
+        let start = std::time::Instant::now();
+        let naive: usize = sample.iter().map(|&o| doc[..o].matches('\n').count() + 1).sum();
+        let scanned = start.elapsed();
+        assert_eq!(naive, sample.iter().map(|&o| index.line(o)).sum::<usize>());
+
+        println!(
+            "{} bytes, {} lines: index built in {:?}, {} lookups in {:?} (sum {}); naive scan of {} offsets took {:?}",
+            doc.len(), index.line_count(), built, offsets.len(), lookups, indexed, sample.len(), scanned
+        );
+        let start = std::time::Instant::now();
+        let parsed = parse_basic(&doc);
+        println!("parse_basic: {} headings in {:?}", parsed.headings.len(), start.elapsed());
+    }
+
+    #[test]
+    fn to_markdown_is_idempotent() {
+        let source = "Intro with [docs](https://d) and [api](https://a).\n\n\
+            Setext title {id=\"s\"}\n============\n\n\
+            ## Plan {level=2 tags=[\"a\", \"b\"] see=\\<https://x>}\n\n\
+            - [ ] Draft [spec](https://s)\n      - [x] Deep jump\n- [x] Ship\n\n\
+            ```rust\nlet s = \"```\";\n```\n\n    indented\n";
+        let first = to_markdown(&parse_basic(source));
+        let second = to_markdown(&parse_basic(&first));
+        assert_eq!(second, first);
+
+        let doc = parse_basic(&first);
+        assert_eq!(doc.headings[0].text, "Setext title");
+        assert_eq!(doc.headings[1].attributes["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(doc.headings[1].attributes["see"]["@id"], "https://x");
+        let tasks: Vec<(bool, usize)> = doc.tasks.iter().map(|t| (t.completed, t.depth)).collect();
+        assert_eq!(tasks, vec![(false, 0), (true, 1), (true, 0)]);
+        assert_eq!(doc.code_blocks.len(), 2);
+        assert_eq!(doc.code_blocks[0].content, "let s = \"```\";\n");
+        let urls: Vec<&str> = doc.links.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://d", "https://a", "https://s"]);
+        assert!(first.starts_with("[docs](https://d) [api](https://a)\n\n# Setext title {id=\"s\"}\n"), "{}", first);
+    }
+
+    fn attr_error_offset(input: &str) -> (usize, String) {
+        match attr_object::parse_attr_object(input, None) {
+            Err(attr_object::Error::ParseError { offset, snippet, .. }) => (offset, snippet),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn attr_errors_report_offsets_into_the_input() {
+        // Unterminated strings point at the opening quote
+        assert_eq!(attr_error_offset(r#"a="open"#), (2, r#"a="open"#.to_string()));
+        // A stray character points at itself, past the stripped `{`
+        assert_eq!(attr_error_offset(r#"  { title="x" count! 3 }"#).0, 19);
+        // Nested objects keep absolute offsets
+        assert_eq!(attr_error_offset("{meta={a=1 b!} x=1}").0, 12);
+
+        let (offset, snippet) = attr_error_offset(&format!("{}z !1", "k=v ".repeat(10)));
+        assert_eq!(offset, 42);
+        assert_eq!(snippet, "v k=v k=v z !1");
+    }
+
+    #[test]
+    fn attr_shorthand_expands_to_id_class_and_flags() {
+        let map = attr_object::parse_attr_object("{#intro .note .warning lang=en draft}", None).unwrap();
+        assert_eq!(serde_json::Value::Object(map), serde_json::json!({"id": "intro", "class": "note warning", "lang": "en", "draft": true}));
+        let map = attr_object::parse_attr_object(r#"class="a b" .b .c"#, None).unwrap();
+        assert_eq!(map["class"], "a b c");
+        assert_eq!(attr_error_offset("{# x}").0, 1);
+    }
+
+    #[test]
+    fn lenient_attr_objects_skip_unreadable_words() {
+        let map = attr_object::parse_attr_object_lenient("a=1, !x ,, # note ld:@type=Article", None).unwrap();
+        assert_eq!(serde_json::Value::Object(map), serde_json::json!({"a": 1, "note": true, "ld:@type": "Article"}));
+        assert_eq!(attr_error_offset("a=1 !x").0, 4);
+        assert!(attr_object::parse_attr_object_lenient(r#"a="open"#, None).is_err());
+    }
+
+    #[test]
+    fn lenient_attributes_merge_blocks_standalone_lines_and_links() {
+        let md = "### Title {.a} {#b}\n{.c data=1}\n\n## Next {bad=[}\n\nText\n\n{.orphan}\n\nSee [docs {.ext}](https://d).\n";
+        let doc = parse_with_options(md, ParseOptions { lenient_attributes: true });
+        assert_eq!(doc.headings[0].text, "Title");
+        assert_eq!(serde_json::Value::Object(doc.headings[0].attributes.clone()), serde_json::json!({"id": "b", "class": "a c", "data": 1}));
+        assert_eq!(&md[doc.headings[0].offset..doc.headings[0].end], "### Title {.a} {#b}\n");
+        assert_eq!((doc.headings[1].text.as_str(), doc.headings[1].attributes.len()), ("Next", 0));
+        assert_eq!((doc.links[0].text.as_str(), &doc.links[0].attributes["class"]), ("docs", &serde_json::json!("ext")));
+        assert_eq!(&md[doc.links[0].offset..doc.links[0].end], "[docs {.ext}](https://d)");
+
+        let strict = parse_basic(md);
+        assert_eq!(strict.headings[0].text, "Title {.a}");
+        assert_eq!(strict.headings[1].text, "Next {bad=[}");
+        assert_eq!((strict.links[0].text.as_str(), strict.links[0].attributes.len()), ("docs {.ext}", 0));
+    }
+
+    #[test]
+    fn task_text_is_sliced_after_the_marker() {
+        let md = "- [ ]日本語のタスク\n\u{3000}\u{3000}- [x] ✓ café\n* [ ] 🎉\n- [ ]\n- []\n";
+        let tasks: Vec<(bool, String, usize)> = extract_tasks(md).into_iter().map(|t| (t.completed, t.text, t.depth)).collect();
+        assert_eq!(tasks, vec![
+            (false, "日本語のタスク".to_string(), 0),
+            (true, "✓ café".to_string(), 1),
+            (false, "🎉".to_string(), 0),
+            (false, String::new(), 0),
+        ]);
+    }
+
+    #[test]
+    fn task_and_code_block_byte_ranges_cover_their_source() {
+        let md = "Intro\r\n\r\n  - [x] shipped  \r\n- [ ] 日本語\n\n```rust\nfn main() {}\n```\n";
+        let tasks: Vec<&str> = extract_tasks(md).iter().map(|t| &md[t.offset..t.end]).collect();
+        assert_eq!(tasks, vec!["- [x] shipped", "- [ ] 日本語"]);
+        let blocks = extract_code_blocks(md);
+        assert_eq!(&md[blocks[0].offset..blocks[0].end], "```rust\nfn main() {}\n```");
+    }
+
+    #[test]
+    fn attr_parser_survives_the_fuzz_corpus() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/attr_object");
+        let mut seeds = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+            if let Ok(input) = std::str::from_utf8(&bytes) {
+                if let Err(attr_object::Error::ParseError { offset, .. }) = attr_object::parse_attr_object(input, None) {
+                    assert!(offset <= input.len(), "{:?}", input);
+                }
+            }
+            seeds += 1;
+        }
+        assert!(seeds > 0);
+
+        // Used to spin until the list limit; now a positioned error
+        assert_eq!(attr_error_offset("{tags=[a } b]}").0, 9);
+        assert_eq!(attr_error_offset("tags=[,,]").0, 6);
+        assert_eq!(attr_object::parse_attr_object("k=\"日本\" t=é", None).unwrap()["t"], "é");
+    }
+}
@@ -1,9 +1,19 @@
 use rustler::{Binary, Env, NifResult, Term, Encoder, Atom};
-use pulldown_cmark::{Parser, Options, Event, Tag, CodeBlockKind, HeadingLevel};
+use markdown_ld_core::{extract_code_blocks, extract_headings, extract_links, extract_tasks, Blockquote, CodeBlock, Emphasis, Heading, Link, Task};
 use std::collections::HashMap;
 use serde_json;
 
 mod simd;
+#[cfg(feature = "telemetry")]
+mod telemetry;
+
+// Telemetry calls compile away entirely unless the `telemetry` feature is on.
+macro_rules! telemetry {
+    ($env:expr, $event:expr) => {
+        #[cfg(feature = "telemetry")]
+        telemetry::emit($env, &$event);
+    };
+}
 
 // Performance counters - help Codex track SIMD usage
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -16,30 +26,40 @@ mod atoms {
         ok,
         error,
         nil,
+        limit_exceeded,
+        telemetry_disabled,
     }
 }
 
 // Elixir-compatible map structure
 type ElixirMap = HashMap<String, Term<'static>>;
 
-#[rustler::nif]
+// Everything that walks a whole document runs on dirty CPU schedulers,
+// including the `*_simd` extractors: links, headings and code blocks are
+// full `markdown_ld_core` parses and tasks a line scan that allocates per
+// task. Only `word_count_simd`, one allocation-free SIMD scan, stays on the
+// normal ones.
+#[rustler::nif(schedule = "DirtyCpu")]
 fn parse_markdown<'a>(env: Env<'a>, content: String, _options: Vec<(String, String)>) -> Term<'a> {
     let result = parse_markdown_content(env, &content);
     (atoms::ok(), result).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn parse_markdown_binary<'a>(env: Env<'a>, binary: Binary, _options: Vec<(String, String)>) -> Term<'a> {
     let content = match std::str::from_utf8(binary.as_slice()) {
         Ok(s) => s,
-        Err(_) => return (atoms::error(), "Invalid UTF-8").encode(env),
+        Err(_) => {
+            telemetry!(env, telemetry::Event::Error { code: "invalid_utf8" });
+            return (atoms::error(), "Invalid UTF-8").encode(env);
+        }
     };
     
     let result = parse_markdown_content(env, content);
     (atoms::ok(), result).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn parse_batch_parallel<'a>(env: Env<'a>, documents: Vec<String>, _options: Vec<(String, String)>) -> Term<'a> {
     let results: Vec<Term> = documents.iter()
         .map(|doc| parse_markdown_content(env, doc))
@@ -55,27 +75,27 @@ fn word_count_simd<'a>(env: Env<'a>, content: String) -> Term<'a> {
     (atoms::ok(), count).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn extract_links_simd<'a>(env: Env<'a>, content: String) -> Term<'a> {
-    let links = extract_links(env, &content);
+    let links = extract_links(&content).encode_term(env);
     (atoms::ok(), links).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn extract_headings_simd<'a>(env: Env<'a>, content: String) -> Term<'a> {
-    let headings = extract_headings(env, &content);
+    let headings = extract_headings(&content).encode_term(env);
     (atoms::ok(), headings).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn extract_code_blocks_simd<'a>(env: Env<'a>, content: String) -> Term<'a> {
-    let code_blocks = extract_code_blocks(env, &content);
+    let code_blocks = extract_code_blocks(&content).encode_term(env);
     (atoms::ok(), code_blocks).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn extract_tasks_simd<'a>(env: Env<'a>, content: String) -> Term<'a> {
-    let tasks = extract_tasks(env, &content);
+    let tasks = extract_tasks(&content).encode_term(env);
     (atoms::ok(), tasks).encode(env)
 }
 
@@ -117,243 +137,197 @@ fn clear_pattern_cache() -> Atom {
 
 #[rustler::nif]
 fn parse_attr_object_json<'a>(env: Env<'a>, content: String) -> Term<'a> {
-    // Simple attribute object parser for now - Codex can enhance this later
-    let mut result = HashMap::new();
-    
-    // Basic key=value parsing
-    for pair in content.split_whitespace() {
-        if let Some((key, value)) = pair.split_once('=') {
-            result.insert(key.to_string(), value.trim_matches('"').to_string());
+    use markdown_ld_core::attr_object::{parse_attr_object, Error};
+
+    match parse_attr_object(&content, None) {
+        Ok(map) => match serde_json::to_string(&map) {
+            Ok(json_string) => (atoms::ok(), json_string).encode(env),
+            Err(_) => {
+                telemetry!(env, telemetry::Event::Error { code: "serialization_error" });
+                (atoms::error(), "serialization_error").encode(env)
+            }
+        },
+        Err(Error::ParseError { message, offset, snippet }) => {
+            telemetry!(env, telemetry::Event::Error { code: "parse_error" });
+            let mut error_map = HashMap::new();
+            error_map.insert("message".to_string(), message.encode(env));
+            error_map.insert("offset".to_string(), offset.encode(env));
+            error_map.insert("snippet".to_string(), snippet.encode(env));
+            (atoms::error(), error_map).encode(env)
+        }
+        Err(Error::LimitExceeded) => {
+            telemetry!(env, telemetry::Event::Error { code: "limit_exceeded" });
+            (atoms::error(), atoms::limit_exceeded()).encode(env)
         }
     }
-    
-    match serde_json::to_string(&result) {
-        Ok(json_string) => (atoms::ok(), json_string).encode(env),
-        Err(_) => (atoms::error(), "serialization_error").encode(env),
+}
+
+/// Registers `pid` to receive the NIF's telemetry events as messages, or
+/// unregisters the current handler when given `nil`
+#[rustler::nif]
+fn set_telemetry_handler<'a>(env: Env<'a>, pid: Option<rustler::LocalPid>) -> Term<'a> {
+    #[cfg(feature = "telemetry")]
+    {
+        telemetry::set_handler(pid);
+        atoms::ok().encode(env)
+    }
+    #[cfg(not(feature = "telemetry"))]
+    {
+        let _ = pid;
+        (atoms::error(), atoms::telemetry_disabled()).encode(env)
     }
 }
 
 // Core parsing function
 fn parse_markdown_content<'a>(env: Env<'a>, content: &str) -> Term<'a> {
+    telemetry!(env, telemetry::Event::ParseStart { bytes: content.len() });
     let start_time = std::time::Instant::now();
     
-    let links = extract_links(env, content);
-    let headings = extract_headings(env, content);
-    let code_blocks = extract_code_blocks(env, content);
-    let tasks = extract_tasks(env, content);
+    let doc = markdown_ld_core::parse_basic(content);
     let word_count = content.split_whitespace().count();
     
     let processing_time = start_time.elapsed().as_micros() as u64;
+    telemetry!(env, telemetry::Event::ParseStop { bytes: content.len(), duration_us: processing_time });
     
     let mut result = HashMap::new();
-    result.insert("headings".to_string(), headings);
-    result.insert("links".to_string(), links);
-    result.insert("code_blocks".to_string(), code_blocks);
-    result.insert("tasks".to_string(), tasks);
+    result.insert("headings".to_string(), doc.headings.encode_term(env));
+    result.insert("links".to_string(), doc.links.encode_term(env));
+    result.insert("code_blocks".to_string(), doc.code_blocks.encode_term(env));
+    result.insert("tasks".to_string(), doc.tasks.encode_term(env));
+    result.insert("emphasis".to_string(), doc.emphasis.encode_term(env));
+    result.insert("blockquotes".to_string(), doc.blockquotes.encode_term(env));
     result.insert("word_count".to_string(), word_count.encode(env));
     result.insert("processing_time_us".to_string(), processing_time.encode(env));
     
     result.encode(env)
 }
 
-fn extract_links<'a>(env: Env<'a>, content: &str) -> Term<'a> {
-    let mut links = Vec::new();
-    let mut line = 1usize;
-    
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    
-    let parser = Parser::new_ext(content, options);
-    
-    let mut link_text = String::new();
-    let mut in_link = false;
-    
-    for event in parser {
-        match event {
-            Event::Start(Tag::Link(_, dest_url, title)) => {
-                in_link = true;
-                link_text.clear();
-                if !title.is_empty() {
-                    link_text.push_str(&title);
-                }
-                
-                if !dest_url.is_empty() {
-                    let mut link_map = HashMap::new();
-                    link_map.insert("text".to_string(), link_text.encode(env));
-                    link_map.insert("url".to_string(), dest_url.to_string().encode(env));
-                    link_map.insert("line".to_string(), line.encode(env));
-                    
-                    links.push(link_map.encode(env));
-                }
-                in_link = false;
-            }
-            Event::Text(text) => {
-                if in_link {
-                    link_text.push_str(&text);
-                }
-                // Count newlines
-                line += text.chars().filter(|&c| c == '\n').count();
-            }
-            Event::SoftBreak | Event::HardBreak => {
-                line += 1;
-            }
-            _ => {}
+/// Term encoding for the extraction results of `markdown_ld_core`
+///
+/// Both the core structs and rustler's `Encoder` are foreign to this crate,
+/// so this local trait adapts one to the other.
+trait EncodeTerm {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a>;
+}
+
+impl<T: EncodeTerm> EncodeTerm for Vec<T> {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.iter().map(|item| item.encode_term(env)).collect::<Vec<Term>>().encode(env)
+    }
+}
+
+impl<T: EncodeTerm> EncodeTerm for Option<T> {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Some(value) => value.encode_term(env),
+            None => atoms::nil().encode(env),
         }
     }
-    
-    links.encode(env)
 }
 
-fn extract_headings<'a>(env: Env<'a>, content: &str) -> Term<'a> {
-    let mut headings = Vec::new();
-    let mut current_line = 1usize;
-    let mut in_heading = false;
-    let mut heading_level = 1u32;
-    let mut heading_text = String::new();
-    
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    
-    let parser = Parser::new_ext(content, options);
-    
-    for event in parser {
-        match event {
-            Event::Start(Tag::Heading(level, _, _)) => {
-                in_heading = true;
-                heading_level = match level {
-                    HeadingLevel::H1 => 1,
-                    HeadingLevel::H2 => 2,
-                    HeadingLevel::H3 => 3,
-                    HeadingLevel::H4 => 4,
-                    HeadingLevel::H5 => 5,
-                    HeadingLevel::H6 => 6,
-                };
-                heading_text.clear();
-            }
-            Event::End(Tag::Heading(_, _, _)) => {
-                if in_heading {
-                    let mut heading_map = HashMap::new();
-                    heading_map.insert("level".to_string(), heading_level.encode(env));
-                    heading_map.insert("text".to_string(), heading_text.clone().encode(env));
-                    heading_map.insert("line".to_string(), current_line.encode(env));
-                    
-                    headings.push(heading_map.encode(env));
-                    in_heading = false;
-                }
-            }
-            Event::Text(text) => {
-                if in_heading {
-                    heading_text.push_str(&text);
-                }
-                // Count newlines
-                current_line += text.chars().filter(|&c| c == '\n').count();
-            }
-            Event::SoftBreak | Event::HardBreak => {
-                current_line += 1;
-            }
-            _ => {}
+impl EncodeTerm for String {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        self.encode(env)
+    }
+}
+
+impl EncodeTerm for serde_json::Value {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        use serde_json::Value;
+        match self {
+            Value::Null => atoms::nil().encode(env),
+            Value::Bool(b) => b.encode(env),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => i.encode(env),
+                (None, Some(u)) => u.encode(env),
+                _ => n.as_f64().unwrap_or(0.0).encode(env),
+            },
+            Value::String(s) => s.encode(env),
+            Value::Array(items) => items.encode_term(env),
+            Value::Object(map) => map
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.encode_term(env)))
+                .collect::<HashMap<&str, Term>>()
+                .encode(env),
         }
     }
-    
-    headings.encode(env)
 }
 
-fn extract_code_blocks<'a>(env: Env<'a>, content: &str) -> Term<'a> {
-    let mut code_blocks = Vec::new();
-    let mut current_line = 1usize;
-    let mut in_code_block = false;
-    let mut current_code = String::new();
-    let mut current_language: Option<String> = None;
-    let mut code_start_line = 1usize;
-    
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    
-    let parser = Parser::new_ext(content, options);
-    
-    for event in parser {
-        match event {
-            Event::Start(Tag::CodeBlock(kind)) => {
-                in_code_block = true;
-                current_language = match kind {
-                    CodeBlockKind::Fenced(lang) => {
-                        if lang.is_empty() {
-                            None
-                        } else {
-                            Some(lang.to_string())
-                        }
-                    }
-                    CodeBlockKind::Indented => None,
-                };
-                code_start_line = current_line;
-                current_code.clear();
-            }
-            Event::End(Tag::CodeBlock(_)) => {
-                if in_code_block {
-                    let mut code_map = HashMap::new();
-                    code_map.insert("language".to_string(), 
-                        match &current_language {
-                            Some(lang) => lang.clone().encode(env),
-                            None => atoms::nil().encode(env),
-                        });
-                    code_map.insert("content".to_string(), current_code.clone().encode(env));
-                    code_map.insert("line".to_string(), code_start_line.encode(env));
-                    
-                    code_blocks.push(code_map.encode(env));
-                    in_code_block = false;
-                }
-            }
-            Event::Text(text) => {
-                if in_code_block {
-                    current_code.push_str(&text);
-                }
-                // Count newlines
-                current_line += text.chars().filter(|&c| c == '\n').count();
-            }
-            Event::SoftBreak | Event::HardBreak => {
-                if in_code_block {
-                    current_code.push('\n');
-                }
-                current_line += 1;
-            }
-            _ => {}
+impl EncodeTerm for Heading {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut heading_map = HashMap::new();
+        heading_map.insert("level", self.level.encode(env));
+        heading_map.insert("text", self.text.encode(env));
+        heading_map.insert("line", self.line.encode(env));
+        heading_map.insert("stable_id", self.stable_id.encode(env));
+        if !self.attributes.is_empty() {
+            heading_map.insert("attributes", serde_json::Value::Object(self.attributes.clone()).encode_term(env));
         }
+        heading_map.encode(env)
     }
-    
-    code_blocks.encode(env)
 }
 
-fn extract_tasks<'a>(env: Env<'a>, content: &str) -> Term<'a> {
-    let mut tasks = Vec::new();
-    let mut line_num = 1usize;
-    
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if trimmed.starts_with("- [ ]") || trimmed.starts_with("* [ ]") {
-            let mut task_map = HashMap::new();
-            task_map.insert("completed".to_string(), false.encode(env));
-            task_map.insert("text".to_string(), trimmed[5..].trim().to_string().encode(env));
-            task_map.insert("line".to_string(), line_num.encode(env));
-            
-            tasks.push(task_map.encode(env));
-        } else if trimmed.starts_with("- [x]") || trimmed.starts_with("* [x]") {
-            let mut task_map = HashMap::new();
-            task_map.insert("completed".to_string(), true.encode(env));
-            task_map.insert("text".to_string(), trimmed[5..].trim().to_string().encode(env));
-            task_map.insert("line".to_string(), line_num.encode(env));
-            
-            tasks.push(task_map.encode(env));
+impl EncodeTerm for Link {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut link_map = HashMap::new();
+        link_map.insert("text", self.text.encode(env));
+        link_map.insert("url", self.url.encode(env));
+        link_map.insert("line", self.line.encode(env));
+        if let Some(title) = &self.title {
+            link_map.insert("title", title.encode(env));
         }
-        line_num += 1;
+        link_map.encode(env)
+    }
+}
+
+impl EncodeTerm for CodeBlock {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut code_map = HashMap::new();
+        code_map.insert("language", self.language.encode_term(env));
+        code_map.insert("content", self.content.encode(env));
+        code_map.insert("line", self.line.encode(env));
+        code_map.encode(env)
+    }
+}
+
+impl EncodeTerm for Task {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut task_map = HashMap::new();
+        task_map.insert("completed", self.completed.encode(env));
+        task_map.insert("text", self.text.encode(env));
+        task_map.insert("line", self.line.encode(env));
+        task_map.insert("depth", self.depth.encode(env));
+        task_map.encode(env)
+    }
+}
+
+impl EncodeTerm for Emphasis {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut span_map = HashMap::new();
+        span_map.insert("kind", self.kind.as_str().encode(env));
+        span_map.insert("text", self.text.encode(env));
+        span_map.insert("line", self.line.encode(env));
+        span_map.insert("col_start", self.col_start.encode(env));
+        span_map.insert("col_end", self.col_end.encode(env));
+        span_map.encode(env)
+    }
+}
+
+impl EncodeTerm for Blockquote {
+    fn encode_term<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mut quote_map = HashMap::new();
+        quote_map.insert("text", self.text.encode(env));
+        quote_map.insert("line_start", self.line_start.encode(env));
+        quote_map.insert("line_end", self.line_end.encode(env));
+        quote_map.insert("depth", self.depth.encode(env));
+        if let Some(callout_type) = &self.callout_type {
+            quote_map.insert("callout_type", callout_type.encode(env));
+        }
+        if let Some(title) = &self.callout_title {
+            quote_map.insert("title", title.encode(env));
+        }
+        quote_map.encode(env)
     }
-    
-    tasks.encode(env)
 }
 
 rustler::init!("Elixir.MarkdownLd.Native");
\ No newline at end of file
@@ -0,0 +1,166 @@
+//! Telemetry events emitted by the NIF (only built with the `telemetry` feature)
+//!
+//! | Event           | Elixir event name                          | Measurements                   | Metadata          |
+//! |-----------------|--------------------------------------------|--------------------------------|-------------------|
+//! | `parse.start`   | `[:markdown_ld, :native, :parse, :start]`  | `%{bytes: n}`                  | `%{}`             |
+//! | `parse.stop`    | `[:markdown_ld, :native, :parse, :stop]`   | `%{bytes: n, duration_us: us}` | `%{}`             |
+//! | `error`         | `[:markdown_ld, :native, :error]`          | `%{}`                          | `%{code: "..."}`  |
+//!
+//! Error codes are `"invalid_utf8"`, `"parse_error"`, `"limit_exceeded"` and
+//! `"serialization_error"`. The crate has no pattern cache yet, so there are
+//! no cache hit/miss events.
+//!
+//! Every event goes to the registered [`Sink`] (by default [`LogSink`], which
+//! writes through the `log` crate under the `markdown_ld` target). If a
+//! process registered itself with `set_telemetry_handler/1`, it also receives
+//! `{:markdown_ld_native, event_name, measurements, metadata}`, ready to hand
+//! to `:telemetry.execute/3`.
+
+use rustler::{Encoder, Env, LocalPid, NifResult, Term};
+use std::sync::{Mutex, RwLock};
+
+mod atoms {
+    rustler::atoms! {
+        markdown_ld,
+        markdown_ld_native,
+        native,
+        parse,
+        start,
+        stop,
+        error,
+        bytes,
+        duration_us,
+        code,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    ParseStart { bytes: usize },
+    ParseStop { bytes: usize, duration_us: u64 },
+    Error { code: &'static str },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ParseStart { .. } => "parse.start",
+            Event::ParseStop { .. } => "parse.stop",
+            Event::Error { .. } => "error",
+        }
+    }
+
+    fn encode_message<'a>(&self, env: Env<'a>) -> NifResult<Term<'a>> {
+        let (name, measurements, metadata) = match *self {
+            Event::ParseStart { bytes } => (
+                vec![atoms::markdown_ld(), atoms::native(), atoms::parse(), atoms::start()],
+                vec![(atoms::bytes(), bytes.encode(env))],
+                vec![],
+            ),
+            Event::ParseStop { bytes, duration_us } => (
+                vec![atoms::markdown_ld(), atoms::native(), atoms::parse(), atoms::stop()],
+                vec![(atoms::bytes(), bytes.encode(env)), (atoms::duration_us(), duration_us.encode(env))],
+                vec![],
+            ),
+            Event::Error { code } => (
+                vec![atoms::markdown_ld(), atoms::native(), atoms::error()],
+                vec![],
+                vec![(atoms::code(), code.encode(env))],
+            ),
+        };
+        let measurements = Term::map_from_pairs(env, &measurements)?;
+        let metadata = Term::map_from_pairs(env, &metadata)?;
+        Ok((atoms::markdown_ld_native(), name, measurements, metadata).encode(env))
+    }
+}
+
+/// Receiver for telemetry events; must be cheap, it runs on the NIF's thread
+pub trait Sink: Send + Sync {
+    fn handle(&self, event: &Event);
+}
+
+/// Default sink, forwarding events to whatever `log` logger is installed
+pub struct LogSink;
+
+impl Sink for LogSink {
+    fn handle(&self, event: &Event) {
+        match *event {
+            Event::ParseStart { bytes } => {
+                log::debug!(target: "markdown_ld", "{} bytes={}", event.name(), bytes)
+            }
+            Event::ParseStop { bytes, duration_us } => {
+                log::debug!(target: "markdown_ld", "{} bytes={} duration_us={}", event.name(), bytes, duration_us)
+            }
+            Event::Error { code } => log::warn!(target: "markdown_ld", "{} code={}", event.name(), code),
+        }
+    }
+}
+
+static SINK: RwLock<Option<Box<dyn Sink>>> = RwLock::new(None);
+static HANDLER: Mutex<Option<LocalPid>> = Mutex::new(None);
+
+/// Replaces the sink; `None` restores the default [`LogSink`]
+pub fn set_sink(sink: Option<Box<dyn Sink>>) {
+    *SINK.write().unwrap_or_else(|e| e.into_inner()) = sink;
+}
+
+/// Registers (or with `None`, removes) the process that receives event messages
+pub fn set_handler(pid: Option<LocalPid>) {
+    *HANDLER.lock().unwrap_or_else(|e| e.into_inner()) = pid;
+}
+
+/// Passes the event to the registered sink only
+pub fn dispatch(event: &Event) {
+    match SINK.read().unwrap_or_else(|e| e.into_inner()).as_deref() {
+        Some(sink) => sink.handle(event),
+        None => LogSink.handle(event),
+    }
+}
+
+/// Passes the event to the sink and sends it to the handler process, if any
+pub fn emit(env: Env, event: &Event) {
+    dispatch(event);
+    let handler = *HANDLER.lock().unwrap_or_else(|e| e.into_inner());
+    if let (Some(pid), Ok(message)) = (handler, event.encode_message(env)) {
+        // A handler that died just misses the event.
+        let _ = env.send(&pid, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct CaptureSink(Arc<Mutex<Vec<Event>>>);
+
+    impl Sink for CaptureSink {
+        fn handle(&self, event: &Event) {
+            self.0.lock().unwrap().push(*event);
+        }
+    }
+
+    #[test]
+    fn registered_sink_captures_events() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        set_sink(Some(Box::new(CaptureSink(captured.clone()))));
+
+        dispatch(&Event::ParseStart { bytes: 12 });
+        dispatch(&Event::ParseStop { bytes: 12, duration_us: 40 });
+        dispatch(&Event::Error { code: "invalid_utf8" });
+        set_sink(None);
+        dispatch(&Event::ParseStart { bytes: 1 });
+
+        let events = captured.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                Event::ParseStart { bytes: 12 },
+                Event::ParseStop { bytes: 12, duration_us: 40 },
+                Event::Error { code: "invalid_utf8" },
+            ]
+        );
+        let names: Vec<&str> = events.iter().map(Event::name).collect();
+        assert_eq!(names, ["parse.start", "parse.stop", "error"]);
+    }
+}
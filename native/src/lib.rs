@@ -1,11 +1,26 @@
 use lazy_static::lazy_static;
+use markdown_ld_core::{
+    attr_object, Blockquote, CodeBlock, Emphasis, Heading, Link, ParsedDoc, Task,
+};
 use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
 use regex::Regex;
-use rustler::{Encoder, Env, Resource, Term};
+use rustler::{Binary, Encoder, Env, MapIterator, Resource, ResourceArc, Term};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// The reference mem8 code: `wave` and `consciousness` back the mem8 NIFs,
+/// and `simd` (with the `simd` feature) lets the benchmark NIFs measure the
+/// real `PerformanceBenchmark`
+#[allow(dead_code)]
+#[path = "../../docs/mem8"]
+mod mem8 {
+    pub mod consciousness;
+    #[cfg(feature = "simd")]
+    pub mod simd;
+    pub mod wave;
+}
 
 mod atoms {
     rustler::atoms! {
@@ -34,13 +49,21 @@ mod atoms {
 }
 
 // Resource types for complex data structures
-#[derive(Debug)]
+
+/// Shared mem8 state handed to Elixir as a resource
+///
+/// Every Elixir process holding the resource shares one grid. Locks are
+/// held only for a single store or query, and never across calls: a NIF
+/// blocked on the lock blocks its scheduler thread, so a long-held lock
+/// stalls unrelated processes. Interference queries take the read lock and
+/// run concurrently; only stores take the write lock.
 pub struct Mem8Context {
-    pub wave_grid: Arc<Mutex<WaveGrid>>,
+    pub wave_grid: Arc<RwLock<mem8::wave::WaveGrid>>,
     pub consciousness: ConsciousnessState,
     pub memory_index: MemIndex,
 }
 
+#[rustler::resource_impl]
 impl Resource for Mem8Context {}
 
 #[derive(Debug)]
@@ -54,15 +77,6 @@ pub struct PolyglotDocument {
 impl Resource for PolyglotDocument {}
 
 // Core data structures from mem8
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WaveGrid {
-    pub width: usize,
-    pub height: usize,
-    pub depth: usize,
-    pub grid: Vec<Option<MemoryWave>>,
-    pub noise_floor: f32,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemoryWave {
     pub amplitude: f32,
@@ -123,6 +137,8 @@ pub struct ConcealmentData {
 // RFC 8785 JCS implementation
 lazy_static! {
     static ref JCS_REGEX: Regex = Regex::new(r#""([^"\\]|\\.)*""#).unwrap();
+    static ref POLYGLOT_COMMENT_REGEX: Regex =
+        Regex::new(r"<!-- polyglot:([^:]+):([^-]+) -->").unwrap();
     static ref POLYGLOT_DETECTORS: Vec<PolyglotDetector> = vec![
         PolyglotDetector::new(
             "dockerfile",
@@ -161,28 +177,96 @@ impl PolyglotDetector {
 }
 
 // Zero-width character concealment
+//
+// `ZERO_WIDTH_CHARS` is everything `strip_all_concealment` removes. Only
+// `SAFE_ZERO_WIDTH_ALPHABET` carries payloads: U+200D (ZWJ) glues emoji
+// sequences such as the family emoji together, U+FEFF doubles as the
+// byte-order mark and U+200C (ZWNJ) is ordinary orthography in Persian and
+// Indic scripts, so all three turn up in real text and would be misread as
+// data.
 const ZERO_WIDTH_CHARS: &[char] = &[
     '\u{200B}', // Zero-width space
     '\u{200C}', // Zero-width non-joiner
     '\u{200D}', // Zero-width joiner
     '\u{2060}', // Word joiner
+    '\u{2062}', // Invisible times
+    '\u{2063}', // Invisible separator
     '\u{FEFF}', // Zero-width no-break space
 ];
 
+/// Default payload alphabet, two bits per character
+///
+/// Word joiner and the invisible math operators essentially never occur in
+/// prose.
+const SAFE_ZERO_WIDTH_ALPHABET: &[char] = &[
+    '\u{200B}', // Zero-width space
+    '\u{2060}', // Word joiner
+    '\u{2062}', // Invisible times
+    '\u{2063}', // Invisible separator
+];
+
 // Main NIF functions
-#[rustler::nif]
+//
+// Scheduling: anything whose cost grows with the whole document runs on a
+// dirty CPU scheduler so a large input can't hold a normal scheduler past
+// its ~1ms budget. That covers `parse_markdown`, `parse_and_merge`,
+// `parse_with_mem8`, `document_fingerprint`, `lint`, `link_graph`,
+// `generate_toc`, `chunk_document`, `document_diff`,
+// `reparse_incremental`, `check_content_links`, the polyglot and
+// concealment scans (`detect_polyglot`, `extract_concealed_data`,
+// `strip_concealment`, `hide_data_zero_width`, `extract_data_zero_width`),
+// `extract_frontmatter_any`, the hashing of a whole binary
+// (`content_address`, `verify_content_link`), the `benchmark_*` NIFs and
+// `mem8_create_context` (which allocates the full 64×64×256 grid).
+// Per-cell grid operations (`mem8_store_wave`, `mem8_get_interference`)
+// and the small string/JSON helpers stay on the normal schedulers, since a
+// dirty hop would cost more than the work.
+//
+// Dirty schedulers are few (one per core by default) and a dirty NIF still
+// can't be preempted, so callers with unbounded input should split it first
+// — `chunk_document` output, or a `Task.async_stream/3` over files — rather
+// than hand a multi-megabyte document to a single call.
+#[rustler::nif(schedule = "DirtyCpu")]
 fn parse_markdown<'a>(env: Env<'a>, content: String, options: Vec<(String, String)>) -> Term<'a> {
     let result = parse_markdown_content(env, &content, &options);
     match result {
         Ok(parsed) => (atoms::ok(), parsed).encode(env),
-        Err(e) => (atoms::error(), format!("Parse error: {:?}", e)).encode(env),
+        Err(e) => (atoms::error(), encode_parse_error(env, e)).encode(env),
     }
 }
 
-#[rustler::nif]
-fn parse_with_mem8<'a>(env: Env<'a>, content: String, _mem8_context: Term<'a>) -> Term<'a> {
+/// Parse `{source, content}` pairs with the same options and merge them
+/// into one index; see `build_corpus_index`
+///
+/// Takes document sources, not earlier `parse_markdown` results: every
+/// document is parsed here. The first one that fails to parse aborts the
+/// merge with `{:error, {source, reason}}`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_and_merge<'a>(
+    env: Env<'a>,
+    documents: Vec<(String, String)>,
+    options: Vec<(String, String)>,
+) -> Term<'a> {
+    let mut parsed = Vec::with_capacity(documents.len());
+    for (source, content) in &documents {
+        match parse_document(content, &options) {
+            Ok(result) => parsed.push((source.as_str(), result)),
+            Err(e) => return (atoms::error(), (source, encode_parse_error(env, e))).encode(env),
+        }
+    }
+    (
+        atoms::ok(),
+        encode_corpus_index(env, &build_corpus_index(&parsed)),
+    )
+        .encode(env)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_with_mem8<'a>(env: Env<'a>, content: String, mem8_context: Term<'a>) -> Term<'a> {
     // Extract mem8 context from resource or create default
-    let context = create_default_mem8_context();
+    let context = mem8_context
+        .decode::<ResourceArc<Mem8Context>>()
+        .unwrap_or_else(|_| ResourceArc::new(create_default_mem8_context()));
 
     match parse_with_memory_context(env, &content, &context) {
         Ok(result) => (atoms::ok(), result).encode(env),
@@ -190,11 +274,31 @@ fn parse_with_mem8<'a>(env: Env<'a>, content: String, _mem8_context: Term<'a>) -
     }
 }
 
-#[rustler::nif]
-fn detect_polyglot<'a>(env: Env<'a>, content: String) -> Term<'a> {
-    match detect_polyglot_document(&content) {
+/// Options tune detection (see `PolyglotConfig::from_options`); the result
+/// always carries `"score"`, the best detector score whether or not it
+/// cleared the threshold, and `"scores"` per language, for calibration
+#[rustler::nif(schedule = "DirtyCpu")]
+fn detect_polyglot<'a>(env: Env<'a>, content: String, options: Vec<(String, String)>) -> Term<'a> {
+    let config = match PolyglotConfig::from_options(&options) {
+        Ok(config) => config,
+        Err(e) => return (atoms::error(), encode_parse_error(env, e)).encode(env),
+    };
+    let scores = polyglot_scores(&content, &config);
+    let best_score = scores
+        .iter()
+        .map(|(_, score)| *score)
+        .fold(0.0f32, f32::max);
+    let score_map: HashMap<&str, f32> = scores
+        .iter()
+        .map(|(language, score)| (language.as_str(), *score))
+        .collect();
+
+    let mut result = HashMap::new();
+    result.insert("score".to_string(), best_score.encode(env));
+    result.insert("scores".to_string(), score_map.encode(env));
+    result.insert("threshold".to_string(), config.threshold.encode(env));
+    match polyglot_document_from_scores(&content, &scores, config.threshold) {
         Some(polyglot) => {
-            let mut result = HashMap::new();
             result.insert("detected".to_string(), true.encode(env));
             result.insert("language".to_string(), polyglot.language.encode(env));
             result.insert(
@@ -219,14 +323,13 @@ fn detect_polyglot<'a>(env: Env<'a>, content: String) -> Term<'a> {
             (atoms::ok(), result).encode(env)
         }
         None => {
-            let mut result = HashMap::new();
             result.insert("detected".to_string(), false.encode(env));
             (atoms::ok(), result).encode(env)
         }
     }
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
 fn extract_concealed_data<'a>(env: Env<'a>, content: String) -> Term<'a> {
     let concealment = extract_all_concealment(&content);
 
@@ -245,7 +348,9 @@ fn extract_concealed_data<'a>(env: Env<'a>, content: String) -> Term<'a> {
     );
 
     if !concealment.zero_width_chars.is_empty() {
-        if let Ok(decoded) = decode_zero_width_data(&concealment.zero_width_chars) {
+        if let Ok(decoded) =
+            decode_zero_width_data(&concealment.zero_width_chars, SAFE_ZERO_WIDTH_ALPHABET)
+        {
             result.insert("decoded_data".to_string(), decoded.encode(env));
         }
     }
@@ -253,13 +358,37 @@ fn extract_concealed_data<'a>(env: Env<'a>, content: String) -> Term<'a> {
     (atoms::ok(), result).encode(env)
 }
 
-#[rustler::nif]
+#[rustler::nif(schedule = "DirtyCpu")]
+fn strip_concealment<'a>(env: Env<'a>, content: String) -> Term<'a> {
+    let (clean, removed) = strip_all_concealment(&content);
+
+    let mut report = HashMap::new();
+    report.insert("zero_width_chars".to_string(), removed.zero_width_chars);
+    report.insert(
+        "trailing_whitespace_lines".to_string(),
+        removed.trailing_whitespace_lines,
+    );
+    report.insert("polyglot_comments".to_string(), removed.polyglot_comments);
+
+    (atoms::ok(), clean, report).encode(env)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
 fn hide_data_zero_width<'a>(env: Env<'a>, text: String, data: String) -> Term<'a> {
-    let encoded = encode_zero_width_data(&data);
+    let encoded = encode_zero_width_data(data.as_bytes(), SAFE_ZERO_WIDTH_ALPHABET);
     let hidden_text = format!("{}{}", text, encoded);
     (atoms::ok(), hidden_text).encode(env)
 }
 
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_data_zero_width<'a>(env: Env<'a>, text: String) -> Term<'a> {
+    let symbols = extract_zero_width_chars(&text);
+    match decode_zero_width_data(&symbols, SAFE_ZERO_WIDTH_ALPHABET) {
+        Ok(data) => (atoms::ok(), data).encode(env),
+        Err(e) => (atoms::error(), e).encode(env),
+    }
+}
+
 #[rustler::nif]
 fn generate_stable_id<'a>(
     env: Env<'a>,
@@ -271,6 +400,237 @@ fn generate_stable_id<'a>(
     (atoms::ok(), stable_id).encode(env)
 }
 
+#[rustler::nif(schedule = "DirtyCpu")]
+fn verify_content_link(hash_hex: String, data: Binary) -> bool {
+    verify_content_hash(&hash_hex, data.as_slice())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn content_address(data: Binary) -> String {
+    sha256_hex(data.as_slice())
+}
+
+/// Partition the document's content-addressed links by whether their hash
+/// is in `known_hashes`: a list of hex digests, or one binary of packed
+/// 32-byte raw digests (a binary is always read as packed, never as hex)
+///
+/// Returns `{:ok, %{"resolved" => links, "missing" => links}}`, each link
+/// a map of `"text"`, `"hash"`, `"line"`, `"byte_start"` and `"byte_end"`.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn check_content_links<'a>(env: Env<'a>, content: String, known_hashes: Term<'a>) -> Term<'a> {
+    let known = if known_hashes.is_binary() {
+        known_hashes
+            .decode::<Binary>()
+            .map_err(|_| "known_hashes: expected a binary".to_string())
+            .and_then(|packed| known_hashes_from_packed(packed.as_slice()))
+    } else {
+        known_hashes
+            .decode::<Vec<String>>()
+            .map_err(|_| "known_hashes: expected a list of strings or a binary".to_string())
+            .and_then(|hashes| known_hashes_from_hex(&hashes))
+    };
+    let known = match known {
+        Ok(known) => known,
+        Err(e) => return (atoms::error(), e).encode(env),
+    };
+
+    let check = check_content_links_against(&content, &known);
+    let encode_all = |links: &[LinkOut]| -> Vec<Term<'a>> {
+        links
+            .iter()
+            .map(|link| {
+                let mut link_map = HashMap::new();
+                link_map.insert("text".to_string(), link.text.encode(env));
+                link_map.insert("hash".to_string(), link.url.encode(env));
+                link_map.insert("line".to_string(), link.line.encode(env));
+                link_map.insert("byte_start".to_string(), link.offset.encode(env));
+                link_map.insert("byte_end".to_string(), link.end.encode(env));
+                link_map.encode(env)
+            })
+            .collect()
+    };
+
+    let mut result = HashMap::new();
+    result.insert(
+        "resolved".to_string(),
+        encode_all(&check.resolved).encode(env),
+    );
+    result.insert(
+        "missing".to_string(),
+        encode_all(&check.missing).encode(env),
+    );
+    (atoms::ok(), result).encode(env)
+}
+
+/// SHA-256 hex of the document with cosmetic formatting normalized away;
+/// see `fingerprint_document`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn document_fingerprint(content: String) -> String {
+    fingerprint_document(&content)
+}
+
+/// `{:ok, %{"format" => "yaml" | "toml" | "json", "data" => map}}` for a
+/// document's frontmatter, `{:ok, nil}` without one; see
+/// `detect_frontmatter`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_frontmatter_any<'a>(env: Env<'a>, content: String) -> Term<'a> {
+    match detect_frontmatter(&content) {
+        Some(frontmatter) => {
+            let mut result = HashMap::new();
+            result.insert("format".to_string(), frontmatter.format.encode(env));
+            result.insert(
+                "data".to_string(),
+                encode_json_value(env, &frontmatter.data),
+            );
+            (atoms::ok(), result).encode(env)
+        }
+        None => (atoms::ok(), atoms::nil()).encode(env),
+    }
+}
+
+/// GitHub's anchor for a single heading text; see `github_slug`
+#[rustler::nif]
+fn github_heading_slug(text: String) -> String {
+    github_slug(&text)
+}
+
+/// `{:ok, markdown}`: a nested list of links to the document's headings;
+/// see `render_toc` and `toc_levels_from_options`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn generate_toc<'a>(env: Env<'a>, content: String, options: Vec<(String, String)>) -> Term<'a> {
+    match toc_levels_from_options(&options) {
+        Ok((min_level, max_level)) => {
+            let headings = collect_headings(&content, parse_structure(&content).headings);
+            (atoms::ok(), render_toc(&headings, min_level, max_level)).encode(env)
+        }
+        Err(e) => (atoms::error(), encode_parse_error(env, e)).encode(env),
+    }
+}
+
+// SIMD benchmarks
+//
+// Each runs one `mem8::simd::PerformanceBenchmark` method and returns
+// `{:ok, %{"operation", "num_items", "standard_ns", "simd_ns", "speedup",
+// "max_error"}}`. A speedup near 1.0 means the vector path is not buying
+// anything on this machine; `speedup` is nil if the SIMD run was too fast
+// to time. Builds without the `simd` feature return `{:error, reason}`, as
+// do the wave benchmarks for more than `MAX_BENCHMARK_WAVES` waves.
+
+/// Largest `num_waves` the wave benchmarks accept. Both runs allocate per
+/// wave, and with `panic = "abort"` an allocation failure would take the
+/// whole VM down, so larger counts are refused up front.
+const MAX_BENCHMARK_WAVES: usize = 1 << 20;
+
+fn check_benchmark_waves(num_waves: usize) -> Result<(), String> {
+    if num_waves > MAX_BENCHMARK_WAVES {
+        Err(format!(
+            "num_waves {} exceeds the maximum of {}",
+            num_waves, MAX_BENCHMARK_WAVES
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Wave values for `num_waves` synthetic waves, scalar against `f32x8`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn benchmark_wave_calculation(env: Env<'_>, num_waves: usize) -> Term<'_> {
+    if let Err(reason) = check_benchmark_waves(num_waves) {
+        return (atoms::error(), reason).encode(env);
+    }
+    #[cfg(feature = "simd")]
+    {
+        let result = mem8::simd::PerformanceBenchmark::new().benchmark_wave_calculation(num_waves);
+        (atoms::ok(), encode_benchmark_result(env, &result)).encode(env)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        (atoms::error(), SIMD_DISABLED).encode(env)
+    }
+}
+
+/// Interference over one 64×64 layer of a populated default grid, cell by
+/// cell against the row-blocked SIMD pass
+#[rustler::nif(schedule = "DirtyCpu")]
+fn benchmark_grid_processing(env: Env<'_>) -> Term<'_> {
+    #[cfg(feature = "simd")]
+    {
+        let result =
+            mem8::simd::PerformanceBenchmark::new().benchmark_grid_processing(&benchmark_grid());
+        (atoms::ok(), encode_benchmark_result(env, &result)).encode(env)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        (atoms::error(), SIMD_DISABLED).encode(env)
+    }
+}
+
+/// Emotional modulation for `num_waves` synthetic waves
+#[rustler::nif(schedule = "DirtyCpu")]
+fn benchmark_emotional_modulation(env: Env<'_>, num_waves: usize) -> Term<'_> {
+    if let Err(reason) = check_benchmark_waves(num_waves) {
+        return (atoms::error(), reason).encode(env);
+    }
+    #[cfg(feature = "simd")]
+    {
+        let result =
+            mem8::simd::PerformanceBenchmark::new().benchmark_emotional_modulation(num_waves);
+        (atoms::ok(), encode_benchmark_result(env, &result)).encode(env)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        (atoms::error(), SIMD_DISABLED).encode(env)
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+const SIMD_DISABLED: &str = "built without the simd feature";
+
+/// A default 64×64×256 grid with the three layers around the middle one
+/// `benchmark_grid_processing` reads filled, so every cell sees a full
+/// neighbourhood
+#[cfg(feature = "simd")]
+fn benchmark_grid() -> mem8::wave::WaveGrid {
+    let mut grid = mem8::wave::WaveGrid::new();
+    let middle = (grid.depth / 2) as u16;
+    for z in middle - 1..=middle + 1 {
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let frequency = ((x * 7 + y * 13 + z as usize) % 1000) as f32;
+                let wave = mem8::wave::MemoryWave::new(frequency.max(1.0), 0.8);
+                grid.store(x as u8, y as u8, z, wave);
+            }
+        }
+    }
+    grid
+}
+
+#[cfg(feature = "simd")]
+fn encode_benchmark_result<'a>(env: Env<'a>, result: &mem8::simd::BenchmarkResult) -> Term<'a> {
+    let mut result_map = HashMap::new();
+    result_map.insert("operation".to_string(), result.operation.encode(env));
+    result_map.insert("num_items".to_string(), result.num_items.encode(env));
+    result_map.insert(
+        "standard_ns".to_string(),
+        (result.standard_duration.as_nanos() as u64).encode(env),
+    );
+    result_map.insert(
+        "simd_ns".to_string(),
+        (result.simd_duration.as_nanos() as u64).encode(env),
+    );
+    // Zero or sub-resolution timings give inf/NaN, which a BEAM float can't hold
+    result_map.insert(
+        "speedup".to_string(),
+        result
+            .speedup
+            .is_finite()
+            .then_some(result.speedup)
+            .encode(env),
+    );
+    result_map.insert("max_error".to_string(), result.max_error.encode(env));
+    result_map.encode(env)
+}
+
 #[rustler::nif]
 fn canonicalize_json<'a>(env: Env<'a>, json_str: String) -> Term<'a> {
     match json_canonicalize(&json_str) {
@@ -279,18 +639,81 @@ fn canonicalize_json<'a>(env: Env<'a>, json_str: String) -> Term<'a> {
     }
 }
 
+#[rustler::nif]
+fn jcs_digest<'a>(env: Env<'a>, json_str: String) -> Term<'a> {
+    let digest = serde_json::from_str(&json_str)
+        .map_err(|e| format!("JSON parse error: {}", e))
+        .and_then(|value| canonical_digest(&value));
+    match digest {
+        Ok(pair) => (atoms::ok(), pair).encode(env),
+        Err(e) => (atoms::error(), format!("JCS error: {}", e)).encode(env),
+    }
+}
+
+#[rustler::nif]
+fn jcs_digest_term<'a>(env: Env<'a>, term: Term<'a>) -> Term<'a> {
+    match term_to_json(term).and_then(|value| canonical_digest(&value)) {
+        Ok(pair) => (atoms::ok(), pair).encode(env),
+        Err(e) => (atoms::error(), format!("JCS error: {}", e)).encode(env),
+    }
+}
+
+/// Structural check of JSON-LD islands: `island_json` is one island object
+/// or an array of them, and the result has one report per island
+#[rustler::nif]
+fn validate_jsonld<'a>(env: Env<'a>, island_json: String, required_keys: Vec<String>) -> Term<'a> {
+    let reports = serde_json::from_str(&island_json)
+        .map_err(|e| format!("JSON parse error: {}", e))
+        .and_then(|value| check_jsonld_shapes(&value, &required_keys));
+    match reports {
+        Ok(reports) => {
+            let reports: Vec<Term> = reports
+                .iter()
+                .map(|report| {
+                    let mut report_map = HashMap::new();
+                    report_map.insert("missing_keys".to_string(), report.missing_keys.encode(env));
+                    report_map.insert("has_context".to_string(), report.has_context.encode(env));
+                    report_map.insert("types".to_string(), report.types.encode(env));
+                    report_map.encode(env)
+                })
+                .collect();
+            (atoms::ok(), reports).encode(env)
+        }
+        Err(e) => (atoms::error(), e).encode(env),
+    }
+}
+
+/// The subtree of an island at an RFC 6901 pointer, e.g. `/@graph/0/name`
+#[rustler::nif]
+fn jsonld_island_pointer<'a>(env: Env<'a>, island_json: String, pointer: String) -> Term<'a> {
+    let resolved = serde_json::from_str(&island_json)
+        .map_err(|e| format!("JSON parse error: {}", e))
+        .and_then(|value| resolve_json_pointer(&value, &pointer).and_then(canonicalize_json_value));
+    match resolved {
+        Ok(canonical) => (atoms::ok(), canonical).encode(env),
+        Err(e) => (atoms::error(), e).encode(env),
+    }
+}
+
 #[rustler::nif]
 fn parse_attribute_object<'a>(env: Env<'a>, attr_str: String, mode: String) -> Term<'a> {
     let strict = mode == "strict";
-    match parse_attribute_object_mini_grammar(&attr_str, strict) {
-        Ok(attrs) => {
-            let encoded_attrs: HashMap<String, Term> = attrs
-                .into_iter()
-                .map(|(k, v)| (k, encode_attribute_value(env, v)))
-                .collect();
-            (atoms::ok(), encoded_attrs).encode(env)
+    match parse_attribute_map(&attr_str, strict) {
+        Ok(attrs) => (atoms::ok(), encode_attributes(env, &attrs)).encode(env),
+        Err(attr_object::Error::ParseError {
+            message,
+            offset,
+            snippet,
+        }) => {
+            let mut error_map = HashMap::new();
+            error_map.insert("message".to_string(), message.encode(env));
+            error_map.insert("offset".to_string(), offset.encode(env));
+            error_map.insert("snippet".to_string(), snippet.encode(env));
+            (atoms::error(), error_map).encode(env)
+        }
+        Err(attr_object::Error::LimitExceeded) => {
+            (atoms::error(), atoms::limit_exceeded()).encode(env)
         }
-        Err(e) => (atoms::error(), format!("Attribute parse error: {:?}", e)).encode(env),
     }
 }
 
@@ -305,12 +728,7 @@ fn create_memory_wave<'a>(env: Env<'a>, frequency: f32, amplitude: f32, phase: f
         decay_tau: Some(5.0), // 5 second default decay
     };
 
-    let mut wave_data = HashMap::new();
-    wave_data.insert("amplitude".to_string(), wave.amplitude.encode(env));
-    wave_data.insert("frequency".to_string(), wave.frequency.encode(env));
-    wave_data.insert("phase".to_string(), wave.phase.encode(env));
-
-    (atoms::ok(), wave_data).encode(env)
+    (atoms::ok(), encode_memory_wave(env, &wave)).encode(env)
 }
 
 #[rustler::nif]
@@ -325,796 +743,6932 @@ fn wave_interference<'a>(env: Env<'a>, _waves: Vec<Term<'a>>, t: f32) -> Term<'a
     (atoms::ok(), total_amplitude).encode(env)
 }
 
-// Implementation functions
-fn parse_markdown_content<'a>(
-    env: Env<'a>,
-    content: &str,
-    _options: &[(String, String)],
-) -> Result<Term<'a>, String> {
-    let start_time = std::time::Instant::now();
-
-    let headings = extract_headings_with_attributes(env, content)?;
-    let links = extract_links_with_attributes(env, content)?;
-    let code_blocks = extract_code_blocks_enhanced(env, content)?;
-    let tasks = extract_tasks_enhanced(env, content)?;
-    let jsonld_islands = extract_jsonld_islands(env, content)?;
+/// A fresh 64×64×256 context for `mem8_store_wave`/`mem8_get_interference`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn mem8_create_context<'a>(env: Env<'a>) -> Term<'a> {
+    (atoms::ok(), ResourceArc::new(create_default_mem8_context())).encode(env)
+}
 
-    // Check for polyglot content
-    let polyglot = detect_polyglot_document(content);
+/// Store a wave map (as from `create_memory_wave`) at a grid cell
+#[rustler::nif]
+fn mem8_store_wave<'a>(
+    env: Env<'a>,
+    ctx: ResourceArc<Mem8Context>,
+    x: usize,
+    y: usize,
+    z: usize,
+    wave_params: Term<'a>,
+) -> Term<'a> {
+    match decode_memory_wave(wave_params).and_then(|wave| mem8_store(&ctx, x, y, z, wave)) {
+        Ok(()) => atoms::ok().encode(env),
+        Err(e) => (atoms::error(), e).encode(env),
+    }
+}
 
-    let processing_time = start_time.elapsed().as_micros() as u64;
+#[rustler::nif]
+fn mem8_get_interference<'a>(
+    env: Env<'a>,
+    ctx: ResourceArc<Mem8Context>,
+    x: usize,
+    y: usize,
+    z: usize,
+    t: f32,
+) -> Term<'a> {
+    match mem8_interference(&ctx, x, y, z, t) {
+        Ok(value) => (atoms::ok(), value).encode(env),
+        Err(e) => (atoms::error(), e).encode(env),
+    }
+}
 
-    let mut result = HashMap::new();
-    result.insert("headings".to_string(), headings);
-    result.insert("links".to_string(), links);
-    result.insert("code_blocks".to_string(), code_blocks);
-    result.insert("tasks".to_string(), tasks);
-    result.insert("jsonld_islands".to_string(), jsonld_islands);
-    result.insert(
-        "processing_time_us".to_string(),
-        processing_time.encode(env),
-    );
+/// `mem8::wave::phase_coherence` of two wave lists
+#[rustler::nif]
+fn phase_coherence<'a>(env: Env<'a>, a: Vec<Term<'a>>, b: Vec<Term<'a>>) -> Term<'a> {
+    let decode = |waves: Vec<Term<'a>>| -> Result<Vec<mem8::wave::MemoryWave>, String> {
+        waves
+            .into_iter()
+            .map(|term| decode_memory_wave(term).map(|wave| wave.to_mem8()))
+            .collect()
+    };
 
-    if let Some(poly) = polyglot {
-        result.insert("polyglot_detected".to_string(), true.encode(env));
-        result.insert("polyglot_language".to_string(), poly.language.encode(env));
+    match (decode(a), decode(b)) {
+        (Ok(a), Ok(b)) => (atoms::ok(), mem8::wave::phase_coherence(&a, &b)).encode(env),
+        (Err(e), _) | (_, Err(e)) => (atoms::error(), e).encode(env),
     }
+}
 
-    Ok(result.encode(env))
+/// Wave count per band, keyed by the `mem8::consciousness` category names
+#[rustler::nif]
+fn band_histogram<'a>(env: Env<'a>, waves: Vec<Term<'a>>) -> Term<'a> {
+    match waves
+        .into_iter()
+        .map(decode_memory_wave)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(waves) => (atoms::ok(), wave_band_histogram(&waves)).encode(env),
+        Err(e) => (atoms::error(), e).encode(env),
+    }
 }
 
-fn parse_with_memory_context<'a>(
-    env: Env<'a>,
-    content: &str,
-    context: &Mem8Context,
-) -> Result<Term<'a>, String> {
-    // Enhanced parsing with mem8 wave context
-    let mut result = HashMap::new();
+#[rustler::nif(schedule = "DirtyCpu")]
+fn lint<'a>(env: Env<'a>, content: String, rules: Vec<String>) -> Term<'a> {
+    let enabled = match select_lint_rules(&rules) {
+        Ok(enabled) => enabled,
+        Err(e) => return (atoms::error(), e).encode(env),
+    };
 
-    // Parse standard markdown
-    let standard_result = parse_markdown_content(env, content, &[])?;
+    let issues: Vec<Term> = lint_document(&content, &enabled)
+        .into_iter()
+        .map(|issue| {
+            let mut issue_map = HashMap::new();
+            issue_map.insert("rule".to_string(), issue.rule.encode(env));
+            issue_map.insert("line".to_string(), issue.line.encode(env));
+            issue_map.insert("message".to_string(), issue.message.encode(env));
+            issue_map.insert("severity".to_string(), issue.severity.encode(env));
+            issue_map.encode(env)
+        })
+        .collect();
 
-    // Add memory context influence
-    let wave_influence = calculate_wave_context_influence(&context.wave_grid, content);
-    let consciousness_level = context.consciousness.awareness_level;
+    (atoms::ok(), issues).encode(env)
+}
 
-    result.insert("standard_parse".to_string(), standard_result);
-    result.insert("wave_influence".to_string(), wave_influence.encode(env));
-    result.insert(
-        "consciousness_level".to_string(),
-        consciousness_level.encode(env),
-    );
+#[rustler::nif(schedule = "DirtyCpu")]
+fn link_graph<'a>(env: Env<'a>, content: String) -> Term<'a> {
+    let edges: Vec<Term> = build_link_graph(&content)
+        .iter()
+        .map(|edge| {
+            let mut edge_map = HashMap::new();
+            edge_map.insert("url".to_string(), edge.url.encode(env));
+            edge_map.insert("text".to_string(), edge.text.encode(env));
+            edge_map.insert("line".to_string(), edge.line.encode(env));
+            edge_map.insert("kind".to_string(), edge.kind.encode(env));
+            edge_map.insert(
+                "resolves".to_string(),
+                match edge.resolves {
+                    Some(resolves) => resolves.encode(env),
+                    None => atoms::nil().encode(env),
+                },
+            );
+            edge_map.encode(env)
+        })
+        .collect();
 
-    Ok(result.encode(env))
+    (atoms::ok(), edges).encode(env)
 }
 
-fn extract_headings_with_attributes<'a>(env: Env<'a>, content: &str) -> Result<Term<'a>, String> {
-    let mut headings = Vec::new();
-    let mut current_line = 1usize;
+#[rustler::nif(schedule = "DirtyCpu")]
+fn chunk_document<'a>(env: Env<'a>, content: String) -> Term<'a> {
+    let chunks: Vec<Term> = chunk_blocks(&content)
+        .iter()
+        .map(|chunk| chunk_map(env, chunk).encode(env))
+        .collect();
 
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
+    (atoms::ok(), chunks).encode(env)
+}
 
-    let parser = Parser::new_ext(content, options);
+/// Block-level alignment of two versions of a document; see
+/// `diff_documents`
+#[rustler::nif(schedule = "DirtyCpu")]
+fn document_diff<'a>(env: Env<'a>, old_content: String, new_content: String) -> Term<'a> {
+    let diff = diff_documents(&old_content, &new_content);
 
-    let mut in_heading = false;
-    let mut heading_level = 1u32;
-    let mut heading_text = String::new();
-    let mut heading_start_line = 1usize;
+    let mut counts: HashMap<&str, usize> = ["unchanged", "modified", "added", "removed"]
+        .into_iter()
+        .map(|op| (op, 0))
+        .collect();
+    let operations: Vec<Term> = diff
+        .operations
+        .iter()
+        .map(|operation| {
+            *counts.entry(operation.op).or_default() += 1;
+            let mut op_map = HashMap::new();
+            op_map.insert("op".to_string(), operation.op.encode(env));
+            op_map.insert("kind".to_string(), operation.kind.encode(env));
+            op_map.insert("old_id".to_string(), operation.old_id.encode(env));
+            op_map.insert("new_id".to_string(), operation.new_id.encode(env));
+            op_map.insert("old_line".to_string(), operation.old_line.encode(env));
+            op_map.insert("new_line".to_string(), operation.new_line.encode(env));
+            op_map.insert("text".to_string(), operation.text.encode(env));
+            op_map.insert("similarity".to_string(), operation.similarity.encode(env));
+            op_map.encode(env)
+        })
+        .collect();
 
-    for event in parser {
-        match event {
-            Event::Start(Tag::Heading(level, _fragment_id, _classes)) => {
-                in_heading = true;
-                heading_level = match level {
-                    HeadingLevel::H1 => 1,
-                    HeadingLevel::H2 => 2,
-                    HeadingLevel::H3 => 3,
-                    HeadingLevel::H4 => 4,
-                    HeadingLevel::H5 => 5,
-                    HeadingLevel::H6 => 6,
-                };
-                heading_text.clear();
-                heading_start_line = current_line;
-            }
-            Event::End(Tag::Heading(_, _, _)) => {
-                if in_heading {
-                    // Parse inline attributes if present
-                    let (clean_text, attributes) = parse_inline_attributes(&heading_text);
-
-                    let mut heading_map = HashMap::new();
-                    heading_map.insert("level".to_string(), heading_level.encode(env));
-                    heading_map.insert("text".to_string(), clean_text.encode(env));
-                    heading_map.insert("line".to_string(), heading_start_line.encode(env));
-
-                    if !attributes.is_empty() {
-                        let attr_map: HashMap<String, Term> = attributes
-                            .into_iter()
-                            .map(|(k, v)| (k, encode_attribute_value(env, v)))
-                            .collect();
-                        heading_map.insert("attributes".to_string(), attr_map.encode(env));
-                    }
+    let mut result = HashMap::new();
+    result.insert("operations".to_string(), operations.encode(env));
+    result.insert("counts".to_string(), counts.encode(env));
+    result.insert("similarity".to_string(), diff.similarity.encode(env));
+    (atoms::ok(), result).encode(env)
+}
 
-                    // Generate stable ID
-                    let stable_id = generate_heading_stable_id(&clean_text, heading_level);
-                    heading_map.insert("stable_id".to_string(), stable_id.encode(env));
+#[rustler::nif(schedule = "DirtyCpu")]
+fn reparse_incremental<'a>(
+    env: Env<'a>,
+    old_blocks: Vec<HashMap<String, Term<'a>>>,
+    new_content: String,
+) -> Term<'a> {
+    let previous = match old_blocks
+        .iter()
+        .map(PreviousBlock::from_map)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(previous) => previous,
+        Err(e) => return (atoms::error(), e).encode(env),
+    };
 
-                    headings.push(heading_map.encode(env));
-                    in_heading = false;
-                }
-            }
-            Event::Text(text) => {
-                if in_heading {
-                    heading_text.push_str(&text);
-                }
-                current_line += text.chars().filter(|&c| c == '\n').count();
-            }
-            Event::SoftBreak | Event::HardBreak => {
-                if in_heading {
-                    heading_text.push(' ');
-                }
-                current_line += 1;
+    let diff = diff_blocks(&previous, chunk_blocks(&new_content));
+
+    let mut ids_by_status: HashMap<&str, Vec<&String>> = HashMap::new();
+    let blocks: Vec<Term> = diff
+        .changes
+        .iter()
+        .map(|change| {
+            ids_by_status
+                .entry(change.status)
+                .or_default()
+                .push(&change.chunk.stable_id);
+
+            let mut block = chunk_map(env, &change.chunk);
+            block.insert("status".to_string(), change.status.encode(env));
+            if let Some(previous_id) = &change.previous_id {
+                block.insert("previous_id".to_string(), previous_id.encode(env));
             }
-            _ => {}
-        }
+            block.encode(env)
+        })
+        .collect();
+
+    let mut result = HashMap::new();
+    result.insert("blocks".to_string(), blocks.encode(env));
+    for status in ["added", "modified", "moved"] {
+        let ids = ids_by_status.remove(status).unwrap_or_default();
+        result.insert(status.to_string(), ids.encode(env));
+    }
+    result.insert("removed".to_string(), diff.removed.encode(env));
+
+    (atoms::ok(), result).encode(env)
+}
+
+// Implementation functions
+#[derive(Debug)]
+enum ParseError {
+    LimitExceeded(String),
+    InvalidOption(String),
+    Message(String),
+}
+
+impl From<String> for ParseError {
+    fn from(message: String) -> Self {
+        ParseError::Message(message)
     }
+}
 
-    Ok(headings.encode(env))
+/// Resource limits for untrusted input
+///
+/// Defaults: 10 MiB of input, 10,000 headings, 10,000 code blocks and 64
+/// levels of nested blocks/inlines. Raise them per call through the
+/// `options` list, e.g. `[{"max_bytes", "52428800"}, {"max_nesting", "128"}]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ParseLimits {
+    max_bytes: usize,
+    max_headings: usize,
+    max_code_blocks: usize,
+    max_nesting: usize,
 }
 
-fn extract_links_with_attributes<'a>(env: Env<'a>, content: &str) -> Result<Term<'a>, String> {
-    let mut links = Vec::new();
-    let mut line = 1usize;
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024 * 1024,
+            max_headings: 10_000,
+            max_code_blocks: 10_000,
+            max_nesting: 64,
+        }
+    }
+}
 
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
+impl ParseLimits {
+    fn from_options(options: &[(String, String)]) -> Result<Self, ParseError> {
+        let mut limits = Self::default();
+
+        for (key, value) in options {
+            let slot = match key.as_str() {
+                "max_bytes" => &mut limits.max_bytes,
+                "max_headings" => &mut limits.max_headings,
+                "max_code_blocks" => &mut limits.max_code_blocks,
+                "max_nesting" => &mut limits.max_nesting,
+                _ => continue,
+            };
+            *slot = value.trim().parse().map_err(|_| {
+                ParseError::InvalidOption(format!("{} must be a non-negative integer", key))
+            })?;
+        }
 
-    let parser = Parser::new_ext(content, options);
-    let mut link_text = String::new();
-    let mut in_link = false;
+        Ok(limits)
+    }
 
-    for event in parser {
-        match event {
-            Event::Start(Tag::Link(_link_type, _dest_url, _title)) => {
-                in_link = true;
-                link_text.clear();
-            }
-            Event::End(Tag::Link(_link_type, dest_url, title)) => {
-                if in_link {
-                    // Check for content-addressed links (SHA-256 hashes)
-                    let is_content_addressed = is_sha256_hash(&dest_url);
-
-                    // Parse inline attributes if present
-                    let (clean_text, attributes) = parse_inline_attributes(&link_text);
-
-                    let mut link_map = HashMap::new();
-                    link_map.insert("text".to_string(), clean_text.encode(env));
-                    link_map.insert("url".to_string(), dest_url.to_string().encode(env));
-                    link_map.insert("line".to_string(), line.encode(env));
-                    link_map.insert(
-                        "content_addressed".to_string(),
-                        is_content_addressed.encode(env),
-                    );
+    /// Single streaming pass that stops at the first exceeded limit, run
+    /// before any extractor allocates results
+    fn check(&self, content: &str) -> Result<(), ParseError> {
+        if content.len() > self.max_bytes {
+            return Err(ParseError::LimitExceeded(format!(
+                "input is {} bytes, max_bytes is {}",
+                content.len(),
+                self.max_bytes
+            )));
+        }
 
-                    if !title.is_empty() {
-                        link_map.insert("title".to_string(), title.to_string().encode(env));
+        let mut headings = 0usize;
+        let mut code_blocks = 0usize;
+        let mut depth = 0usize;
+
+        for event in Parser::new_ext(content, markdown_parser_options()) {
+            match event {
+                Event::Start(tag) => {
+                    depth += 1;
+                    if depth > self.max_nesting {
+                        return Err(ParseError::LimitExceeded(format!(
+                            "nesting exceeds max_nesting ({})",
+                            self.max_nesting
+                        )));
                     }
 
-                    if !attributes.is_empty() {
-                        let attr_map: HashMap<String, Term> = attributes
-                            .into_iter()
-                            .map(|(k, v)| (k, encode_attribute_value(env, v)))
-                            .collect();
-                        link_map.insert("attributes".to_string(), attr_map.encode(env));
+                    match tag {
+                        Tag::Heading(..) => {
+                            headings += 1;
+                            if headings > self.max_headings {
+                                return Err(ParseError::LimitExceeded(format!(
+                                    "more than max_headings ({}) headings",
+                                    self.max_headings
+                                )));
+                            }
+                        }
+                        Tag::CodeBlock(_) => {
+                            code_blocks += 1;
+                            if code_blocks > self.max_code_blocks {
+                                return Err(ParseError::LimitExceeded(format!(
+                                    "more than max_code_blocks ({}) code blocks",
+                                    self.max_code_blocks
+                                )));
+                            }
+                        }
+                        _ => {}
                     }
-
-                    links.push(link_map.encode(env));
-                    in_link = false;
-                }
-            }
-            Event::Text(text) => {
-                if in_link {
-                    link_text.push_str(&text);
                 }
-                line += text.chars().filter(|&c| c == '\n').count();
-            }
-            Event::SoftBreak | Event::HardBreak => {
-                line += 1;
+                Event::End(_) => depth = depth.saturating_sub(1),
+                _ => {}
             }
-            _ => {}
         }
+
+        Ok(())
     }
+}
 
-    Ok(links.encode(env))
+/// Everything `parse_markdown` reports, built without touching the BEAM
+///
+/// `encode_parse_result` turns it into the NIF's result map; serializing it
+/// with serde gives the same data as JSON.
+#[derive(Debug, Clone, Serialize)]
+struct ParseResult {
+    headings: Vec<HeadingOut>,
+    links: Vec<LinkOut>,
+    code_blocks: Vec<CodeBlockOut>,
+    tasks: Vec<TaskOut>,
+    emphasis: Vec<EmphasisOut>,
+    blockquotes: Vec<BlockquoteOut>,
+    definition_lists: Vec<DefinitionOut>,
+    /// Raw HTML, collected only with the `html` option
+    html: Vec<HtmlOut>,
+    math: Vec<MathOut>,
+    jsonld_islands: Vec<IslandOut>,
+    jsonld_graph: JsonLdGraph,
+    active_context: ActiveContext,
+    mq2_blocks: Vec<Mq2Fence>,
+    mem8_waves: Vec<Mem8WaveSpec>,
+    stats: DocumentStats,
+    processing_time_us: u64,
+    /// The first H1, moved out of `headings` by the `title_from_first_h1`
+    /// option; `None` without the option or without an H1
+    title: Option<String>,
+    /// Set when the document is a polyglot artifact
+    polyglot_language: Option<String>,
+    /// Result keys requested through the `only` option; `None` means all
+    #[serde(skip)]
+    only: Option<BTreeSet<&'static str>>,
 }
 
-fn extract_code_blocks_enhanced<'a>(env: Env<'a>, content: &str) -> Result<Term<'a>, String> {
-    let mut code_blocks = Vec::new();
-    let mut current_line = 1usize;
-    let mut in_code_block = false;
-    let mut current_code = String::new();
-    let mut current_language: Option<String> = None;
-    let mut code_start_line = 1usize;
+/// Result keys the `only` option can name
+const PARSE_ELEMENTS: &[&str] = &[
+    "headings",
+    "links",
+    "code_blocks",
+    "tasks",
+    "emphasis",
+    "blockquotes",
+    "definition_lists",
+    "html",
+    "math",
+    "jsonld_islands",
+    "jsonld_graph",
+    "active_context",
+    "mq2_blocks",
+    "mem8_waves",
+    "stats",
+    "title",
+    "polyglot_language",
+];
+
+/// `only` option: comma-separated result keys to extract, e.g.
+/// `{"only", "links"}`; every other extractor is skipped
+fn only_from_options(
+    options: &[(String, String)],
+) -> Result<Option<BTreeSet<&'static str>>, ParseError> {
+    let Some((_, value)) = options.iter().rev().find(|(key, _)| key == "only") else {
+        return Ok(None);
+    };
+    let mut only = BTreeSet::new();
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let element = PARSE_ELEMENTS
+            .iter()
+            .find(|element| **element == name)
+            .ok_or_else(|| ParseError::InvalidOption(format!("only: unknown element {}", name)))?;
+        only.insert(*element);
+    }
+    if only.is_empty() {
+        return Err(ParseError::InvalidOption(
+            "only must name at least one element".to_string(),
+        ));
+    }
+    Ok(Some(only))
+}
+
+fn parse_document(content: &str, options: &[(String, String)]) -> Result<ParseResult, ParseError> {
+    ParseLimits::from_options(options)?.check(content)?;
+    let reading_wpm = reading_wpm_from_options(options)?;
+    let positions = positions_from_options(options)?;
+    let validate_code = validate_code_from_options(options)?;
+    let number_from = number_headings_from_options(options)?;
+    let github_ids = bool_option(options, "github_stable_ids")?;
+    let title_from_h1 = bool_option(options, "title_from_first_h1")?;
+    let include_html = bool_option(options, "html")?;
+    let only = only_from_options(options)?;
+
+    let start_time = std::time::Instant::now();
+
+    let wants = |element: &str| only.as_ref().is_none_or(|only| only.contains(element));
+    let mut islands = if ["jsonld_islands", "jsonld_graph", "active_context"]
+        .iter()
+        .any(|element| wants(element))
+    {
+        collect_jsonld_islands(content)
+    } else {
+        Vec::new()
+    };
+    let active_context = resolve_jsonld_contexts(&mut islands);
+
+    let doc = if [
+        "headings",
+        "title",
+        "links",
+        "code_blocks",
+        "tasks",
+        "emphasis",
+        "blockquotes",
+    ]
+    .iter()
+    .any(|element| wants(element))
+    {
+        parse_structure(content)
+    } else {
+        ParsedDoc::default()
+    };
+    let tasks = if wants("tasks") {
+        collect_tasks(&doc.headings, doc.tasks)
+    } else {
+        Vec::new()
+    };
+
+    let mut result = ParseResult {
+        headings: if wants("headings") || (title_from_h1 && wants("title")) {
+            collect_headings(content, doc.headings)
+        } else {
+            Vec::new()
+        },
+        links: if wants("links") {
+            collect_links(doc.links)
+        } else {
+            Vec::new()
+        },
+        code_blocks: if wants("code_blocks") {
+            collect_code_blocks(doc.code_blocks)
+        } else {
+            Vec::new()
+        },
+        tasks,
+        emphasis: if wants("emphasis") {
+            collect_emphasis(doc.emphasis)
+        } else {
+            Vec::new()
+        },
+        blockquotes: if wants("blockquotes") {
+            collect_blockquotes(doc.blockquotes)
+        } else {
+            Vec::new()
+        },
+        definition_lists: if wants("definition_lists") {
+            collect_definition_lists(content)
+        } else {
+            Vec::new()
+        },
+        html: if include_html && wants("html") {
+            collect_html(content)
+        } else {
+            Vec::new()
+        },
+        math: if wants("math") {
+            collect_math(content)
+        } else {
+            Vec::new()
+        },
+        jsonld_graph: build_jsonld_graph(&islands),
+        jsonld_islands: islands.iter().map(IslandOut::from).collect(),
+        active_context,
+        mq2_blocks: if wants("mq2_blocks") {
+            collect_mq2_blocks(content)
+        } else {
+            Vec::new()
+        },
+        mem8_waves: if wants("mem8_waves") {
+            collect_mem8_waves(content)
+        } else {
+            Vec::new()
+        },
+        stats: if wants("stats") {
+            collect_document_stats(content, reading_wpm)
+        } else {
+            DocumentStats::default()
+        },
+        processing_time_us: 0,
+        title: None,
+        // Check for polyglot content
+        polyglot_language: if wants("polyglot_language") {
+            detect_polyglot_document(content).map(|poly| poly.language)
+        } else {
+            None
+        },
+        only,
+    };
+
+    if title_from_h1 {
+        if let Some(index) = result.headings.iter().position(|h| h.level == 1) {
+            result.title = Some(result.headings.remove(index).text);
+        }
+    }
+
+    if positions {
+        let line_index = LineIndex::new(content);
+        for heading in &mut result.headings {
+            heading.position = Some(line_index.position(content, heading.offset));
+        }
+        for link in &mut result.links {
+            link.position = Some(line_index.position(content, link.offset));
+        }
+        for block in &mut result.code_blocks {
+            block.position = Some(line_index.position(content, block.offset));
+        }
+    }
+
+    if github_ids {
+        for heading in &mut result.headings {
+            heading.stable_id = heading.anchor.clone();
+        }
+    }
+
+    if let Some(from_level) = number_from {
+        let numbers = section_numbers(result.headings.iter().map(|h| h.level), from_level);
+        for (heading, number) in result.headings.iter_mut().zip(numbers) {
+            heading.number = number;
+        }
+    }
+
+    if validate_code {
+        for block in &mut result.code_blocks {
+            block.validation = validate_code_syntax(&block.language, &block.content);
+        }
+    }
+
+    result.processing_time_us = start_time.elapsed().as_micros() as u64;
+    Ok(result)
+}
+
+/// The reason term of a `ParseError`: `{:limit_exceeded, detail}`,
+/// `{:invalid_value, detail}` or a message string
+fn encode_parse_error<'a>(env: Env<'a>, error: ParseError) -> Term<'a> {
+    match error {
+        ParseError::LimitExceeded(detail) => (atoms::limit_exceeded(), detail).encode(env),
+        ParseError::InvalidOption(detail) => (atoms::invalid_value(), detail).encode(env),
+        ParseError::Message(e) => format!("Parse error: {}", e).encode(env),
+    }
+}
+
+fn parse_markdown_content<'a>(
+    env: Env<'a>,
+    content: &str,
+    options: &[(String, String)],
+) -> Result<Term<'a>, ParseError> {
+    Ok(encode_parse_result(env, &parse_document(content, options)?))
+}
+
+fn encode_parse_result<'a>(env: Env<'a>, parsed: &ParseResult) -> Term<'a> {
+    let headings: Vec<Term> = parsed
+        .headings
+        .iter()
+        .map(|heading| encode_heading(env, heading))
+        .collect();
+    let links: Vec<Term> = parsed
+        .links
+        .iter()
+        .map(|link| encode_link(env, link))
+        .collect();
+    let code_blocks: Vec<Term> = parsed
+        .code_blocks
+        .iter()
+        .map(|block| encode_code_block(env, block))
+        .collect();
+    let tasks: Vec<Term> = parsed
+        .tasks
+        .iter()
+        .map(|task| encode_task_item(env, task))
+        .collect();
+    let emphasis: Vec<Term> = parsed
+        .emphasis
+        .iter()
+        .map(|span| encode_emphasis(env, span))
+        .collect();
+    let blockquotes: Vec<Term> = parsed
+        .blockquotes
+        .iter()
+        .map(|quote| encode_blockquote(env, quote))
+        .collect();
+    let definition_lists: Vec<Term> = parsed
+        .definition_lists
+        .iter()
+        .map(|entry| encode_definition(env, entry))
+        .collect();
+    let html: Vec<Term> = parsed
+        .html
+        .iter()
+        .map(|html| encode_html(env, html))
+        .collect();
+    let math: Vec<Term> = parsed
+        .math
+        .iter()
+        .map(|math| encode_math(env, math))
+        .collect();
+
+    let mut result = HashMap::new();
+    result.insert("headings".to_string(), headings.encode(env));
+    result.insert("title".to_string(), parsed.title.encode(env));
+    result.insert("links".to_string(), links.encode(env));
+    result.insert("code_blocks".to_string(), code_blocks.encode(env));
+    result.insert("tasks".to_string(), tasks.encode(env));
+    result.insert("emphasis".to_string(), emphasis.encode(env));
+    result.insert("blockquotes".to_string(), blockquotes.encode(env));
+    result.insert("definition_lists".to_string(), definition_lists.encode(env));
+    result.insert("html".to_string(), html.encode(env));
+    result.insert("math".to_string(), math.encode(env));
+    result.insert(
+        "jsonld_islands".to_string(),
+        encode_jsonld_islands(env, &parsed.jsonld_islands),
+    );
+    result.insert(
+        "jsonld_graph".to_string(),
+        encode_jsonld_graph(env, &parsed.jsonld_graph),
+    );
+    result.insert(
+        "mq2_blocks".to_string(),
+        encode_mq2_blocks(env, &parsed.mq2_blocks),
+    );
+    result.insert(
+        "mem8_waves".to_string(),
+        encode_mem8_waves(env, &parsed.mem8_waves),
+    );
+    result.insert(
+        "stats".to_string(),
+        encode_document_stats(env, &parsed.stats),
+    );
+    result.insert(
+        "active_context".to_string(),
+        encode_active_context(env, &parsed.active_context),
+    );
+    result.insert(
+        "processing_time_us".to_string(),
+        parsed.processing_time_us.encode(env),
+    );
+
+    if let Some(language) = &parsed.polyglot_language {
+        result.insert("polyglot_detected".to_string(), true.encode(env));
+        result.insert("polyglot_language".to_string(), language.encode(env));
+    }
+
+    if let Some(only) = &parsed.only {
+        result.retain(|key, _| {
+            key == "processing_time_us"
+                || only.contains(key.as_str())
+                || (key == "polyglot_detected" && only.contains("polyglot_language"))
+        });
+    }
+
+    result.encode(env)
+}
+
+fn parse_with_memory_context<'a>(
+    env: Env<'a>,
+    content: &str,
+    context: &Mem8Context,
+) -> Result<Term<'a>, ParseError> {
+    // Enhanced parsing with mem8 wave context
+    let mut result = HashMap::new();
+
+    // Parse standard markdown
+    let standard_result = parse_markdown_content(env, content, &[])?;
+
+    // Add memory context influence
+    let wave_influence = calculate_wave_context_influence(&context.wave_grid, content);
+    let consciousness_level = context.consciousness.awareness_level;
+
+    result.insert("standard_parse".to_string(), standard_result);
+    result.insert("wave_influence".to_string(), wave_influence.encode(env));
+    result.insert(
+        "consciousness_level".to_string(),
+        consciousness_level.encode(env),
+    );
+
+    Ok(result.encode(env))
+}
+
+fn encode_heading<'a>(env: Env<'a>, heading: &HeadingOut) -> Term<'a> {
+    let mut heading_map = HashMap::new();
+    heading_map.insert("level".to_string(), heading.level.encode(env));
+    heading_map.insert("text".to_string(), heading.text.encode(env));
+    heading_map.insert("line".to_string(), heading.line.encode(env));
+    heading_map.insert("style".to_string(), heading.style.encode(env));
+
+    if !heading.attributes.is_empty() {
+        heading_map.insert(
+            "attributes".to_string(),
+            encode_attributes(env, &heading.attributes),
+        );
+    }
+
+    heading_map.insert("stable_id".to_string(), heading.stable_id.encode(env));
+    heading_map.insert("anchor".to_string(), heading.anchor.encode(env));
+    heading_map.insert("byte_start".to_string(), heading.offset.encode(env));
+    heading_map.insert("byte_end".to_string(), heading.end.encode(env));
+
+    if let Some(position) = &heading.position {
+        heading_map.insert("position".to_string(), encode_position(env, position));
+    }
+
+    if let Some(number) = &heading.number {
+        heading_map.insert("number".to_string(), number.encode(env));
+    }
+
+    heading_map.encode(env)
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct HeadingOut {
+    level: u32,
+    text: String,
+    line: usize,
+    /// `"atx"` (`## Title`) or `"setext"` (text underlined by `===`/`---`)
+    style: &'static str,
+    attributes: BTreeMap<String, AttributeValue>,
+    stable_id: String,
+    /// The anchor GitHub renders for this heading, `-1`, `-2` suffixed
+    /// for repeats within the document
+    anchor: String,
+    /// Byte offset of the heading start
+    #[serde(skip)]
+    offset: usize,
+    /// Byte offset just past the heading (the underline, for setext)
+    #[serde(skip)]
+    end: usize,
+    /// Filled in by `parse_document` when the `positions` option is set
+    position: Option<Position>,
+    /// Section number such as `"2.1"`, set by the `number_headings` option
+    number: Option<String>,
+}
+
+/// Hierarchical section numbers (`1`, `1.1`, `1.2`, `2`) for heading levels
+///
+/// Levels above `from_level` get `None` and don't affect the count, so
+/// `from_level = 2` numbers the sections under an H1 title. A skipped level
+/// nests one step instead of leaving a gap (H1 then H3 is `1.1`), and a
+/// heading shallower than the open ones continues the count of the
+/// shallowest one it closes.
+fn section_numbers(levels: impl IntoIterator<Item = u32>, from_level: u32) -> Vec<Option<String>> {
+    // (level, count) of each open section, outermost first
+    let mut open: Vec<(u32, usize)> = Vec::new();
+    levels
+        .into_iter()
+        .map(|level| {
+            if level < from_level {
+                return None;
+            }
+            let mut closed = None;
+            while open
+                .last()
+                .is_some_and(|&(open_level, _)| open_level > level)
+            {
+                closed = open.pop();
+            }
+            match open.last_mut() {
+                Some((open_level, count)) if *open_level == level => *count += 1,
+                _ => open.push((level, closed.map_or(1, |(_, count)| count + 1))),
+            }
+            let parts: Vec<String> = open.iter().map(|(_, count)| count.to_string()).collect();
+            Some(parts.join("."))
+        })
+        .collect()
+}
+
+/// Core parse feeding the heading, link, code block, task, emphasis and
+/// blockquote collectors; `parse_document` runs it once per document
+///
+/// Attributes use the lenient mode: every trailing `{...}` block counts, and
+/// a paragraph of nothing but attribute blocks right after a heading
+/// (`## Title` then `{#t .note}` on the next line) merges into that
+/// heading's attributes.
+fn parse_structure(content: &str) -> ParsedDoc {
+    markdown_ld_core::parse_with_options(
+        content,
+        markdown_ld_core::ParseOptions {
+            lenient_attributes: true,
+        },
+    )
+}
+
+/// Headings of `parse_structure` with their style and GitHub anchor; `line`
+/// comes from the byte offset of the heading start, so blank lines, tables
+/// and list markers cannot skew it
+///
+/// For setext headings the line is that of the (first) text line, not the
+/// underline. A `---` underline directly below a paragraph line makes that
+/// line an H2; separated by a blank line it is a thematic break instead.
+fn collect_headings(content: &str, headings: Vec<Heading>) -> Vec<HeadingOut> {
+    let mut slugger = GithubSlugger::default();
+    headings
+        .into_iter()
+        .map(|heading| {
+            let style = if content[heading.offset..heading.end]
+                .trim_start()
+                .starts_with('#')
+            {
+                "atx"
+            } else {
+                "setext"
+            };
+            HeadingOut {
+                level: heading.level,
+                anchor: slugger.slug(&heading.text),
+                text: heading.text,
+                line: heading.line,
+                style,
+                attributes: attributes_from_json(heading.attributes),
+                stable_id: heading.stable_id,
+                offset: heading.offset,
+                end: heading.end,
+                position: None,
+                number: None,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LinkOut {
+    text: String,
+    url: String,
+    line: usize,
+    /// The URL is a bare SHA-256 hex digest
+    content_addressed: bool,
+    title: Option<String>,
+    attributes: BTreeMap<String, AttributeValue>,
+    /// Byte offset of the opening `[`
+    #[serde(skip)]
+    offset: usize,
+    /// Byte offset just past the closing `)` (or `]` of a reference)
+    #[serde(skip)]
+    end: usize,
+    /// Filled in by `parse_document` when the `positions` option is set
+    position: Option<Position>,
+}
+
+/// Links of `parse_structure`; link-like text in code is never a link
+fn collect_links(links: Vec<Link>) -> Vec<LinkOut> {
+    links
+        .into_iter()
+        .map(|link| LinkOut {
+            // Check for content-addressed links (SHA-256 hashes)
+            content_addressed: is_sha256_hash(&link.url),
+            text: link.text,
+            url: link.url,
+            line: link.line,
+            title: link.title,
+            attributes: attributes_from_json(link.attributes),
+            offset: link.offset,
+            end: link.end,
+            position: None,
+        })
+        .collect()
+}
+
+fn encode_link<'a>(env: Env<'a>, link: &LinkOut) -> Term<'a> {
+    let mut link_map = HashMap::new();
+    link_map.insert("text".to_string(), link.text.encode(env));
+    link_map.insert("url".to_string(), link.url.encode(env));
+    link_map.insert("line".to_string(), link.line.encode(env));
+    link_map.insert(
+        "content_addressed".to_string(),
+        link.content_addressed.encode(env),
+    );
+    link_map.insert("byte_start".to_string(), link.offset.encode(env));
+    link_map.insert("byte_end".to_string(), link.end.encode(env));
+
+    if let Some(title) = &link.title {
+        link_map.insert("title".to_string(), title.encode(env));
+    }
+
+    if !link.attributes.is_empty() {
+        link_map.insert(
+            "attributes".to_string(),
+            encode_attributes(env, &link.attributes),
+        );
+    }
+
+    if let Some(position) = &link.position {
+        link_map.insert("position".to_string(), encode_position(env, position));
+    }
+
+    link_map.encode(env)
+}
+
+/// A link classified for site-wide link analysis
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct LinkEdge {
+    url: String,
+    text: String,
+    line: usize,
+    /// `"anchor"` (`#frag`), `"relative"` or `"absolute"` (has a scheme or
+    /// starts with `//`)
+    kind: &'static str,
+    /// For anchors, whether the fragment names a heading of this document;
+    /// `None` for other kinds
+    resolves: Option<bool>,
+}
+
+/// Every link with its kind; anchors are checked against each heading's
+/// stable id, slug, GitHub anchor and explicit `id` attribute
+fn build_link_graph(content: &str) -> Vec<LinkEdge> {
+    let doc = parse_structure(content);
+    let targets = heading_anchor_targets(&collect_headings(content, doc.headings));
+
+    collect_links(doc.links)
+        .into_iter()
+        .map(|link| {
+            let (kind, resolves) = match link.url.strip_prefix('#') {
+                Some(fragment) => ("anchor", Some(targets.contains(fragment))),
+                None if has_url_scheme(&link.url) || link.url.starts_with("//") => {
+                    ("absolute", None)
+                }
+                None => ("relative", None),
+            };
+            LinkEdge {
+                url: link.url,
+                text: link.text,
+                line: link.line,
+                kind,
+                resolves,
+            }
+        })
+        .collect()
+}
+
+/// Every fragment that names one of `headings`
+fn heading_anchor_targets(headings: &[HeadingOut]) -> HashSet<String> {
+    let mut targets = HashSet::new();
+    for heading in headings {
+        if let Some(AttributeValue::String(id)) = heading.attributes.get("id") {
+            targets.insert(id.clone());
+        }
+        targets.insert(markdown_ld_core::heading_slug(&heading.text));
+        targets.insert(heading.stable_id.clone());
+        targets.insert(heading.anchor.clone());
+    }
+    targets
+}
+
+/// Headings, links and JSON-LD of a set of documents, each tagged with its
+/// source
+#[derive(Debug, Clone, Default, Serialize)]
+struct CorpusIndex {
+    headings: Vec<CorpusHeading>,
+    links: Vec<CorpusLink>,
+    jsonld_islands: Vec<CorpusIsland>,
+    /// Union of the documents' graphs, so an `@id` defined in one document
+    /// satisfies references from the others
+    jsonld_graph: JsonLdGraph,
+    /// Links with a fragment (`#setup`, `guide.md#setup`) whose document
+    /// is not in the set or has no heading by that name
+    unresolved_anchors: Vec<CorpusLink>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct CorpusHeading {
+    source: String,
+    level: u32,
+    text: String,
+    line: usize,
+    anchor: String,
+    /// `{source}#{stable_id}`, unique across the set
+    stable_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct CorpusLink {
+    source: String,
+    url: String,
+    text: String,
+    line: usize,
+    /// The merged document the link points into, if any
+    target: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct CorpusIsland {
+    source: String,
+    line: usize,
+    expanded: Option<String>,
+}
+
+/// Merge per-document parses, keyed by source path
+///
+/// Relative links are resolved against the linking document's directory
+/// (`/`-separated, `.` and `..` collapsed, a leading `/` meaning the set's
+/// root) to find the document they point into.
+fn build_corpus_index(documents: &[(&str, ParseResult)]) -> CorpusIndex {
+    let targets: HashMap<&str, HashSet<String>> = documents
+        .iter()
+        .map(|(source, parsed)| (*source, heading_anchor_targets(&parsed.headings)))
+        .collect();
+
+    let mut index = CorpusIndex::default();
+    for (source, parsed) in documents {
+        index
+            .headings
+            .extend(parsed.headings.iter().map(|heading| CorpusHeading {
+                source: source.to_string(),
+                level: heading.level,
+                text: heading.text.clone(),
+                line: heading.line,
+                anchor: heading.anchor.clone(),
+                stable_id: format!("{}#{}", source, heading.stable_id),
+            }));
+
+        for link in &parsed.links {
+            let resolved = resolve_relative_link(source, &link.url);
+            let corpus_link = CorpusLink {
+                source: source.to_string(),
+                url: link.url.clone(),
+                text: link.text.clone(),
+                line: link.line,
+                target: resolved
+                    .as_ref()
+                    .filter(|(path, _)| targets.contains_key(path.as_str()))
+                    .map(|(path, _)| path.clone()),
+            };
+            if let Some((path, Some(fragment))) = &resolved {
+                let found = targets
+                    .get(path.as_str())
+                    .is_some_and(|anchors| anchors.contains(*fragment));
+                if !found {
+                    index.unresolved_anchors.push(corpus_link.clone());
+                }
+            }
+            index.links.push(corpus_link);
+        }
+
+        index
+            .jsonld_islands
+            .extend(parsed.jsonld_islands.iter().map(|island| CorpusIsland {
+                source: source.to_string(),
+                line: island.line,
+                expanded: island.expanded.clone(),
+            }));
+        index.jsonld_graph.merge(&parsed.jsonld_graph);
+    }
+    index
+}
+
+/// The document path and fragment a relative link points at, `None` for
+/// absolute URLs
+fn resolve_relative_link<'u>(source: &str, url: &'u str) -> Option<(String, Option<&'u str>)> {
+    if has_url_scheme(url) || url.starts_with("//") {
+        return None;
+    }
+    let (path, fragment) = match url.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (url, None),
+    };
+    let path = path.split('?').next().unwrap_or_default();
+    if path.is_empty() {
+        return Some((source.to_string(), fragment));
+    }
+
+    let mut segments: Vec<&str> = match path.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => source
+            .rsplit_once('/')
+            .map_or(Vec::new(), |(dir, _)| dir.split('/').collect()),
+    };
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some((segments.join("/"), fragment))
+}
+
+fn encode_corpus_index<'a>(env: Env<'a>, index: &CorpusIndex) -> Term<'a> {
+    let encode_link = |link: &CorpusLink| {
+        let mut link_map = HashMap::new();
+        link_map.insert("source".to_string(), link.source.encode(env));
+        link_map.insert("url".to_string(), link.url.encode(env));
+        link_map.insert("text".to_string(), link.text.encode(env));
+        link_map.insert("line".to_string(), link.line.encode(env));
+        link_map.insert("target".to_string(), link.target.encode(env));
+        link_map.encode(env)
+    };
+
+    let headings: Vec<Term> = index
+        .headings
+        .iter()
+        .map(|heading| {
+            let mut heading_map = HashMap::new();
+            heading_map.insert("source".to_string(), heading.source.encode(env));
+            heading_map.insert("level".to_string(), heading.level.encode(env));
+            heading_map.insert("text".to_string(), heading.text.encode(env));
+            heading_map.insert("line".to_string(), heading.line.encode(env));
+            heading_map.insert("anchor".to_string(), heading.anchor.encode(env));
+            heading_map.insert("stable_id".to_string(), heading.stable_id.encode(env));
+            heading_map.encode(env)
+        })
+        .collect();
+    let islands: Vec<Term> = index
+        .jsonld_islands
+        .iter()
+        .map(|island| {
+            let mut island_map = HashMap::new();
+            island_map.insert("source".to_string(), island.source.encode(env));
+            island_map.insert("line".to_string(), island.line.encode(env));
+            island_map.insert("expanded".to_string(), island.expanded.encode(env));
+            island_map.encode(env)
+        })
+        .collect();
+    let links: Vec<Term> = index.links.iter().map(encode_link).collect();
+    let unresolved: Vec<Term> = index.unresolved_anchors.iter().map(encode_link).collect();
+
+    let mut result = HashMap::new();
+    result.insert("headings".to_string(), headings.encode(env));
+    result.insert("links".to_string(), links.encode(env));
+    result.insert("jsonld_islands".to_string(), islands.encode(env));
+    result.insert(
+        "jsonld_graph".to_string(),
+        encode_jsonld_graph(env, &index.jsonld_graph),
+    );
+    result.insert("unresolved_anchors".to_string(), unresolved.encode(env));
+    result.encode(env)
+}
+
+/// RFC 3986 scheme followed by `:` (`https:`, `mailto:`, `urn:` ...)
+fn has_url_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct CodeBlockOut {
+    /// Fence info string, `"unknown"` for indented blocks and bare fences
+    language: String,
+    content: String,
+    line: usize,
+    is_jsonld: bool,
+    is_mem8: bool,
+    is_mq2: bool,
+    /// Polyglot artifact detected in the body (see `detect_code_block_artifact`)
+    artifact_type: Option<String>,
+    /// Byte offset of the opening fence (or first indented line)
+    #[serde(skip)]
+    offset: usize,
+    /// Byte offset just past the block, closing fence included
+    #[serde(skip)]
+    end: usize,
+    /// Filled in by `parse_document` when the `positions` option is set
+    position: Option<Position>,
+    /// Filled in by `parse_document` when the `validate_code` option is set
+    /// and the language is one `validate_code_syntax` knows
+    validation: Option<SyntaxCheck>,
+}
+
+/// Outcome of parsing a code block body as its fence language
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct SyntaxCheck {
+    valid: bool,
+    error: Option<String>,
+}
+
+/// Parse `content` as JSON, YAML or TOML according to the fence language;
+/// `None` for any other language
+fn validate_code_syntax(language: &str, content: &str) -> Option<SyntaxCheck> {
+    let result = match language.to_ascii_lowercase().as_str() {
+        "json" => serde_json::from_str::<serde_json::Value>(content)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        "yaml" | "yml" => yaml_rust::YamlLoader::load_from_str(content)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        "toml" => content
+            .parse::<toml::Table>()
+            .map(|_| ())
+            .map_err(|e| e.to_string().trim_end().to_string()),
+        _ => return None,
+    };
+    Some(SyntaxCheck {
+        valid: result.is_ok(),
+        error: result.err(),
+    })
+}
+
+fn collect_code_blocks(blocks: Vec<CodeBlock>) -> Vec<CodeBlockOut> {
+    blocks
+        .into_iter()
+        .map(|block| {
+            let language = block.language.unwrap_or_else(|| "unknown".to_string());
+            CodeBlockOut {
+                // Detect special markdown-ld languages
+                is_jsonld: is_jsonld_language(&language),
+                is_mem8: language == "mem8",
+                is_mq2: language == "mq2",
+                // Extract polyglot artifacts from code blocks
+                artifact_type: detect_code_block_artifact(&block.content),
+                language,
+                content: block.content,
+                line: block.line,
+                offset: block.offset,
+                end: block.end,
+                position: None,
+                validation: None,
+            }
+        })
+        .collect()
+}
+
+fn encode_code_block<'a>(env: Env<'a>, block: &CodeBlockOut) -> Term<'a> {
+    let mut code_map = HashMap::new();
+    code_map.insert("language".to_string(), block.language.encode(env));
+    code_map.insert("content".to_string(), block.content.encode(env));
+    code_map.insert("line".to_string(), block.line.encode(env));
+    code_map.insert("is_jsonld".to_string(), block.is_jsonld.encode(env));
+    code_map.insert("is_mem8".to_string(), block.is_mem8.encode(env));
+    code_map.insert("is_mq2".to_string(), block.is_mq2.encode(env));
+    code_map.insert("byte_start".to_string(), block.offset.encode(env));
+    code_map.insert("byte_end".to_string(), block.end.encode(env));
+
+    if let Some(artifact_type) = &block.artifact_type {
+        code_map.insert("artifact_type".to_string(), artifact_type.encode(env));
+    }
+
+    if let Some(position) = &block.position {
+        code_map.insert("position".to_string(), encode_position(env, position));
+    }
+
+    if let Some(check) = &block.validation {
+        let mut check_map = HashMap::new();
+        check_map.insert("valid".to_string(), check.valid.encode(env));
+        check_map.insert(
+            "error".to_string(),
+            match &check.error {
+                Some(error) => error.encode(env),
+                None => atoms::nil().encode(env),
+            },
+        );
+        code_map.insert("validation".to_string(), check_map.encode(env));
+    }
+
+    code_map.encode(env)
+}
+
+/// Tasks with the heading section they sit in
+///
+/// Headings and tasks are both in line order, so one merge pass keeps the
+/// running heading stack. Stable IDs follow `chunk_blocks`: heading path,
+/// normalized text and the occurrence count of that text in the section,
+/// so a task keeps its ID while it stays in its section with its text
+/// unchanged, whatever its checkbox state.
+fn collect_tasks(headings: &[Heading], tasks: Vec<Task>) -> Vec<TaskOut> {
+    let mut headings = headings.iter().peekable();
+    let mut path: Vec<(u32, &str)> = Vec::new();
+    let mut occurrences: HashMap<(Vec<String>, String), u32> = HashMap::new();
+
+    tasks
+        .into_iter()
+        .map(|task| {
+            while let Some(heading) = headings.next_if(|heading| heading.line < task.line) {
+                path.retain(|(level, _)| *level < heading.level);
+                path.push((heading.level, &heading.text));
+            }
+            let heading_path: Vec<String> = path.iter().map(|(_, text)| text.to_string()).collect();
+            let occurrence = occurrences
+                .entry((heading_path.clone(), normalize_text_for_hash(&task.text)))
+                .or_insert(0);
+            let stable_id = generate_stable_chunk_id(&heading_path, *occurrence, &task.text);
+            *occurrence += 1;
+            TaskOut {
+                completed: task.completed,
+                text: task.text,
+                line: task.line,
+                depth: task.depth,
+                heading_path,
+                stable_id,
+                offset: task.offset,
+                end: task.end,
+            }
+        })
+        .collect()
+}
+
+/// Strong, emphasis and strikethrough spans, nested ones included
+fn collect_emphasis(spans: Vec<Emphasis>) -> Vec<EmphasisOut> {
+    spans
+        .into_iter()
+        .map(|span| EmphasisOut {
+            kind: span.kind.as_str(),
+            text: span.text,
+            line: span.line,
+            col_start: span.col_start,
+            col_end: span.col_end,
+        })
+        .collect()
+}
+
+/// Blockquotes and `> [!TYPE]` callouts, nested ones included
+fn collect_blockquotes(quotes: Vec<Blockquote>) -> Vec<BlockquoteOut> {
+    quotes
+        .into_iter()
+        .map(|quote| BlockquoteOut {
+            text: quote.text,
+            line_start: quote.line_start,
+            line_end: quote.line_end,
+            depth: quote.depth,
+            callout_type: quote.callout_type,
+            callout_title: quote.callout_title,
+        })
+        .collect()
+}
+
+// Definition lists
+//
+// PHP Markdown Extra syntax, which pulldown-cmark reads as a paragraph:
+//
+//     Term
+//     : First definition
+//     : Second definition
+//
+// A blank line may separate the term from its first definition and
+// definitions from each other; lines indented by four spaces or a tab
+// continue the previous definition. The term must open a block, so a `: `
+// line under ordinary prose is left alone.
+fn collect_definition_lists(content: &str) -> Vec<DefinitionOut> {
+    let lines: Vec<&str> = content.lines().collect();
+    let in_code = code_block_lines(content, lines.len());
+    let usable = |i: usize| i < lines.len() && !in_code[i];
+    let is_blank = |i: usize| lines.get(i).is_none_or(|line| line.trim().is_empty());
+    let definition = |i: usize| {
+        if usable(i) {
+            definition_line_text(lines[i])
+        } else {
+            None
+        }
+    };
+
+    let mut entries = Vec::new();
+    // Whether the previous line ends a block, so the next line may be a term
+    let mut at_block_start = true;
+    let mut i = 0;
+    while i < lines.len() {
+        let starts_entry = at_block_start
+            && usable(i)
+            && !is_blank(i)
+            && definition(i).is_none()
+            && is_definition_term(lines[i]);
+        let first = if is_blank(i + 1) { i + 2 } else { i + 1 };
+        if !starts_entry || definition(first).is_none() {
+            at_block_start = is_blank(i);
+            i += 1;
+            continue;
+        }
+
+        let mut definitions: Vec<String> = Vec::new();
+        let mut j = first;
+        while j < lines.len() {
+            if let Some(text) = definition(j) {
+                definitions.push(text.to_string());
+            } else if usable(j) && !is_blank(j) && is_continuation(lines[j]) {
+                let last = definitions.last_mut().expect("a definition precedes");
+                last.push(' ');
+                last.push_str(lines[j].trim());
+            } else if !(is_blank(j) && definition(j + 1).is_some()) {
+                break;
+            }
+            j += 1;
+        }
+
+        entries.push(DefinitionOut {
+            term: lines[i].trim().to_string(),
+            definitions,
+            line: i + 1,
+        });
+        at_block_start = true;
+        i = j;
+    }
+    entries
+}
+
+/// Per line, whether it is inside a fenced or indented code block
+fn code_block_lines(content: &str, line_count: usize) -> Vec<bool> {
+    let line_index = LineIndex::new(content);
+    let mut in_code = vec![false; line_count];
+    for (event, range) in Parser::new_ext(content, markdown_parser_options()).into_offset_iter() {
+        if let Event::Start(Tag::CodeBlock(_)) = event {
+            let first = line_index.line(range.start);
+            let last = line_index.line(range.end.saturating_sub(1).max(range.start));
+            for flag in in_code.iter_mut().take(last).skip(first - 1) {
+                *flag = true;
+            }
+        }
+    }
+    in_code
+}
+
+/// The text after a `: ` marker indented by at most three spaces
+fn definition_line_text(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let text = trimmed.strip_prefix(':')?;
+    if !text.starts_with([' ', '\t']) {
+        return None;
+    }
+    Some(text.trim()).filter(|text| !text.is_empty())
+}
+
+/// A line that could be a term rather than another block's opener
+fn is_definition_term(line: &str) -> bool {
+    lazy_static! {
+        static ref BLOCK_OPENER: Regex =
+            Regex::new(r"^(#|>|\||[-*+]\s|\d+[.)]\s|```|~~~)").unwrap();
+    }
+    !is_continuation(line) && !BLOCK_OPENER.is_match(line.trim_start())
+}
+
+fn is_continuation(line: &str) -> bool {
+    line.starts_with("    ") || line.starts_with('\t')
+}
+
+/// Reported form of a `JsonLdIsland`
+#[derive(Debug, Clone, Serialize)]
+struct IslandOut {
+    source: &'static str,
+    line: usize,
+    content: String,
+    /// Canonical (JCS) JSON of the expanded island
+    expanded: Option<String>,
+    /// Undefined prefixes, encoded as `{:unknown_prefix, prefix}`
+    unknown_prefixes: Vec<String>,
+    diagnostic: Option<IslandDiagnostic>,
+    /// Byte range of the source block, see `JsonLdIsland`
+    #[serde(skip)]
+    offset: usize,
+    #[serde(skip)]
+    end: usize,
+}
+
+impl From<&JsonLdIsland> for IslandOut {
+    fn from(island: &JsonLdIsland) -> Self {
+        Self {
+            source: island.source,
+            line: island.line,
+            content: island.content.clone(),
+            expanded: island
+                .expanded
+                .as_ref()
+                .and_then(|expanded| canonicalize_json_value(expanded).ok()),
+            unknown_prefixes: island.unknown_prefixes.clone(),
+            diagnostic: island.diagnostic.clone(),
+            offset: island.offset,
+            end: island.end,
+        }
+    }
+}
+
+fn encode_jsonld_islands<'a>(env: Env<'a>, islands: &[IslandOut]) -> Term<'a> {
+    let encoded: Vec<Term> = islands
+        .iter()
+        .map(|island| {
+            let mut island_map = HashMap::new();
+            island_map.insert("content".to_string(), island.content.encode(env));
+            island_map.insert("line".to_string(), island.line.encode(env));
+            island_map.insert("source".to_string(), island.source.encode(env));
+            island_map.insert("byte_start".to_string(), island.offset.encode(env));
+            island_map.insert("byte_end".to_string(), island.end.encode(env));
+
+            if let Some(expanded) = &island.expanded {
+                island_map.insert("expanded".to_string(), expanded.encode(env));
+            }
+
+            let errors: Vec<Term> = island
+                .unknown_prefixes
+                .iter()
+                .map(|prefix| (atoms::unknown_prefix(), prefix).encode(env))
+                .collect();
+            island_map.insert("errors".to_string(), errors.encode(env));
+
+            let mut validation = HashMap::new();
+            validation.insert("valid".to_string(), island.diagnostic.is_none().encode(env));
+            if let Some(diagnostic) = &island.diagnostic {
+                validation.insert("error".to_string(), diagnostic.error.encode(env));
+                validation.insert("hint".to_string(), diagnostic.hint.encode(env));
+                validation.insert("line".to_string(), diagnostic.line.encode(env));
+                validation.insert("column".to_string(), diagnostic.column.encode(env));
+            }
+            island_map.insert("validation".to_string(), validation.encode(env));
+
+            island_map.encode(env)
+        })
+        .collect();
+
+    encoded.encode(env)
+}
+
+#[derive(Debug, Clone)]
+struct JsonLdIsland {
+    source: &'static str,
+    line: usize,
+    content: String,
+    /// Parsed JSON, `None` if the island is malformed
+    value: Option<serde_json::Value>,
+    /// `value` with compact `@id`/`@type` values expanded against the
+    /// active context (see `resolve_jsonld_contexts`)
+    expanded: Option<serde_json::Value>,
+    /// Prefixes used in `@id`/`@type` that no context defines
+    unknown_prefixes: Vec<String>,
+    /// Set when the island is not JSON, or has no context to expand against
+    diagnostic: Option<IslandDiagnostic>,
+    /// Byte range of the island's block: the whole frontmatter, `---`
+    /// lines included, or the whole code fence
+    offset: usize,
+    end: usize,
+}
+
+/// Collect JSON-LD from frontmatter and from json-ld code fences, in
+/// document order
+fn collect_jsonld_islands(content: &str) -> Vec<JsonLdIsland> {
+    let mut islands = Vec::new();
+
+    // Extract JSON-LD from frontmatter
+    if let Some(frontmatter) = extract_frontmatter(content) {
+        if let Some(jsonld) = extract_jsonld_from_frontmatter(&frontmatter) {
+            let value = yaml_rust::YamlLoader::load_from_str(&jsonld)
+                .ok()
+                .and_then(|docs| docs.into_iter().next())
+                .map(|doc| yaml_to_json(&doc));
+            islands.push(JsonLdIsland {
+                source: "frontmatter",
+                line: 1,
+                content: jsonld,
+                value,
+                expanded: None,
+                unknown_prefixes: Vec::new(),
+                diagnostic: None,
+                offset: 0,
+                // Opening `---\n`, body, then `\n---`
+                end: frontmatter.len() + 8,
+            });
+        }
+    }
+
+    // Extract JSON-LD from code fences; a frontmatter island always carries
+    // the document `@context`
+    let document_context = !islands.is_empty();
+    for fence in collect_fenced_blocks(content, is_jsonld_language) {
+        let (value, diagnostic) = match diagnose_island_json(&fence.body, fence.line + 1) {
+            Ok(value) if !document_context && !has_jsonld_context(&value) => {
+                let diagnostic = IslandDiagnostic {
+                    error: "missing @context".to_string(),
+                    hint: JSONLD_HINT_MISSING_CONTEXT,
+                    line: fence.line + 1,
+                    column: 1,
+                };
+                (Some(value), Some(diagnostic))
+            }
+            Ok(value) => (Some(value), None),
+            Err(diagnostic) => (None, Some(diagnostic)),
+        };
+        islands.push(JsonLdIsland {
+            source: "code_fence",
+            line: fence.line,
+            content: fence.body,
+            value,
+            expanded: None,
+            unknown_prefixes: Vec::new(),
+            diagnostic,
+            offset: fence.offset,
+            end: fence.end,
+        });
+    }
+
+    islands
+}
+
+/// Why an island is not usable JSON-LD, with a suggested fix
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct IslandDiagnostic {
+    /// serde_json's message, or `"missing @context"`
+    error: String,
+    hint: &'static str,
+    /// Document line, 1-based
+    line: usize,
+    /// Byte column within the line, 1-based
+    column: usize,
+}
+
+const JSONLD_HINT_TRAILING_COMMA: &str =
+    "Remove the comma after the last item; JSON does not allow trailing commas";
+const JSONLD_HINT_SINGLE_QUOTES: &str =
+    "Use double quotes; JSON strings and keys cannot use single quotes";
+const JSONLD_HINT_UNQUOTED_KEY: &str = "Wrap the key in double quotes; JSON keys must be strings";
+const JSONLD_HINT_MISSING_COMMA: &str = "Add a comma between the two items";
+const JSONLD_HINT_UNCLOSED: &str = "Close every `{` and `[`; the island ends inside one";
+const JSONLD_HINT_GENERIC: &str = "Check the JSON syntax at this position";
+const JSONLD_HINT_MISSING_CONTEXT: &str =
+    "Add an \"@context\" (e.g. \"https://schema.org/\") or declare one in the frontmatter";
+
+/// Parse an island body, turning a serde_json error into a diagnostic
+/// whose line is counted from `first_line`, the document line of the body
+fn diagnose_island_json(
+    body: &str,
+    first_line: usize,
+) -> Result<serde_json::Value, IslandDiagnostic> {
+    serde_json::from_str(body).map_err(|e| {
+        let at = body
+            .lines()
+            .nth(e.line().saturating_sub(1))
+            .and_then(|line| line.get(e.column().saturating_sub(1)..))
+            .and_then(|rest| rest.chars().next());
+        let message = e.to_string();
+        let hint = if message.starts_with("trailing comma") {
+            JSONLD_HINT_TRAILING_COMMA
+        } else if at == Some('\'') {
+            JSONLD_HINT_SINGLE_QUOTES
+        } else if message.starts_with("key must be a string") {
+            JSONLD_HINT_UNQUOTED_KEY
+        } else if message.starts_with("expected `,`") {
+            JSONLD_HINT_MISSING_COMMA
+        } else if e.is_eof() {
+            JSONLD_HINT_UNCLOSED
+        } else {
+            JSONLD_HINT_GENERIC
+        };
+        IslandDiagnostic {
+            error: message,
+            hint,
+            line: first_line + e.line().saturating_sub(1),
+            column: e.column().max(1),
+        }
+    })
+}
+
+/// An object with `@context`, or an array of only such objects
+fn has_jsonld_context(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Object(obj) => obj.contains_key("@context"),
+        serde_json::Value::Array(items) => {
+            !items.is_empty() && items.iter().all(has_jsonld_context)
+        }
+        _ => false,
+    }
+}
+
+fn is_jsonld_language(language: &str) -> bool {
+    matches!(language, "json-ld" | "jsonld" | "application/ld+json")
+}
+
+fn yaml_to_json(yaml: &yaml_rust::Yaml) -> serde_json::Value {
+    use yaml_rust::Yaml;
+
+    match yaml {
+        Yaml::Real(r) => r
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or_else(
+                || serde_json::Value::String(r.clone()),
+                serde_json::Value::Number,
+            ),
+        Yaml::Integer(i) => serde_json::Value::from(*i),
+        Yaml::String(s) => serde_json::Value::String(s.clone()),
+        Yaml::Boolean(b) => serde_json::Value::Bool(*b),
+        Yaml::Array(items) => serde_json::Value::Array(items.iter().map(yaml_to_json).collect()),
+        Yaml::Hash(hash) => serde_json::Value::Object(
+            hash.iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Yaml::String(s) => s.clone(),
+                        other => yaml_to_json(other).to_string(),
+                    };
+                    (key, yaml_to_json(v))
+                })
+                .collect(),
+        ),
+        Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => serde_json::Value::Null,
+    }
+}
+
+/// IRI schemes that are never treated as compact IRI prefixes
+const ABSOLUTE_IRI_SCHEMES: &[&str] = &[
+    "http", "https", "urn", "mailto", "tag", "did", "data", "file", "ftp", "ipfs",
+];
+
+/// Prefix and vocabulary mappings from `@context` declarations
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+struct ActiveContext {
+    prefixes: BTreeMap<String, String>,
+    vocab: Option<String>,
+}
+
+impl ActiveContext {
+    /// Merge a `@context` value over this one, later definitions winning
+    ///
+    /// A bare IRI string (e.g. `"https://schema.org/"`) sets `@vocab`;
+    /// `null` resets the context.
+    fn merge(&mut self, context: &serde_json::Value) {
+        match context {
+            serde_json::Value::Null => *self = Self::default(),
+            serde_json::Value::String(iri) => self.vocab = Some(iri.clone()),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.merge(item);
+                }
+            }
+            serde_json::Value::Object(obj) => {
+                for (term, definition) in obj {
+                    let iri = match definition {
+                        serde_json::Value::String(iri) => Some(iri.as_str()),
+                        serde_json::Value::Object(def) => def.get("@id").and_then(|id| id.as_str()),
+                        _ => None,
+                    };
+                    match (term.as_str(), iri) {
+                        ("@vocab", Some(iri)) => self.vocab = Some(iri.to_string()),
+                        (term, Some(iri)) if !term.starts_with('@') => {
+                            self.prefixes.insert(term.to_string(), iri.to_string());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Expand a compact IRI; `@type` values also resolve terms and `@vocab`
+    ///
+    /// Returns `Err(prefix)` when a compact IRI uses an undefined prefix.
+    fn expand(&self, value: &str, is_type: bool) -> Result<String, String> {
+        if let Some((prefix, suffix)) = value.split_once(':') {
+            if prefix == "_" || suffix.starts_with("//") || ABSOLUTE_IRI_SCHEMES.contains(&prefix) {
+                return Ok(value.to_string());
+            }
+            return match self.prefixes.get(prefix) {
+                Some(iri) => Ok(format!("{}{}", iri, suffix)),
+                None => Err(prefix.to_string()),
+            };
+        }
+
+        if is_type {
+            if let Some(iri) = self.prefixes.get(value) {
+                return Ok(iri.clone());
+            }
+            if let Some(vocab) = &self.vocab {
+                return Ok(format!("{}{}", vocab, value));
+            }
+        }
+
+        Ok(value.to_string())
+    }
+}
+
+/// Expand every island against the frontmatter `@context`
+///
+/// Each island's own `@context` (and any node-level `@context`) is merged
+/// over the document context for that subtree only. Returns the document
+/// context, which is what `active_context` reports.
+fn resolve_jsonld_contexts(islands: &mut [JsonLdIsland]) -> ActiveContext {
+    let mut document_context = ActiveContext::default();
+    if let Some(context) = islands
+        .iter()
+        .filter(|island| island.source == "frontmatter")
+        .find_map(|island| island.value.as_ref()?.get("@context"))
+    {
+        document_context.merge(context);
+    }
+
+    for island in islands.iter_mut() {
+        if let Some(value) = &island.value {
+            let mut unknown = BTreeSet::new();
+            island.expanded = Some(expand_jsonld_node(value, &document_context, &mut unknown));
+            island.unknown_prefixes = unknown.into_iter().collect();
+        }
+    }
+
+    document_context
+}
+
+fn expand_jsonld_node(
+    value: &serde_json::Value,
+    context: &ActiveContext,
+    unknown: &mut BTreeSet<String>,
+) -> serde_json::Value {
+    match value {
+        serde_json::Value::Array(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .map(|item| expand_jsonld_node(item, context, unknown))
+                .collect(),
+        ),
+        serde_json::Value::Object(obj) => {
+            let scoped;
+            let context = match obj.get("@context") {
+                Some(local) => {
+                    let mut merged = context.clone();
+                    merged.merge(local);
+                    scoped = merged;
+                    &scoped
+                }
+                None => context,
+            };
+
+            let mut expanded = serde_json::Map::new();
+            for (key, child) in obj {
+                let child = match key.as_str() {
+                    "@context" => child.clone(),
+                    "@id" => expand_jsonld_iri(child, false, context, unknown),
+                    "@type" => match child {
+                        serde_json::Value::Array(types) => serde_json::Value::Array(
+                            types
+                                .iter()
+                                .map(|t| expand_jsonld_iri(t, true, context, unknown))
+                                .collect(),
+                        ),
+                        single => expand_jsonld_iri(single, true, context, unknown),
+                    },
+                    _ => expand_jsonld_node(child, context, unknown),
+                };
+                expanded.insert(key.clone(), child);
+            }
+            serde_json::Value::Object(expanded)
+        }
+        other => other.clone(),
+    }
+}
+
+/// Expand an `@id`/`@type` string, recording undefined prefixes and keeping
+/// the original value for them
+fn expand_jsonld_iri(
+    iri: &serde_json::Value,
+    is_type: bool,
+    context: &ActiveContext,
+    unknown: &mut BTreeSet<String>,
+) -> serde_json::Value {
+    match iri {
+        serde_json::Value::String(s) => match context.expand(s, is_type) {
+            Ok(expanded) => serde_json::Value::String(expanded),
+            Err(prefix) => {
+                unknown.insert(prefix);
+                iri.clone()
+            }
+        },
+        other => other.clone(),
+    }
+}
+
+fn encode_active_context<'a>(env: Env<'a>, context: &ActiveContext) -> Term<'a> {
+    let prefixes: HashMap<&String, &String> = context.prefixes.iter().collect();
+
+    let mut context_map = HashMap::new();
+    context_map.insert("prefixes".to_string(), prefixes.encode(env));
+    context_map.insert(
+        "vocab".to_string(),
+        match &context.vocab {
+            Some(vocab) => vocab.encode(env),
+            None => atoms::nil().encode(env),
+        },
+    );
+    context_map.encode(env)
+}
+
+/// `@id`/`@type` cross-reference summary across all islands
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+struct JsonLdGraph {
+    defined_ids: BTreeSet<String>,
+    referenced_ids: BTreeSet<String>,
+    types: BTreeMap<String, usize>,
+}
+
+impl JsonLdGraph {
+    fn merge(&mut self, other: &JsonLdGraph) {
+        self.defined_ids.extend(other.defined_ids.iter().cloned());
+        self.referenced_ids
+            .extend(other.referenced_ids.iter().cloned());
+        for (ty, count) in &other.types {
+            *self.types.entry(ty.clone()).or_insert(0) += count;
+        }
+    }
+
+    fn undefined_ids(&self) -> Vec<String> {
+        self.referenced_ids
+            .difference(&self.defined_ids)
+            .cloned()
+            .collect()
+    }
+}
+
+fn build_jsonld_graph(islands: &[JsonLdIsland]) -> JsonLdGraph {
+    let mut graph = JsonLdGraph::default();
+    let values = islands
+        .iter()
+        .filter_map(|island| island.expanded.as_ref().or(island.value.as_ref()));
+    for value in values {
+        walk_jsonld_node(value, true, &mut graph);
+    }
+    graph
+}
+
+/// A node object with an `@id` and other properties defines that id; an
+/// object holding only `@id` references it. Top-level nodes and `@graph`
+/// members always count as definitions.
+fn walk_jsonld_node(value: &serde_json::Value, top_level: bool, graph: &mut JsonLdGraph) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                walk_jsonld_node(item, top_level, graph);
+            }
+        }
+        serde_json::Value::Object(obj) => {
+            if let Some(id) = obj.get("@id").and_then(|id| id.as_str()) {
+                if top_level || obj.len() > 1 {
+                    graph.defined_ids.insert(id.to_string());
+                } else {
+                    graph.referenced_ids.insert(id.to_string());
+                }
+            }
+
+            match obj.get("@type") {
+                Some(serde_json::Value::String(t)) => {
+                    *graph.types.entry(t.clone()).or_insert(0) += 1;
+                }
+                Some(serde_json::Value::Array(ts)) => {
+                    for t in ts.iter().filter_map(|t| t.as_str()) {
+                        *graph.types.entry(t.to_string()).or_insert(0) += 1;
+                    }
+                }
+                _ => {}
+            }
+
+            for (key, child) in obj {
+                match key.as_str() {
+                    "@context" | "@id" | "@type" => {}
+                    "@graph" => walk_jsonld_node(child, true, graph),
+                    _ => walk_jsonld_node(child, false, graph),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Keys every island must have, before any caller-supplied ones
+const JSONLD_REQUIRED_KEYS: &[&str] = &["@context", "@type"];
+
+/// Outcome of `check_jsonld_shapes` for one island
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct JsonLdShapeReport {
+    /// `@context`, `@type` and the requested keys the island lacks, in
+    /// that order
+    missing_keys: Vec<String>,
+    has_context: bool,
+    /// `@type` values, whether given as a string or an array
+    types: Vec<String>,
+}
+
+/// Top-level key presence only: no context resolution or expansion, so a
+/// compact IRI and its expanded form are different keys
+fn check_jsonld_shapes(
+    value: &serde_json::Value,
+    required_keys: &[String],
+) -> Result<Vec<JsonLdShapeReport>, String> {
+    let islands = match value {
+        serde_json::Value::Array(items) => items.as_slice(),
+        single => std::slice::from_ref(single),
+    };
+
+    let mut keys: Vec<&str> = JSONLD_REQUIRED_KEYS.to_vec();
+    for key in required_keys {
+        if !keys.contains(&key.as_str()) {
+            keys.push(key);
+        }
+    }
+
+    islands
+        .iter()
+        .enumerate()
+        .map(|(index, island)| {
+            let obj = island
+                .as_object()
+                .ok_or_else(|| format!("island {} is not a JSON object", index))?;
+            let types = match obj.get("@type") {
+                Some(serde_json::Value::String(t)) => vec![t.clone()],
+                Some(serde_json::Value::Array(ts)) => ts
+                    .iter()
+                    .filter_map(|t| t.as_str().map(str::to_string))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Ok(JsonLdShapeReport {
+                missing_keys: keys
+                    .iter()
+                    .filter(|key| !obj.contains_key(**key))
+                    .map(|key| key.to_string())
+                    .collect(),
+                has_context: obj.contains_key("@context"),
+                types,
+            })
+        })
+        .collect()
+}
+
+/// RFC 6901 resolution: `""` is the whole document, each `/`-separated
+/// token unescapes `~1` to `/` then `~0` to `~`, and array tokens must be
+/// canonical decimal indices (no sign or leading zeros, `-` never resolves)
+fn resolve_json_pointer<'v>(
+    value: &'v serde_json::Value,
+    pointer: &str,
+) -> Result<&'v serde_json::Value, String> {
+    if pointer.is_empty() {
+        return Ok(value);
+    }
+    let tokens = pointer
+        .strip_prefix('/')
+        .ok_or_else(|| format!("pointer must be empty or start with '/': {:?}", pointer))?;
+
+    let mut current = value;
+    for raw in tokens.split('/') {
+        if raw.replace("~0", "").replace("~1", "").contains('~') {
+            return Err(format!("invalid escape in pointer token {:?}", raw));
+        }
+        let token = raw.replace("~1", "/").replace("~0", "~");
+        current = match current {
+            serde_json::Value::Object(obj) => obj
+                .get(&token)
+                .ok_or_else(|| format!("key {:?} not found", token))?,
+            serde_json::Value::Array(items) => {
+                let canonical = token == "0"
+                    || (!token.starts_with('0') && token.bytes().all(|b| b.is_ascii_digit()));
+                let index = token
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|_| canonical)
+                    .ok_or_else(|| format!("invalid array index {:?}", token))?;
+                items.get(index).ok_or_else(|| {
+                    format!("index {} out of range for array of {}", index, items.len())
+                })?
+            }
+            _ => return Err(format!("cannot descend into scalar at {:?}", token)),
+        };
+    }
+    Ok(current)
+}
+
+fn encode_jsonld_graph<'a>(env: Env<'a>, graph: &JsonLdGraph) -> Term<'a> {
+    let defined: Vec<&String> = graph.defined_ids.iter().collect();
+    let referenced: Vec<&String> = graph.referenced_ids.iter().collect();
+    let types: HashMap<&String, usize> = graph.types.iter().map(|(t, n)| (t, *n)).collect();
+
+    let mut graph_map = HashMap::new();
+    graph_map.insert("defined_ids".to_string(), defined.encode(env));
+    graph_map.insert("referenced_ids".to_string(), referenced.encode(env));
+    graph_map.insert(
+        "undefined_ids".to_string(),
+        graph.undefined_ids().encode(env),
+    );
+    graph_map.insert("types".to_string(), types.encode(env));
+    graph_map.encode(env)
+}
+
+// Accessibility and structure linting
+const LINT_RULES: &[&str] = &[
+    "missing_alt",
+    "uninformative_link_text",
+    "empty_heading",
+    "skipped_heading_level",
+    "duplicate_heading_id",
+];
+
+const UNINFORMATIVE_LINK_TEXT: &[&str] = &[
+    "click here",
+    "here",
+    "link",
+    "this link",
+    "read more",
+    "more",
+    "this",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct LintIssue {
+    rule: &'static str,
+    line: usize,
+    message: String,
+    severity: &'static str,
+}
+
+/// Resolve requested rule names; an empty list enables every rule
+fn select_lint_rules(rules: &[String]) -> Result<HashSet<&'static str>, String> {
+    if rules.is_empty() {
+        return Ok(LINT_RULES.iter().copied().collect());
+    }
+
+    rules
+        .iter()
+        .map(|rule| {
+            LINT_RULES
+                .iter()
+                .find(|&&known| known == rule)
+                .copied()
+                .ok_or_else(|| format!("Unknown lint rule: {}", rule))
+        })
+        .collect()
+}
+
+/// Issues of the enabled rules, ordered by line
+fn lint_document(content: &str, enabled: &HashSet<&'static str>) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let line_index = LineIndex::new(content);
+    let parser = Parser::new_ext(content, markdown_parser_options());
+
+    // Label of the open link or image
+    let mut text = String::new();
+    let mut previous_level: Option<u32> = None;
+    let mut seen_ids: HashMap<String, usize> = HashMap::new();
+    let mut start_line = 1usize;
+
+    let mut report = |rule: &'static str, line: usize, message: String, severity| {
+        if enabled.contains(rule) {
+            issues.push(LintIssue {
+                rule,
+                line,
+                message,
+                severity,
+            });
+        }
+    };
+
+    // Heading text and attributes as every other extractor reads them
+    for heading in collect_headings(content, parse_structure(content).headings) {
+        if heading.text.trim().is_empty() {
+            report(
+                "empty_heading",
+                heading.line,
+                format!("Level {} heading has no text", heading.level),
+                "error",
+            );
+        }
+
+        if let Some(previous) = previous_level {
+            if heading.level > previous + 1 {
+                report(
+                    "skipped_heading_level",
+                    heading.line,
+                    format!(
+                        "Heading level jumps from h{} to h{}",
+                        previous, heading.level
+                    ),
+                    "warning",
+                );
+            }
+        }
+        previous_level = Some(heading.level);
+
+        let id = match heading.attributes.get("id") {
+            Some(AttributeValue::String(id)) => id.clone(),
+            _ => markdown_ld_core::heading_slug(&heading.text),
+        };
+        if !id.is_empty() {
+            if let Some(first_line) = seen_ids.get(&id) {
+                report(
+                    "duplicate_heading_id",
+                    heading.line,
+                    format!("Heading id \"{}\" already used on line {}", id, first_line),
+                    "warning",
+                );
+            } else {
+                seen_ids.insert(id, heading.line);
+            }
+        }
+    }
+
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Link(..)) | Event::Start(Tag::Image(..)) => {
+                text.clear();
+                start_line = line_index.line(range.start);
+            }
+            Event::End(Tag::Image(_, url, _)) if text.trim().is_empty() => {
+                report(
+                    "missing_alt",
+                    start_line,
+                    format!("Image {} has no alt text", url),
+                    "error",
+                );
+            }
+            Event::End(Tag::Link(_, url, _)) => {
+                let label = text.trim();
+                let lowered = label.to_lowercase();
+                let is_bare_url = label == url.as_ref()
+                    || lowered.starts_with("http://")
+                    || lowered.starts_with("https://")
+                    || lowered.starts_with("www.");
+
+                if is_bare_url || UNINFORMATIVE_LINK_TEXT.contains(&lowered.as_str()) {
+                    report(
+                        "uninformative_link_text",
+                        start_line,
+                        format!("Link text \"{}\" does not describe {}", label, url),
+                        "warning",
+                    );
+                }
+            }
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+/// Maps parser byte ranges to 1-based lines; built once per document with
+/// the SIMD newline scan of `markdown_ld_core::LineIndex`
+struct LineIndex {
+    index: markdown_ld_core::LineIndex,
+}
+
+impl LineIndex {
+    fn new(content: &str) -> Self {
+        Self {
+            index: markdown_ld_core::LineIndex::new(content),
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.index.line(offset)
+    }
+
+    /// Editor position of a byte offset into `content` (the indexed text)
+    fn position(&self, content: &str, offset: usize) -> Position {
+        let (line, utf8_col) = self.index.offset_to_line_col(offset);
+        Position {
+            line,
+            utf8_col,
+            utf16_col: content[offset - utf8_col..offset].encode_utf16().count(),
+            byte_offset: offset,
+        }
+    }
+}
+
+/// Location of an element for editor integrations
+///
+/// `line` is 1-based like every other line in the result; the columns are
+/// 0-based offsets from the start of that line, in UTF-8 bytes and UTF-16
+/// code units (the LSP default encoding).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct Position {
+    line: usize,
+    utf8_col: usize,
+    utf16_col: usize,
+    byte_offset: usize,
+}
+
+fn encode_position<'a>(env: Env<'a>, position: &Position) -> Term<'a> {
+    let mut position_map = HashMap::new();
+    position_map.insert("line".to_string(), position.line);
+    position_map.insert("utf8_col".to_string(), position.utf8_col);
+    position_map.insert("utf16_col".to_string(), position.utf16_col);
+    position_map.insert("byte_offset".to_string(), position.byte_offset);
+    position_map.encode(env)
+}
+
+/// `positions` option: attach a `position` to headings, links and code blocks
+fn positions_from_options(options: &[(String, String)]) -> Result<bool, ParseError> {
+    bool_option(options, "positions")
+}
+
+/// `validate_code` option: syntax-check json, yaml and toml code blocks
+fn validate_code_from_options(options: &[(String, String)]) -> Result<bool, ParseError> {
+    bool_option(options, "validate_code")
+}
+
+/// `number_headings` option: the heading level (1-6) that gets top-level
+/// section numbers, e.g. `"2"` to leave an H1 title unnumbered; off when
+/// absent
+fn number_headings_from_options(options: &[(String, String)]) -> Result<Option<u32>, ParseError> {
+    match options
+        .iter()
+        .rev()
+        .find(|(key, _)| key == "number_headings")
+    {
+        Some((_, value)) => match value.trim().parse::<u32>() {
+            Ok(level) if (1..=6).contains(&level) => Ok(Some(level)),
+            _ => Err(ParseError::InvalidOption(
+                "number_headings must be a heading level from 1 to 6".to_string(),
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+/// `min_depth` and `max_depth` options for `generate_toc`: the heading
+/// levels (1-6) to include, 1 and 6 when absent; `min_depth` `"2"` leaves
+/// out an H1 title
+fn toc_levels_from_options(options: &[(String, String)]) -> Result<(u32, u32), ParseError> {
+    let level = |name: &str, default: u32| match options.iter().rev().find(|(key, _)| key == name) {
+        Some((_, value)) => match value.trim().parse::<u32>() {
+            Ok(level) if (1..=6).contains(&level) => Ok(level),
+            _ => Err(ParseError::InvalidOption(format!(
+                "{} must be a heading level from 1 to 6",
+                name
+            ))),
+        },
+        None => Ok(default),
+    };
+    let (min_level, max_level) = (level("min_depth", 1)?, level("max_depth", 6)?);
+    if min_level > max_level {
+        return Err(ParseError::InvalidOption(
+            "min_depth must not exceed max_depth".to_string(),
+        ));
+    }
+    Ok((min_level, max_level))
+}
+
+/// A `"true"`/`"false"` option, off when absent; the last occurrence wins
+fn bool_option(options: &[(String, String)], name: &str) -> Result<bool, ParseError> {
+    match options.iter().rev().find(|(key, _)| key == name) {
+        Some((_, value)) => match value.trim() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(ParseError::InvalidOption(format!(
+                "{} must be true or false",
+                name
+            ))),
+        },
+        None => Ok(false),
+    }
+}
+
+fn markdown_parser_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options
+}
+
+fn heading_level_number(level: HeadingLevel) -> u32 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+// Block chunking
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    stable_id: String,
+    kind: &'static str,
+    text: String,
+    line: usize,
+    /// Position among all emitted blocks, in document order
+    block_index: u32,
+    heading_path: Vec<String>,
+}
+
+/// Segment a document into paragraphs, list items, code blocks and tables
+///
+/// Stable IDs are derived from the heading path and normalized text only:
+/// the `block_index` handed to `generate_stable_chunk_id` is the occurrence
+/// count of identical text under the same heading path, not the position.
+/// Moving a block within its section (or inserting blocks around it) keeps
+/// its ID, at the cost that any edit to its text, or moving it under another
+/// heading, yields a new ID. Identical blocks in one section are told apart
+/// by their order, so swapping two of them swaps their IDs.
+///
+/// Nested list items are separate blocks; an item's text covers only its own
+/// paragraphs, not its children.
+fn chunk_blocks(content: &str) -> Vec<Chunk> {
+    let line_index = LineIndex::new(content);
+    let parser = Parser::new_ext(content, markdown_parser_options());
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    // Indices into `chunks` of the blocks currently open, innermost last
+    let mut open: Vec<usize> = Vec::new();
+    let mut headings: Vec<(u32, String)> = Vec::new();
+    let mut heading_text: Option<String> = None;
+
+    for (event, range) in parser.into_offset_iter() {
+        let kind = match &event {
+            Event::Start(Tag::Paragraph) if open.is_empty() => Some("paragraph"),
+            Event::Start(Tag::Item) => Some("list_item"),
+            Event::Start(Tag::CodeBlock(_)) => Some("code_block"),
+            Event::Start(Tag::Table(_)) => Some("table"),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            open.push(chunks.len());
+            chunks.push(Chunk {
+                stable_id: String::new(),
+                kind,
+                text: String::new(),
+                line: line_index.line(range.start),
+                block_index: 0,
+                heading_path: headings.iter().map(|(_, text)| text.clone()).collect(),
+            });
+            continue;
+        }
+
+        if matches!(event, Event::End(Tag::Paragraph))
+            && open.last().is_some_and(|&i| chunks[i].kind == "paragraph")
+        {
+            open.pop();
+            continue;
+        }
+
+        let current = open.last().map(|&i| &mut chunks[i].text);
+        match event {
+            Event::Start(Tag::Heading(..)) => heading_text = Some(String::new()),
+            Event::End(Tag::Heading(level, ..)) => {
+                let level = heading_level_number(level);
+                let text = heading_text.take().unwrap_or_default();
+                headings.retain(|(l, _)| *l < level);
+                headings.push((level, text.trim().to_string()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = heading_text.as_mut() {
+                    heading.push_str(&text);
+                } else if let Some(current) = current {
+                    current.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(heading) = heading_text.as_mut() {
+                    heading.push(' ');
+                } else if let Some(current) = current {
+                    current.push('\n');
+                }
+            }
+            Event::End(Tag::Paragraph) => {
+                if let Some(current) = current {
+                    current.push('\n');
+                }
+            }
+            Event::End(Tag::TableCell) => {
+                if let Some(current) = current {
+                    current.push_str(" | ");
+                }
+            }
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => {
+                if let Some(current) = current {
+                    current.truncate(current.trim_end_matches(" | ").len());
+                    current.push('\n');
+                }
+            }
+            Event::End(Tag::Item) | Event::End(Tag::CodeBlock(_)) | Event::End(Tag::Table(_)) => {
+                open.pop();
+            }
+            Event::Start(Tag::List(_)) => {
+                // Children of an item start on their own line of the parent
+                if let Some(current) = current {
+                    if !current.is_empty() && !current.ends_with('\n') {
+                        current.push('\n');
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut occurrences: HashMap<(Vec<String>, String), u32> = HashMap::new();
+    let mut emitted = Vec::new();
+    for mut chunk in chunks {
+        let text = chunk.text.trim().to_string();
+        if text.is_empty() {
+            continue;
+        }
+
+        let occurrence = occurrences
+            .entry((chunk.heading_path.clone(), normalize_text_for_hash(&text)))
+            .or_insert(0);
+        chunk.stable_id = generate_stable_chunk_id(&chunk.heading_path, *occurrence, &text);
+        *occurrence += 1;
+
+        chunk.block_index = emitted.len() as u32;
+        chunk.text = text;
+        emitted.push(chunk);
+    }
+
+    emitted
+}
+
+fn chunk_map<'a>(env: Env<'a>, chunk: &Chunk) -> HashMap<String, Term<'a>> {
+    let mut chunk_map = HashMap::new();
+    chunk_map.insert("stable_id".to_string(), chunk.stable_id.encode(env));
+    chunk_map.insert("kind".to_string(), chunk.kind.encode(env));
+    chunk_map.insert("text".to_string(), chunk.text.encode(env));
+    chunk_map.insert("line".to_string(), chunk.line.encode(env));
+    chunk_map.insert("block_index".to_string(), chunk.block_index.encode(env));
+    chunk_map.insert("heading_path".to_string(), chunk.heading_path.encode(env));
+    chunk_map
+}
+
+// Incremental re-parse
+/// The parts of a previous `chunk_document` block needed for matching
+#[derive(Debug, Clone, PartialEq)]
+struct PreviousBlock {
+    stable_id: String,
+    kind: String,
+    text: String,
+}
+
+impl PreviousBlock {
+    fn from_map(map: &HashMap<String, Term>) -> Result<Self, String> {
+        let field = |key: &str| -> Result<String, String> {
+            map.get(key)
+                .and_then(|term| term.decode::<String>().ok())
+                .ok_or_else(|| format!("old block is missing string field '{}'", key))
+        };
+
+        Ok(Self {
+            stable_id: field("stable_id")?,
+            kind: field("kind")?,
+            text: field("text")?,
+        })
+    }
+
+    fn content_hash(&self) -> String {
+        sha256_hash(&normalize_text_for_hash(&self.text))
+    }
+}
+
+impl From<&Chunk> for PreviousBlock {
+    fn from(chunk: &Chunk) -> Self {
+        Self {
+            stable_id: chunk.stable_id.clone(),
+            kind: chunk.kind.to_string(),
+            text: chunk.text.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BlockChange {
+    chunk: Chunk,
+    /// `"unchanged"`, `"moved"`, `"modified"` or `"added"`
+    status: &'static str,
+    /// Matched old ID when it differs from the new one
+    previous_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct BlockDiff {
+    changes: Vec<BlockChange>,
+    removed: Vec<String>,
+}
+
+/// Match new blocks against the previous block list
+///
+/// 1. Stable ID: the same text under the same heading path. Such blocks are
+///    `unchanged` unless their relative order changed, in which case the
+///    ones outside the longest order-preserving run are `moved`.
+/// 2. Content hash: identical text whose ID changed because its heading path
+///    did, reported as `moved`.
+/// 3. Position: a remaining new block is `modified` if an unmatched old block
+///    of the same kind sits in the same gap between matched blocks.
+///
+/// Anything left over is `added` (new) or removed (old).
+fn diff_blocks(previous: &[PreviousBlock], current: Vec<Chunk>) -> BlockDiff {
+    // Pass 1: stable IDs
+    let (mut matched, mut old_used) = match_stable_ids(
+        previous.iter().map(|block| block.stable_id.as_str()),
+        current.iter().map(|chunk| chunk.stable_id.as_str()),
+    );
+    let id_matched: Vec<bool> = matched.iter().map(Option::is_some).collect();
+
+    // Pass 2: content hashes
+    let mut old_by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, block) in previous.iter().enumerate().rev() {
+        if !old_used[i] {
+            old_by_hash.entry(block.content_hash()).or_default().push(i);
+        }
+    }
+    for (slot, chunk) in matched.iter_mut().zip(&current) {
+        if slot.is_none() {
+            let hash = sha256_hash(&normalize_text_for_hash(&chunk.text));
+            if let Some(i) = old_by_hash.get_mut(&hash).and_then(|indices| indices.pop()) {
+                old_used[i] = true;
+                *slot = Some(i);
+            }
+        }
+    }
+
+    // Pass 3: same kind in the same gap between matched anchors
+    let mut lower = 0usize;
+    for (n, chunk) in current.iter().enumerate() {
+        if let Some(i) = matched[n] {
+            lower = lower.max(i + 1);
+            continue;
+        }
+        let upper = matched[n..]
+            .iter()
+            .flatten()
+            .copied()
+            .find(|&i| i >= lower)
+            .unwrap_or(previous.len());
+        if let Some(i) = (lower..upper).find(|&i| !old_used[i] && previous[i].kind == chunk.kind) {
+            old_used[i] = true;
+            matched[n] = Some(i);
+            lower = i + 1;
+        }
+    }
+
+    let in_order = longest_increasing_run(
+        &matched
+            .iter()
+            .zip(&id_matched)
+            .map(|(slot, &by_id)| if by_id { *slot } else { None })
+            .collect::<Vec<_>>(),
+    );
+
+    let changes = current
+        .into_iter()
+        .enumerate()
+        .map(|(n, chunk)| {
+            let (status, previous_id) = match matched[n] {
+                None => ("added", None),
+                Some(_) if id_matched[n] && in_order[n] => ("unchanged", None),
+                Some(_) if id_matched[n] => ("moved", None),
+                Some(i) => {
+                    let status = if previous[i].text == chunk.text {
+                        "moved"
+                    } else {
+                        "modified"
+                    };
+                    (status, Some(previous[i].stable_id.clone()))
+                }
+            };
+            BlockChange {
+                chunk,
+                status,
+                previous_id,
+            }
+        })
+        .collect();
+
+    let removed = previous
+        .iter()
+        .zip(&old_used)
+        .filter(|(_, &used)| !used)
+        .map(|(block, _)| block.stable_id.clone())
+        .collect();
+
+    BlockDiff { changes, removed }
+}
+
+/// Pair each new block with the first unused old block of the same stable
+/// ID; returns the old index per new block and which old blocks were used
+fn match_stable_ids<'a>(
+    old: impl ExactSizeIterator<Item = &'a str>,
+    new: impl Iterator<Item = &'a str>,
+) -> (Vec<Option<usize>>, Vec<bool>) {
+    let mut old_used = vec![false; old.len()];
+    let mut old_by_id: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, id) in old.enumerate() {
+        old_by_id.entry(id).or_default().push(i);
+    }
+    for indices in old_by_id.values_mut() {
+        indices.reverse();
+    }
+
+    let matched = new
+        .map(|id| {
+            let i = old_by_id.get_mut(id).and_then(|indices| indices.pop())?;
+            old_used[i] = true;
+            Some(i)
+        })
+        .collect();
+    (matched, old_used)
+}
+
+/// Mark the members of a longest strictly increasing subsequence of the
+/// `Some` values; `None` entries are never members
+fn longest_increasing_run(values: &[Option<usize>]) -> Vec<bool> {
+    // tails[k] = index into `values` of the smallest tail of a run of length k+1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut parent: Vec<Option<usize>> = vec![None; values.len()];
+
+    for (n, value) in values.iter().enumerate() {
+        let Some(value) = *value else { continue };
+        let k = tails.partition_point(|&t| values[t].is_some_and(|tail| tail < value));
+        parent[n] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(n);
+        } else {
+            tails[k] = n;
+        }
+    }
+
+    let mut members = vec![false; values.len()];
+    let mut next = tails.last().copied();
+    while let Some(n) = next {
+        members[n] = true;
+        next = parent[n];
+    }
+    members
+}
+
+// Document diff
+/// Blocks whose text similarity reaches this are paired as `modified`
+const DIFF_MATCH_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct BlockOperation {
+    /// `"unchanged"`, `"modified"`, `"added"` or `"removed"`
+    op: &'static str,
+    kind: &'static str,
+    old_id: Option<String>,
+    new_id: Option<String>,
+    old_line: Option<usize>,
+    new_line: Option<usize>,
+    /// New text, or the old text for removed blocks
+    text: String,
+    /// 1.0 for unchanged blocks, 0.0 for added and removed ones
+    similarity: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DocumentDiff {
+    /// New blocks in document order, then removed blocks in old order
+    operations: Vec<BlockOperation>,
+    /// Matched similarity over all blocks of both versions: 1.0 for
+    /// identical documents, 0.0 when nothing matches
+    similarity: f64,
+}
+
+/// Align the `chunk_blocks` of two versions of a document
+///
+/// Blocks with equal stable IDs (same text under the same heading path)
+/// are `unchanged`. The rest are paired greedily, most similar first, with
+/// an unmatched block of the same kind when their `text_similarity` is at
+/// least `DIFF_MATCH_THRESHOLD`; those are `modified`. Unlike `diff_blocks`
+/// this ignores order, so moves show up as unchanged.
+///
+/// Each pair is compared over its full text, but `similarity_at_least`
+/// gives up as soon as the threshold is out of reach.
+fn diff_documents(old_content: &str, new_content: &str) -> DocumentDiff {
+    let old = chunk_blocks(old_content);
+    let new = chunk_blocks(new_content);
+
+    let (id_matched, mut old_used) = match_stable_ids(
+        old.iter().map(|chunk| chunk.stable_id.as_str()),
+        new.iter().map(|chunk| chunk.stable_id.as_str()),
+    );
+    let mut matched: Vec<Option<(usize, f64)>> = id_matched
+        .into_iter()
+        .map(|slot| slot.map(|i| (i, 1.0)))
+        .collect();
+
+    let old_chars: Vec<Vec<char>> = old
+        .iter()
+        .map(|chunk| normalized_chars(&chunk.text))
+        .collect();
+    let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+    for (n, chunk) in new.iter().enumerate() {
+        if matched[n].is_some() {
+            continue;
+        }
+        let chars = normalized_chars(&chunk.text);
+        for (i, old_chunk) in old.iter().enumerate() {
+            if old_used[i] || old_chunk.kind != chunk.kind {
+                continue;
+            }
+            if let Some(similarity) =
+                similarity_at_least(&old_chars[i], &chars, DIFF_MATCH_THRESHOLD)
+            {
+                candidates.push((similarity, n, i));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+    for (similarity, n, i) in candidates {
+        if matched[n].is_none() && !old_used[i] {
+            matched[n] = Some((i, similarity));
+            old_used[i] = true;
+        }
+    }
+
+    let mut operations: Vec<BlockOperation> = new
+        .iter()
+        .zip(&matched)
+        .map(|(chunk, slot)| {
+            let old_chunk = slot.map(|(i, _)| &old[i]);
+            let similarity = slot.map_or(0.0, |(_, similarity)| similarity);
+            BlockOperation {
+                op: match slot {
+                    None => "added",
+                    Some(_) if old_chunk.is_some_and(|old| old.stable_id == chunk.stable_id) => {
+                        "unchanged"
+                    }
+                    Some(_) => "modified",
+                },
+                kind: chunk.kind,
+                old_id: old_chunk.map(|old| old.stable_id.clone()),
+                new_id: Some(chunk.stable_id.clone()),
+                old_line: old_chunk.map(|old| old.line),
+                new_line: Some(chunk.line),
+                text: chunk.text.clone(),
+                similarity,
+            }
+        })
+        .collect();
+    let matched_similarity: f64 = operations.iter().map(|op| op.similarity).sum();
+    operations.extend(
+        old.iter()
+            .zip(&old_used)
+            .filter(|(_, &used)| !used)
+            .map(|(chunk, _)| BlockOperation {
+                op: "removed",
+                kind: chunk.kind,
+                old_id: Some(chunk.stable_id.clone()),
+                new_id: None,
+                old_line: Some(chunk.line),
+                new_line: None,
+                text: chunk.text.clone(),
+                similarity: 0.0,
+            }),
+    );
+
+    let total = old.len() + new.len();
+    DocumentDiff {
+        operations,
+        similarity: if total == 0 {
+            1.0
+        } else {
+            2.0 * matched_similarity / total as f64
+        },
+    }
+}
+
+/// Characters of the text with whitespace runs collapsed to one space
+fn normalized_chars(text: &str) -> Vec<char> {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect()
+}
+
+/// `1 - levenshtein / longer length` over two `normalized_chars`, if that
+/// reaches `threshold`
+///
+/// The threshold caps the edit distance worth computing. Pairs whose length
+/// difference or bag distance (characters one text has beyond the other,
+/// counted with repeats) already exceeds the cap are rejected outright; the
+/// rest go to `bounded_levenshtein`.
+fn similarity_at_least(a: &[char], b: &[char], threshold: f64) -> Option<f64> {
+    let longer = a.len().max(b.len());
+    if longer == 0 {
+        return Some(1.0);
+    }
+    let max_distance = ((1.0 - threshold) * longer as f64).floor() as usize;
+    if a.len().abs_diff(b.len()) > max_distance || bag_distance(a, b) > max_distance {
+        return None;
+    }
+    let distance = bounded_levenshtein(a, b, max_distance)?;
+    Some(1.0 - distance as f64 / longer as f64)
+}
+
+/// A lower bound on the edit distance: each edit adds or removes at most
+/// one character of either side's multiset
+fn bag_distance(a: &[char], b: &[char]) -> usize {
+    let mut counts: HashMap<char, isize> = HashMap::new();
+    for c in a {
+        *counts.entry(*c).or_default() += 1;
+    }
+    for c in b {
+        *counts.entry(*c).or_default() -= 1;
+    }
+    let (extra_a, extra_b) = counts.values().fold((0, 0), |(extra_a, extra_b), &n| {
+        (extra_a + n.max(0) as usize, extra_b + (-n).max(0) as usize)
+    });
+    extra_a.max(extra_b)
+}
+
+/// Levenshtein distance of `a` and `b` if it is at most `max`
+///
+/// A path within that distance never leaves the band of cells within `max`
+/// of the diagonal, so each row fills only that band, and the scan stops
+/// once a whole band exceeds `max`: O(max × length) at worst.
+fn bounded_levenshtein(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let out_of_band = max + 1;
+    let mut previous: Vec<usize> = (0..=b.len())
+        .map(|j| if j <= max { j } else { out_of_band })
+        .collect();
+    let mut row = vec![out_of_band; b.len() + 1];
+    for (i, ca) in (1usize..).zip(a) {
+        let from = i.saturating_sub(max);
+        let to = (i + max).min(b.len());
+        row[0] = if i <= max { i } else { out_of_band };
+        if from > 1 {
+            row[from - 1] = out_of_band;
+        }
+        let mut band_min = row[0];
+        for j in from.max(1)..=to {
+            let substitution = previous[j - 1] + usize::from(*ca != b[j - 1]);
+            row[j] = substitution
+                .min(previous[j] + 1)
+                .min(row[j - 1] + 1)
+                .min(out_of_band);
+            band_min = band_min.min(row[j]);
+        }
+        if to < b.len() {
+            row[to + 1] = out_of_band;
+        }
+        if band_min > max {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut row);
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= max)
+}
+
+// MQ2 (MarkQant v2) block parsing
+//
+// ```text
+// block    := header NL table NL [ext NL] "~~~~" NL body
+// header   := "MQ2~" hex "~" hex "~" hex "~" hex "~" ("mq" | "mqb") "~" ("L0" | "L1" | "L2")
+// table    := "~T" entry ("|" entry)* ["|"]
+// ext      := "~X" entry ("|" entry)* ["|"]       (L1 and up)
+// entry    := token pattern
+// token    := "\x" HEX HEX | U+0080..U+00FE       (base: 0x80-0xFE)
+//           | "\x7F" "\x" HEX HEX                (extended: second byte 0x00-0x0F)
+// ```
+//
+// Header fields are timestamp, original size, compressed size and token
+// count. Tokens may be written as `\xNN` escapes or as the raw Latin-1
+// character.
+const MQ2_MAX_TOKEN_TABLE: usize = 64 * 1024;
+const MQ2_SEPARATOR: &str = "~~~~";
+const MQ2_X_TOKEN: u8 = 0x7F;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Mq2Header {
+    timestamp: u64,
+    original_size: u64,
+    compressed_size: u64,
+    token_count: u64,
+    format: String,
+    level: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Mq2Token {
+    bytes: Vec<u8>,
+    pattern: String,
+}
+
+impl Mq2Token {
+    /// Token bytes in `\xNN` escape form
+    fn escaped(&self) -> String {
+        self.bytes.iter().map(|b| format!("\\x{:02X}", b)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Mq2Block {
+    header: Mq2Header,
+    tokens: Vec<Mq2Token>,
+    extended_tokens: Vec<Mq2Token>,
+    body: String,
+}
+
+/// Parse error with a 0-based line offset into the block
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Mq2Error {
+    message: String,
+    line: usize,
+}
+
+impl Mq2Error {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            line,
+        }
+    }
+}
+
+fn parse_mq2_block(input: &str) -> Result<Mq2Block, Mq2Error> {
+    let mut lines = input.split('\n').map(|line| line.trim_end_matches('\r'));
+
+    let header = parse_mq2_header(lines.next().unwrap_or(""))?;
+
+    let table = lines.next().unwrap_or("");
+    let tokens = match table.strip_prefix("~T") {
+        Some(entries) => parse_mq2_token_table(entries, 1, false)?,
+        None => return Err(Mq2Error::new(1, "expected '~T' token table")),
+    };
+
+    let mut line_no = 2;
+    let mut next = lines.next();
+    let mut extended_tokens = Vec::new();
+    if let Some(entries) = next.and_then(|line| line.strip_prefix("~X")) {
+        if header.level < 1 {
+            return Err(Mq2Error::new(
+                line_no,
+                "'~X' table requires level L1 or higher",
+            ));
+        }
+        extended_tokens = parse_mq2_token_table(entries, line_no, true)?;
+        line_no += 1;
+        next = lines.next();
+    }
+
+    if next != Some(MQ2_SEPARATOR) {
+        return Err(Mq2Error::new(line_no, "expected '~~~~' separator"));
+    }
+
+    let mut seen = HashSet::new();
+    for token in tokens.iter().chain(&extended_tokens) {
+        if !seen.insert(token.bytes.clone()) {
+            return Err(Mq2Error::new(
+                1,
+                format!("duplicate token {}", token.escaped()),
+            ));
+        }
+    }
+
+    Ok(Mq2Block {
+        header,
+        tokens,
+        extended_tokens,
+        body: lines.collect::<Vec<_>>().join("\n"),
+    })
+}
+
+fn parse_mq2_header(line: &str) -> Result<Mq2Header, Mq2Error> {
+    let fields: Vec<&str> = line.split('~').collect();
+    if fields[0] != "MQ2" {
+        return Err(Mq2Error::new(0, "expected 'MQ2' signature"));
+    }
+    if fields.len() != 7 {
+        return Err(Mq2Error::new(
+            0,
+            format!("expected 6 header fields, found {}", fields.len() - 1),
+        ));
+    }
+
+    let hex = |index: usize, name: &str| {
+        u64::from_str_radix(fields[index], 16)
+            .map_err(|_| Mq2Error::new(0, format!("{} is not hex: '{}'", name, fields[index])))
+    };
+
+    let format = match fields[5] {
+        "mq" | "mqb" => fields[5].to_string(),
+        other => return Err(Mq2Error::new(0, format!("unknown format '{}'", other))),
+    };
+    let level = match fields[6] {
+        "L0" => 0,
+        "L1" => 1,
+        "L2" => 2,
+        other => return Err(Mq2Error::new(0, format!("unknown level '{}'", other))),
+    };
+
+    Ok(Mq2Header {
+        timestamp: hex(1, "timestamp")?,
+        original_size: hex(2, "original size")?,
+        compressed_size: hex(3, "compressed size")?,
+        token_count: hex(4, "token count")?,
+        format,
+        level,
+    })
+}
+
+fn parse_mq2_token_table(
+    entries: &str,
+    line: usize,
+    extended: bool,
+) -> Result<Vec<Mq2Token>, Mq2Error> {
+    if entries.len() > MQ2_MAX_TOKEN_TABLE {
+        return Err(Mq2Error::new(line, "token table exceeds 64KB"));
+    }
+
+    let b = entries.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0usize;
+    while i < b.len() {
+        let mut bytes = vec![parse_mq2_token_byte(entries, &mut i, line)?];
+        if extended {
+            if bytes[0] != MQ2_X_TOKEN {
+                return Err(Mq2Error::new(line, "extended tokens must start with \\x7F"));
+            }
+            let id = parse_mq2_token_byte(entries, &mut i, line)?;
+            if id > 0x0F {
+                return Err(Mq2Error::new(
+                    line,
+                    format!("extended token id \\x{:02X} out of range", id),
+                ));
+            }
+            bytes.push(id);
+        } else if !(0x80..=0xFE).contains(&bytes[0]) {
+            return Err(Mq2Error::new(
+                line,
+                format!("base token \\x{:02X} outside 0x80-0xFE", bytes[0]),
+            ));
+        }
+
+        let start = i;
+        while i < b.len() && b[i] != b'|' {
+            i += 1;
+        }
+        if start == i {
+            return Err(Mq2Error::new(line, "token without a pattern"));
+        }
+        tokens.push(Mq2Token {
+            bytes,
+            pattern: entries[start..i].to_string(),
+        });
+        i += 1; // skip '|'
+    }
+
+    Ok(tokens)
+}
+
+fn parse_mq2_token_byte(s: &str, i: &mut usize, line: usize) -> Result<u8, Mq2Error> {
+    let rest = &s[*i..];
+    if let Some(escape) = rest.strip_prefix("\\x") {
+        let hex = escape
+            .get(..2)
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| Mq2Error::new(line, "malformed \\x escape"))?;
+        *i += 4;
+        return Ok(hex);
+    }
+
+    match rest.chars().next() {
+        Some(c) if (0x7F..=0xFF).contains(&(c as u32)) => {
+            *i += c.len_utf8();
+            Ok(c as u8)
+        }
+        Some(c) => Err(Mq2Error::new(
+            line,
+            format!("expected token, found {:?}", c),
+        )),
+        None => Err(Mq2Error::new(line, "expected token")),
+    }
+}
+
+/// An `mq2` fence (opening fence line) and its parse outcome
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Mq2Fence {
+    line: usize,
+    parsed: Result<Mq2Block, Mq2Error>,
+}
+
+fn collect_mq2_blocks(content: &str) -> Vec<Mq2Fence> {
+    collect_fenced_blocks(content, |lang| lang == "mq2")
+        .into_iter()
+        .map(|fence| Mq2Fence {
+            line: fence.line,
+            parsed: parse_mq2_block(&fence.body),
+        })
+        .collect()
+}
+
+fn encode_mq2_blocks<'a>(env: Env<'a>, fences: &[Mq2Fence]) -> Term<'a> {
+    let blocks: Vec<Term> = fences
+        .iter()
+        .map(|fence| {
+            let mut block_map = HashMap::new();
+            match &fence.parsed {
+                Ok(block) => {
+                    block_map.insert("valid".to_string(), true.encode(env));
+                    block_map.insert("line".to_string(), fence.line.encode(env));
+                    block_map.insert("ast".to_string(), encode_mq2_ast(env, block));
+                }
+                Err(e) => {
+                    block_map.insert("valid".to_string(), false.encode(env));
+                    block_map.insert("error".to_string(), e.message.encode(env));
+                    // Body lines start on the line after the opening fence
+                    block_map.insert("line".to_string(), (fence.line + 1 + e.line).encode(env));
+                }
+            }
+            block_map.encode(env)
+        })
+        .collect();
+
+    blocks.encode(env)
+}
+
+fn encode_mq2_ast<'a>(env: Env<'a>, block: &Mq2Block) -> Term<'a> {
+    let mut header = HashMap::new();
+    header.insert("timestamp".to_string(), block.header.timestamp.encode(env));
+    header.insert(
+        "original_size".to_string(),
+        block.header.original_size.encode(env),
+    );
+    header.insert(
+        "compressed_size".to_string(),
+        block.header.compressed_size.encode(env),
+    );
+    header.insert(
+        "token_count".to_string(),
+        block.header.token_count.encode(env),
+    );
+    header.insert("format".to_string(), block.header.format.encode(env));
+    header.insert("level".to_string(), block.header.level.encode(env));
+
+    let encode_tokens = |tokens: &[Mq2Token]| -> Term<'a> {
+        tokens
+            .iter()
+            .map(|token| {
+                let mut token_map = HashMap::new();
+                token_map.insert("token".to_string(), token.escaped());
+                token_map.insert("pattern".to_string(), token.pattern.clone());
+                token_map
+            })
+            .collect::<Vec<_>>()
+            .encode(env)
+    };
+
+    let mut ast = HashMap::new();
+    ast.insert("header".to_string(), header.encode(env));
+    ast.insert("tokens".to_string(), encode_tokens(&block.tokens));
+    ast.insert(
+        "extended_tokens".to_string(),
+        encode_tokens(&block.extended_tokens),
+    );
+    ast.insert("body".to_string(), block.body.encode(env));
+    ast.encode(env)
+}
+
+/// A fenced code block body and the 1-based line of its opening fence
+#[derive(Debug, Clone, PartialEq)]
+struct FencedBlock {
+    line: usize,
+    body: String,
+    /// Byte range of the whole block, fences included
+    offset: usize,
+    end: usize,
+}
+
+fn collect_fenced_blocks(
+    content: &str,
+    matches_language: impl Fn(&str) -> bool,
+) -> Vec<FencedBlock> {
+    let line_index = LineIndex::new(content);
+    let mut blocks = Vec::new();
+    let mut current: Option<FencedBlock> = None;
+
+    for (event, range) in Parser::new_ext(content, markdown_parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
+                if matches_language(lang.as_ref()) =>
+            {
+                current = Some(FencedBlock {
+                    line: line_index.line(range.start),
+                    body: String::new(),
+                    offset: range.start,
+                    end: range.end,
+                });
+            }
+            Event::Text(text) => {
+                if let Some(block) = current.as_mut() {
+                    block.body.push_str(&text);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => blocks.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+// mem8 fence parsing
+//
+// Each non-blank line of a `mem8` fence declares one wave as attribute
+// pairs, e.g. `freq=440 amp=0.8 valence=-0.2`; lines starting with `#` are
+// comments.
+const MEM8_DEFAULT_DECAY_TAU: f32 = 5.0;
+
+#[derive(Debug, Clone, Serialize)]
+struct Mem8WaveSpec {
+    line: usize,
+    params: BTreeMap<String, AttributeValue>,
+    /// `None` when a required or malformed parameter prevents construction
+    wave: Option<MemoryWave>,
+    errors: Vec<String>,
+}
+
+fn collect_mem8_waves(content: &str) -> Vec<Mem8WaveSpec> {
+    let mut specs = Vec::new();
+    for fence in collect_fenced_blocks(content, |lang| lang == "mem8") {
+        for (offset, text) in fence.body.lines().enumerate() {
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            specs.push(parse_mem8_wave_line(text, fence.line + 1 + offset));
+        }
+    }
+    specs
+}
+
+fn parse_mem8_wave_line(text: &str, line: usize) -> Mem8WaveSpec {
+    let params = match parse_attribute_map(text, false) {
+        Ok(params) => params,
+        Err(e) => {
+            let error = match e {
+                attr_object::Error::ParseError {
+                    message, offset, ..
+                } => format!("{} at byte {}", message, offset),
+                attr_object::Error::LimitExceeded => "attribute limits exceeded".to_string(),
+            };
+            return Mem8WaveSpec {
+                line,
+                params: BTreeMap::new(),
+                wave: None,
+                errors: vec![error],
+            };
+        }
+    };
+
+    let mut errors = Vec::new();
+    let mut keys: Vec<&String> = params.keys().collect();
+    keys.sort();
+
+    let mut frequency = None;
+    let mut wave = MemoryWave {
+        amplitude: 1.0,
+        frequency: 0.0,
+        phase: 0.0,
+        valence: 0.0,
+        arousal: 0.0,
+        decay_tau: Some(MEM8_DEFAULT_DECAY_TAU),
+    };
+
+    for key in keys {
+        let value = match &params[key] {
+            AttributeValue::Number(n) => *n as f32,
+            _ => {
+                errors.push(format!("{} must be a number", key));
+                continue;
+            }
+        };
+        match key.as_str() {
+            "freq" | "frequency" => frequency = Some(value),
+            "amp" | "amplitude" => wave.amplitude = value,
+            "phase" => wave.phase = value,
+            "valence" => wave.valence = value,
+            "arousal" => wave.arousal = value,
+            "decay" | "decay_tau" => wave.decay_tau = Some(value),
+            other => errors.push(format!("unknown parameter '{}'", other)),
+        }
+    }
+
+    match frequency {
+        // Same range create_memory_wave enforces
+        Some(f) if f.is_finite() => wave.frequency = f.clamp(0.0, 1000.0),
+        Some(_) => errors.push("freq must be finite".to_string()),
+        None => errors.push("missing freq".to_string()),
+    }
+    if wave.amplitude < 0.0 {
+        errors.push("amplitude must be non-negative".to_string());
+    }
+    // Anything above prevents construction; out-of-range emotion is clamped
+    let constructible = errors.is_empty();
+
+    if !(-1.0..=1.0).contains(&wave.valence) {
+        errors.push(format!("valence {} outside [-1, 1]", wave.valence));
+        wave.valence = wave.valence.clamp(-1.0, 1.0);
+    }
+    if !(0.0..=1.0).contains(&wave.arousal) {
+        errors.push(format!("arousal {} outside [0, 1]", wave.arousal));
+        wave.arousal = wave.arousal.clamp(0.0, 1.0);
+    }
+
+    Mem8WaveSpec {
+        line,
+        params,
+        wave: constructible.then_some(wave),
+        errors,
+    }
+}
+
+fn encode_mem8_waves<'a>(env: Env<'a>, specs: &[Mem8WaveSpec]) -> Term<'a> {
+    let encoded: Vec<Term> = specs
+        .iter()
+        .map(|spec| {
+            let mut spec_map = HashMap::new();
+            spec_map.insert("line".to_string(), spec.line.encode(env));
+            spec_map.insert("params".to_string(), encode_attributes(env, &spec.params));
+            spec_map.insert(
+                "wave".to_string(),
+                match &spec.wave {
+                    Some(wave) => encode_memory_wave(env, wave),
+                    None => atoms::nil().encode(env),
+                },
+            );
+            spec_map.insert("errors".to_string(), spec.errors.encode(env));
+            spec_map.encode(env)
+        })
+        .collect();
+
+    encoded.encode(env)
+}
+
+fn encode_memory_wave<'a>(env: Env<'a>, wave: &MemoryWave) -> Term<'a> {
+    let mut wave_data = HashMap::new();
+    wave_data.insert("amplitude".to_string(), wave.amplitude.encode(env));
+    wave_data.insert("frequency".to_string(), wave.frequency.encode(env));
+    wave_data.insert("phase".to_string(), wave.phase.encode(env));
+    wave_data.insert("valence".to_string(), wave.valence.encode(env));
+    wave_data.insert("arousal".to_string(), wave.arousal.encode(env));
+    wave_data.insert(
+        "decay_tau".to_string(),
+        match wave.decay_tau {
+            Some(tau) => tau.encode(env),
+            None => atoms::nil().encode(env),
+        },
+    );
+    wave_data.encode(env)
+}
+
+/// Decode a wave map as produced by `create_memory_wave`
+fn decode_memory_wave(term: Term) -> Result<MemoryWave, String> {
+    serde_json::from_value(term_to_json(term)?).map_err(|e| format!("invalid wave: {}", e))
+}
+
+/// `mem8::wave::band_histogram` keyed by `MemoryRegion::semantic_category`
+fn wave_band_histogram(waves: &[MemoryWave]) -> HashMap<&'static str, usize> {
+    let waves: Vec<_> = waves.iter().map(MemoryWave::to_mem8).collect();
+    mem8::wave::band_histogram(&waves)
+        .into_iter()
+        .map(|(band, count)| {
+            (
+                mem8::consciousness::MemoryRegion::semantic_category(band),
+                count,
+            )
+        })
+        .collect()
+}
+
+// Prose statistics
+const DEFAULT_READING_WPM: u32 = 200;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+struct DocumentStats {
+    word_count: usize,
+    /// Non-whitespace characters of prose
+    char_count: usize,
+    sentence_count: usize,
+    reading_time_seconds: u64,
+}
+
+fn reading_wpm_from_options(options: &[(String, String)]) -> Result<u32, ParseError> {
+    match options.iter().rev().find(|(key, _)| key == "reading_wpm") {
+        Some((_, value)) => match value.trim().parse::<u32>() {
+            Ok(wpm) if wpm > 0 => Ok(wpm),
+            _ => Err(ParseError::InvalidOption(
+                "reading_wpm must be a positive integer".to_string(),
+            )),
+        },
+        None => Ok(DEFAULT_READING_WPM),
+    }
+}
+
+/// Word, sentence and reading-time statistics over prose only
+///
+/// Frontmatter and code-block contents are skipped; inline code counts as
+/// prose. Block ends act as word and sentence boundaries.
+fn collect_document_stats(content: &str, wpm: u32) -> DocumentStats {
+    let body = &content[frontmatter_len(content)..];
+    let mut prose = String::new();
+    let mut in_code_block = false;
+
+    for event in Parser::new_ext(body, markdown_parser_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(text) | Event::Code(text) if !in_code_block => prose.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => prose.push(' '),
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::Heading(..))
+            | Event::End(Tag::Item)
+            | Event::End(Tag::TableCell) => prose.push('\n'),
+            _ => {}
+        }
+    }
+
+    let word_count = count_words(&prose);
+    DocumentStats {
+        word_count,
+        char_count: prose.chars().filter(|c| !c.is_whitespace()).count(),
+        sentence_count: count_sentences(&prose),
+        reading_time_seconds: (word_count as u64 * 60).div_ceil(wpm as u64),
+    }
+}
+
+/// Byte length of a leading frontmatter block in any format recognised by
+/// `detect_frontmatter`, or 0
+fn frontmatter_len(content: &str) -> usize {
+    detect_frontmatter(content).map_or(0, |frontmatter| frontmatter.len)
+}
+
+/// Runs of `.`, `?` or `!` followed by whitespace or end of text
+fn count_sentences(text: &str) -> usize {
+    let mut count = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if matches!(c, '.' | '?' | '!') {
+            while chars.next_if(|c| matches!(c, '.' | '?' | '!')).is_some() {}
+            if chars.peek().is_none_or(|next| next.is_whitespace()) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn encode_document_stats<'a>(env: Env<'a>, stats: &DocumentStats) -> Term<'a> {
+    let mut stats_map = HashMap::new();
+    stats_map.insert("word_count".to_string(), stats.word_count as u64);
+    stats_map.insert("char_count".to_string(), stats.char_count as u64);
+    stats_map.insert("sentence_count".to_string(), stats.sentence_count as u64);
+    stats_map.insert(
+        "reading_time_seconds".to_string(),
+        stats.reading_time_seconds,
+    );
+    stats_map.encode(env)
+}
+
+/// Count words separated by ASCII whitespace, 16 bytes at a time
+#[cfg(feature = "simd")]
+fn count_words(text: &str) -> usize {
+    use wide::u8x16;
+
+    let bytes = text.as_bytes();
+    let mut count = 0usize;
+    let mut previous_is_word = false;
+
+    let mut chunks = bytes.chunks_exact(16);
+    for chunk in &mut chunks {
+        let lanes = u8x16::new(chunk.try_into().expect("16-byte chunk"));
+        let whitespace = [b' ', b'\t', b'\n', b'\r', 0x0C]
+            .iter()
+            .fold(u8x16::splat(0), |acc, &ws| {
+                acc | lanes.cmp_eq(u8x16::splat(ws))
+            });
+        let word_bits = !(whitespace.move_mask() as u32) & 0xFFFF;
+        let shifted = (word_bits << 1) | previous_is_word as u32;
+        count += (word_bits & !shifted).count_ones() as usize;
+        previous_is_word = word_bits & 0x8000 != 0;
+    }
+
+    for &b in chunks.remainder() {
+        let is_word = !b.is_ascii_whitespace();
+        if is_word && !previous_is_word {
+            count += 1;
+        }
+        previous_is_word = is_word;
+    }
+
+    count
+}
+
+#[cfg(not(feature = "simd"))]
+fn count_words(text: &str) -> usize {
+    text.split_ascii_whitespace().count()
+}
+
+// Polyglot detection
+//
+// Each detector scores `primary matches × primary weight + secondary
+// matches × secondary weight`; the highest score above the threshold wins,
+// the earlier detector on a tie.
+
+/// Multipliers for a detector's primary and secondary pattern matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PolyglotWeights {
+    primary: f32,
+    secondary: f32,
+}
+
+const DEFAULT_POLYGLOT_WEIGHTS: PolyglotWeights = PolyglotWeights {
+    primary: 2.0,
+    secondary: 0.5,
+};
+const DEFAULT_POLYGLOT_THRESHOLD: f32 = 2.0;
+
+/// Detector weights and the score a document must exceed to count as
+/// polyglot
+#[derive(Debug, Clone, PartialEq)]
+struct PolyglotConfig {
+    threshold: f32,
+    /// Keyed by detector language
+    weights: HashMap<String, PolyglotWeights>,
+}
+
+impl Default for PolyglotConfig {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_POLYGLOT_THRESHOLD,
+            weights: POLYGLOT_DETECTORS
+                .iter()
+                .map(|detector| (detector.language.clone(), DEFAULT_POLYGLOT_WEIGHTS))
+                .collect(),
+        }
+    }
+}
+
+impl PolyglotConfig {
+    /// `threshold`, `primary_weight` and `secondary_weight` options, plus
+    /// per-detector `<language>.primary_weight` / `<language>.secondary_weight`
+    /// (e.g. `{"bash.primary_weight", "1.0"}`), which win over the global
+    /// weights; all non-negative numbers
+    fn from_options(options: &[(String, String)]) -> Result<Self, ParseError> {
+        let number = |name: &str| match options.iter().rev().find(|(key, _)| key == name) {
+            Some((_, value)) => match value.trim().parse::<f32>() {
+                Ok(n) if n.is_finite() && n >= 0.0 => Ok(Some(n)),
+                _ => Err(ParseError::InvalidOption(format!(
+                    "{} must be a non-negative number",
+                    name
+                ))),
+            },
+            None => Ok(None),
+        };
+
+        let mut config = Self::default();
+        if let Some(threshold) = number("threshold")? {
+            config.threshold = threshold;
+        }
+        let primary = number("primary_weight")?;
+        let secondary = number("secondary_weight")?;
+        for (language, weights) in config.weights.iter_mut() {
+            if let Some(weight) = number(&format!("{}.primary_weight", language))?.or(primary) {
+                weights.primary = weight;
+            }
+            if let Some(weight) = number(&format!("{}.secondary_weight", language))?.or(secondary) {
+                weights.secondary = weight;
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Score of every detector, in detector order
+fn polyglot_scores(content: &str, config: &PolyglotConfig) -> Vec<(String, f32)> {
+    POLYGLOT_DETECTORS
+        .iter()
+        .map(|detector| {
+            let weights = config
+                .weights
+                .get(&detector.language)
+                .copied()
+                .unwrap_or(DEFAULT_POLYGLOT_WEIGHTS);
+            let primary_matches = detector.primary_pattern.find_iter(content).count();
+            let secondary_matches = detector.secondary_pattern.find_iter(content).count();
+            let score = primary_matches as f32 * weights.primary
+                + secondary_matches as f32 * weights.secondary;
+            (detector.language.clone(), score)
+        })
+        .collect()
+}
+
+fn polyglot_document_from_scores(
+    content: &str,
+    scores: &[(String, f32)],
+    threshold: f32,
+) -> Option<PolyglotDocument> {
+    let mut best: Option<(&String, f32)> = None;
+    for (language, score) in scores {
+        if *score > threshold && best.is_none_or(|(_, max)| *score > max) {
+            best = Some((language, *score));
+        }
+    }
+
+    best.map(|(language, _)| PolyglotDocument {
+        language: language.clone(),
+        artifacts: extract_artifacts_for_language(content, language),
+        metadata: HashMap::new(),
+        concealment: extract_all_concealment(content),
+    })
+}
+
+fn detect_polyglot_document(content: &str) -> Option<PolyglotDocument> {
+    let config = PolyglotConfig::default();
+    polyglot_document_from_scores(
+        content,
+        &polyglot_scores(content, &config),
+        config.threshold,
+    )
+}
+
+fn extract_artifacts_for_language(content: &str, language: &str) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    let mut line_num = 1usize;
+
+    match language {
+        "dockerfile" => {
+            for line in content.lines() {
+                if line.trim_start().to_uppercase().starts_with("FROM") {
+                    artifacts.push(Artifact {
+                        artifact_type: "dockerfile".to_string(),
+                        content: line.to_string(),
+                        language: Some("dockerfile".to_string()),
+                        line_start: line_num,
+                        line_end: line_num,
+                    });
+                }
+                line_num += 1;
+            }
+        }
+        "kubernetes" => {
+            // Extract YAML blocks that look like k8s
+            if content.contains("apiVersion:") && content.contains("kind:") {
+                artifacts.push(Artifact {
+                    artifact_type: "kubernetes".to_string(),
+                    content: content.to_string(),
+                    language: Some("yaml".to_string()),
+                    line_start: 1,
+                    line_end: content.lines().count(),
+                });
+            }
+        }
+        "bash" => {
+            for line in content.lines() {
+                if line.trim_start().starts_with("#!/bin/bash")
+                    || line.trim_start().starts_with("#!/bin/sh")
+                {
+                    artifacts.push(Artifact {
+                        artifact_type: "executable".to_string(),
+                        content: content.to_string(),
+                        language: Some("bash".to_string()),
+                        line_start: 1,
+                        line_end: content.lines().count(),
+                    });
+                    break;
+                }
+                line_num += 1;
+            }
+        }
+        _ => {}
+    }
+
+    artifacts
+}
+
+// Character concealment functions
+fn extract_all_concealment(content: &str) -> ConcealmentData {
+    ConcealmentData {
+        zero_width_chars: extract_zero_width_chars(content),
+        content_addressed_links: extract_content_addressed_links(content),
+        whitespace_patterns: extract_whitespace_patterns(content),
+        html_comments: extract_html_comments(content),
+    }
+}
+
+fn extract_zero_width_chars(content: &str) -> Vec<u8> {
+    zero_width_symbols(content, SAFE_ZERO_WIDTH_ALPHABET)
+}
+
+/// Index into `alphabet` of every alphabet character in `content`
+fn zero_width_symbols(content: &str, alphabet: &[char]) -> Vec<u8> {
+    content
+        .chars()
+        .filter_map(|ch| alphabet.iter().position(|&c| c == ch))
+        .map(|index| index as u8)
+        .collect()
+}
+
+/// Bits carried by one character of `alphabet`; it must have 2, 4 or 16
+/// characters so a byte splits into whole symbols
+fn zero_width_bits(alphabet: &[char]) -> Result<u32, String> {
+    match alphabet.len() {
+        2 | 4 | 16 => Ok(alphabet.len().trailing_zeros()),
+        n => Err(format!(
+            "alphabet must have 2, 4 or 16 characters, got {}",
+            n
+        )),
+    }
+}
+
+/// Encode each byte as `8 / bits` alphabet characters, most significant first
+fn encode_zero_width_data(data: &[u8], alphabet: &[char]) -> String {
+    let bits = zero_width_bits(alphabet).expect("invalid zero-width alphabet");
+    let mask = (1u8 << bits) - 1;
+
+    let mut result = String::new();
+    for byte in data {
+        for shift in (0..8).step_by(bits as usize).rev() {
+            result.push(alphabet[((byte >> shift) & mask) as usize]);
+        }
+    }
+    result
+}
+
+fn decode_zero_width_data(symbols: &[u8], alphabet: &[char]) -> Result<String, String> {
+    let bits = zero_width_bits(alphabet)?;
+    let per_byte = (8 / bits) as usize;
+    if !symbols.len().is_multiple_of(per_byte) {
+        return Err(format!(
+            "{} zero-width symbols is not a whole number of bytes",
+            symbols.len()
+        ));
+    }
+
+    let decoded: Vec<u8> = symbols
+        .chunks(per_byte)
+        .map(|chunk| chunk.iter().fold(0u8, |byte, &s| (byte << bits) | s))
+        .collect();
+
+    String::from_utf8(decoded).map_err(|e| format!("UTF-8 decode error: {}", e))
+}
+
+/// What `strip_all_concealment` removed, by category
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct StripReport {
+    zero_width_chars: usize,
+    /// Lines whose trailing whitespace was dropped or normalized
+    trailing_whitespace_lines: usize,
+    polyglot_comments: usize,
+}
+
+/// Remove the channels `extract_all_concealment` reads
+///
+/// Zero-width characters are dropped except a ZWJ joining two emoji, which
+/// is part of the visible glyph. Trailing whitespace is removed, except that
+/// two or more spaces become exactly two so Markdown hard breaks survive.
+/// Works on `char`s and `&str` slices only, so multibyte text stays intact.
+fn strip_all_concealment(content: &str) -> (String, StripReport) {
+    let mut report = StripReport::default();
+
+    let chars: Vec<char> = content.chars().collect();
+    let mut visible = String::with_capacity(content.len());
+    for (i, &ch) in chars.iter().enumerate() {
+        let emoji_joiner = ch == '\u{200D}'
+            && i > 0
+            && is_emoji_component(chars[i - 1])
+            && chars
+                .get(i + 1)
+                .is_some_and(|&next| is_emoji_component(next));
+        if ZERO_WIDTH_CHARS.contains(&ch) && !emoji_joiner {
+            report.zero_width_chars += 1;
+        } else {
+            visible.push(ch);
+        }
+    }
+
+    report.polyglot_comments = POLYGLOT_COMMENT_REGEX.find_iter(&visible).count();
+    let visible = POLYGLOT_COMMENT_REGEX.replace_all(&visible, "");
+
+    let mut clean = String::with_capacity(visible.len());
+    for line in visible.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[body.len()..];
+        let text = body.trim_end();
+        let trailing = &body[text.len()..];
+
+        let kept = if !text.is_empty() && trailing.len() >= 2 && trailing.bytes().all(|b| b == b' ')
+        {
+            "  "
+        } else {
+            ""
+        };
+        if trailing != kept {
+            report.trailing_whitespace_lines += 1;
+        }
+
+        clean.push_str(text);
+        clean.push_str(kept);
+        clean.push_str(ending);
+    }
+
+    (clean, report)
+}
+
+/// Pictographs, modifiers and variation selectors that appear in emoji ZWJ
+/// sequences
+fn is_emoji_component(ch: char) -> bool {
+    matches!(ch as u32, 0x1F000..=0x1FAFF | 0x2300..=0x23FF | 0x2600..=0x27BF | 0xFE0F)
+}
+
+fn extract_content_addressed_links(content: &str) -> Vec<String> {
+    lazy_static! {
+        static ref SHA256_REGEX: Regex = Regex::new(r"\[([^\]]+)\]\(([a-f0-9]{64})\)").unwrap();
+    }
+
+    SHA256_REGEX
+        .captures_iter(content)
+        .filter_map(|cap| cap.get(2))
+        .map(|hash| hash.as_str().to_string())
+        .collect()
+}
+
+fn extract_whitespace_patterns(content: &str) -> Vec<u8> {
+    // Extract patterns from trailing whitespace
+    let mut patterns = Vec::new();
+
+    for line in content.lines() {
+        let trailing_spaces = line.len() - line.trim_end().len();
+        if trailing_spaces > 0 {
+            patterns.push(trailing_spaces as u8);
+        }
+    }
+
+    patterns
+}
+
+fn extract_html_comments(content: &str) -> Vec<String> {
+    POLYGLOT_COMMENT_REGEX
+        .captures_iter(content)
+        .filter_map(|cap| cap.get(2))
+        .map(|payload| payload.as_str().to_string())
+        .collect()
+}
+
+// RFC 8785 JSON Canonicalization Scheme
+fn json_canonicalize(json_str: &str) -> Result<String, String> {
+    // Parse JSON
+    let value: serde_json::Value =
+        serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
+
+    // Convert to canonical form (JCS)
+    canonicalize_json_value(&value)
+}
+
+fn canonicalize_json_value(value: &serde_json::Value) -> Result<String, String> {
+    match value {
+        serde_json::Value::Null => Ok("null".to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        serde_json::Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| format!("number {} has no double value", n))
+            .and_then(ecmascript_number),
+        serde_json::Value::String(s) => Ok(format!("\"{}\"", escape_json_string(s))),
+        serde_json::Value::Array(arr) => {
+            let items: Result<Vec<_>, _> = arr.iter().map(canonicalize_json_value).collect();
+            Ok(format!("[{}]", items?.join(",")))
+        }
+        serde_json::Value::Object(obj) => {
+            // RFC 8785 orders keys by UTF-16 code units, which differs from
+            // byte order once keys mix supplementary and U+E000..U+FFFF chars
+            let mut keys: Vec<_> = obj.keys().collect();
+            keys.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+
+            let items: Result<Vec<_>, String> = keys
+                .iter()
+                .map(|k| {
+                    let key = format!("\"{}\"", escape_json_string(k));
+                    let value = canonicalize_json_value(obj.get(*k).unwrap())?;
+                    Ok(format!("{}:{}", key, value))
+                })
+                .collect();
+            Ok(format!("{{{}}}", items?.join(",")))
+        }
+    }
+}
+
+/// A double as ECMAScript's `Number.prototype.toString` writes it, which
+/// RFC 8785 requires: the shortest digits that round-trip, plain notation
+/// for exponents -7 < e < 21 (`1`, `0.000001`, `1e+21`, `1e-7`), and
+/// integers past 2^53 rounded like any other double
+fn ecmascript_number(value: f64) -> Result<String, String> {
+    if !value.is_finite() {
+        return Err(format!("{} is not a valid JSON number", value));
+    }
+    if value == 0.0 {
+        // Negative zero too
+        return Ok("0".to_string());
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+
+    // `{:e}` gives the shortest round-trip digits as `d.ddde<exp>`. Of the
+    // candidates with that many digits ECMAScript wants the one closest to
+    // the value, the even one on a tie, which is what exact formatting to
+    // the same precision yields whenever it still round-trips.
+    let shortest = format!("{:e}", value.abs());
+    let precision = shortest
+        .split_once('e')
+        .map_or(0, |(m, _)| m.len().saturating_sub(2));
+    let nearest = format!("{:.*e}", precision, value.abs());
+    let scientific = if nearest.parse::<f64>() == Ok(value.abs()) {
+        nearest
+    } else {
+        shortest
+    };
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    // value = 0.digits × 10^n
+    let n = exponent.parse::<i32>().map_err(|e| e.to_string())? + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        let exponent = n - 1;
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        match digits.split_at(1) {
+            (first, "") => format!("{}e{}{}", first, exponent_sign, exponent.abs()),
+            (first, rest) => format!("{}.{}e{}{}", first, rest, exponent_sign, exponent.abs()),
+        }
+    };
+    Ok(format!("{}{}", sign, body))
+}
+
+/// Canonical JSON and the SHA-256 hex digest of exactly that string
+fn canonical_digest(value: &serde_json::Value) -> Result<(String, String), String> {
+    let canonical = canonicalize_json_value(value)?;
+    let digest = sha256_hash(&canonical);
+    Ok((canonical, digest))
+}
+
+/// Convert an Elixir term to JSON
+///
+/// Maps (binary or atom keys), lists, binaries, numbers and `true`/`false`/
+/// `nil` map directly; other atoms become strings. Tuples, pids and the like
+/// have no JSON form and are rejected.
+fn term_to_json(term: Term) -> Result<serde_json::Value, String> {
+    if term.is_map() {
+        let mut obj = serde_json::Map::new();
+        for (key, value) in MapIterator::new(term).ok_or("invalid map")? {
+            let key = if key.is_atom() {
+                key.atom_to_string().map_err(|_| "invalid atom key")?
+            } else {
+                key.decode::<String>()
+                    .map_err(|_| "map keys must be strings or atoms")?
+            };
+            obj.insert(key, term_to_json(value)?);
+        }
+        Ok(serde_json::Value::Object(obj))
+    } else if term.is_list() {
+        let items: Vec<Term> = term.decode().map_err(|_| "improper list")?;
+        items
+            .into_iter()
+            .map(term_to_json)
+            .collect::<Result<_, _>>()
+            .map(serde_json::Value::Array)
+    } else if term.is_binary() {
+        term.decode::<String>()
+            .map(serde_json::Value::String)
+            .map_err(|_| "binaries must be valid UTF-8".to_string())
+    } else if term.is_float() {
+        let f: f64 = term.decode().map_err(|_| "invalid float")?;
+        serde_json::Number::from_f64(f)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| "non-finite float".to_string())
+    } else if term.is_number() {
+        if let Ok(i) = term.decode::<i64>() {
+            Ok(i.into())
+        } else {
+            term.decode::<u64>()
+                .map(Into::into)
+                .map_err(|_| "integer out of range".to_string())
+        }
+    } else if term.is_atom() {
+        match term.atom_to_string().map_err(|_| "invalid atom")?.as_str() {
+            "true" => Ok(serde_json::Value::Bool(true)),
+            "false" => Ok(serde_json::Value::Bool(false)),
+            "nil" => Ok(serde_json::Value::Null),
+            other => Ok(serde_json::Value::String(other.to_string())),
+        }
+    } else {
+        Err("term has no JSON representation".to_string())
+    }
+}
+
+/// Escape a string body as RFC 8785 does: `\"` and `\\`, the short forms
+/// `\b \f \n \r \t`, `\u00xx` for the other characters below U+0020, and
+/// everything else (U+007F..U+009F included) as is
+fn escape_json_string(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '"' => "\\\"".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\u{8}' => "\\b".to_string(),
+            '\u{c}' => "\\f".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Stable ID generation
+fn generate_stable_chunk_id(heading_path: &[String], block_index: u32, text: &str) -> String {
+    let normalized_text = normalize_text_for_hash(text);
+    let text_hash = sha256_hash(&normalized_text);
+
+    let payload = serde_json::json!({
+        "heading_path": heading_path,
+        "block_index": block_index,
+        "text_hash": text_hash
+    });
+
+    let canonical = json_canonicalize(&payload.to_string()).unwrap_or_default();
+    let chunk_hash = sha256_hash(&canonical);
+    chunk_hash[..12].to_string()
+}
+
+/// The anchor GitHub gives a heading (github-slugger rules)
+///
+/// Lowercases, drops punctuation, symbols and emoji while keeping letters,
+/// digits, combining marks, `-` and `_` in any script, then turns each
+/// space into `-` without collapsing runs, so `C++ & Rust` is `c--rust`.
+/// Repeats are handled by `GithubSlugger`.
+fn github_slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() || is_combining_mark(c) => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The main combining diacritic blocks, which GitHub keeps in slugs
+fn is_combining_mark(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// Document-scoped `github_slug`: a repeated slug gets `-1`, `-2`, ...,
+/// skipping suffixes that an earlier heading already produced
+#[derive(Debug, Default)]
+struct GithubSlugger {
+    occurrences: HashMap<String, usize>,
+}
+
+impl GithubSlugger {
+    fn slug(&mut self, text: &str) -> String {
+        let base = github_slug(text);
+        let mut slug = base.clone();
+        while self.occurrences.contains_key(&slug) {
+            let count = self
+                .occurrences
+                .get_mut(&base)
+                .expect("base slug seen first");
+            *count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.occurrences.insert(slug.clone(), 0);
+        slug
+    }
+}
+
+/// Markdown table of contents: one `- [Heading](#anchor)` item per heading
+/// with a level in `min_level..=max_level`, nested two spaces per level
+///
+/// Nesting follows the same rules as `section_numbers`: a skipped level
+/// nests one step, and the shallowest included heading sits at the left
+/// margin. Anchors are the document-scoped `HeadingOut::anchor`, so
+/// repeated headings link to the right section even when some of the
+/// repeats are outside the depth range.
+fn render_toc(headings: &[HeadingOut], min_level: u32, max_level: u32) -> String {
+    let mut toc = String::new();
+    // Levels of the open items, outermost first
+    let mut open: Vec<u32> = Vec::new();
+    for heading in headings {
+        if heading.level < min_level || heading.level > max_level {
+            continue;
+        }
+        while open.last().is_some_and(|&level| level >= heading.level) {
+            open.pop();
+        }
+        let text: String = heading
+            .text
+            .chars()
+            .flat_map(|c| {
+                let escape = matches!(c, '\\' | '[' | ']').then_some('\\');
+                escape.into_iter().chain(std::iter::once(c))
+            })
+            .collect();
+        toc.push_str(&"  ".repeat(open.len()));
+        toc.push_str(&format!("- [{}](#{})\n", text, heading.anchor));
+        open.push(heading.level);
+    }
+    toc
+}
+
+fn normalize_text_for_hash(text: &str) -> String {
+    text.lines()
+        .map(|line| line.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+/// Hash of a document's normalized block stream
+///
+/// Frontmatter contributes its canonical JSON, so key order does not
+/// matter. Each block then contributes one `kind:text` line: whitespace
+/// runs collapse to one space, heading text is lowercased, code loses all
+/// whitespace, and markup (ATX vs setext, `*` vs `_`, bullets) is gone
+/// because only parser events are read.
+fn fingerprint_document(content: &str) -> String {
+    let mut stream = Vec::new();
+    let frontmatter = detect_frontmatter(content);
+    if let Some(canonical) = frontmatter
+        .as_ref()
+        .and_then(|frontmatter| canonicalize_json_value(&frontmatter.data).ok())
+    {
+        stream.push(format!("frontmatter:{}", canonical));
+    }
+
+    let body = &content[frontmatter.map_or(0, |frontmatter| frontmatter.len)..];
+    let mut kind = String::new();
+    let mut text = String::new();
+    let mut item_depth = 0usize;
+    for event in Parser::new_ext(body, markdown_parser_options()) {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                kind = format!("h{}", heading_level_number(level));
+            }
+            Event::Start(Tag::CodeBlock(code_kind)) => {
+                kind = match code_kind {
+                    CodeBlockKind::Fenced(lang) => format!("code:{}", lang.trim()),
+                    CodeBlockKind::Indented => "code:".to_string(),
+                };
+            }
+            Event::Start(Tag::Item) => item_depth += 1,
+            // A tight item's own text ends where its nested list starts
+            Event::Start(Tag::List(_)) if !text.trim().is_empty() => {
+                stream.push(format!("li:{}", normalize_block_text(&text)));
+                text.clear();
+            }
+            Event::Text(t) | Event::Code(t) | Event::Html(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(
+                tag @ (Tag::Paragraph
+                | Tag::Heading(..)
+                | Tag::CodeBlock(_)
+                | Tag::Item
+                | Tag::TableCell),
+            ) => {
+                let normalized = match &tag {
+                    Tag::CodeBlock(_) => text.split_whitespace().collect(),
+                    Tag::Heading(..) => normalize_block_text(&text).to_lowercase(),
+                    _ => normalize_block_text(&text),
+                };
+                if !normalized.is_empty() {
+                    if kind.is_empty() {
+                        kind = if item_depth > 0 { "li" } else { "p" }.to_string();
+                    }
+                    stream.push(format!("{}:{}", kind, normalized));
+                }
+                if matches!(tag, Tag::Item) {
+                    item_depth -= 1;
+                }
+                kind.clear();
+                text.clear();
+            }
+            _ => {}
+        }
+    }
+
+    sha256_hash(&stream.join("\n"))
+}
+
+/// `normalize_text_for_hash` with inner whitespace runs collapsed too
+fn normalize_block_text(text: &str) -> String {
+    normalize_text_for_hash(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn sha256_hash(data: &str) -> String {
+    sha256_hex(data.as_bytes())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Check a content-addressed link target against the bytes it should name;
+/// malformed hashes never match
+fn verify_content_hash(hash_hex: &str, data: &[u8]) -> bool {
+    is_sha256_hash(hash_hex) && hash_hex.eq_ignore_ascii_case(&sha256_hex(data))
+}
+
+/// SHA-256 digest bytes of a 64-digit hex string, either case
+fn decode_sha256_hex(hash_hex: &str) -> Option<[u8; 32]> {
+    if !is_sha256_hash(hash_hex) {
+        return None;
+    }
+    let mut digest = [0u8; 32];
+    for (byte, pair) in digest.iter_mut().zip(hash_hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+fn known_hashes_from_hex(hashes: &[String]) -> Result<HashSet<[u8; 32]>, String> {
+    hashes
+        .iter()
+        .map(|hash| {
+            decode_sha256_hex(hash)
+                .ok_or_else(|| format!("known_hashes: {:?} is not a SHA-256 hex digest", hash))
+        })
+        .collect()
+}
+
+fn known_hashes_from_packed(packed: &[u8]) -> Result<HashSet<[u8; 32]>, String> {
+    let digests = packed.chunks_exact(32);
+    if !digests.remainder().is_empty() {
+        return Err(format!(
+            "known_hashes: packed binary of {} bytes is not a multiple of 32",
+            packed.len()
+        ));
+    }
+    Ok(digests
+        .map(|digest| digest.try_into().expect("chunks are 32 bytes"))
+        .collect())
+}
+
+/// Content-addressed links, split by membership in a set of known digests
+#[derive(Debug, Clone, Default)]
+struct ContentLinkCheck {
+    resolved: Vec<LinkOut>,
+    missing: Vec<LinkOut>,
+}
+
+fn check_content_links_against(content: &str, known: &HashSet<[u8; 32]>) -> ContentLinkCheck {
+    let mut check = ContentLinkCheck::default();
+    for link in collect_links(parse_structure(content).links) {
+        let Some(digest) = link
+            .content_addressed
+            .then(|| decode_sha256_hex(&link.url))
+            .flatten()
+        else {
+            continue;
+        };
+        if known.contains(&digest) {
+            check.resolved.push(link);
+        } else {
+            check.missing.push(link);
+        }
+    }
+    check
+}
+
+// Attribute object mini-grammar parser
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+enum AttributeValue {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    List(Vec<AttributeValue>),
+    Object(BTreeMap<String, AttributeValue>),
+}
+
+impl From<serde_json::Value> for AttributeValue {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(s) => AttributeValue::String(s),
+            serde_json::Value::Number(n) => AttributeValue::Number(n.as_f64().unwrap_or_default()),
+            serde_json::Value::Bool(b) => AttributeValue::Boolean(b),
+            serde_json::Value::Array(list) => {
+                AttributeValue::List(list.into_iter().map(AttributeValue::from).collect())
+            }
+            serde_json::Value::Object(map) => AttributeValue::Object(attributes_from_json(map)),
+            // The core attribute grammar has no null
+            serde_json::Value::Null => AttributeValue::String(String::new()),
+        }
+    }
+}
+
+/// Attributes read by `markdown_ld_core`, as the NIF's attribute map
+fn attributes_from_json(
+    map: serde_json::Map<String, serde_json::Value>,
+) -> BTreeMap<String, AttributeValue> {
+    map.into_iter()
+        .map(|(key, value)| (key, value.into()))
+        .collect()
+}
+
+/// Attributes in the `markdown_ld_core::attr_object` grammar, the one
+/// heading and link attribute blocks use; lenient mode skips words that
+/// cannot start a pair instead of failing on them
+fn parse_attribute_map(
+    input: &str,
+    strict: bool,
+) -> Result<BTreeMap<String, AttributeValue>, attr_object::Error> {
+    let parsed = if strict {
+        attr_object::parse_attr_object(input, None)
+    } else {
+        attr_object::parse_attr_object_lenient(input, None)
+    };
+    parsed.map(attributes_from_json)
+}
+
+fn encode_attribute_value<'a>(env: Env<'a>, value: &AttributeValue) -> Term<'a> {
+    match value {
+        AttributeValue::String(s) => s.encode(env),
+        AttributeValue::Number(n) => n.encode(env),
+        AttributeValue::Boolean(b) => b.encode(env),
+        AttributeValue::List(list) => {
+            let encoded_list: Vec<Term> = list
+                .iter()
+                .map(|v| encode_attribute_value(env, v))
+                .collect();
+            encoded_list.encode(env)
+        }
+        AttributeValue::Object(obj) => encode_attributes(env, obj),
+    }
+}
+
+/// Attribute maps are `BTreeMap`s so their serde output is key-ordered;
+/// BEAM maps keep no insertion order, so the encoded term needs no sorting
+fn encode_attributes<'a>(env: Env<'a>, attributes: &BTreeMap<String, AttributeValue>) -> Term<'a> {
+    let encoded: HashMap<&String, Term> = attributes
+        .iter()
+        .map(|(k, v)| (k, encode_attribute_value(env, v)))
+        .collect();
+    encoded.encode(env)
+}
+
+// Helper functions
+fn is_sha256_hash(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn detect_code_block_artifact(content: &str) -> Option<String> {
+    if content.contains("FROM ") && content.contains("RUN ") {
+        Some("dockerfile".to_string())
+    } else if content.contains("apiVersion:") && content.contains("kind:") {
+        Some("kubernetes".to_string())
+    } else if content.contains("resource ") && content.contains("provider ") {
+        Some("terraform".to_string())
+    } else {
+        None
+    }
+}
 
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
+#[derive(Debug, Clone, Serialize)]
+struct TaskOut {
+    completed: bool,
+    text: String,
+    line: usize,
+    /// Nesting level from indentation, 0 for top-level items
+    depth: usize,
+    /// Texts of the enclosing headings, outermost first
+    heading_path: Vec<String>,
+    stable_id: String,
+    /// Byte offset of the list marker
+    #[serde(skip)]
+    offset: usize,
+    /// Byte offset just past the task text
+    #[serde(skip)]
+    end: usize,
+}
 
-    let parser = Parser::new_ext(content, options);
+fn encode_task_item<'a>(env: Env<'a>, task: &TaskOut) -> Term<'a> {
+    let mut task_map = HashMap::new();
+    task_map.insert("completed".to_string(), task.completed.encode(env));
+    task_map.insert("text".to_string(), task.text.encode(env));
+    task_map.insert("line".to_string(), task.line.encode(env));
+    task_map.insert("depth".to_string(), task.depth.encode(env));
+    task_map.insert("heading_path".to_string(), task.heading_path.encode(env));
+    task_map.insert("stable_id".to_string(), task.stable_id.encode(env));
+    task_map.insert("byte_start".to_string(), task.offset.encode(env));
+    task_map.insert("byte_end".to_string(), task.end.encode(env));
+    task_map.encode(env)
+}
 
-    for event in parser {
-        match event {
-            Event::Start(Tag::CodeBlock(kind)) => {
-                in_code_block = true;
-                current_language = match kind {
-                    CodeBlockKind::Fenced(lang) => {
-                        if lang.is_empty() {
-                            None
-                        } else {
-                            Some(lang.to_string())
-                        }
-                    }
-                    CodeBlockKind::Indented => None,
-                };
-                code_start_line = current_line;
-                current_code.clear();
-            }
-            Event::End(Tag::CodeBlock(_)) => {
-                if in_code_block {
-                    let mut code_map = HashMap::new();
-
-                    let language = current_language
-                        .clone()
-                        .unwrap_or_else(|| "unknown".to_string());
-                    code_map.insert("language".to_string(), language.encode(env));
-                    code_map.insert("content".to_string(), current_code.clone().encode(env));
-                    code_map.insert("line".to_string(), code_start_line.encode(env));
-
-                    // Detect special markdown-ld languages
-                    let is_jsonld = matches!(
-                        language.as_str(),
-                        "json-ld" | "jsonld" | "application/ld+json"
-                    );
-                    let is_mem8 = language == "mem8";
-                    let is_mq2 = language == "mq2";
+/// Columns are 0-based byte columns; `col_end` is just past the closing
+/// delimiter on the span's last line
+#[derive(Debug, Clone, Serialize)]
+struct EmphasisOut {
+    kind: &'static str,
+    text: String,
+    line: usize,
+    col_start: usize,
+    col_end: usize,
+}
 
-                    code_map.insert("is_jsonld".to_string(), is_jsonld.encode(env));
-                    code_map.insert("is_mem8".to_string(), is_mem8.encode(env));
-                    code_map.insert("is_mq2".to_string(), is_mq2.encode(env));
+fn encode_emphasis<'a>(env: Env<'a>, span: &EmphasisOut) -> Term<'a> {
+    let mut span_map = HashMap::new();
+    span_map.insert("kind".to_string(), span.kind.encode(env));
+    span_map.insert("text".to_string(), span.text.encode(env));
+    span_map.insert("line".to_string(), span.line.encode(env));
+    span_map.insert("col_start".to_string(), span.col_start.encode(env));
+    span_map.insert("col_end".to_string(), span.col_end.encode(env));
+    span_map.encode(env)
+}
 
-                    // Extract polyglot artifacts from code blocks
-                    if let Some(artifact_type) = detect_code_block_artifact(&current_code) {
-                        code_map.insert("artifact_type".to_string(), artifact_type.encode(env));
-                    }
+#[derive(Debug, Clone, Serialize)]
+struct BlockquoteOut {
+    text: String,
+    line_start: usize,
+    line_end: usize,
+    depth: usize,
+    callout_type: Option<String>,
+    callout_title: Option<String>,
+}
 
-                    code_blocks.push(code_map.encode(env));
-                    in_code_block = false;
-                }
+fn encode_blockquote<'a>(env: Env<'a>, quote: &BlockquoteOut) -> Term<'a> {
+    let mut quote_map = HashMap::new();
+    quote_map.insert("text".to_string(), quote.text.encode(env));
+    quote_map.insert("line_start".to_string(), quote.line_start.encode(env));
+    quote_map.insert("line_end".to_string(), quote.line_end.encode(env));
+    quote_map.insert("depth".to_string(), quote.depth.encode(env));
+    if let Some(callout_type) = &quote.callout_type {
+        quote_map.insert("callout_type".to_string(), callout_type.encode(env));
+    }
+    if let Some(title) = &quote.callout_title {
+        quote_map.insert("title".to_string(), title.encode(env));
+    }
+    quote_map.encode(env)
+}
+
+/// One term of a definition list and its definitions, in source order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct DefinitionOut {
+    term: String,
+    definitions: Vec<String>,
+    /// Line of the term
+    line: usize,
+}
+
+fn encode_definition<'a>(env: Env<'a>, entry: &DefinitionOut) -> Term<'a> {
+    let mut entry_map = HashMap::new();
+    entry_map.insert("term".to_string(), entry.term.encode(env));
+    entry_map.insert("definitions".to_string(), entry.definitions.encode(env));
+    entry_map.insert("line".to_string(), entry.line.encode(env));
+    entry_map.encode(env)
+}
+
+/// Raw HTML found in the document
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct HtmlOut {
+    /// `"block"` for an HTML block, `"inline"` for a tag inside text
+    kind: &'static str,
+    /// Source text; a block keeps its trailing newline
+    content: String,
+    line: usize,
+    byte_start: usize,
+    byte_end: usize,
+}
+
+/// Raw HTML blocks and inline tags in document order
+///
+/// pulldown-cmark reports both as `Event::Html`, one event per line for
+/// blocks, so contiguous block lines are merged back into one entry and
+/// the kind comes from context: HTML inside a paragraph, heading or table
+/// cell, or after text in a tight list item, is inline.
+fn collect_html(content: &str) -> Vec<HtmlOut> {
+    let line_index = LineIndex::new(content);
+    let mut html: Vec<HtmlOut> = Vec::new();
+    // Open paragraphs, headings and table cells
+    let mut inline_containers = 0usize;
+
+    for (event, range) in Parser::new_ext(content, markdown_parser_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Paragraph | Tag::Heading(..) | Tag::TableCell) => {
+                inline_containers += 1;
             }
-            Event::Text(text) => {
-                if in_code_block {
-                    current_code.push_str(&text);
-                }
-                current_line += text.chars().filter(|&c| c == '\n').count();
+            Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::TableCell) => {
+                inline_containers -= 1;
             }
-            Event::SoftBreak | Event::HardBreak => {
-                if in_code_block {
-                    current_code.push('\n');
-                }
-                current_line += 1;
+            // Tight list items carry no paragraph, so outside one an HTML
+            // block is told apart by owning its lines
+            Event::Html(_)
+                if inline_containers > 0 || !spans_whole_lines(content, range.clone()) =>
+            {
+                html.push(HtmlOut {
+                    kind: "inline",
+                    content: content[range.clone()].to_string(),
+                    line: line_index.line(range.start),
+                    byte_start: range.start,
+                    byte_end: range.end,
+                });
             }
+            Event::Html(_) => match html.last_mut() {
+                Some(last) if last.kind == "block" && last.byte_end == range.start => {
+                    last.content.push_str(&content[range.clone()]);
+                    last.byte_end = range.end;
+                }
+                _ => html.push(HtmlOut {
+                    kind: "block",
+                    content: content[range.clone()].to_string(),
+                    line: line_index.line(range.start),
+                    byte_start: range.start,
+                    byte_end: range.end,
+                }),
+            },
             _ => {}
         }
     }
+    html
+}
 
-    Ok(code_blocks.encode(env))
+/// Whether `range` starts after nothing but indentation and container
+/// markers (`>`, bullets, ordinals) and runs to the end of its line
+fn spans_whole_lines(content: &str, range: std::ops::Range<usize>) -> bool {
+    let line_start = content[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let only_markers = content[line_start..range.start]
+        .replace('>', " ")
+        .split_whitespace()
+        .all(|token| {
+            matches!(token, "-" | "*" | "+")
+                || token.strip_suffix(['.', ')']).is_some_and(|digits| {
+                    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+                })
+        });
+    let rest = &content[range.end..];
+    only_markers
+        && (content[range].ends_with('\n')
+            || rest.is_empty()
+            || rest.starts_with('\n')
+            || rest.starts_with("\r\n"))
 }
 
-fn extract_tasks_enhanced<'a>(env: Env<'a>, content: &str) -> Result<Term<'a>, String> {
-    let mut tasks = Vec::new();
-    let mut line_num = 1usize;
+fn encode_html<'a>(env: Env<'a>, html: &HtmlOut) -> Term<'a> {
+    let mut html_map = HashMap::new();
+    html_map.insert("kind".to_string(), html.kind.encode(env));
+    html_map.insert("content".to_string(), html.content.encode(env));
+    html_map.insert("line".to_string(), html.line.encode(env));
+    html_map.insert("byte_start".to_string(), html.byte_start.encode(env));
+    html_map.insert("byte_end".to_string(), html.byte_end.encode(env));
+    html_map.encode(env)
+}
 
-    for line in content.lines() {
-        let trimmed = line.trim();
-        if let Some(task) = parse_task_line(trimmed, line_num) {
-            tasks.push(encode_task_item(env, task));
+/// A `$...$` or `$$...$$` math expression
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct MathOut {
+    /// `"inline"` for `$...$`, `"display"` for `$$...$$`
+    kind: &'static str,
+    /// Text between the delimiters; trimmed for display math
+    content: String,
+    line: usize,
+    /// Byte range including the delimiters
+    byte_start: usize,
+    byte_end: usize,
+}
+
+/// Math expressions in document order
+///
+/// pulldown-cmark 0.9 has no math extension, so this scans the source
+/// around code spans, code blocks and raw HTML, which never hold math.
+/// Following Pandoc, inline math opens on a `$` followed by a non-space and
+/// closes on a `$` after a non-space that no digit follows, so `$5 and $10`
+/// is prose; `\$` is a literal dollar. Neither kind spans a blank line.
+fn collect_math(content: &str) -> Vec<MathOut> {
+    let line_index = LineIndex::new(content);
+    let mut skipped: Vec<std::ops::Range<usize>> = Vec::new();
+    for (event, range) in Parser::new_ext(content, markdown_parser_options()).into_offset_iter() {
+        if matches!(
+            event,
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) | Event::Html(_)
+        ) {
+            skipped.push(range);
         }
-        line_num += 1;
     }
+    // These ranges never overlap, so sorted by start they are also sorted by end.
+    skipped.sort_by_key(|range| range.start);
 
-    Ok(tasks.encode(env))
-}
-
-fn extract_jsonld_islands<'a>(env: Env<'a>, content: &str) -> Result<Term<'a>, String> {
-    let mut islands = Vec::new();
+    let bytes = content.as_bytes();
+    let mut skipped_start = vec![false; bytes.len() + 2];
+    for range in &skipped {
+        skipped_start[range.start] = true;
+    }
+    // `inline_close[i]` / `display_close[i]`: the closing delimiter a scan
+    // starting at `i` finds, or `None` if it first reaches a blank line, a
+    // skipped range or the end. Filled in one backward pass, so every
+    // opener resolves in O(1) however many openers never close.
+    let mut inline_close: Vec<Option<usize>> = vec![None; bytes.len() + 2];
+    let mut display_close: Vec<Option<usize>> = vec![None; bytes.len() + 2];
+    for i in (0..bytes.len()).rev() {
+        (inline_close[i], display_close[i]) = match bytes[i] {
+            _ if skipped_start[i] => (None, None),
+            b'\\' if skipped_start[i + 1] => (None, None),
+            b'\\' => (inline_close[i + 2], display_close[i + 2]),
+            b'\n'
+                if content[i + 1..]
+                    .trim_start_matches([' ', '\t', '\r'])
+                    .starts_with('\n') =>
+            {
+                (None, None)
+            }
+            b'$' => {
+                let closes_inline = i > 0
+                    && !bytes[i - 1].is_ascii_whitespace()
+                    && !bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                let closes_display = bytes.get(i + 1) == Some(&b'$');
+                (
+                    if closes_inline {
+                        Some(i)
+                    } else {
+                        inline_close[i + 1]
+                    },
+                    if closes_display {
+                        Some(i)
+                    } else {
+                        display_close[i + 1]
+                    },
+                )
+            }
+            _ => (inline_close[i + 1], display_close[i + 1]),
+        };
+    }
 
-    // Extract JSON-LD from frontmatter
-    if let Some(frontmatter) = extract_frontmatter(content) {
-        if let Some(jsonld) = extract_jsonld_from_frontmatter(&frontmatter) {
-            islands.push(create_jsonld_island(env, jsonld, 1, "frontmatter"));
+    let mut math = Vec::new();
+    let mut next_skipped = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        while skipped
+            .get(next_skipped)
+            .is_some_and(|range| range.end <= i)
+        {
+            next_skipped += 1;
+        }
+        if let Some(range) = skipped.get(next_skipped).filter(|range| range.start <= i) {
+            i = range.end;
+            continue;
+        }
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'$' if bytes.get(i + 1) == Some(&b'$') => match display_close[i + 2] {
+                Some(close) => {
+                    math.push(MathOut {
+                        kind: "display",
+                        content: content[i + 2..close].trim().to_string(),
+                        line: line_index.line(i),
+                        byte_start: i,
+                        byte_end: close + 2,
+                    });
+                    i = close + 2;
+                }
+                None => i += 2,
+            },
+            b'$' if bytes.get(i + 1).is_some_and(|b| !b.is_ascii_whitespace()) => {
+                match inline_close[i + 1] {
+                    Some(close) => {
+                        math.push(MathOut {
+                            kind: "inline",
+                            content: content[i + 1..close].to_string(),
+                            line: line_index.line(i),
+                            byte_start: i,
+                            byte_end: close + 1,
+                        });
+                        i = close + 1;
+                    }
+                    None => i += 1,
+                }
+            }
+            _ => i += 1,
         }
     }
+    math
+}
 
-    // Extract JSON-LD from code fences
-    let _code_blocks = extract_code_blocks_enhanced(env, content)?;
-    // This would iterate through code blocks and find JSON-LD ones
-
-    Ok(islands.encode(env))
+fn encode_math<'a>(env: Env<'a>, math: &MathOut) -> Term<'a> {
+    let mut math_map = HashMap::new();
+    math_map.insert("kind".to_string(), math.kind.encode(env));
+    math_map.insert("content".to_string(), math.content.encode(env));
+    math_map.insert("line".to_string(), math.line.encode(env));
+    math_map.insert("byte_start".to_string(), math.byte_start.encode(env));
+    math_map.insert("byte_end".to_string(), math.byte_end.encode(env));
+    math_map.encode(env)
 }
 
-// Polyglot detection
-fn detect_polyglot_document(content: &str) -> Option<PolyglotDocument> {
-    let mut max_score = 0.0f32;
-    let mut best_match: Option<String> = None;
-    let mut artifacts = Vec::new();
+fn extract_frontmatter(content: &str) -> Option<String> {
+    if content.starts_with("---\n") {
+        if let Some(end) = content[4..].find("\n---\n") {
+            return Some(content[4..end + 4].to_string());
+        }
+    }
+    None
+}
 
-    // Check each detector
-    for detector in POLYGLOT_DETECTORS.iter() {
-        let primary_matches = detector.primary_pattern.find_iter(content).count();
-        let secondary_matches = detector.secondary_pattern.find_iter(content).count();
+/// Frontmatter of any supported format, parsed to JSON
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Frontmatter {
+    /// `"yaml"`, `"toml"` or `"json"`
+    format: &'static str,
+    /// Always an object
+    data: serde_json::Value,
+    /// Bytes from the start of the document through the closing delimiter
+    /// line
+    #[serde(skip)]
+    len: usize,
+}
 
-        let score = (primary_matches as f32 * 2.0) + (secondary_matches as f32 * 0.5);
+/// Frontmatter at the very start of a document
+///
+/// Recognizes `---` YAML, `+++` TOML (Hugo), `;;;`-fenced JSON and a bare
+/// leading JSON object. The block must parse to a mapping: a `---` pair
+/// around prose (a thematic break and a setext heading) or around nothing
+/// is ordinary markdown, not frontmatter.
+fn detect_frontmatter(content: &str) -> Option<Frontmatter> {
+    let (format, data, len) = if let Some((body, len)) = fenced_frontmatter(content, "---") {
+        let doc = yaml_rust::YamlLoader::load_from_str(body)
+            .ok()?
+            .into_iter()
+            .next()?;
+        ("yaml", yaml_to_json(&doc), len)
+    } else if let Some((body, len)) = fenced_frontmatter(content, "+++") {
+        let table = body.parse::<toml::Table>().ok()?;
+        ("toml", toml_to_json(&toml::Value::Table(table)), len)
+    } else if let Some((body, len)) = fenced_frontmatter(content, ";;;") {
+        ("json", serde_json::from_str(body).ok()?, len)
+    } else if content.starts_with('{') {
+        let mut values = serde_json::Deserializer::from_str(content).into_iter();
+        let data: serde_json::Value = values.next()?.ok()?;
+        // The object must end its line
+        let end = values.byte_offset();
+        let line_end = content[end..]
+            .find('\n')
+            .map_or(content.len(), |i| end + i + 1);
+        if !content[end..line_end].trim().is_empty() {
+            return None;
+        }
+        ("json", data, line_end)
+    } else {
+        return None;
+    };
+    data.is_object()
+        .then_some(Frontmatter { format, data, len })
+}
 
-        if score > max_score && score > 2.0 {
-            max_score = score;
-            best_match = Some(detector.language.clone());
+/// Text between a leading `fence` line and the next `fence` line, and the
+/// length through the closing line
+fn fenced_frontmatter<'c>(content: &'c str, fence: &str) -> Option<(&'c str, usize)> {
+    let first_end = content.find('\n')?;
+    if content[..first_end].trim_end() != fence {
+        return None;
+    }
+    let body_start = first_end + 1;
+    let mut line_start = body_start;
+    loop {
+        let line_end = content[line_start..]
+            .find('\n')
+            .map_or(content.len(), |i| line_start + i);
+        if content[line_start..line_end].trim_end() == fence {
+            let len = (line_end + 1).min(content.len());
+            return Some((&content[body_start..line_start], len));
+        }
+        if line_end == content.len() {
+            return None;
+        }
+        line_start = line_end + 1;
+    }
+}
 
-            // Extract artifacts for this language
-            artifacts = extract_artifacts_for_language(content, &detector.language);
+fn toml_to_json(value: &toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::String(s.clone()),
+        toml::Value::Integer(i) => serde_json::Value::from(*i),
+        toml::Value::Float(f) => serde_json::Number::from_f64(*f)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number),
+        toml::Value::Boolean(b) => serde_json::Value::Bool(*b),
+        toml::Value::Datetime(datetime) => serde_json::Value::String(datetime.to_string()),
+        toml::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(toml_to_json).collect())
         }
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), toml_to_json(value)))
+                .collect(),
+        ),
     }
+}
 
-    if let Some(language) = best_match {
-        Some(PolyglotDocument {
-            language,
-            artifacts,
-            metadata: HashMap::new(),
-            concealment: extract_all_concealment(content),
-        })
+/// A JSON value as the equivalent Elixir term, with `null` as `nil`
+fn encode_json_value<'a>(env: Env<'a>, value: &serde_json::Value) -> Term<'a> {
+    match value {
+        serde_json::Value::Null => atoms::nil().encode(env),
+        serde_json::Value::Bool(b) => b.encode(env),
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.encode(env),
+            (None, Some(u)) => u.encode(env),
+            _ => n.as_f64().unwrap_or(f64::NAN).encode(env),
+        },
+        serde_json::Value::String(s) => s.encode(env),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| encode_json_value(env, item))
+            .collect::<Vec<Term>>()
+            .encode(env),
+        serde_json::Value::Object(obj) => obj
+            .iter()
+            .map(|(key, value)| (key, encode_json_value(env, value)))
+            .collect::<HashMap<&String, Term>>()
+            .encode(env),
+    }
+}
+
+fn extract_jsonld_from_frontmatter(frontmatter: &str) -> Option<String> {
+    // Parse YAML and extract JSON-LD context
+    // Simplified for now
+    if frontmatter.contains("@context") {
+        Some(frontmatter.to_string())
     } else {
         None
     }
 }
 
-fn extract_artifacts_for_language(content: &str, language: &str) -> Vec<Artifact> {
-    let mut artifacts = Vec::new();
-    let mut line_num = 1usize;
+// Mem8 integration
+fn create_default_mem8_context() -> Mem8Context {
+    Mem8Context {
+        wave_grid: Arc::new(RwLock::new(mem8::wave::WaveGrid::new())),
+        consciousness: ConsciousnessState {
+            attention_weights: HashMap::new(),
+            active_memories: Vec::new(),
+            awareness_level: 0.5,
+        },
+        memory_index: MemIndex {
+            version: "1.0.0".to_string(),
+            blocks: HashMap::new(),
+            projects: HashMap::new(),
+        },
+    }
+}
 
-    match language {
-        "dockerfile" => {
-            for line in content.lines() {
-                if line.trim_start().to_uppercase().starts_with("FROM") {
-                    artifacts.push(Artifact {
-                        artifact_type: "dockerfile".to_string(),
-                        content: line.to_string(),
-                        language: Some("dockerfile".to_string()),
-                        line_start: line_num,
-                        line_end: line_num,
-                    });
-                }
-                line_num += 1;
-            }
-        }
-        "kubernetes" => {
-            // Extract YAML blocks that look like k8s
-            if content.contains("apiVersion:") && content.contains("kind:") {
-                artifacts.push(Artifact {
-                    artifact_type: "kubernetes".to_string(),
-                    content: content.to_string(),
-                    language: Some("yaml".to_string()),
-                    line_start: 1,
-                    line_end: content.lines().count(),
-                });
-            }
+fn calculate_wave_context_influence(
+    _wave_grid: &Arc<RwLock<mem8::wave::WaveGrid>>,
+    content: &str,
+) -> f32 {
+    // Simplified wave influence calculation
+    let word_count = content.split_whitespace().count() as f32;
+    let influence = (word_count / 1000.0).min(1.0) * 0.5;
+    influence
+}
+
+impl MemoryWave {
+    /// The `mem8` wave, created now; `decay_tau` must already be valid
+    fn to_mem8(&self) -> mem8::wave::MemoryWave {
+        mem8::wave::MemoryWave {
+            amplitude: self.amplitude,
+            frequency: self.frequency,
+            phase: self.phase,
+            valence: self.valence,
+            arousal: self.arousal,
+            created_at: std::time::Instant::now(),
+            decay_tau: self
+                .decay_tau
+                .and_then(|tau| std::time::Duration::try_from_secs_f32(tau).ok()),
         }
-        "bash" => {
-            for line in content.lines() {
-                if line.trim_start().starts_with("#!/bin/bash")
-                    || line.trim_start().starts_with("#!/bin/sh")
-                {
-                    artifacts.push(Artifact {
-                        artifact_type: "executable".to_string(),
-                        content: content.to_string(),
-                        language: Some("bash".to_string()),
-                        line_start: 1,
-                        line_end: content.lines().count(),
-                    });
-                    break;
-                }
-                line_num += 1;
-            }
+    }
+}
+
+/// Grid coordinates of a cell, or an error naming it when outside `grid`
+fn mem8_cell(
+    grid: &mem8::wave::WaveGrid,
+    x: usize,
+    y: usize,
+    z: usize,
+) -> Result<(u8, u8, u16), String> {
+    match (u8::try_from(x), u8::try_from(y), u16::try_from(z)) {
+        (Ok(cx), Ok(cy), Ok(cz)) if x < grid.width && y < grid.height && z < grid.depth => {
+            Ok((cx, cy, cz))
         }
-        _ => {}
+        _ => Err(format!("cell ({}, {}, {}) is outside the grid", x, y, z)),
+    }
+}
+
+/// Store under the write lock; waves at or below the noise floor, or with a
+/// `decay_tau` that is not a positive number of seconds, are refused
+///
+/// The wave's decay runs from the moment it is stored, as in `mem8`.
+fn mem8_store(
+    context: &Mem8Context,
+    x: usize,
+    y: usize,
+    z: usize,
+    wave: MemoryWave,
+) -> Result<(), String> {
+    // A panic while holding the lock cannot leave a cell half-written, so a
+    // poisoned lock is still safe to use
+    let mut grid = context
+        .wave_grid
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    let (cx, cy, cz) = mem8_cell(&grid, x, y, z)?;
+    if wave.amplitude <= grid.noise_floor {
+        return Err(format!(
+            "amplitude {} is at or below the noise floor {}",
+            wave.amplitude, grid.noise_floor
+        ));
+    }
+    if let Some(tau) = wave
+        .decay_tau
+        .filter(|tau| !(tau.is_finite() && *tau > 0.0))
+    {
+        return Err(format!("decay_tau {} is not a positive duration", tau));
     }
+    grid.store(cx, cy, cz, wave.to_mem8());
+    Ok(())
+}
 
-    artifacts
+/// `mem8` interference at a cell under the read lock; `t` drives the
+/// oscillation, while decay follows each wave's age
+fn mem8_interference(
+    context: &Mem8Context,
+    x: usize,
+    y: usize,
+    z: usize,
+    t: f32,
+) -> Result<f32, String> {
+    let grid = context
+        .wave_grid
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    let (cx, cy, cz) = mem8_cell(&grid, x, y, z)?;
+    Ok(grid.calculate_interference(cx, cy, cz, t))
 }
 
-// Character concealment functions
-fn extract_all_concealment(content: &str) -> ConcealmentData {
-    ConcealmentData {
-        zero_width_chars: extract_zero_width_chars(content),
-        content_addressed_links: extract_content_addressed_links(content),
-        whitespace_patterns: extract_whitespace_patterns(content),
-        html_comments: extract_html_comments(content),
+rustler::init!("Elixir.MarkdownLd.Native");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_limits_read_options() {
+        assert_eq!(
+            ParseLimits::from_options(&[]).unwrap(),
+            ParseLimits::default()
+        );
+
+        let limits =
+            ParseLimits::from_options(&options(&[("max_headings", "2"), ("other", "x")])).unwrap();
+        assert_eq!(limits.max_headings, 2);
+        assert_eq!(limits.max_bytes, ParseLimits::default().max_bytes);
+
+        assert!(matches!(
+            ParseLimits::from_options(&options(&[("max_bytes", "lots")])),
+            Err(ParseError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn parse_limits_trip_early() {
+        let tripped = |pairs: &[(&str, &str)], content: &str| {
+            matches!(
+                ParseLimits::from_options(&options(pairs))
+                    .unwrap()
+                    .check(content),
+                Err(ParseError::LimitExceeded(_))
+            )
+        };
+
+        assert!(tripped(&[("max_bytes", "4")], "# Hello"));
+        assert!(tripped(&[("max_headings", "1")], "# A\n\n# B\n"));
+        assert!(!tripped(&[("max_headings", "2")], "# A\n\n# B\n"));
+        assert!(tripped(&[("max_code_blocks", "0")], "```\nx\n```\n"));
+
+        let nested = format!("{}deep\n", "> ".repeat(100));
+        assert!(tripped(&[], &nested));
+        assert!(!tripped(&[("max_nesting", "200")], &nested));
+    }
+
+    fn all_rules() -> HashSet<&'static str> {
+        select_lint_rules(&[]).unwrap()
+    }
+
+    fn rules_of(issues: &[LintIssue]) -> Vec<(&'static str, usize)> {
+        issues
+            .iter()
+            .map(|issue| (issue.rule, issue.line))
+            .collect()
+    }
+
+    #[test]
+    fn jsonld_graph_finds_undefined_references() {
+        let content = r##"---
+"@context": "https://schema.org/"
+"@id": "#doc"
+"@type": CreativeWork
+---
+
+```json-ld
+{"@id": "#a", "@type": "Person", "name": "A"}
+```
+
+Prose in between.
+
+```jsonld
+{"@type": ["Note", "Person"], "about": {"@id": "#a"}, "seeAlso": [{"@id": "#b"}]}
+```
+"##;
+        let islands = collect_jsonld_islands(content);
+        assert_eq!(
+            islands
+                .iter()
+                .map(|island| (island.source, island.line))
+                .collect::<Vec<_>>(),
+            vec![("frontmatter", 1), ("code_fence", 7), ("code_fence", 13)]
+        );
+        assert!(islands.iter().all(|island| island.value.is_some()));
+
+        let graph = build_jsonld_graph(&islands);
+        assert_eq!(
+            graph.defined_ids.iter().collect::<Vec<_>>(),
+            vec!["#a", "#doc"]
+        );
+        assert_eq!(graph.undefined_ids(), vec!["#b".to_string()]);
+        assert_eq!(graph.types.get("Person"), Some(&2));
+        assert_eq!(graph.types.get("CreativeWork"), Some(&1));
+
+        // With the frontmatter context applied, types expand against @vocab
+        let mut islands = islands;
+        resolve_jsonld_contexts(&mut islands);
+        let graph = build_jsonld_graph(&islands);
+        assert_eq!(graph.types.get("https://schema.org/Person"), Some(&2));
+        assert_eq!(graph.undefined_ids(), vec!["#b".to_string()]);
     }
+
+    #[test]
+    fn jsonld_islands_resolve_against_document_context() {
+        let content = r##"---
+"@context":
+  ex: "https://example.org/"
+  "@vocab": "https://schema.org/"
+---
+
+```json-ld
+{"@id": "ex:alice", "@type": "Person", "knows": {"@id": "ex:bob"}}
+```
+
+```json-ld
+{
+  "@context": {"foaf": "http://xmlns.com/foaf/0.1/"},
+  "@id": "ex:bob",
+  "@type": ["foaf:Person", "_:local"],
+  "seeAlso": {"@id": "nope:x"},
+  "homepage": {"@id": "https://bob.example/"}
 }
+```
+"##;
+        let mut islands = collect_jsonld_islands(content);
+        let context = resolve_jsonld_contexts(&mut islands);
+
+        assert_eq!(
+            context.prefixes.get("ex").map(String::as_str),
+            Some("https://example.org/")
+        );
+        assert_eq!(context.vocab.as_deref(), Some("https://schema.org/"));
+        // Island-local prefixes do not leak into the document context
+        assert!(!context.prefixes.contains_key("foaf"));
+
+        let alice = islands[1].expanded.as_ref().unwrap();
+        assert_eq!(alice["@id"], "https://example.org/alice");
+        assert_eq!(alice["@type"], "https://schema.org/Person");
+        assert_eq!(alice["knows"]["@id"], "https://example.org/bob");
+        assert!(islands[1].unknown_prefixes.is_empty());
+
+        let bob = islands[2].expanded.as_ref().unwrap();
+        assert_eq!(bob["@type"][0], "http://xmlns.com/foaf/0.1/Person");
+        assert_eq!(bob["@type"][1], "_:local");
+        assert_eq!(bob["seeAlso"]["@id"], "nope:x");
+        assert_eq!(bob["homepage"]["@id"], "https://bob.example/");
+        assert_eq!(islands[2].unknown_prefixes, vec!["nope".to_string()]);
+
+        let graph = build_jsonld_graph(&islands);
+        assert_eq!(
+            graph.undefined_ids(),
+            vec!["https://bob.example/".to_string(), "nope:x".to_string()]
+        );
+    }
+
+    #[test]
+    fn chunk_ids_survive_reordering_within_a_section() {
+        let original = "# Guide\n\nFirst paragraph.\n\nSecond paragraph.\n\n- item one\n- item two\n  - nested\n\n## Code\n\n```rust\nfn main() {}\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+        let reordered = "# Guide\n\nSecond paragraph.\n\nFirst paragraph.\n\n- item one\n- item two\n  - nested\n\n## Code\n\n```rust\nfn main() {}\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n";
+
+        let chunks = chunk_blocks(original);
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| (chunk.kind, chunk.text.as_str(), chunk.line))
+                .collect::<Vec<_>>(),
+            vec![
+                ("paragraph", "First paragraph.", 3),
+                ("paragraph", "Second paragraph.", 5),
+                ("list_item", "item one", 7),
+                ("list_item", "item two", 8),
+                ("list_item", "nested", 9),
+                ("code_block", "fn main() {}", 13),
+                ("table", "a | b\n1 | 2", 17),
+            ]
+        );
+        assert_eq!(chunks[5].heading_path, vec!["Guide", "Code"]);
+
+        let moved = chunk_blocks(reordered);
+        assert_eq!(moved[0].text, "Second paragraph.");
+        assert_eq!(moved[0].block_index, 0);
+        assert_eq!(moved[0].stable_id, chunks[1].stable_id);
+        assert_eq!(moved[1].stable_id, chunks[0].stable_id);
+    }
+
+    #[test]
+    fn chunk_ids_disambiguate_repeated_text() {
+        let chunks = chunk_blocks("# A\n\nSame.\n\nSame.\n\n# B\n\nSame.\n");
+        assert_eq!(chunks.len(), 3);
+        assert_ne!(chunks[0].stable_id, chunks[1].stable_id);
+        assert_ne!(chunks[0].stable_id, chunks[2].stable_id);
+        assert_eq!(
+            chunks[2].stable_id,
+            generate_stable_chunk_id(&["B".to_string()], 0, "Same.")
+        );
+    }
+
+    #[test]
+    fn content_hash_verification() {
+        let data = b"hello world";
+        let address = sha256_hex(data);
+        assert_eq!(
+            address,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert!(verify_content_hash(&address, data));
+        assert!(verify_content_hash(&address.to_uppercase(), data));
+        assert!(!verify_content_hash(&address, b"hello world!"));
+        assert!(!verify_content_hash(&address[..63], data));
+
+        let link = format!("[doc]({})", address);
+        assert_eq!(extract_content_addressed_links(&link), vec![address]);
+    }
+
+    #[test]
+    fn mq2_blocks_parse_and_report_errors() {
+        let block = parse_mq2_block(
+            "MQ2~6743A100~4C3~156~22~mq~L1\n~T\\x80# |\\x81## |\u{83}**|\n~X\\x7F\\x00 compression algorithm|\n~~~~\n\\x80Title\n\\x7F\\x00\n",
+        )
+        .unwrap();
+        assert_eq!(block.header.timestamp, 0x6743A100);
+        assert_eq!(block.header.original_size, 0x4C3);
+        assert_eq!(block.header.level, 1);
+        assert_eq!(
+            block
+                .tokens
+                .iter()
+                .map(|token| (token.escaped(), token.pattern.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("\\x80".to_string(), "# "),
+                ("\\x81".to_string(), "## "),
+                ("\\x83".to_string(), "**"),
+            ]
+        );
+        assert_eq!(block.extended_tokens[0].escaped(), "\\x7F\\x00");
+        assert_eq!(block.body, "\\x80Title\n\\x7F\\x00\n");
+
+        let error = |input: &str| parse_mq2_block(input).unwrap_err();
+        assert_eq!(error("MQ2~1~2~3~4~mq\n").line, 0);
+        assert_eq!(
+            error("MQ2~1~2~3~zz~mq~L0\n").message,
+            "token count is not hex: 'zz'"
+        );
+        assert_eq!(error("MQ2~1~2~3~4~mq~L0\n~T\\x20a|\n~~~~\n").line, 1);
+        assert_eq!(
+            error("MQ2~1~2~3~4~mq~L0\n~Tx|\n~~~~\n").message,
+            "expected token, found 'x'"
+        );
+        assert_eq!(
+            error("MQ2~1~2~3~4~mq~L0\n~T\\x80a|\n~X\\x7F\\x00b|\n~~~~\n").line,
+            2
+        );
+        assert_eq!(error("MQ2~1~2~3~4~mq~L0\n~T\\x80a|\nbody\n").line, 2);
+        assert_eq!(
+            error("MQ2~1~2~3~4~mq~L0\n~T\\x80a|\\x80b\n~~~~\n").message,
+            "duplicate token \\x80"
+        );
+
+        let fences = collect_fenced_blocks("Intro\n\n```mq2\nMQ2~bad\n```\n", |lang| lang == "mq2");
+        assert_eq!(fences.len(), 1);
+        assert_eq!(fences[0].line, 3);
+        assert_eq!(fences[0].body, "MQ2~bad\n");
+    }
+
+    #[test]
+    fn mem8_fences_become_wave_specs() {
+        let content = "```mem8\n# waves\nfreq=440 amp=0.8 valence=-0.2\nfreq=5000 arousal=2\n\namp=0.5\nfreq=10 amp=loud pitch=3\n```\n";
+        let specs = collect_mem8_waves(content);
+        assert_eq!(
+            specs.iter().map(|spec| spec.line).collect::<Vec<_>>(),
+            vec![3, 4, 6, 7]
+        );
+
+        let wave = specs[0].wave.as_ref().unwrap();
+        assert_eq!(wave.frequency, 440.0);
+        assert_eq!(wave.amplitude, 0.8);
+        assert_eq!(wave.valence, -0.2);
+        assert_eq!(wave.decay_tau, Some(MEM8_DEFAULT_DECAY_TAU));
+        assert!(specs[0].errors.is_empty());
+
+        let clamped = specs[1].wave.as_ref().unwrap();
+        assert_eq!(clamped.frequency, 1000.0);
+        assert_eq!(clamped.arousal, 1.0);
+        assert_eq!(specs[1].errors, vec!["arousal 2 outside [0, 1]"]);
+
+        assert!(specs[2].wave.is_none());
+        assert_eq!(specs[2].errors, vec!["missing freq"]);
+
+        assert!(specs[3].wave.is_none());
+        assert_eq!(
+            specs[3].errors,
+            vec!["amp must be a number", "unknown parameter 'pitch'"]
+        );
+    }
+
+    #[test]
+    fn capture_extraction_skips_near_misses() {
+        let near_misses = format!(
+            "[short]({})\n[upper]({})\n<!-- polyglot:only -->\n<!-- polyglot:a:b-c -->\nTitle {{}}",
+            "a".repeat(63),
+            "A".repeat(64)
+        );
+        assert!(extract_content_addressed_links(&near_misses).is_empty());
+        assert!(extract_html_comments(&near_misses).is_empty());
+
+        let headings = parse_structure("# Title {}\n\n# Title {level=2}\n").headings;
+        assert_eq!(headings[0].text, "Title {}");
+        assert!(headings[0].attributes.is_empty());
+        assert_eq!(headings[1].text, "Title");
+        assert_eq!(headings[1].attributes.len(), 1);
+    }
+
+    #[test]
+    fn heading_lines_match_source_after_tables_and_blank_lines() {
+        let content = "# Title\n\n\n| a | b |\n|---|---|\n| 1 | 2 |\n| 3 | 4 |\n\n\n- one\n- two\n\n\n\n## Section {id=sec}\n";
+        let headings = collect_headings(content, parse_structure(content).headings);
+        assert_eq!(
+            headings
+                .iter()
+                .map(|heading| (heading.level, heading.text.as_str(), heading.line))
+                .collect::<Vec<_>>(),
+            vec![(1, "Title", 1), (2, "Section", 15)]
+        );
+        assert_eq!(content.lines().nth(14), Some("## Section {id=sec}"));
+        assert!(headings[1].attributes.contains_key("id"));
+        assert_eq!(headings[1].stable_id, "h2-section");
+    }
+
+    #[test]
+    fn setext_headings_report_text_line_and_style() {
+        let summary = |content: &str| {
+            collect_headings(content, parse_structure(content).headings)
+                .into_iter()
+                .map(|heading| (heading.level, heading.text, heading.line, heading.style))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            summary("Title\n=====\n\n## Next\n"),
+            vec![
+                (1, "Title".to_string(), 1, "setext"),
+                (2, "Next".to_string(), 4, "atx"),
+            ]
+        );
+        assert_eq!(
+            summary("Intro\n\nSub title\nspans lines\n---\n"),
+            vec![(2, "Sub title spans lines".to_string(), 3, "setext")]
+        );
+        // A blank line turns `---` into a thematic break
+        assert!(summary("Intro\n\nParagraph\n\n---\n").is_empty());
+    }
+
+    #[test]
+    fn stats_skip_frontmatter_and_code() {
+        let content = "---\ntitle: Ignored words here\n---\n# Intro\n\nOne two three. Four five?\nSix `code` seven!\n\n```rust\nlet skipped = words;\n```\n\n- Item eight... nine\n";
+        let stats = collect_document_stats(content, 200);
+        assert_eq!(stats.word_count, 12);
+        assert_eq!(stats.sentence_count, 4);
+        assert_eq!(
+            stats.char_count,
+            "IntroOnetwothree.Fourfive?Sixcodeseven!Itemeight...nine".len()
+        );
+        assert_eq!(stats.reading_time_seconds, 4);
+
+        assert_eq!(collect_document_stats("v1.2 is out", 200).sentence_count, 0);
+        assert_eq!(collect_document_stats("", 200).reading_time_seconds, 0);
+
+        let wpm = |value: &str| {
+            reading_wpm_from_options(&[("reading_wpm".to_string(), value.to_string())])
+        };
+        assert_eq!(wpm("120").unwrap(), 120);
+        assert!(matches!(wpm("0"), Err(ParseError::InvalidOption(_))));
+        assert_eq!(reading_wpm_from_options(&[]).unwrap(), DEFAULT_READING_WPM);
+    }
+
+    #[test]
+    fn word_count_matches_scalar_split() {
+        let samples = [
+            "",
+            "word",
+            "  leading and trailing  ",
+            "exactly sixteen!",
+            "a b c d e f g h i j k l m n o p q r s t u v w x y z",
+            "tabs\tand\nnewlines\r\nand\x0cfeeds across a chunk boundary here",
+            "multi-byte café naïve words straddle the sixteen byte lanes ok",
+        ];
+        for sample in samples {
+            assert_eq!(
+                count_words(sample),
+                sample.split_ascii_whitespace().count(),
+                "{:?}",
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_reparse_marks_only_the_edited_block() {
+        let original = "# Doc\n\nAlpha paragraph.\n\nBeta paragraph.\n\nGamma paragraph.\n";
+        let previous: Vec<PreviousBlock> = chunk_blocks(original).iter().map(Into::into).collect();
+
+        let status = |content: &str| {
+            let diff = diff_blocks(&previous, chunk_blocks(content));
+            let statuses: Vec<(&'static str, String)> = diff
+                .changes
+                .into_iter()
+                .map(|change| (change.status, change.chunk.text))
+                .collect();
+            (statuses, diff.removed.len())
+        };
+
+        let (edited, removed) =
+            status("# Doc\n\nAlpha paragraph.\n\nBeta, edited.\n\nGamma paragraph.\n");
+        assert_eq!(
+            edited.iter().map(|(s, _)| *s).collect::<Vec<_>>(),
+            vec!["unchanged", "modified", "unchanged"]
+        );
+        assert_eq!(removed, 0);
+
+        let (swapped, _) =
+            status("# Doc\n\nBeta paragraph.\n\nAlpha paragraph.\n\nGamma paragraph.\n");
+        assert_eq!(
+            swapped.iter().map(|(s, _)| *s).collect::<Vec<_>>(),
+            vec!["moved", "unchanged", "unchanged"]
+        );
+
+        let (inserted, removed) = status(
+            "# Doc\n\nAlpha paragraph.\n\nNew paragraph.\n\nBeta paragraph.\n\nGamma paragraph.\n",
+        );
+        assert_eq!(
+            inserted.iter().map(|(s, _)| *s).collect::<Vec<_>>(),
+            vec!["unchanged", "added", "unchanged", "unchanged"]
+        );
+        assert_eq!(removed, 0);
+
+        let (rehomed, removed) = status(
+            "# Doc\n\nAlpha paragraph.\n\nBeta paragraph.\n\n## Later\n\nGamma paragraph.\n",
+        );
+        assert_eq!(rehomed[2], ("moved", "Gamma paragraph.".to_string()));
+        assert_eq!(removed, 0);
+
+        let (_, removed) = status("# Doc\n\nAlpha paragraph.\n\nGamma paragraph.\n");
+        assert_eq!(removed, 1);
+    }
+
+    #[test]
+    fn parse_document_serializes_without_a_vm() {
+        let content = r##"# Guide {#intro}
+
+See [the spec](https://example.org/spec "Spec") for details.
+
+- [ ] Read it
+- [x] Skim it *now*
+
+> [!WARNING] Heads up
+> Draft only.
+
+```json-ld
+{"@id": "https://example.org/guide", "@type": "https://schema.org/Article"}
+```
+"##;
+        let parsed = parse_document(content, &[]).unwrap();
+
+        assert_eq!(parsed.headings[0].text, "Guide");
+        assert_eq!(parsed.links[0].title.as_deref(), Some("Spec"));
+        assert_eq!(parsed.code_blocks[0].language, "json-ld");
+        assert!(parsed.code_blocks[0].is_jsonld);
+        assert_eq!(parsed.tasks.len(), 2);
+        assert!(parsed.tasks[1].completed);
+        assert_eq!(parsed.emphasis.len(), 1);
+        assert_eq!(parsed.emphasis[0].text, "now");
+        assert_eq!(parsed.emphasis[0].line, 6);
+        assert_eq!(parsed.emphasis[0].col_start, 14);
+        assert_eq!(
+            parsed.blockquotes[0].callout_type.as_deref(),
+            Some("WARNING")
+        );
+        assert_eq!(
+            parsed.blockquotes[0].callout_title.as_deref(),
+            Some("Heads up")
+        );
+        assert_eq!(parsed.blockquotes[0].text, "Draft only.");
+        assert_eq!(
+            (
+                parsed.blockquotes[0].line_start,
+                parsed.blockquotes[0].line_end
+            ),
+            (8, 9)
+        );
+        assert_eq!(parsed.jsonld_islands[0].line, 11);
+
+        let json = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(json["headings"][0]["level"], 1);
+        assert_eq!(json["headings"][0]["style"], "atx");
+        assert_eq!(json["links"][0]["url"], "https://example.org/spec");
+        assert_eq!(json["tasks"][0]["text"], "Read it");
+        assert_eq!(json["emphasis"][0]["kind"], "emphasis");
+        assert_eq!(json["jsonld_islands"][0]["source"], "code_fence");
+        assert_eq!(
+            json["jsonld_graph"]["types"]["https://schema.org/Article"],
+            1
+        );
+        assert_eq!(json["stats"]["word_count"], 20);
+        assert!(json["polyglot_language"].is_null());
+
+        assert!(matches!(
+            parse_document(content, &[("reading_wpm".to_string(), "0".to_string())]),
+            Err(ParseError::InvalidOption(_))
+        ));
+    }
 
-fn extract_zero_width_chars(content: &str) -> Vec<u8> {
-    let mut zero_width_data = Vec::new();
+    #[test]
+    fn heading_attributes_serialize_in_key_order() {
+        let content = "# Title {zeta=1 alpha=\"a\" mid=true id=\"t\" beta=x}\n";
+        let serialized = || {
+            let parsed = parse_document(content, &[]).unwrap();
+            serde_json::to_string(&parsed.headings[0].attributes).unwrap()
+        };
+
+        assert_eq!(
+            serialized(),
+            r#"{"alpha":"a","beta":"x","id":"t","mid":true,"zeta":1.0}"#
+        );
+        assert_eq!(serialized(), serialized());
+    }
 
-    for ch in content.chars() {
-        if ZERO_WIDTH_CHARS.contains(&ch) {
-            // Convert zero-width char to data bit
-            let index = ZERO_WIDTH_CHARS.iter().position(|&c| c == ch).unwrap_or(0);
-            zero_width_data.push(index as u8);
-        }
+    #[test]
+    fn definition_lists_collect_terms_with_one_or_more_definitions() {
+        let content = "Intro paragraph.\n\nApple\n: A red fruit\n\nOrange\n: A citrus fruit\n: A colour\n    between red and yellow\n\n: Also a telecom brand\nPear\n\n: Pome fruit\n";
+        let defs = collect_definition_lists(content);
+
+        assert_eq!(
+            defs,
+            vec![
+                DefinitionOut {
+                    term: "Apple".to_string(),
+                    definitions: vec!["A red fruit".to_string()],
+                    line: 3,
+                },
+                DefinitionOut {
+                    term: "Orange".to_string(),
+                    definitions: vec![
+                        "A citrus fruit".to_string(),
+                        "A colour between red and yellow".to_string(),
+                        "Also a telecom brand".to_string(),
+                    ],
+                    line: 6,
+                },
+                DefinitionOut {
+                    term: "Pear".to_string(),
+                    definitions: vec!["Pome fruit".to_string()],
+                    line: 12,
+                },
+            ]
+        );
     }
 
-    zero_width_data
-}
+    #[test]
+    fn definition_lists_ignore_colons_in_prose_and_code() {
+        let content = "Note: this is prose.\nTime: 10:30\n\nA paragraph that\nruns on\n: but this line is not a definition\n\n:smile: is an emoji code\n\n- Item\n: not a term\n\n```yaml\nkey\n: value\n```\n";
+        assert!(collect_definition_lists(content).is_empty());
 
-fn encode_zero_width_data(data: &str) -> String {
-    let mut result = String::new();
+        let only = [("only".to_string(), "definition_lists".to_string())];
+        let parsed = parse_document("Term\n: Def\n", &only).unwrap();
+        assert_eq!(parsed.definition_lists[0].definitions, vec!["Def"]);
+    }
 
-    for byte in data.bytes() {
-        let char_index = (byte % ZERO_WIDTH_CHARS.len() as u8) as usize;
-        result.push(ZERO_WIDTH_CHARS[char_index]);
+    #[test]
+    fn section_numbers_follow_heading_nesting() {
+        let numbers = |levels: &[u32], from: u32| -> Vec<Option<String>> {
+            section_numbers(levels.iter().copied(), from)
+        };
+        let some = |numbers: &[&str]| -> Vec<Option<String>> {
+            numbers.iter().map(|n| Some(n.to_string())).collect()
+        };
+
+        assert_eq!(
+            numbers(&[1, 2, 2, 3, 2], 1),
+            some(&["1", "1.1", "1.2", "1.2.1", "1.3"])
+        );
+        let mut from_h2 = some(&["1", "2", "2.1", "3"]);
+        from_h2.insert(0, None);
+        assert_eq!(numbers(&[1, 2, 2, 3, 2], 2), from_h2);
+        // Skipped levels nest without gaps; the count carries over when
+        // a shallower heading closes them
+        assert_eq!(numbers(&[1, 3, 3, 2], 1), some(&["1", "1.1", "1.2", "1.3"]));
+        assert_eq!(numbers(&[2, 2, 1, 2], 1), some(&["1", "2", "3", "3.1"]));
+
+        let content = "# Manual\n\n## Install\n\n## Use\n\n### CLI\n\n## FAQ\n";
+        let options = [("number_headings".to_string(), "1".to_string())];
+        let parsed = parse_document(content, &options).unwrap();
+        let numbered: Vec<Option<&str>> = parsed
+            .headings
+            .iter()
+            .map(|h| h.number.as_deref())
+            .collect();
+        assert_eq!(
+            numbered,
+            vec![
+                Some("1"),
+                Some("1.1"),
+                Some("1.2"),
+                Some("1.2.1"),
+                Some("1.3")
+            ]
+        );
+        assert!(parse_document(content, &[]).unwrap().headings[0]
+            .number
+            .is_none());
+        assert!(matches!(
+            parse_document(content, &[("number_headings".to_string(), "7".to_string())]),
+            Err(ParseError::InvalidOption(_))
+        ));
     }
 
-    result
-}
+    #[test]
+    fn frontmatter_is_detected_in_yaml_toml_and_json() {
+        let yaml = detect_frontmatter("---\ntitle: Post\ntags: [a, b]\n---\n# Body\n").unwrap();
+        assert_eq!(yaml.format, "yaml");
+        assert_eq!(
+            yaml.data,
+            serde_json::json!({"title": "Post", "tags": ["a", "b"]})
+        );
+        assert_eq!(yaml.len, "---\ntitle: Post\ntags: [a, b]\n---\n".len());
+
+        let content =
+            "+++\ntitle = \"Post\"\ndate = 2024-05-01\n[params]\ndraft = true\n+++\nBody\n";
+        let toml = detect_frontmatter(content).unwrap();
+        assert_eq!(toml.format, "toml");
+        assert_eq!(
+            toml.data,
+            serde_json::json!({"title": "Post", "date": "2024-05-01", "params": {"draft": true}})
+        );
+        assert_eq!(&content[toml.len..], "Body\n");
+
+        let fenced = detect_frontmatter(";;;\n{\"title\": \"Post\"}\n;;;\nBody").unwrap();
+        assert_eq!(
+            (fenced.format, fenced.data["title"].as_str()),
+            ("json", Some("Post"))
+        );
+
+        let content = "{\n  \"title\": \"Post\",\n  \"n\": 2\n}\n\n# Body\n";
+        let bare = detect_frontmatter(content).unwrap();
+        assert_eq!(bare.data, serde_json::json!({"title": "Post", "n": 2}));
+        assert_eq!(&content[bare.len..], "\n# Body\n");
+    }
 
-fn decode_zero_width_data(data: &[u8]) -> Result<String, String> {
-    // Simple decode - in reality would need proper base64 or similar
-    let decoded: Vec<u8> = data
-        .iter()
-        .map(|&b| {
-            if b < ZERO_WIDTH_CHARS.len() as u8 {
-                b + 32
-            } else {
-                b
-            }
-        })
-        .collect();
+    #[test]
+    fn frontmatter_rejects_thematic_breaks_and_non_mappings() {
+        // Thematic break, paragraph, and a setext underline
+        assert!(detect_frontmatter("---\nJust a paragraph\n---\n\nText\n").is_none());
+        assert!(detect_frontmatter("---\n---\n").is_none());
+        assert!(detect_frontmatter("---\n\n# Heading\n").is_none());
+        assert!(detect_frontmatter("---\n- a\n- b\n---\n").is_none());
+        assert!(detect_frontmatter("+++\nnot = = toml\n+++\n").is_none());
+        assert!(detect_frontmatter("{\"a\": 1} trailing text\n").is_none());
+        assert!(detect_frontmatter("# Title\n---\na: 1\n---\n").is_none());
+
+        // Stats no longer skip a thematic break as if it were frontmatter
+        let stats = collect_document_stats("---\nThree words here\n---\n", 200);
+        assert_eq!(stats.word_count, 3);
+        let stats = collect_document_stats("+++\ntitle = \"Skipped\"\n+++\nOne two\n", 200);
+        assert_eq!(stats.word_count, 2);
+    }
 
-    String::from_utf8(decoded).map_err(|e| format!("UTF-8 decode error: {}", e))
-}
+    #[test]
+    fn github_slugs_match_rendered_anchors() {
+        let cases = [
+            ("Hello World", "hello-world"),
+            ("What's new?", "whats-new"),
+            ("C++ & Rust", "c--rust"),
+            ("🎉 Release notes", "-release-notes"),
+            ("Über Café", "über-café"),
+            ("snake_case and kebab-case", "snake_case-and-kebab-case"),
+            ("API v2.0 (beta)", "api-v20-beta"),
+            ("`code` in *headings*", "code-in-headings"),
+            ("日本語の見出し", "日本語の見出し"),
+            ("Cafe\u{301}", "cafe\u{301}"),
+        ];
+        for (text, slug) in cases {
+            assert_eq!(github_slug(text), slug, "{:?}", text);
+        }
 
-fn extract_content_addressed_links(content: &str) -> Vec<String> {
-    lazy_static! {
-        static ref SHA256_REGEX: Regex = Regex::new(r"\[([^\]]+)\]\(([a-f0-9]{64})\)").unwrap();
+        let mut slugger = GithubSlugger::default();
+        let slugs: Vec<String> = ["Intro", "Intro", "Intro-1", "Intro", "Setup"]
+            .iter()
+            .map(|text| slugger.slug(text))
+            .collect();
+        assert_eq!(slugs, ["intro", "intro-1", "intro-1-1", "intro-2", "setup"]);
+
+        let content = "# FAQ\n\n## Why?\n\n# FAQ\n";
+        let anchors: Vec<String> = collect_headings(content, parse_structure(content).headings)
+            .into_iter()
+            .map(|heading| heading.anchor)
+            .collect();
+        assert_eq!(anchors, ["faq", "why", "faq-1"]);
+
+        let options = [("github_stable_ids".to_string(), "true".to_string())];
+        let parsed = parse_document(content, &options).unwrap();
+        assert_eq!(parsed.headings[2].stable_id, "faq-1");
+        assert_eq!(
+            parse_document(content, &[]).unwrap().headings[2].stable_id,
+            "h1-faq"
+        );
     }
 
-    SHA256_REGEX
-        .captures_iter(content)
-        .map(|cap| cap.get(2).unwrap().as_str().to_string())
-        .collect()
-}
-
-fn extract_whitespace_patterns(content: &str) -> Vec<u8> {
-    // Extract patterns from trailing whitespace
-    let mut patterns = Vec::new();
+    #[test]
+    fn tasks_carry_their_heading_section() {
+        let content = "# Project\n\n- [ ] Scope it\n\n## Backend\n\n- [x] Schema\n  - [ ] Migrations\n\n### API\n\n- [ ] Endpoints\n\n## Frontend\n\n- [ ] Schema\n- [ ] Schema\n";
+        let doc = parse_structure(content);
+        let tasks = collect_tasks(&doc.headings, doc.tasks);
+
+        let sections: Vec<(&str, Vec<&str>, usize)> = tasks
+            .iter()
+            .map(|task| {
+                let path = task.heading_path.iter().map(String::as_str).collect();
+                (task.text.as_str(), path, task.depth)
+            })
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                ("Scope it", vec!["Project"], 0),
+                ("Schema", vec!["Project", "Backend"], 0),
+                ("Migrations", vec!["Project", "Backend"], 1),
+                ("Endpoints", vec!["Project", "Backend", "API"], 0),
+                ("Schema", vec!["Project", "Frontend"], 0),
+                ("Schema", vec!["Project", "Frontend"], 0),
+            ]
+        );
+
+        // Same text in another section, or repeated in one, gets its own ID
+        assert_ne!(tasks[1].stable_id, tasks[4].stable_id);
+        assert_ne!(tasks[4].stable_id, tasks[5].stable_id);
+
+        // Checking a task off or adding tasks elsewhere keeps its ID
+        let edited = content
+            .replace("- [ ] Endpoints", "- [x] Endpoints")
+            .replace("- [ ] Scope it", "- [ ] Scope it\n- [ ] Estimate");
+        let doc = parse_structure(&edited);
+        let endpoints = collect_tasks(&doc.headings, doc.tasks)
+            .into_iter()
+            .find(|task| task.text == "Endpoints")
+            .unwrap();
+        assert_eq!(endpoints.stable_id, tasks[3].stable_id);
+
+        // The section path uses heading text as `headings` reports it
+        let content = "## Backend {.api} {#be}\n\n- [ ] Schema\n";
+        let parsed = parse_document(content, &[]).unwrap();
+        assert_eq!(parsed.headings[0].text, "Backend");
+        assert_eq!(parsed.tasks[0].heading_path, ["Backend"]);
+    }
 
-    for line in content.lines() {
-        let trailing_spaces = line.len() - line.trim_end().len();
-        if trailing_spaces > 0 {
-            patterns.push(trailing_spaces as u8);
-        }
+    #[test]
+    fn malformed_islands_get_positioned_hints() {
+        let diagnose = |body: &str| diagnose_island_json(body, 10).unwrap_err();
+
+        let trailing = diagnose("{\n  \"@type\": \"Article\",\n}\n");
+        assert_eq!(trailing.hint, JSONLD_HINT_TRAILING_COMMA);
+        assert_eq!((trailing.line, trailing.column), (12, 1));
+
+        let unquoted = diagnose("{\"@context\": \"https://schema.org/\", name: \"A\"}");
+        assert_eq!(unquoted.hint, JSONLD_HINT_UNQUOTED_KEY);
+        assert_eq!((unquoted.line, unquoted.column), (10, 37));
+
+        assert_eq!(
+            diagnose("{'@type': 'Article'}").hint,
+            JSONLD_HINT_SINGLE_QUOTES
+        );
+        assert_eq!(
+            diagnose("{\"@type\": 'Article'}").hint,
+            JSONLD_HINT_SINGLE_QUOTES
+        );
+        assert_eq!(
+            diagnose("{\"a\": 1 \"b\": 2}").hint,
+            JSONLD_HINT_MISSING_COMMA
+        );
+        assert_eq!(diagnose("{\"a\": [1, 2").hint, JSONLD_HINT_UNCLOSED);
+        assert_eq!(diagnose("{\"a\": tru}").hint, JSONLD_HINT_GENERIC);
     }
 
-    patterns
-}
+    #[test]
+    fn islands_without_a_context_are_flagged() {
+        let content = "# Doc\n\n```json-ld\n{\"@type\": \"Article\"}\n```\n\n```json-ld\n{\"@context\": \"https://schema.org/\", \"@type\": \"Person\"}\n```\n\n```json-ld\n{\"@type\": \"Event\",}\n```\n";
+        let islands = collect_jsonld_islands(content);
+
+        let missing = islands[0].diagnostic.as_ref().unwrap();
+        assert_eq!(missing.hint, JSONLD_HINT_MISSING_CONTEXT);
+        assert_eq!(missing.line, 4);
+        assert!(islands[0].value.is_some());
+        assert!(islands[1].diagnostic.is_none());
+        assert_eq!(
+            islands[2].diagnostic.as_ref().unwrap().hint,
+            JSONLD_HINT_TRAILING_COMMA
+        );
+        assert!(islands[2].value.is_none());
+
+        // A frontmatter context covers every island
+        let with_frontmatter = format!(
+            "---\n\"@context\":\n  schema: \"https://schema.org/\"\n---\n{}",
+            content
+        );
+        let islands = collect_jsonld_islands(&with_frontmatter);
+        assert!(islands[1].diagnostic.is_none());
+    }
 
-fn extract_html_comments(content: &str) -> Vec<String> {
-    lazy_static! {
-        static ref COMMENT_REGEX: Regex = Regex::new(r"<!-- polyglot:([^:]+):([^-]+) -->").unwrap();
+    #[test]
+    fn title_from_first_h1_moves_it_out_of_headings() {
+        let options = [
+            ("title_from_first_h1".to_string(), "true".to_string()),
+            ("number_headings".to_string(), "2".to_string()),
+        ];
+        let content =
+            "Preamble\n\n# User Guide {id=\"guide\"}\n\n## Install\n\n# Appendix\n\n## Notes\n";
+        let parsed = parse_document(content, &options).unwrap();
+
+        assert_eq!(parsed.title.as_deref(), Some("User Guide"));
+        let headings: Vec<(&str, Option<&str>)> = parsed
+            .headings
+            .iter()
+            .map(|h| (h.text.as_str(), h.number.as_deref()))
+            .collect();
+        assert_eq!(
+            headings,
+            vec![
+                ("Install", Some("1")),
+                ("Appendix", None),
+                ("Notes", Some("2"))
+            ]
+        );
+
+        let untitled = parse_document("## Only sections\n", &options).unwrap();
+        assert!(untitled.title.is_none());
+        assert_eq!(untitled.headings.len(), 1);
+
+        // Off by default
+        let parsed = parse_document(content, &[]).unwrap();
+        assert!(parsed.title.is_none());
+        assert_eq!(parsed.headings.len(), 4);
+
+        let only = [
+            ("title_from_first_h1".to_string(), "true".to_string()),
+            ("only".to_string(), "title".to_string()),
+        ];
+        let parsed = parse_document(content, &only).unwrap();
+        assert_eq!(parsed.title.as_deref(), Some("User Guide"));
     }
 
-    COMMENT_REGEX
-        .captures_iter(content)
-        .map(|cap| cap.get(2).unwrap().as_str().to_string())
-        .collect()
-}
+    #[test]
+    fn positions_count_utf16_units_separately_from_bytes() {
+        // "😀" is 4 bytes / 2 UTF-16 units; "e\u{301}" is 3 bytes / 2 units
+        let content = "# Intro\n\nHi 😀 e\u{301} [link](https://x)\n\n```rust\nfn main() {}\n```\n";
+        let options = [("positions".to_string(), "true".to_string())];
+        let parsed = parse_document(content, &options).unwrap();
+
+        let link = parsed.links[0].position.unwrap();
+        assert_eq!(link.line, 3);
+        assert_eq!(link.utf8_col, 12);
+        assert_eq!(link.utf16_col, 9);
+        assert_eq!(&content[link.byte_offset..link.byte_offset + 6], "[link]");
+
+        assert_eq!(
+            parsed.headings[0].position,
+            Some(Position {
+                line: 1,
+                utf8_col: 0,
+                utf16_col: 0,
+                byte_offset: 0
+            })
+        );
+        let code = parsed.code_blocks[0].position.unwrap();
+        assert_eq!((code.line, code.utf16_col), (5, 0));
+
+        // Off by default
+        let parsed = parse_document(content, &[]).unwrap();
+        assert!(parsed.links[0].position.is_none());
+        let json = serde_json::to_value(&parsed.links[0]).unwrap();
+        assert!(json.get("offset").is_none());
+    }
 
-// RFC 8785 JSON Canonicalization Scheme
-fn json_canonicalize(json_str: &str) -> Result<String, String> {
-    // Parse JSON
-    let value: serde_json::Value =
-        serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
+    #[test]
+    fn validate_code_checks_json_yaml_and_toml_fences() {
+        let content = "```json\n{\"a\": 1}\n```\n\n```json\n{\"a\": }\n```\n\n```yaml\nkey: [1, 2\n```\n\n```toml\n[server]\nport = 8080\n```\n\n```TOML\nport = \n```\n\n```rust\nnot checked\n```\n";
+        let options = [("validate_code".to_string(), "true".to_string())];
+        let parsed = parse_document(content, &options).unwrap();
+
+        let checks: Vec<Option<bool>> = parsed
+            .code_blocks
+            .iter()
+            .map(|block| block.validation.as_ref().map(|check| check.valid))
+            .collect();
+        assert_eq!(
+            checks,
+            vec![
+                Some(true),
+                Some(false),
+                Some(false),
+                Some(true),
+                Some(false),
+                None
+            ]
+        );
+        assert_eq!(
+            parsed.code_blocks[0].validation.as_ref().unwrap().error,
+            None
+        );
+        for index in [1, 2, 4] {
+            let error = parsed.code_blocks[index]
+                .validation
+                .as_ref()
+                .unwrap()
+                .error
+                .as_ref();
+            assert!(error.is_some_and(|e| !e.is_empty()));
+        }
 
-    // Convert to canonical form (JCS)
-    canonicalize_json_value(&value)
-}
+        // Off by default
+        let parsed = parse_document(content, &[]).unwrap();
+        assert!(parsed
+            .code_blocks
+            .iter()
+            .all(|block| block.validation.is_none()));
+        assert!(matches!(
+            parse_document(content, &[("validate_code".to_string(), "yes".to_string())]),
+            Err(ParseError::InvalidOption(_))
+        ));
+    }
 
-fn canonicalize_json_value(value: &serde_json::Value) -> Result<String, String> {
-    match value {
-        serde_json::Value::Null => Ok("null".to_string()),
-        serde_json::Value::Bool(b) => Ok(b.to_string()),
-        serde_json::Value::Number(n) => Ok(n.to_string()),
-        serde_json::Value::String(s) => Ok(format!("\"{}\"", escape_json_string(s))),
-        serde_json::Value::Array(arr) => {
-            let items: Result<Vec<_>, _> = arr.iter().map(canonicalize_json_value).collect();
-            Ok(format!("[{}]", items?.join(",")))
+    #[test]
+    fn only_option_projects_the_requested_elements() {
+        let content = "# Title\n\nSee [a](https://a) and [b](https://b).\n\n- [ ] task\n\n```json-ld\n{\"@id\": \"#x\"}\n```\n";
+        let only = |value: &str| [("only".to_string(), value.to_string())];
+
+        let parsed = parse_document(content, &only("links")).unwrap();
+        assert_eq!(parsed.links.len(), 2);
+        assert!(parsed.headings.is_empty());
+        assert!(parsed.tasks.is_empty());
+        assert!(parsed.code_blocks.is_empty());
+        assert!(parsed.jsonld_islands.is_empty());
+        assert_eq!(parsed.stats, DocumentStats::default());
+
+        let parsed = parse_document(content, &only(" headings , jsonld_graph")).unwrap();
+        assert_eq!(parsed.headings.len(), 1);
+        assert!(parsed.links.is_empty());
+        assert_eq!(parsed.jsonld_islands.len(), 1);
+        assert_eq!(
+            parsed.only.unwrap().into_iter().collect::<Vec<_>>(),
+            vec!["headings", "jsonld_graph"]
+        );
+
+        assert!(parse_document(content, &[]).unwrap().only.is_none());
+        for bad in ["links,pictures", " , "] {
+            assert!(matches!(
+                parse_document(content, &only(bad)),
+                Err(ParseError::InvalidOption(_))
+            ));
         }
-        serde_json::Value::Object(obj) => {
-            let mut keys: Vec<_> = obj.keys().collect();
-            keys.sort();
+    }
 
-            let items: Result<Vec<_>, String> = keys
-                .iter()
-                .map(|k| {
-                    let key = format!("\"{}\"", escape_json_string(k));
-                    let value = canonicalize_json_value(obj.get(*k).unwrap())?;
-                    Ok(format!("{}:{}", key, value))
-                })
+    #[test]
+    fn link_graph_classifies_links_and_resolves_anchors() {
+        let content = "# Intro\n\n## Setup {id=\"install\"}\n\nSee [setup](#setup), [install](#install), [id](#h2-setup), [gone](#teardown).\n\n[guide](docs/guide.md#setup) [site](https://example.org/#setup) [cdn](//cdn.example.org/x.js) [mail](mailto:a@b.c)\n";
+        let graph = build_link_graph(content);
+        let edges: Vec<(&str, &str, Option<bool>)> = graph
+            .iter()
+            .map(|edge| (edge.text.as_str(), edge.kind, edge.resolves))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                ("setup", "anchor", Some(true)),
+                ("install", "anchor", Some(true)),
+                ("id", "anchor", Some(true)),
+                ("gone", "anchor", Some(false)),
+                ("guide", "relative", None),
+                ("site", "absolute", None),
+                ("cdn", "absolute", None),
+                ("mail", "absolute", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn corpus_index_merges_documents_and_checks_cross_file_anchors() {
+        let index_md = "# Home\n\nRead [install](docs/guide.md#install), [usage](docs/guide.md#usage), [faq](faq.md#top) and [top](#home).\n\n```json-ld\n{\"@context\": \"https://schema.org/\", \"@id\": \"urn:site\", \"@type\": \"WebSite\", \"hasPart\": {\"@id\": \"urn:guide\"}}\n```\n";
+        let guide_md = "# Guide\n\n## Install\n\nBack [home](../index.md#home) or to [the picture](img/a.png).\n\n```json-ld\n{\"@context\": \"https://schema.org/\", \"@id\": \"urn:guide\", \"@type\": \"WebPage\"}\n```\n";
+        let documents: Vec<(&str, ParseResult)> =
+            [("index.md", index_md), ("docs/guide.md", guide_md)]
+                .into_iter()
+                .map(|(source, content)| (source, parse_document(content, &[]).unwrap()))
                 .collect();
-            Ok(format!("{{{}}}", items?.join(",")))
+        let index = build_corpus_index(&documents);
+
+        let stable_ids: Vec<&str> = index
+            .headings
+            .iter()
+            .map(|h| h.stable_id.as_str())
+            .collect();
+        assert_eq!(
+            stable_ids,
+            [
+                "index.md#h1-home",
+                "docs/guide.md#h1-guide",
+                "docs/guide.md#h2-install"
+            ]
+        );
+
+        let targets: Vec<(&str, Option<&str>)> = index
+            .links
+            .iter()
+            .map(|link| (link.text.as_str(), link.target.as_deref()))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                ("install", Some("docs/guide.md")),
+                ("usage", Some("docs/guide.md")),
+                ("faq", None),
+                ("top", Some("index.md")),
+                ("home", Some("index.md")),
+                ("the picture", None),
+            ]
+        );
+        let unresolved: Vec<(&str, &str)> = index
+            .unresolved_anchors
+            .iter()
+            .map(|link| (link.source.as_str(), link.url.as_str()))
+            .collect();
+        assert_eq!(
+            unresolved,
+            [
+                ("index.md", "docs/guide.md#usage"),
+                ("index.md", "faq.md#top")
+            ]
+        );
+
+        assert_eq!(index.jsonld_islands.len(), 2);
+        assert!(index.jsonld_graph.undefined_ids().is_empty());
+        assert_eq!(index.jsonld_graph.types.len(), 2);
+    }
+
+    #[test]
+    fn document_diff_pairs_edited_blocks_and_reports_additions() {
+        let old = "# Guide\n\nInstall the package with mix.\n\nRun the tests before you ship.\n\nThe end.\n";
+        let new = "# Guide\n\nInstall the package with mix or rebar.\n\nRun the tests before you ship.\n\nA brand new paragraph about something else entirely.\n\nThe end.\n";
+        let diff = diff_documents(old, new);
+
+        let ops: Vec<(&str, Option<usize>, Option<usize>)> = diff
+            .operations
+            .iter()
+            .map(|op| (op.op, op.old_line, op.new_line))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                ("modified", Some(3), Some(3)),
+                ("unchanged", Some(5), Some(5)),
+                ("added", None, Some(7)),
+                ("unchanged", Some(7), Some(9)),
+            ]
+        );
+        let edited = &diff.operations[0];
+        assert_ne!(edited.old_id, edited.new_id);
+        assert!(edited.similarity > 0.7 && edited.similarity < 1.0);
+        assert!(diff.similarity > 0.7 && diff.similarity < 1.0);
+
+        let removed = diff_documents(new, old);
+        assert_eq!(
+            removed.operations.last().map(|op| (op.op, op.old_line)),
+            Some(("removed", Some(7)))
+        );
+        assert_eq!(diff_documents(old, old).similarity, 1.0);
+
+        let similarity = |a, b, threshold| {
+            similarity_at_least(&normalized_chars(a), &normalized_chars(b), threshold)
+        };
+        assert_eq!(similarity("kitten", "sitting", 0.0), Some(1.0 - 3.0 / 7.0));
+        assert_eq!(similarity("kitten", "sitting", 0.6), None);
+        assert_eq!(similarity("a  b", "a b", 1.0), Some(1.0));
+        assert_eq!(similarity("abc", "cba", 0.0), Some(1.0 - 2.0 / 3.0));
+        assert_eq!(bounded_levenshtein(&['a'; 50], &['b'; 50], 49), None);
+
+        // The band and early exit agree with a full table on small inputs
+        let full = |a: &[char], b: &[char]| {
+            let mut previous: Vec<usize> = (0..=b.len()).collect();
+            for (i, ca) in a.iter().enumerate() {
+                let mut row = vec![i + 1];
+                for (j, cb) in b.iter().enumerate() {
+                    let cell = (previous[j] + usize::from(ca != cb))
+                        .min(previous[j + 1] + 1)
+                        .min(row[j] + 1);
+                    row.push(cell);
+                }
+                previous = row;
+            }
+            previous[b.len()]
+        };
+        let words: Vec<Vec<char>> = [
+            "", "a", "ab", "ba", "abc", "acb", "kitten", "sitting", "aabbcc", "abcabc",
+        ]
+        .iter()
+        .map(|w| w.chars().collect())
+        .collect();
+        for a in &words {
+            for b in &words {
+                let distance = full(a, b);
+                for max in 0..8 {
+                    assert_eq!(
+                        bounded_levenshtein(a, b, max),
+                        (distance <= max).then_some(distance),
+                        "{a:?} {b:?} {max}"
+                    );
+                }
+            }
         }
+
+        // An edit far into a long block still counts
+        let long = "word ".repeat(1000);
+        let diff = diff_documents(&format!("{long}old\n"), &format!("{long}new\n"));
+        assert_eq!(diff.operations.len(), 1);
+        assert_eq!(diff.operations[0].op, "modified");
+        assert!(diff.operations[0].similarity > 0.99 && diff.operations[0].similarity < 1.0);
+        assert!(diff.similarity < 1.0);
     }
-}
 
-fn escape_json_string(s: &str) -> String {
-    s.chars()
-        .map(|c| match c {
-            '"' => "\\\"".to_string(),
-            '\\' => "\\\\".to_string(),
-            '\n' => "\\n".to_string(),
-            '\r' => "\\r".to_string(),
-            '\t' => "\\t".to_string(),
-            c if c.is_control() => format!("\\u{:04x}", c as u32),
-            c => c.to_string(),
-        })
-        .collect()
-}
+    #[test]
+    fn toc_nests_headings_and_links_deduplicated_anchors() {
+        let content = "# Guide\n\n## Install\n\n### From [source]\n\n## Usage\n\n#### Flags\n\n## Install\n\n# Appendix\n";
+        let headings = collect_headings(content, parse_structure(content).headings);
+
+        assert_eq!(
+            render_toc(&headings, 1, 6),
+            "- [Guide](#guide)\n  - [Install](#install)\n    - [From \\[source\\]](#from-source)\n  - [Usage](#usage)\n    - [Flags](#flags)\n  - [Install](#install-1)\n- [Appendix](#appendix)\n"
+        );
+        assert_eq!(
+            render_toc(&headings, 2, 3),
+            "- [Install](#install)\n  - [From \\[source\\]](#from-source)\n- [Usage](#usage)\n- [Install](#install-1)\n"
+        );
+
+        let options = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(toc_levels_from_options(&[]).unwrap(), (1, 6));
+        assert_eq!(
+            toc_levels_from_options(&options(&[("min_depth", "2"), ("max_depth", "3")])).unwrap(),
+            (2, 3)
+        );
+        assert!(toc_levels_from_options(&options(&[("max_depth", "7")])).is_err());
+        assert!(
+            toc_levels_from_options(&options(&[("min_depth", "4"), ("max_depth", "2")])).is_err()
+        );
+    }
 
-// Stable ID generation
-fn generate_stable_chunk_id(heading_path: &[String], block_index: u32, text: &str) -> String {
-    let normalized_text = normalize_text_for_hash(text);
-    let text_hash = sha256_hash(&normalized_text);
+    #[test]
+    fn byte_ranges_slice_out_each_element() {
+        let content = "# Überblick {#top}\n\nSee [the docs](https://example.com \"Docs\") and [ref][r].\n\nSetext\n======\n\n  - [x] done  \n- [ ] todo\n\n```json-ld\n{\"@id\": \"ex:a\"}\n```\n\n    indented\n\n[r]: /ref\n";
+        let parsed = parse_document(content, &[]).unwrap();
+        let slice = |start: usize, end: usize| &content[start..end];
+
+        let headings: Vec<&str> = parsed
+            .headings
+            .iter()
+            .map(|h| slice(h.offset, h.end))
+            .collect();
+        assert_eq!(headings, vec!["# Überblick {#top}\n", "Setext\n======\n"]);
+
+        let links: Vec<&str> = parsed
+            .links
+            .iter()
+            .map(|l| slice(l.offset, l.end))
+            .collect();
+        assert_eq!(
+            links,
+            vec!["[the docs](https://example.com \"Docs\")", "[ref][r]"]
+        );
+
+        let blocks: Vec<&str> = parsed
+            .code_blocks
+            .iter()
+            .map(|b| slice(b.offset, b.end))
+            .collect();
+        assert_eq!(
+            blocks,
+            vec!["```json-ld\n{\"@id\": \"ex:a\"}\n```", "indented\n"]
+        );
+
+        let tasks: Vec<&str> = parsed
+            .tasks
+            .iter()
+            .map(|t| slice(t.offset, t.end))
+            .collect();
+        assert_eq!(tasks, vec!["- [x] done", "- [ ] todo"]);
+
+        let island = &parsed.jsonld_islands[0];
+        assert_eq!(
+            slice(island.offset, island.end),
+            "```json-ld\n{\"@id\": \"ex:a\"}\n```"
+        );
+        let frontmatter = "---\ntitle: Ranges\n\"@context\": https://schema.org/\n---\nBody\n";
+        let island = &collect_jsonld_islands(frontmatter)[0];
+        assert_eq!(
+            &frontmatter[island.offset..island.end],
+            "---\ntitle: Ranges\n\"@context\": https://schema.org/\n---"
+        );
+    }
 
-    let payload = serde_json::json!({
-        "heading_path": heading_path,
-        "block_index": block_index,
-        "text_hash": text_hash
-    });
+    #[test]
+    fn html_option_reports_blocks_and_inline_tags() {
+        let content = "# Notes\n\nSee E=mc<sup>2</sup>.\n\n<details>\n<summary>More</summary>\n\nHidden *body*\n\n</details>\n\n- tight <b>x</b>\n";
+        assert!(parse_document(content, &[]).unwrap().html.is_empty());
+
+        let parsed = parse_document(content, &[("html".to_string(), "true".to_string())]).unwrap();
+        let html: Vec<(&str, &str, usize)> = parsed
+            .html
+            .iter()
+            .map(|h| (h.kind, &content[h.byte_start..h.byte_end], h.line))
+            .collect();
+        assert_eq!(
+            html,
+            vec![
+                ("inline", "<sup>", 3),
+                ("inline", "</sup>", 3),
+                ("block", "<details>\n<summary>More</summary>\n", 5),
+                ("block", "</details>\n", 10),
+                ("inline", "<b>", 12),
+                ("inline", "</b>", 12),
+            ]
+        );
+        assert!(parsed
+            .html
+            .iter()
+            .all(|h| h.content == content[h.byte_start..h.byte_end]));
+    }
 
-    let canonical = json_canonicalize(&payload.to_string()).unwrap_or_default();
-    let chunk_hash = sha256_hash(&canonical);
-    chunk_hash[..12].to_string()
-}
+    #[test]
+    fn html_in_tight_list_items_is_classified_by_line() {
+        let content = "- <b>x</b> tail
+- <div>
+  </div>
+1. item <br>
+> - <i>y</i>
+";
+        let html = collect_html(content);
+        let kinds: Vec<(&str, &str)> = html.iter().map(|h| (h.kind, h.content.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("inline", "<b>"),
+                ("inline", "</b>"),
+                ("block", "<div>\n"),
+                ("block", "</div>\n"),
+                ("inline", "<br>"),
+                ("inline", "<i>"),
+                ("inline", "</i>"),
+            ]
+        );
+    }
 
-fn generate_heading_stable_id(text: &str, level: u32) -> String {
-    let slug = create_heading_slug(text);
-    format!("h{}-{}", level, slug)
-}
+    #[cfg(feature = "simd")]
+    #[test]
+    fn benchmark_grid_covers_the_measured_layer() {
+        let grid = benchmark_grid();
+        assert_eq!(grid.occupied_count(), 3 * 64 * 64);
+        let result = mem8::simd::PerformanceBenchmark::new().benchmark_grid_processing(&grid);
+        assert_eq!(result.num_items, 64 * 64);
+        assert!(result.max_error < 1e-2, "{}", result);
+    }
 
-fn create_heading_slug(text: &str) -> String {
-    text.to_lowercase()
-        .chars()
-        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-')
-        .collect::<String>()
-        .split_whitespace()
-        .collect::<Vec<_>>()
-        .join("-")
-}
+    #[test]
+    fn benchmark_wave_counts_are_capped() {
+        assert!(check_benchmark_waves(0).is_ok());
+        assert!(check_benchmark_waves(MAX_BENCHMARK_WAVES).is_ok());
+        let err = check_benchmark_waves(MAX_BENCHMARK_WAVES + 1).unwrap_err();
+        assert!(err.contains("exceeds the maximum"), "{}", err);
+        assert!(check_benchmark_waves(usize::MAX).is_err());
+    }
 
-fn normalize_text_for_hash(text: &str) -> String {
-    text.lines()
-        .map(|line| line.trim_end())
-        .collect::<Vec<_>>()
-        .join("\n")
-        .trim_end()
-        .to_string()
-}
+    #[test]
+    fn trailing_and_standalone_attribute_blocks_merge() {
+        let content = "# Title {.a #first} {.b .a} {#b lang=en}\n";
+        let heading = collect_headings(content, parse_structure(content).headings).remove(0);
+        let (text, attrs) = (heading.text, heading.attributes);
+        assert_eq!(text, "Title");
+        assert_eq!(attrs["id"], AttributeValue::String("b".to_string()));
+        assert_eq!(attrs["class"], AttributeValue::String("a b".to_string()));
+        assert_eq!(attrs["lang"], AttributeValue::String("en".to_string()));
+
+        let shorthand = parse_attribute_map("#intro .note class=x", true).unwrap();
+        assert_eq!(shorthand["id"], AttributeValue::String("intro".to_string()));
+        assert_eq!(
+            shorthand["class"],
+            AttributeValue::String("note x".to_string())
+        );
+        let lenient = parse_attribute_map("# note\n.x", false).unwrap();
+        assert_eq!(lenient["class"], AttributeValue::String("x".to_string()));
+        assert_eq!(lenient["note"], AttributeValue::Boolean(true));
+
+        let content = "### Title {.a} {#b}\n{.c data=1}\n\n## Next\n\nText\n\n{.orphan}\n";
+        let headings = collect_headings(content, parse_structure(content).headings);
+        assert_eq!(headings[0].text, "Title");
+        assert_eq!(
+            headings[0].attributes["class"],
+            AttributeValue::String("a c".to_string())
+        );
+        assert_eq!(
+            headings[0].attributes["id"],
+            AttributeValue::String("b".to_string())
+        );
+        assert!(matches!(headings[0].attributes["data"], AttributeValue::Number(n) if n == 1.0));
+        assert!(headings[1].attributes.is_empty());
+    }
 
-fn sha256_hash(data: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data.as_bytes());
-    format!("{:x}", hasher.finalize())
-}
+    #[test]
+    fn attribute_shorthand_expands_to_id_class_and_flags() {
+        let attrs = parse_attribute_map("#intro .note .warning lang=en draft", true).unwrap();
+        assert_eq!(attrs["id"], AttributeValue::String("intro".to_string()));
+        assert_eq!(
+            attrs["class"],
+            AttributeValue::String("note warning".to_string())
+        );
+        assert_eq!(attrs["lang"], AttributeValue::String("en".to_string()));
+        assert_eq!(attrs["draft"], AttributeValue::Boolean(true));
+        assert_eq!(attrs.len(), 4);
+
+        let heading = &parse_structure("## Intro {#intro .note .warning lang=en}\n").headings[0];
+        assert_eq!(heading.text, "Intro");
+        assert_eq!(heading.attributes["id"], "intro");
+    }
 
-// Attribute object mini-grammar parser
-#[derive(Debug, Clone)]
-enum AttributeValue {
-    String(String),
-    Number(f64),
-    Boolean(bool),
-    List(Vec<AttributeValue>),
-    Object(HashMap<String, AttributeValue>),
-}
+    #[test]
+    fn math_extraction_skips_escaped_dollars_prices_and_code() {
+        let content = "Energy $x^2$ costs \\$5, or $5 and $10.\n\n$$\n\\int_0^1 f(x)\\,dx\n$$\n\nSee `$y$` and\n\n```\n$z$\n```\n";
+        let math = collect_math(content);
+        let found: Vec<(&str, &str, usize)> = math
+            .iter()
+            .map(|m| (m.kind, m.content.as_str(), m.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("inline", "x^2", 1), ("display", "\\int_0^1 f(x)\\,dx", 3)]
+        );
+        assert_eq!(&content[math[0].byte_start..math[0].byte_end], "$x^2$");
+        assert!(content[math[1].byte_start..math[1].byte_end].starts_with("$$\n"));
+        assert!(collect_math("Only \\$5 here, and $ 3 $ too.").is_empty());
+
+        let only = [("only".to_string(), "math".to_string())];
+        let parsed = parse_document("Inline $a_1 + b_1$ math", &only).unwrap();
+        assert_eq!(parsed.math[0].content, "a_1 + b_1");
+
+        // Escapes pair up from the opener, and openers that never close
+        // don't rescan the rest of the paragraph
+        assert_eq!(collect_math("$a\\\\$ b")[0].content, "a\\\\");
+        assert!(collect_math("$a\\$ b").is_empty());
+        let unclosed = "$a ".repeat(160_000);
+        let started = std::time::Instant::now();
+        assert!(collect_math(&unclosed).is_empty());
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
 
-fn parse_attribute_object_mini_grammar(
-    attr_str: &str,
-    strict: bool,
-) -> Result<HashMap<String, AttributeValue>, String> {
-    let mut result = HashMap::new();
+    #[test]
+    fn content_links_split_into_resolved_and_missing() {
+        let known = sha256_hex(b"known object");
+        let unknown = sha256_hex(b"unknown object");
+        let content = format!(
+            "See [the spec]({}) and [a draft]({}).\n\n[site](https://example.com)\n",
+            known.to_uppercase(),
+            unknown
+        );
+
+        let from_hex = known_hashes_from_hex(std::slice::from_ref(&known)).unwrap();
+        let packed = decode_sha256_hex(&known).unwrap();
+        let from_packed = known_hashes_from_packed(&packed).unwrap();
+        assert_eq!(from_hex, from_packed);
+        assert!(known_hashes_from_packed(&packed[..31]).is_err());
+        assert!(known_hashes_from_hex(&["abc".to_string()]).is_err());
+
+        let check = check_content_links_against(&content, &from_hex);
+        let resolved: Vec<&str> = check.resolved.iter().map(|l| l.text.as_str()).collect();
+        let missing: Vec<(&str, &str)> = check
+            .missing
+            .iter()
+            .map(|l| (l.text.as_str(), l.url.as_str()))
+            .collect();
+        assert_eq!(resolved, vec!["the spec"]);
+        assert_eq!(missing, vec![("a draft", unknown.as_str())]);
+    }
 
-    // Simple key=value parser for now
-    let pairs = attr_str.split_whitespace();
+    #[test]
+    fn polyglot_threshold_and_weights_decide_borderline_documents() {
+        let content = "```dockerfile\nFROM alpine\nRUN apk add git\n```\n";
+        let options = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let detect = |pairs: &[(&str, &str)]| {
+            let config = PolyglotConfig::from_options(&options(pairs)).unwrap();
+            let scores = polyglot_scores(content, &config);
+            let docker = scores
+                .iter()
+                .find(|(language, _)| language == "dockerfile")
+                .unwrap()
+                .1;
+            let detected = polyglot_document_from_scores(content, &scores, config.threshold)
+                .map(|doc| doc.language);
+            (docker, detected)
+        };
+
+        // FROM, plus RUN and ADD (from "add")
+        let (score, detected) = detect(&[]);
+        assert_eq!(score, 3.0);
+        assert_eq!(detected.as_deref(), Some("dockerfile"));
+        assert_eq!(
+            detect_polyglot_document(content).map(|doc| doc.language),
+            detected
+        );
+
+        assert_eq!(detect(&[("threshold", "3.0")]), (3.0, None));
+        let (score, detected) = detect(&[
+            ("threshold", "3.0"),
+            ("secondary_weight", "0.25"),
+            ("dockerfile.primary_weight", "3"),
+        ]);
+        assert_eq!(score, 3.5);
+        assert_eq!(detected.as_deref(), Some("dockerfile"));
+
+        assert!(matches!(
+            PolyglotConfig::from_options(&options(&[("threshold", "-1")])),
+            Err(ParseError::InvalidOption(_))
+        ));
+    }
 
-    for pair in pairs {
-        if let Some((key, value)) = pair.split_once('=') {
-            let parsed_value = parse_attribute_value(value, strict)?;
-            result.insert(key.to_string(), parsed_value);
-        }
+    #[test]
+    fn links_inside_code_are_not_reported() {
+        let content = "Try `https://example.org/api` or `[x](https://x)` first.\n\n```text\n[fenced](https://fenced) https://bare\n```\n\nThen read [the docs](https://docs) or <https://auto>.\n";
+        let links: Vec<(String, usize)> = collect_links(parse_structure(content).links)
+            .into_iter()
+            .map(|link| (link.url, link.line))
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                ("https://docs".to_string(), 7),
+                ("https://auto".to_string(), 7)
+            ]
+        );
     }
 
-    Ok(result)
-}
+    #[test]
+    fn jsonld_shape_check_reports_missing_keys() {
+        let islands: serde_json::Value = serde_json::from_str(
+            r#"[
+                {"@context": "https://schema.org/", "@type": ["Article", "Thing"], "name": "A"},
+                {"@context": "https://schema.org/", "name": "No type"},
+                {"@type": "Person"}
+            ]"#,
+        )
+        .unwrap();
+        let required = vec!["name".to_string(), "@type".to_string()];
+        let reports = check_jsonld_shapes(&islands, &required).unwrap();
+
+        assert_eq!(
+            reports[0],
+            JsonLdShapeReport {
+                missing_keys: vec![],
+                has_context: true,
+                types: vec!["Article".to_string(), "Thing".to_string()],
+            }
+        );
+        assert_eq!(reports[1].missing_keys, vec!["@type"]);
+        assert!(reports[1].types.is_empty());
+        assert_eq!(reports[2].missing_keys, vec!["@context", "name"]);
+        assert!(!reports[2].has_context);
+
+        let single = serde_json::json!({"@context": {}, "@type": "Event"});
+        assert_eq!(check_jsonld_shapes(&single, &[]).unwrap().len(), 1);
+        assert!(check_jsonld_shapes(&serde_json::json!([1]), &[]).is_err());
+    }
 
-fn parse_attribute_value(value: &str, _strict: bool) -> Result<AttributeValue, String> {
-    // Remove quotes if present
-    let value = if value.starts_with('"') && value.ends_with('"') {
-        &value[1..value.len() - 1]
-    } else {
-        value
-    };
+    #[test]
+    fn mem8_context_stores_then_queries_across_threads() {
+        let ctx = Arc::new(create_default_mem8_context());
+        let wave = |amplitude: f32, phase: f32| MemoryWave {
+            amplitude,
+            frequency: 1.0,
+            phase,
+            valence: 0.0,
+            arousal: 0.0,
+            decay_tau: None,
+        };
+
+        assert_eq!(mem8_interference(&ctx, 10, 10, 10, 0.0).unwrap(), 0.0);
+        mem8_store(&ctx, 10, 10, 10, wave(1.0, std::f32::consts::FRAC_PI_2)).unwrap();
+        // A face neighbour counts half
+        mem8_store(&ctx, 11, 10, 10, wave(1.0, std::f32::consts::FRAC_PI_2)).unwrap();
+
+        let shared = ctx.clone();
+        let handle = std::thread::spawn(move || mem8_interference(&shared, 10, 10, 10, 0.0));
+        let value = handle.join().unwrap().unwrap();
+        assert!((value - 1.5).abs() < 1e-5, "{}", value);
+
+        // The corner cell sees only in-bounds neighbours
+        mem8_store(&ctx, 0, 0, 0, wave(2.0, std::f32::consts::FRAC_PI_2)).unwrap();
+        assert!((mem8_interference(&ctx, 0, 0, 0, 0.0).unwrap() - 2.0).abs() < 1e-5);
+
+        assert!(mem8_store(&ctx, 64, 0, 0, wave(1.0, 0.0)).is_err());
+        assert!(mem8_store(&ctx, 1, 1, 1, wave(0.05, 0.0)).is_err());
+        let invalid_decay = MemoryWave {
+            decay_tau: Some(-1.0),
+            ..wave(1.0, 0.0)
+        };
+        assert!(mem8_store(&ctx, 1, 1, 1, invalid_decay).is_err());
+        assert!(mem8_interference(&ctx, 0, 0, 256, 0.0).is_err());
+    }
 
-    // Try parsing as number
-    if let Ok(n) = value.parse::<f64>() {
-        return Ok(AttributeValue::Number(n));
+    #[test]
+    fn json_pointer_resolves_nested_and_escaped_tokens() {
+        let island = serde_json::json!({
+            "@context": "https://schema.org/",
+            "@graph": [
+                {"@type": "Article", "name": "First", "author": {"name": "Ada"}},
+                {"@type": "Person", "a/b": 1, "m~n": {"z": true, "a": null}}
+            ]
+        });
+        let at = |pointer: &str| {
+            resolve_json_pointer(&island, pointer).and_then(canonicalize_json_value)
+        };
+
+        assert_eq!(at("/@graph/0/name").unwrap(), "\"First\"");
+        assert_eq!(at("/@graph/0/author/name").unwrap(), "\"Ada\"");
+        assert_eq!(at("/@graph/1/a~1b").unwrap(), "1");
+        assert_eq!(at("/@graph/1/m~0n").unwrap(), r#"{"a":null,"z":true}"#);
+        assert_eq!(at("").unwrap(), canonicalize_json_value(&island).unwrap());
     }
 
-    // Try parsing as boolean
-    if let Ok(b) = value.parse::<bool>() {
-        return Ok(AttributeValue::Boolean(b));
+    #[test]
+    fn json_pointer_rejects_unresolvable_paths() {
+        let island = serde_json::json!({"@graph": [{"name": "Only"}], "n": 3});
+        let err = |pointer: &str| resolve_json_pointer(&island, pointer).unwrap_err();
+
+        assert!(err("/@graph/1").contains("out of range"));
+        assert!(err("/@graph/-").contains("invalid array index"));
+        assert!(err("/@graph/01").contains("invalid array index"));
+        assert!(err("/@graph/+0").contains("invalid array index"));
+        assert!(err("/missing").contains("not found"));
+        assert!(err("/n/0").contains("scalar"));
+        assert!(err("/bad~2").contains("invalid escape"));
+        assert!(err("@graph").contains("start with '/'"));
     }
 
-    // Default to string
-    Ok(AttributeValue::String(value.to_string()))
-}
+    #[test]
+    fn canonical_digest_hashes_the_returned_string() {
+        let value: serde_json::Value =
+            serde_json::from_str(r#"{"b": [1, true, null], "a": "x", "\ue000": 1, "😀": 2}"#)
+                .unwrap();
+        let (canonical, digest) = canonical_digest(&value).unwrap();
+
+        // UTF-16 order puts the surrogate pair (0xD83D) before U+E000
+        assert_eq!(
+            canonical,
+            "{\"a\":\"x\",\"b\":[1,true,null],\"😀\":2,\"\u{e000}\":1}"
+        );
+        assert_eq!(digest, sha256_hex(canonical.as_bytes()));
+
+        let reordered: serde_json::Value =
+            serde_json::from_str(r#"{"😀": 2, "\ue000": 1, "a": "x", "b": [1, true, null]}"#)
+                .unwrap();
+        assert_eq!(canonical_digest(&reordered).unwrap().1, digest);
+    }
 
-fn encode_attribute_value<'a>(env: Env<'a>, value: AttributeValue) -> Term<'a> {
-    match value {
-        AttributeValue::String(s) => s.encode(env),
-        AttributeValue::Number(n) => n.encode(env),
-        AttributeValue::Boolean(b) => b.encode(env),
-        AttributeValue::List(list) => {
-            let encoded_list: Vec<Term> = list
-                .into_iter()
-                .map(|v| encode_attribute_value(env, v))
-                .collect();
-            encoded_list.encode(env)
-        }
-        AttributeValue::Object(obj) => {
-            let encoded_obj: HashMap<String, Term> = obj
-                .into_iter()
-                .map(|(k, v)| (k, encode_attribute_value(env, v)))
-                .collect();
-            encoded_obj.encode(env)
+    #[test]
+    fn canonical_json_matches_rfc8785_vectors() {
+        // Appendix B: IEEE 754 bit patterns and their ECMAScript form
+        let numbers: &[(u64, &str)] = &[
+            (0x0000000000000000, "0"),
+            (0x8000000000000000, "0"),
+            (0x0000000000000001, "5e-324"),
+            (0x8000000000000001, "-5e-324"),
+            (0x7fefffffffffffff, "1.7976931348623157e+308"),
+            (0xffefffffffffffff, "-1.7976931348623157e+308"),
+            (0x4340000000000000, "9007199254740992"),
+            (0xc340000000000000, "-9007199254740992"),
+            (0x4430000000000000, "295147905179352830000"),
+            (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+            (0x44b52d02c7e14af6, "1e+23"),
+            (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+            (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+            (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+            (0x444b1ae4d6e2ef50, "1e+21"),
+            (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+            (0x3eb0c6f7a0b5ed8d, "0.000001"),
+            (0x41b3de4355555553, "333333333.3333332"),
+            (0x41b3de4355555554, "333333333.33333325"),
+            (0x41b3de4355555555, "333333333.3333333"),
+            (0x41b3de4355555556, "333333333.3333334"),
+            (0x41b3de4355555557, "333333333.33333343"),
+            (0xbecbf647612f3696, "-0.0000033333333333333333"),
+            (0x43143ff3c1cb0959, "1424953923781206.2"),
+        ];
+        for &(bits, expected) in numbers {
+            assert_eq!(
+                ecmascript_number(f64::from_bits(bits)).unwrap(),
+                expected,
+                "{:016x}",
+                bits
+            );
         }
+        assert!(ecmascript_number(f64::NAN).is_err());
+        assert!(ecmascript_number(f64::INFINITY).is_err());
+
+        // Section 3.2.2 sample
+        let input = r#"{
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+            "literals": [null, true, false]
+        }"#;
+        assert_eq!(
+            json_canonicalize(input).unwrap(),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+
+        // Section 3.2.3 sorting sample; U+0080 stays unescaped
+        let input = r#"{"\u20ac": "Euro Sign", "\r": "Carriage Return", "\ufb33": "Hebrew Letter Dalet With Dagesh", "1": "One", "\ud83d\ude00": "Emoji: Grinning Face", "\u0080": "Control", "\u00f6": "Latin Small Letter O With Diaeresis"}"#;
+        assert_eq!(
+            json_canonicalize(input).unwrap(),
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\"ö\":\"Latin Small Letter O With Diaeresis\",\"€\":\"Euro Sign\",\"😀\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+        );
+        assert_eq!(
+            json_canonicalize(r#""\b\f\u001f\u007f""#).unwrap(),
+            "\"\\b\\f\\u001f\u{7f}\""
+        );
     }
-}
 
-// Inline attributes parsing
-fn parse_inline_attributes(text: &str) -> (String, HashMap<String, AttributeValue>) {
-    lazy_static! {
-        static ref ATTR_REGEX: Regex = Regex::new(r"\s*\{([^}]+)\}\s*$").unwrap();
+    #[test]
+    fn attribute_modes_differ_on_unreadable_words() {
+        let error_at = |input: &str| match parse_attribute_map(input, true) {
+            Err(attr_object::Error::ParseError {
+                message, offset, ..
+            }) => (message, offset),
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+
+        let input = "a=1, b=\"two words\", c.d=x,\n";
+        let strict = parse_attribute_map(input, true).unwrap();
+        assert!(matches!(strict["a"], AttributeValue::Number(n) if n == 1.0));
+        assert!(matches!(&strict["b"], AttributeValue::String(s) if s == "two words"));
+        assert!(matches!(&strict["c.d"], AttributeValue::String(s) if s == "x"));
+        assert_eq!(parse_attribute_map(input, false).unwrap(), strict);
+
+        assert_eq!(error_at("a=1 !x"), ("expected a key".to_string(), 4));
+        assert_eq!(parse_attribute_map("a=1 !x", false).unwrap().len(), 1);
+        assert_eq!(error_at("// note\nb=1").1, 0);
+        assert_eq!(parse_attribute_map("// note\nb=1", false).unwrap().len(), 2);
+        assert_eq!(error_at("a=\"open"), ("unterminated string".to_string(), 2));
+        assert!(parse_attribute_map("a=\"open", false).is_err());
     }
 
-    if let Some(captures) = ATTR_REGEX.captures(text) {
-        let clean_text = ATTR_REGEX.replace(text, "").trim().to_string();
-        let attr_str = captures.get(1).unwrap().as_str();
-
-        let attributes = parse_attribute_object_mini_grammar(attr_str, false).unwrap_or_default();
+    #[test]
+    fn attribute_objects_and_heading_blocks_share_one_grammar() {
+        for input in [
+            "#todo fix this",
+            "#intro .note lang=en",
+            "# note .x",
+            "// note",
+            "ld:@type=Article n=2",
+        ] {
+            let content = format!("# Title {{{}}}\n", input);
+            let heading = collect_headings(&content, parse_structure(&content).headings).remove(0);
+            assert_eq!(
+                heading.attributes,
+                parse_attribute_map(input, true).unwrap_or_default(),
+                "{}",
+                input
+            );
+        }
 
-        (clean_text, attributes)
-    } else {
-        (text.to_string(), HashMap::new())
+        let todo = parse_attribute_map("#todo fix this", false).unwrap();
+        assert_eq!(todo["id"], AttributeValue::String("todo".to_string()));
+        assert_eq!(todo["fix"], AttributeValue::Boolean(true));
+        assert!(parse_attribute_map("# note .x", true).is_err());
     }
-}
 
-// Helper functions
-fn is_sha256_hash(s: &str) -> bool {
-    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
-}
-
-fn detect_code_block_artifact(content: &str) -> Option<String> {
-    if content.contains("FROM ") && content.contains("RUN ") {
-        Some("dockerfile".to_string())
-    } else if content.contains("apiVersion:") && content.contains("kind:") {
-        Some("kubernetes".to_string())
-    } else if content.contains("resource ") && content.contains("provider ") {
-        Some("terraform".to_string())
-    } else {
-        None
+    #[test]
+    fn strip_concealment_cleans_without_corrupting_text() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        let content = format!(
+            "Café\u{200B} naïve{family}\u{2060}   \r\nline two  \n<!-- polyglot:dockerfile:RlJPTQ== -->\n日本語\t\n"
+        );
+        let (clean, report) = strip_all_concealment(&content);
+
+        assert_eq!(
+            clean,
+            format!("Café naïve{family}  \r\nline two  \n\n日本語\n")
+        );
+        assert_eq!(
+            report,
+            StripReport {
+                zero_width_chars: 2,
+                trailing_whitespace_lines: 2,
+                polyglot_comments: 1,
+            }
+        );
+        assert!(extract_all_concealment(&clean).html_comments.is_empty());
     }
-}
 
-fn parse_task_line(line: &str, line_num: usize) -> Option<TaskItem> {
-    if line.starts_with("- [ ]") || line.starts_with("* [ ]") {
-        Some(TaskItem {
-            completed: false,
-            text: line[5..].trim().to_string(),
-            line: line_num,
-        })
-    } else if line.starts_with("- [x]") || line.starts_with("* [x]") {
-        Some(TaskItem {
-            completed: true,
-            text: line[5..].trim().to_string(),
-            line: line_num,
-        })
-    } else {
-        None
+    #[test]
+    fn zero_width_payloads_ignore_emoji_joiners() {
+        let family = "Family: 👨\u{200D}👩\u{200D}👧\u{FEFF}";
+        assert!(extract_zero_width_chars(family).is_empty());
+
+        let hidden = format!(
+            "{}{}",
+            family,
+            encode_zero_width_data("ключ 🔑".as_bytes(), SAFE_ZERO_WIDTH_ALPHABET)
+        );
+        let symbols = extract_zero_width_chars(&hidden);
+        assert_eq!(symbols.len(), "ключ 🔑".len() * 4);
+        assert_eq!(
+            decode_zero_width_data(&symbols, SAFE_ZERO_WIDTH_ALPHABET).unwrap(),
+            "ключ 🔑"
+        );
+
+        // A custom two-character alphabet carries one bit per character
+        let binary = ['\u{2062}', '\u{2063}'];
+        let encoded = encode_zero_width_data(b"A", &binary);
+        assert_eq!(encoded.chars().count(), 8);
+        assert_eq!(
+            decode_zero_width_data(&zero_width_symbols(&encoded, &binary), &binary).unwrap(),
+            "A"
+        );
+        assert!(decode_zero_width_data(&[0, 1], SAFE_ZERO_WIDTH_ALPHABET).is_err());
     }
-}
 
-#[derive(Debug, Clone)]
-struct TaskItem {
-    completed: bool,
-    text: String,
-    line: usize,
-}
+    #[test]
+    fn phase_coherence_measures_offset_locking() {
+        let wave = |phase: f32| MemoryWave {
+            amplitude: 1.0,
+            frequency: 100.0,
+            phase,
+            valence: 0.0,
+            arousal: 0.0,
+            decay_tau: None,
+        };
+        let coherence = |a: &[MemoryWave], b: &[MemoryWave]| {
+            let convert = |waves: &[MemoryWave]| -> Vec<_> {
+                waves.iter().map(MemoryWave::to_mem8).collect()
+            };
+            mem8::wave::phase_coherence(&convert(a), &convert(b))
+        };
+        let a: Vec<MemoryWave> = (0..40).map(|i| wave(i as f32 * 0.4)).collect();
+        let shifted: Vec<MemoryWave> = a.iter().map(|w| wave(w.phase - 2.0)).collect();
+        assert!((coherence(&a, &a) - 1.0).abs() < 1e-6);
+        assert!((coherence(&a, &shifted) - 1.0).abs() < 1e-5);
+
+        // Opposite pairs cancel
+        let b = vec![wave(0.0), wave(0.0)];
+        let c = vec![wave(0.0), wave(std::f32::consts::PI)];
+        assert!(coherence(&b, &c) < 1e-6);
+        assert_eq!(coherence(&[], &a), 0.0);
+    }
 
-fn encode_task_item<'a>(env: Env<'a>, task: TaskItem) -> Term<'a> {
-    let mut task_map = HashMap::new();
-    task_map.insert("completed".to_string(), task.completed.encode(env));
-    task_map.insert("text".to_string(), task.text.encode(env));
-    task_map.insert("line".to_string(), task.line.encode(env));
-    task_map.encode(env)
-}
+    #[test]
+    fn band_histogram_counts_semantic_and_brainwave_bands() {
+        let waves: Vec<MemoryWave> = [20.0, 50.0, 150.0, 450.0, 999.0]
+            .into_iter()
+            .map(|frequency| MemoryWave {
+                amplitude: 1.0,
+                frequency,
+                phase: 0.0,
+                valence: 0.0,
+                arousal: 0.0,
+                decay_tau: None,
+            })
+            .collect();
+
+        let histogram = wave_band_histogram(&waves);
+        assert_eq!(histogram.len(), 7);
+        assert_eq!(histogram["structural"], 3);
+        assert_eq!(histogram["beta_awareness"], 1);
+        assert_eq!(histogram["gamma_consciousness"], 1);
+        assert_eq!(histogram["conversational"], 0);
+        assert_eq!(histogram["technical"], 1);
+        assert_eq!(histogram["abstract"], 1);
+    }
 
-fn extract_frontmatter(content: &str) -> Option<String> {
-    if content.starts_with("---\n") {
-        if let Some(end) = content[4..].find("\n---\n") {
-            return Some(content[4..end + 4].to_string());
+    #[test]
+    fn fingerprint_ignores_cosmetic_formatting() {
+        let original = "---\ntitle: Plan\ntags: [a, b]\n---\n# Getting Started\n\nShip the *first* draft\nby Friday.   \n\n- one\n- two\n\n```rust\nfn main() { run(); }\n```\n";
+        let reformatted = "---\ntags:\n  - a\n  - b\ntitle: Plan\n---\nGETTING started\n===============\n\n\nShip   the _first_ draft by Friday.\n\n* one\n\n* two\n\n```rust\nfn main() {\n    run();\n}\n```\n";
+        let fingerprint = fingerprint_document(original);
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(fingerprint_document(reformatted), fingerprint);
+
+        for changed in [
+            original.replace("Friday", "Monday"),
+            original.replace("title: Plan", "title: Draft"),
+            original.replace("run();", "walk();"),
+            original.replace("# Getting", "## Getting"),
+            original.replace("- two", "- three"),
+            original.replace("- two", "- t\n  - wo"),
+        ] {
+            assert_ne!(fingerprint_document(&changed), fingerprint, "{}", changed);
         }
     }
-    None
-}
 
-fn extract_jsonld_from_frontmatter(frontmatter: &str) -> Option<String> {
-    // Parse YAML and extract JSON-LD context
-    // Simplified for now
-    if frontmatter.contains("@context") {
-        Some(frontmatter.to_string())
-    } else {
-        None
+    #[test]
+    fn lint_reports_each_rule_with_lines() {
+        let content = "# Intro\n\n\
+                       ![](diagram.png) and ![chart](chart.png)\n\n\
+                       See [click here](https://a.example) or <https://b.example>.\n\n\
+                       ### Deep\n\n\
+                       ##\n\n\
+                       ## Intro\n";
+        let issues = lint_document(content, &all_rules());
+
+        assert_eq!(
+            rules_of(&issues),
+            vec![
+                ("missing_alt", 3),
+                ("uninformative_link_text", 5),
+                ("uninformative_link_text", 5),
+                ("skipped_heading_level", 7),
+                ("empty_heading", 9),
+                ("duplicate_heading_id", 11),
+            ]
+        );
+        assert!(issues.iter().all(|issue| !issue.message.is_empty()));
     }
-}
 
-fn create_jsonld_island<'a>(env: Env<'a>, content: String, line: usize, source: &str) -> Term<'a> {
-    let mut island = HashMap::new();
-    island.insert("content".to_string(), content.encode(env));
-    island.insert("line".to_string(), line.encode(env));
-    island.insert("source".to_string(), source.encode(env));
-    island.encode(env)
-}
+    #[test]
+    fn lint_honours_enabled_rules() {
+        let content = "# A\n\n### B\n\n![](x.png)\n";
+        let enabled = select_lint_rules(&["missing_alt".to_string()]).unwrap();
 
-// Mem8 integration
-fn create_default_mem8_context() -> Mem8Context {
-    Mem8Context {
-        wave_grid: Arc::new(Mutex::new(WaveGrid {
-            width: 64,
-            height: 64,
-            depth: 256,
-            grid: vec![None; 64 * 64 * 256],
-            noise_floor: 0.1,
-        })),
-        consciousness: ConsciousnessState {
-            attention_weights: HashMap::new(),
-            active_memories: Vec::new(),
-            awareness_level: 0.5,
-        },
-        memory_index: MemIndex {
-            version: "1.0.0".to_string(),
-            blocks: HashMap::new(),
-            projects: HashMap::new(),
-        },
+        assert_eq!(
+            rules_of(&lint_document(content, &enabled)),
+            vec![("missing_alt", 5)]
+        );
+        assert!(select_lint_rules(&["no_such_rule".to_string()]).is_err());
     }
-}
 
-fn calculate_wave_context_influence(_wave_grid: &Arc<Mutex<WaveGrid>>, content: &str) -> f32 {
-    // Simplified wave influence calculation
-    let word_count = content.split_whitespace().count() as f32;
-    let influence = (word_count / 1000.0).min(1.0) * 0.5;
-    influence
-}
+    #[test]
+    fn lint_uses_explicit_heading_ids() {
+        let content = "## Setup {id=install}\n\n## Install\n\n## Other {id=unique}\n";
+        let issues = lint_document(content, &all_rules());
 
-rustler::init!("Elixir.MarkdownLd.Native");
+        assert_eq!(rules_of(&issues), vec![("duplicate_heading_id", 3)]);
+
+        // A link in a heading doesn't replace the heading's text
+        let content = "# See [here](x)\n\n## Here\n\n## See here\n";
+        assert_eq!(
+            rules_of(&lint_document(content, &all_rules())),
+            vec![("uninformative_link_text", 1), ("duplicate_heading_id", 5)]
+        );
+    }
+}